@@ -18,6 +18,12 @@ async fn main() -> Result<()> {
                 },
             ],
             body: None,
+            expect: None,
+            slo: None,
+            extract: Vec::new(),
+            when: None,
+            checks: Vec::new(),
+            tags: Default::default(),
         },
         
         // POST request with JSON body
@@ -35,6 +41,12 @@ async fn main() -> Result<()> {
                 },
             ],
             body: Some(r#"{"name": "John Doe", "email": "john@example.com"}"#.as_bytes().to_vec()),
+            expect: None,
+            slo: None,
+            extract: Vec::new(),
+            when: None,
+            checks: Vec::new(),
+            tags: Default::default(),
         },
         
         // PUT request
@@ -48,6 +60,12 @@ async fn main() -> Result<()> {
                 },
             ],
             body: Some(r#"{"name": "Jane Doe", "email": "jane@example.com"}"#.as_bytes().to_vec()),
+            expect: None,
+            slo: None,
+            extract: Vec::new(),
+            when: None,
+            checks: Vec::new(),
+            tags: Default::default(),
         },
     ];
 
@@ -8,20 +8,29 @@ use opentelemetry_otlp::WithExportConfig;
 use opentelemetry_sdk::logs::LoggerProvider;
 use opentelemetry_sdk::metrics::MeterProviderBuilder;
 use opentelemetry_sdk::Resource;
+use bytes::Bytes;
+use reqwest::cookie::Jar;
 use reqwest::Client;
-use std::io::Write;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{BufWriter, Read};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tokio::time::sleep;
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::{prelude::*, EnvFilter};
+use uuid::Uuid;
 
 // Struct to hold our metrics
 #[derive(Debug, Default, Clone)]
 struct AttackMetrics {
     total_requests: u64,
     success_requests: u64,
+    not_modified_requests: u64,
+    check_failures: u64,
     failure_requests: u64,
     timeout_requests: u64,
     bytes_in: u64,
@@ -35,6 +44,8 @@ impl AttackMetrics {
         Self {
             total_requests: 0,
             success_requests: 0,
+            not_modified_requests: 0,
+            check_failures: 0,
             failure_requests: 0,
             timeout_requests: 0,
             bytes_in: 0,
@@ -56,6 +67,14 @@ impl AttackMetrics {
         self.success_requests += 1;
     }
 
+    fn increment_not_modified(&mut self) {
+        self.not_modified_requests += 1;
+    }
+
+    fn increment_check_failure(&mut self) {
+        self.check_failures += 1;
+    }
+
     fn increment_failure(&mut self) {
         self.failure_requests += 1;
     }
@@ -81,48 +100,209 @@ impl AttackMetrics {
     }
 }
 
-use crate::models::{AttackConfig, Header, Result as AttackResult, Target};
-use crate::utils::{get_reader, parse_headers, parse_http_targets, parse_json_targets, parse_rate, parse_file_targets};
-
-/// Run the attack command with the given arguments
-pub async fn run(
-    body: Option<String>,
-    cert: Option<String>,
-    chunked: bool,
-    connections: usize,
-    dns_ttl: humantime::Duration,
-    duration: Option<humantime::Duration>,
-    format: String,
-    h2c: bool,
-    headers: Vec<String>,
-    http2: bool,
-    insecure: bool,
-    keepalive: bool,
-    key: Option<String>,
-    laddr: String,
-    lazy: bool,
-    max_body: i64,
-    max_connections: Option<usize>,
-    max_workers: Option<u64>,
-    name: Option<String>,
-    output: String,
-    opentelemetry_addr: Option<String>,
-    proxy_headers: Vec<String>,
-    rate: String,
-    redirects: i32,
-    _resolvers: Vec<String>,
-    root_certs: Vec<String>,
-    _session_tickets: bool,
-    targets: String,
-    timeout: humantime::Duration,
-    http_timeout: humantime::Duration,
-    _unix_socket: Option<String>,
-    workers: u64,
-    tolerance: f64,
-) -> Result<()> {
+use crate::auth::{spawn_oauth2_refresh, spawn_token_refresh, DigestAuth, OAuth2Config, ProxyAuth, TokenSource};
+use crate::cache::ValidatorCache;
+use crate::fuzz::{self, parse_strategies};
+use crate::hooks::{MiddlewareChain, OnResponse, RequestSpec, ResponseData};
+use crate::models::{AttackConfig, Expect, RunConfig, Golden, GoldenCompare, Header, Result as AttackResult, Row, Scenario, ScenarioStep, Stage, Target};
+use crate::plan::Plan;
+use crate::plugin::Plugin;
+use crate::results::{Encoding, ResultWriter};
+use crate::scenario;
+use crate::script::Script;
+use crate::utils::{build_cookie_jar, build_proxies, get_reader, parse_credentials, parse_headers, parse_metadata, parse_http_targets, parse_json_targets, parse_rate, parse_file_targets, parse_scenarios, parse_server_timing};
+
+/// Options of the attack command, one field per flag
+pub struct AttackOptions {
+    pub accept_encoding: String,
+    pub basic_auth: Option<String>,
+    pub bearer_token_cmd: Option<String>,
+    pub bearer_token_file: Option<String>,
+    pub bearer_token_refresh: humantime::Duration,
+    pub body: Option<String>,
+    pub cert: Option<String>,
+    pub chunked: bool,
+    pub conditional: bool,
+    pub connections: usize,
+    pub cookies: Vec<String>,
+    pub cookie_jar: bool,
+    pub digest_auth: Option<String>,
+    pub dns_ttl: humantime::Duration,
+    pub duration: Option<humantime::Duration>,
+    pub encoding: String,
+    pub format: String,
+    pub fuzz: bool,
+    pub fuzz_strategies: String,
+    pub h2c: bool,
+    pub headers: Vec<String>,
+    pub http2: bool,
+    pub insecure: bool,
+    pub keepalive: bool,
+    pub key: Option<String>,
+    pub laddr: String,
+    pub lazy: bool,
+    pub max_body: i64,
+    pub max_connections: Option<usize>,
+    pub max_workers: Option<u64>,
+    pub metadata: Vec<String>,
+    pub name: Option<String>,
+    pub no_decompress: bool,
+    pub oauth2_client_id: Option<String>,
+    pub oauth2_client_secret: Option<String>,
+    pub oauth2_scopes: Vec<String>,
+    pub oauth2_token_url: Option<String>,
+    pub output: String,
+    pub output_append: bool,
+    pub output_per_target: Option<String>,
+    pub opentelemetry_addr: Option<String>,
+    pub plan: Option<Plan>,
+    pub plugins: Vec<String>,
+    pub proxy: Option<String>,
+    pub proxy_auth: Option<String>,
+    pub proxy_bearer_token: Option<String>,
+    pub proxy_headers: Vec<String>,
+    pub rate: String,
+    pub redirects: i32,
+    pub request_id_header: Option<String>,
+    pub root_certs: Vec<String>,
+    pub run_id: Option<String>,
+    pub scenarios: Option<String>,
+    pub script: Option<String>,
+    pub setup: Option<String>,
+    pub stages: Vec<Stage>,
+    pub targets: String,
+    pub teardown: Option<String>,
+    pub timeout: humantime::Duration,
+    pub http_timeout: humantime::Duration,
+    pub upload: Option<String>,
+    pub verify_request_id: bool,
+    pub vu_shared_state: bool,
+    pub vus: Option<u64>,
+    pub workers: u64,
+    pub tolerance: f64,
+}
+
+/// Run the attack command with the given options
+pub async fn run(options: AttackOptions) -> Result<()> {
+    let AttackOptions {
+        accept_encoding, basic_auth, bearer_token_cmd, bearer_token_file, bearer_token_refresh, body, cert,
+        chunked, conditional, connections, cookies, cookie_jar, digest_auth, dns_ttl, duration, encoding,
+        format, fuzz, fuzz_strategies, h2c, headers, http2, insecure, keepalive, key, laddr, lazy, max_body,
+        max_connections, max_workers, metadata, name, no_decompress, oauth2_client_id, oauth2_client_secret,
+        oauth2_scopes, oauth2_token_url, output, output_append, output_per_target, opentelemetry_addr, plan,
+        plugins, proxy, proxy_auth, proxy_bearer_token, proxy_headers, rate, redirects, request_id_header,
+        root_certs, run_id, scenarios, script, setup, stages, targets, teardown, timeout, http_timeout,
+        upload, verify_request_id, vu_shared_state, vus, workers, tolerance,
+    } = options;
+
     // Parse rate
     let rate_value = parse_rate(&rate)?;
 
+    // Only results written to a file can be uploaded
+    let upload = upload.map(|destination| destination.parse::<crate::upload::Destination>()).transpose()?;
+    let upload_path = output.strip_prefix(crate::sqlite::SCHEME).unwrap_or(&output).to_string();
+    let streamed = crate::tsdb::is_tsdb_url(&output) || crate::socket::is_socket_url(&output);
+    if upload.is_some() && (output == "stdout" || streamed) {
+        anyhow::bail!("--upload needs results written to a file with --output");
+    }
+
+    // Parse the results encoding
+    let encoding: Encoding = encoding.parse()?;
+
+    // Results can only be appended to a file holding results in the same encoding
+    if output_append {
+        let file = output != "stdout" && !streamed;
+        if !file && output_per_target.is_none() {
+            anyhow::bail!("--output-append needs results written to a file with --output");
+        }
+        if file && !output.starts_with(crate::sqlite::SCHEME) {
+            crate::results::check_append(&output, encoding)?;
+        }
+    }
+
+    // Virtual users run until the duration has elapsed
+    if vus.is_some() && duration.is_none() {
+        anyhow::bail!("Virtual users require a duration");
+    }
+    if vus == Some(0) {
+        anyhow::bail!("At least one virtual user is required");
+    }
+    if vu_shared_state && vus.is_none() {
+        anyhow::bail!("--vu-shared-state only applies to virtual users, run with --vus or --stages");
+    }
+
+    // Parse basic auth credentials
+    let basic_auth = basic_auth.as_deref().map(parse_credentials).transpose()?;
+
+    // Parse proxy credentials
+    let proxy_auth = match (proxy_auth, proxy_bearer_token) {
+        (Some(credentials), _) => {
+            let (user, pass) = parse_credentials(&credentials)?;
+            Some(ProxyAuth::Basic(user, pass))
+        }
+        (None, Some(token)) => Some(ProxyAuth::Bearer(token)),
+        (None, None) => None,
+    };
+
+    // Parse fuzz strategies
+    let fuzz = if fuzz { parse_strategies(&fuzz_strategies)? } else { Vec::new() };
+
+    // Parse digest auth credentials
+    let digest_auth = digest_auth
+        .as_deref()
+        .map(|credentials| parse_credentials(credentials).map(|(user, pass)| DigestAuth::new(&user, &pass)))
+        .transpose()?;
+
+    // Start refreshing the bearer token if a source was given
+    let token_source = match (bearer_token_file, bearer_token_cmd) {
+        (Some(path), _) => Some(TokenSource::File(path)),
+        (None, Some(command)) => Some(TokenSource::Command(command)),
+        (None, None) => None,
+    };
+    let mut bearer_token = token_source
+        .map(|source| spawn_token_refresh(source, bearer_token_refresh.into()))
+        .transpose()?;
+
+    // Fetch an OAuth2 token if a token endpoint was given
+    if let (Some(token_url), Some(client_id), Some(client_secret)) = (oauth2_token_url, oauth2_client_id, oauth2_client_secret) {
+        let oauth2 = OAuth2Config {
+            token_url,
+            client_id,
+            client_secret,
+            scopes: oauth2_scopes,
+        };
+        let token_client = Client::builder().danger_accept_invalid_certs(insecure).build()?;
+        bearer_token = Some(spawn_oauth2_refresh(oauth2, token_client).await?);
+    }
+
+    // Load the script, hooking its functions into every request
+    let mut middleware = MiddlewareChain::default();
+    let mut on_response = Vec::new();
+    if let Some(path) = &script {
+        let script = Arc::new(Script::load(path)?);
+        if script.has_request() {
+            middleware.push(script.clone());
+        }
+        if script.has_response() {
+            on_response.push(OnResponse::new(move |response, result| script.on_response(response, result)));
+        }
+    }
+
+    // Load the plugins after the script, so e.g. a signing plugin sees the final request
+    let plugins = plugins
+        .iter()
+        .map(|path| Plugin::load(path).map(Arc::new))
+        .collect::<Result<Vec<_>>>()?;
+    for plugin in &plugins {
+        if plugin.has_pre_request() {
+            middleware.push(plugin.clone());
+        }
+        if plugin.has_post_response() {
+            let plugin = plugin.clone();
+            on_response.push(OnResponse::new(move |response, result| plugin.post_response(response, result)));
+        }
+    }
+
     // Create attack config
     let config = AttackConfig {
         rate: rate_value,
@@ -136,16 +316,37 @@ pub async fn run(
         max_connections,
         http2,
         name: name.clone(),
+        run_id: run_id.unwrap_or_else(|| Uuid::new_v4().to_string()),
+        metadata: parse_metadata(&metadata)?,
         max_body,
         dns_ttl: dns_ttl.into(),
         laddr: laddr.clone(),
         lazy,
         opentelemetry_addr: opentelemetry_addr.clone(),
         tolerance: Some(tolerance),
+        cookie_jar,
+        cookies,
+        basic_auth,
+        digest_auth,
+        bearer_token,
+        before_send: Vec::new(),
+        middleware,
+        on_response,
+        accept_encoding,
+        decompress: !no_decompress,
+        request_id_header,
+        verify_request_id,
+        conditional: conditional.then(ValidatorCache::new),
+        fuzz,
+        stages,
+        sequence: Arc::new(AtomicU64::new(0)),
     };
 
-    // Parse headers
-    let parsed_headers = parse_headers(&headers)?;
+    // Parse headers, adding the scenario file's headers
+    let mut parsed_headers = parse_headers(&headers)?;
+    if let Some(plan) = &plan {
+        parsed_headers.extend(plan.headers.iter().cloned());
+    }
 
     // Parse proxy headers
     let parsed_proxy_headers = parse_headers(&proxy_headers)?;
@@ -159,58 +360,63 @@ pub async fn run(
         None
     };
 
-    // Read targets
+    // Read scenarios if provided, from the scenario file or the scenarios file
+    let scenarios = match &plan {
+        Some(plan) => Some(plan.scenarios.clone()).filter(|s| !s.is_empty()),
+        None => scenarios
+            .as_deref()
+            .map(|path| get_reader(path).and_then(parse_scenarios))
+            .transpose()?,
+    };
+    check_feeders(scenarios.as_deref().unwrap_or_default())?;
+
+    // Read targets, or use the scenario steps as the targets
     // Note: The lazy parameter is stored in the config but not fully implemented.
     // In a full implementation, this would read targets on-demand instead of all at once.
-    let reader = get_reader(&targets)?;
-    let targets_list = match format.as_str() {
-        "http" => parse_http_targets(reader)?,
-        "json" => parse_json_targets(reader)?,
-        "file" => parse_file_targets(reader)?,
-        _ => anyhow::bail!("Unsupported format: {}", format),
+    let targets_list = match (&scenarios, &plan) {
+        (Some(scenarios), _) => scenarios.iter().flat_map(|s| s.steps.clone()).collect(),
+        (None, Some(plan)) => plan.targets.clone(),
+        (None, None) if plugins.iter().any(|p| p.has_targets()) => {
+            let mut targets = Vec::new();
+            for plugin in plugins.iter().filter(|p| p.has_targets()) {
+                targets.extend(plugin.targets()?);
+            }
+            targets
+        }
+        (None, None) => {
+            let reader = get_reader(&targets)?;
+            match format.as_str() {
+                "http" => parse_http_targets(reader)?,
+                "json" => parse_json_targets(reader)?,
+                "file" => parse_file_targets(reader)?,
+                _ => anyhow::bail!("Unsupported format: {}", format),
+            }
+        }
     };
 
     if targets_list.is_empty() {
         anyhow::bail!("No targets specified");
     }
 
-    // Create HTTP client
-    let mut client_builder = Client::builder()
-        .timeout(config.http_timeout)
-        .pool_max_idle_per_host(config.connections);
-
-    if let Some(max_conns) = config.max_connections {
-        client_builder = client_builder.pool_max_idle_per_host(max_conns);
-    }
-
-    if !config.keepalive {
-        client_builder = client_builder.pool_idle_timeout(None);
-    }
-
-    if insecure {
-        client_builder = client_builder.danger_accept_invalid_certs(true);
-    }
-
-    if h2c {
-        client_builder = client_builder.http2_prior_knowledge();
-    } else if config.http2 {
-        client_builder = client_builder.http2_adaptive_window(true);
-    }
+    // Route requests through the proxy, authenticating if credentials were given
+    let proxies = build_proxies(proxy.as_deref(), proxy_auth.as_ref())?;
 
     // Configure local address binding
-    if config.laddr != "0.0.0.0" {
+    let local_addr = if config.laddr != "0.0.0.0" {
         // Parse the local address
         let local_addr = config.laddr.parse::<std::net::IpAddr>()
             .context(format!("Failed to parse local address: {}", config.laddr))?;
-        client_builder = client_builder.local_address(local_addr);
-    }
+        Some(local_addr)
+    } else {
+        None
+    };
 
     // Note: DNS TTL configuration is not directly supported by reqwest in the way we need it.
     // The dns_ttl parameter is stored in the config but not fully implemented.
     // In a full implementation, this would configure DNS caching behavior.
 
-    // Set up TLS client certificate and key if provided
-    if let (Some(cert_path), Some(key_path)) = (&cert, &key) {
+    // Read the TLS client certificate and key if provided
+    let identity = if let (Some(cert_path), Some(key_path)) = (&cert, &key) {
         let cert_bytes = std::fs::read(cert_path)
             .context(format!("Failed to read certificate file: {}", cert_path))?;
         let key_bytes = std::fs::read(key_path)
@@ -218,27 +424,99 @@ pub async fn run(
 
         let identity = reqwest::Identity::from_pem(&[cert_bytes, key_bytes].concat())
             .context("Failed to create identity from certificate and key")?;
+        Some(identity)
+    } else {
+        None
+    };
 
-        client_builder = client_builder.identity(identity);
-    }
-
-    // Set up TLS root certificates if provided
+    // Read the TLS root certificates if provided
+    let mut root_certificates = Vec::new();
     for cert_path in &root_certs {
         let cert_bytes = std::fs::read(cert_path)
             .context(format!("Failed to read root certificate file: {}", cert_path))?;
         let cert = reqwest::Certificate::from_pem(&cert_bytes)
             .context(format!("Failed to parse root certificate: {}", cert_path))?;
-        client_builder = client_builder.add_root_certificate(cert);
+        root_certificates.push(cert);
     }
 
-    // Set up redirects policy
-    if redirects >= 0 {
-        client_builder = client_builder.redirect(reqwest::redirect::Policy::limited(redirects as usize));
-    } else {
-        client_builder = client_builder.redirect(reqwest::redirect::Policy::none());
-    }
+    // Create HTTP clients, each with its own connection pool and optional cookie jar
+    // Automatic decompression is disabled so wire and decoded body sizes can both be recorded
+    let build_client = |jar: Option<Arc<Jar>>| -> Result<Client> {
+        let mut client_builder = Client::builder()
+            .timeout(config.http_timeout)
+            .pool_max_idle_per_host(config.connections)
+            .no_gzip()
+            .no_brotli()
+            .no_deflate();
+
+        if let Some(max_conns) = config.max_connections {
+            client_builder = client_builder.pool_max_idle_per_host(max_conns);
+        }
+
+        if !config.keepalive {
+            client_builder = client_builder.pool_idle_timeout(None);
+        }
+
+        if let Some(jar) = jar {
+            client_builder = client_builder.cookie_provider(jar);
+        }
+
+        for proxy in &proxies {
+            client_builder = client_builder.proxy(proxy.clone());
+        }
+
+        if insecure {
+            client_builder = client_builder.danger_accept_invalid_certs(true);
+        }
+
+        if h2c {
+            client_builder = client_builder.http2_prior_knowledge();
+        } else if config.http2 {
+            client_builder = client_builder.http2_adaptive_window(true);
+        }
+
+        if let Some(local_addr) = local_addr {
+            client_builder = client_builder.local_address(local_addr);
+        }
 
-    let client = Arc::new(client_builder.build()?);
+        if let Some(identity) = &identity {
+            client_builder = client_builder.identity(identity.clone());
+        }
+
+        for cert in &root_certificates {
+            client_builder = client_builder.add_root_certificate(cert.clone());
+        }
+
+        // Set up redirects policy
+        if redirects >= 0 {
+            client_builder = client_builder.redirect(reqwest::redirect::Policy::limited(redirects as usize));
+        } else {
+            client_builder = client_builder.redirect(reqwest::redirect::Policy::none());
+        }
+
+        Ok(client_builder.build()?)
+    };
+
+    let clients = match vus {
+        // Virtual users sharing state use one client, so one connection pool and cookie jar
+        Some(vus) if vu_shared_state => {
+            let client = Arc::new(build_client(Some(build_cookie_jar(&config.cookies, &targets_list)?))?);
+            (0..vus).map(|_| client.clone()).collect()
+        }
+        // Each virtual user keeps its own session: a separate client, connections, and cookie jar
+        Some(vus) => (0..vus)
+            .map(|_| Ok(Arc::new(build_client(Some(build_cookie_jar(&config.cookies, &targets_list)?))?)))
+            .collect::<Result<Vec<_>>>()?,
+        None => {
+            // Set up a cookie jar, seeded with any initial cookies
+            let jar = if config.cookie_jar || !config.cookies.is_empty() {
+                Some(build_cookie_jar(&config.cookies, &targets_list)?)
+            } else {
+                None
+            };
+            vec![Arc::new(build_client(jar)?)]
+        }
+    };
 
     // Set up a single progress bar for all progress information
     let progress_style = ProgressStyle::default_bar()
@@ -247,7 +525,7 @@ pub async fn run(
         .progress_chars("##-");
 
     // Create a single progress bar that shows both time and request progress
-    let progress_bar = if duration.is_some() {
+    let progress_bar = if duration.is_some() && vus.is_none() {
         let expected_requests = (rate_value * duration.unwrap().as_secs_f64()) as u64;
         let pb = ProgressBar::new(expected_requests);
         pb.set_style(progress_style);
@@ -258,6 +536,9 @@ pub async fn run(
         None
     };
 
+    // Describe the attack for the output, before it starts
+    let run_config = RunConfig::new(&config, targets_list.len());
+
     // Set up channels
     let (tx, mut rx) = mpsc::channel::<AttackResult>(1000);
 
@@ -270,7 +551,7 @@ pub async fn run(
 
     // Set up OpenTelemetry metrics and logs if an address is provided
     if let Some(addr) = &config.opentelemetry_addr {
-        println!("Setting up OpenTelemetry endpoint at: {}", addr);
+        eprintln!("Setting up OpenTelemetry endpoint at: {}", addr);
 
         // Initialize the OpenTelemetry OTLP exporter for metrics
         let _metrics_exporter = opentelemetry_otlp::new_exporter()
@@ -299,6 +580,16 @@ pub async fn run(
             .with_description("Number of successful requests")
             .init();
 
+        let not_modified_counter = meter
+            .u64_counter("not_modified_requests")
+            .with_description("Number of conditional requests answered with 304 Not Modified")
+            .init();
+
+        let check_failure_counter = meter
+            .u64_counter("check_failures")
+            .with_description("Number of responses that failed their target's expectations")
+            .init();
+
         let failure_counter = meter
             .u64_counter("failure_requests")
             .with_description("Number of failed requests")
@@ -325,7 +616,7 @@ pub async fn run(
             .init();
 
         // Set up OpenTelemetry logging
-        println!("Setting up OpenTelemetry logging...");
+        eprintln!("Setting up OpenTelemetry logging...");
 
         // Create a stdout exporter for logs (for testing)
         let logs_exporter = opentelemetry_stdout::LogExporter::default();
@@ -369,6 +660,8 @@ pub async fn run(
             let mut interval = tokio::time::interval(Duration::from_secs(1));
             let mut last_total = 0;
             let mut last_success = 0;
+            let mut last_not_modified = 0;
+            let mut last_check_failures = 0;
             let mut last_failure = 0;
             let mut last_bytes_in = 0;
             let mut last_bytes_out = 0;
@@ -389,6 +682,8 @@ pub async fn run(
                 // Update counters with the delta values
                 let total_delta = current_metrics.total_requests - last_total;
                 let success_delta = current_metrics.success_requests - last_success;
+                let not_modified_delta = current_metrics.not_modified_requests - last_not_modified;
+                let check_failures_delta = current_metrics.check_failures - last_check_failures;
                 let failure_delta = current_metrics.failure_requests - last_failure;
                 let bytes_in_delta = current_metrics.bytes_in - last_bytes_in;
                 let bytes_out_delta = current_metrics.bytes_out - last_bytes_out;
@@ -399,6 +694,12 @@ pub async fn run(
                 if success_delta > 0 {
                     success_counter.add(success_delta, &attributes);
                 }
+                if not_modified_delta > 0 {
+                    not_modified_counter.add(not_modified_delta, &attributes);
+                }
+                if check_failures_delta > 0 {
+                    check_failure_counter.add(check_failures_delta, &attributes);
+                }
                 if failure_delta > 0 {
                     failure_counter.add(failure_delta, &attributes);
                 }
@@ -425,6 +726,8 @@ pub async fn run(
                 // Update last values
                 last_total = current_metrics.total_requests;
                 last_success = current_metrics.success_requests;
+                last_not_modified = current_metrics.not_modified_requests;
+                last_check_failures = current_metrics.check_failures;
                 last_failure = current_metrics.failure_requests;
                 last_bytes_in = current_metrics.bytes_in;
                 last_bytes_out = current_metrics.bytes_out;
@@ -443,16 +746,146 @@ pub async fn run(
             }
         });
 
-        println!("  - Tracking: requests, latency, success/failure, bytes in/out");
-        println!("  - Publishing metrics and logs to the OpenTelemetry collector at: {}", addr);
+        eprintln!("  - Tracking: requests, latency, success/failure, bytes in/out");
+        eprintln!("  - Publishing metrics and logs to the OpenTelemetry collector at: {}", addr);
     }
 
+    // A flow makes one request per step, so waiting for in-flight flows can take several timeouts
+    let longest_flow = scenarios
+        .as_ref()
+        .and_then(|scenarios| scenarios.iter().map(|s| s.steps.len()).max())
+        .unwrap_or(1);
+
+    // Run the setup steps once before load starts, making their variables available to every flow
+    let mut variables = BTreeMap::new();
+    let setup_steps = match &plan {
+        Some(plan) => Some(plan.setup.clone()).filter(|s| !s.is_empty()),
+        None => setup.as_deref().map(|path| get_reader(path).and_then(parse_json_targets)).transpose()?,
+    };
+    if let Some(steps) = setup_steps {
+        run_phase("Setup", &clients[0], steps, &mut variables, &parsed_headers, &config).await?;
+    }
+
+    // Read the teardown steps now, so a bad file is reported before the attack runs
+    let teardown_steps = match plan {
+        Some(plan) => Some(plan.teardown).filter(|s| !s.is_empty()),
+        None => teardown
+            .as_deref()
+            .map(|path| get_reader(path).and_then(parse_json_targets))
+            .transpose()?,
+    };
+
+    // Apply the global body, chunked encoding, and proxy headers to every target and scenario step
+    let mut targets_list = targets_list;
+    let mut scenarios = scenarios.unwrap_or_default();
+    for target in targets_list.iter_mut().chain(scenarios.iter_mut().flat_map(|s| s.steps.iter_mut())) {
+        // Apply global body content if target doesn't have its own body
+        if target.body.is_none() && body_content.is_some() {
+            target.body = body_content.clone();
+        }
+
+        // Add chunked transfer encoding header if requested
+        if chunked && target.body.is_some() {
+            target.headers.push(Header {
+                name: "Transfer-Encoding".to_string(),
+                value: "chunked".to_string(),
+            });
+        }
+
+        // Add proxy headers if provided
+        for header in &parsed_proxy_headers {
+            target.headers.push(header.clone());
+        }
+    }
+
+    // Keep what the teardown steps need, they run after the attack with the setup variables
+    let phase_client = clients[0].clone();
+    let mut phase_variables = variables.clone();
+    let phase_headers = parsed_headers.clone();
+    let phase_config = config.clone();
+
     // Start attack
     let attack_handle = tokio::spawn(async move {
         let targets = Arc::new(targets_list);
+        let scenarios = Arc::new(scenarios);
         let headers = Arc::new(parsed_headers);
         let config = Arc::new(config);
         let metrics = metrics.clone();
+        let variables = Arc::new(variables);
+
+        let start_time = Instant::now();
+
+        // Set up end time if duration is specified
+        let end_time = config.duration.map(|d| start_time + d);
+
+        // Closed loop: every virtual user runs flows back to back until the duration has elapsed
+        if let Some(end) = end_time.filter(|_| vus.is_some()) {
+            let next_flow = Arc::new(AtomicU64::new(0));
+            let mut handles = Vec::new();
+
+            for (index, client) in clients.into_iter().enumerate() {
+                let targets = targets.clone();
+                let scenarios = scenarios.clone();
+                let headers = headers.clone();
+                let config = vu_config(&config, !vu_shared_state);
+                let metrics = metrics.clone();
+                let next_flow = next_flow.clone();
+                let tx = tx.clone();
+                let variables = variables.clone();
+
+                handles.push(tokio::spawn(async move {
+                    // Variables extracted by a virtual user stay with it across iterations
+                    let mut variables = BTreeMap::clone(&variables);
+                    let (mut flows, mut exhausted, mut active) = (0, false, false);
+                    while Instant::now() < end {
+                        // Idle while the stages call for fewer virtual users, finishing the current flow first
+                        if active_vus(&config.stages, start_time.elapsed()).is_some_and(|vus| index as u64 >= vus) {
+                            if active {
+                                metrics.lock().unwrap().decrement_active_workers();
+                                active = false;
+                            }
+                            sleep(STAGE_POLL.min(end.saturating_duration_since(Instant::now()))).await;
+                            continue;
+                        }
+                        if !active {
+                            metrics.lock().unwrap().increment_active_workers();
+                            active = true;
+                        }
+
+                        let Some(flow) = Flow::next(&scenarios, &targets, next_flow.fetch_add(1, Ordering::Relaxed)) else {
+                            exhausted = true;
+                            break;
+                        };
+                        run_flow(&client, index as u64, flow, &mut variables, &headers, &config, |r| record_result(&metrics, r), &tx).await;
+                        flows += 1;
+                    }
+
+                    if active {
+                        metrics.lock().unwrap().decrement_active_workers();
+                    }
+                    (flows, exhausted)
+                }));
+            }
+
+            let (mut flows, mut exhausted) = (0, false);
+            for handle in handles {
+                let (vu_flows, vu_exhausted) = handle.await?;
+                flows += vu_flows;
+                exhausted |= vu_exhausted;
+            }
+
+            if exhausted {
+                eprintln!("Feeder exhausted, stopping the attack");
+            }
+            if config.stages.is_empty() {
+                eprintln!("Completed {} flows with {} virtual users", flows, vus.unwrap_or_default());
+            } else {
+                eprintln!("Completed {} flows with up to {} virtual users", flows, vus.unwrap_or_default());
+            }
+            return Ok(());
+        }
+
+        let client = clients[0].clone();
 
         // Calculate delay between requests based on rate
         let delay = if rate_value > 0.0 {
@@ -461,12 +894,8 @@ pub async fn run(
             Duration::from_secs(0)
         };
 
-        let start_time = Instant::now();
         let mut request_count = 0;
 
-        // Set up end time if duration is specified
-        let end_time = config.duration.map(|d| start_time + d);
-
         // Calculate expected number of requests if duration is specified
         let expected_requests = config.duration.map(|d| (config.rate * d.as_secs_f64()) as usize);
 
@@ -475,6 +904,7 @@ pub async fn run(
 
         // Create a semaphore to limit concurrent workers
         let worker_semaphore = Arc::new(tokio::sync::Semaphore::new(config.workers as usize));
+        let worker_ids = WorkerIds::default();
 
         // If max_workers is set, adjust the number of workers over time
         if let Some(max_workers) = config.max_workers {
@@ -531,27 +961,11 @@ pub async fn run(
                                       elapsed, active_workers));
             }
 
-            // Get the next target (round-robin)
-            let target_index = request_count % targets.len();
-            let mut target = targets[target_index].clone();
-
-            // Apply global body content if target doesn't have its own body
-            if target.body.is_none() && body_content.is_some() {
-                target.body = body_content.clone();
-            }
-
-            // Add chunked transfer encoding header if requested
-            if chunked && target.body.is_some() {
-                target.headers.push(Header {
-                    name: "Transfer-Encoding".to_string(),
-                    value: "chunked".to_string(),
-                });
-            }
-
-            // Add proxy headers if provided
-            for header in &parsed_proxy_headers {
-                target.headers.push(header.clone());
-            }
+            // Get the next target (round-robin), or every step of the next scenario
+            let Some(flow) = Flow::next(&scenarios, &targets, request_count as u64) else {
+                eprintln!("Feeder exhausted, stopping the attack");
+                break;
+            };
 
             // Clone necessary data for the request
             let client = client.clone();
@@ -559,6 +973,7 @@ pub async fn run(
             let config_clone = config.clone();
             let tx = tx.clone();
             let semaphore = worker_semaphore.clone();
+            let variables = variables.clone();
 
             // Acquire a permit from the semaphore before spawning the task
             // This ensures we don't exceed the worker limit
@@ -578,83 +993,21 @@ pub async fn run(
                 metrics.increment_active_workers();
             }
 
-            // Spawn a task to make the request
+            // Spawn a task to make the request, or every step of the scenario in order
             let metrics_clone = metrics.clone();
+            let worker_ids = worker_ids.clone();
+            let worker = worker_ids.acquire();
             tokio::spawn(async move {
-                // Increment the total requests counter
-                {
-                    let mut metrics = metrics_clone.lock().unwrap();
-                    metrics.increment_requests();
-                }
-
-                debug!(
-                    event = "request_start",
-                    method = target.method,
-                    url = target.url.to_string(),
-                    message = "Starting request"
-                );
-
-                let result = make_request(client, target, &headers, &config_clone).await;
-
-                // Log the result
-                if result.status_code >= 200 && result.status_code < 300 {
-                    info!(
-                        event = "request_success",
-                        method = result.target.method,
-                        url = result.target.url.to_string(),
-                        status_code = result.status_code,
-                        latency_ms = result.latency.as_millis() as u64,
-                        bytes_in = result.bytes_in,
-                        bytes_out = result.bytes_out,
-                        message = "Request completed successfully"
-                    );
-                } else if result.status_code > 0 {
-                    warn!(
-                        event = "request_failure",
-                        method = result.target.method,
-                        url = result.target.url.to_string(),
-                        status_code = result.status_code,
-                        latency_ms = result.latency.as_millis() as u64,
-                        bytes_in = result.bytes_in,
-                        bytes_out = result.bytes_out,
-                        message = "Request failed with non-2xx status code"
-                    );
-                } else if let Some(error) = &result.error {
-                    error!(
-                        event = "request_error",
-                        method = result.target.method,
-                        url = result.target.url.to_string(),
-                        latency_ms = result.latency.as_millis() as u64,
-                        error = error,
-                        message = "Request failed with error"
-                    );
-                }
+                let mut variables = BTreeMap::clone(&variables);
+                run_flow(&client, worker, flow, &mut variables, &headers, &config_clone, |r| record_result(&metrics_clone, r), &tx).await;
+                worker_ids.release(worker);
 
-                // Update metrics based on the result
+                // Decrement active workers
                 {
                     let mut metrics = metrics_clone.lock().unwrap();
-
-                    // Record the request duration
-                    metrics.record_duration(result.latency.as_secs_f64());
-
-                    // Increment success, failure, or timeout counter based on result
-                    if result.timed_out {
-                        metrics.increment_timeout();
-                    } else if result.status_code >= 200 && result.status_code < 300 {
-                        metrics.increment_success();
-                    } else {
-                        metrics.increment_failure();
-                    }
-
-                    // Add to bytes in/out counters
-                    metrics.add_bytes_in(result.bytes_in as u64);
-                    metrics.add_bytes_out(result.bytes_out as u64);
-
-                    // Decrement active workers
                     metrics.decrement_active_workers();
                 }
 
-                let _ = tx.send(result).await;
                 // Permit is automatically dropped when the task completes, releasing the worker
                 drop(permit);
             });
@@ -669,7 +1022,7 @@ pub async fn run(
             let expected_requests = (config.rate * duration.as_secs_f64()) as usize;
 
             // Log the actual vs expected requests
-            println!("Completed {} requests out of {} expected ({:.2}%)", 
+            eprintln!("Completed {} requests out of {} expected ({:.2}%)", 
                      request_count, 
                      expected_requests, 
                      (request_count as f64 / expected_requests as f64) * 100.0);
@@ -692,7 +1045,7 @@ pub async fn run(
         }
 
         // Wait for all active requests to complete or timeout
-        let timeout_duration = config.timeout.max(config.http_timeout);
+        let timeout_duration = config.timeout.max(config.http_timeout) * longest_flow as u32;
         let wait_start = Instant::now();
 
         loop {
@@ -709,7 +1062,7 @@ pub async fn run(
             // Check if we've waited too long
             let elapsed = Instant::now().duration_since(wait_start);
             if elapsed > timeout_duration {
-                println!("Timeout waiting for requests to complete. Some requests may still be in progress.");
+                eprintln!("Timeout waiting for requests to complete. Some requests may still be in progress.");
                 break;
             }
 
@@ -730,36 +1083,131 @@ pub async fn run(
         Ok(())
     });
 
+    // With teardown steps to run, Ctrl-C stops the attack instead of exiting right away
+    let has_teardown = teardown_steps.is_some();
+    let interrupt = async move {
+        if has_teardown {
+            let _ = tokio::signal::ctrl_c().await;
+        } else {
+            std::future::pending::<()>().await;
+        }
+    };
+    tokio::pin!(interrupt);
+    let attack_abort = attack_handle.abort_handle();
+    let mut interrupted = false;
+
     // Process results
-    // Only write detailed results to a file, not to stdout
-    if output != "stdout" {
-        let mut writer = crate::utils::get_writer(&output)?;
+    let process_results = async {
+        let mut per_target = output_per_target
+            .as_deref()
+            .map(|dir| crate::results::TargetFiles::create(dir, encoding, output_append))
+            .transpose()?;
+
+        let written = async {
+            // Only write detailed results to a file or database, not to stdout
+            if output.starts_with(crate::sqlite::SCHEME) {
+                let mut writer = crate::sqlite::SqliteWriter::open(&output)?;
+                writer.write_config(&run_config)?;
+
+                while let Some(result) = next_result(&mut rx, interrupt.as_mut(), &attack_abort, &mut interrupted).await {
+                    if let Some(files) = &mut per_target {
+                        files.write(&result)?;
+                    }
+                    writer.write(&result)?;
+                }
+                writer.finish()?;
+            } else if crate::tsdb::is_tsdb_url(&output) {
+                let mut writer = crate::tsdb::TsdbWriter::open(&output).await?;
+
+                while let Some(result) = next_result(&mut rx, interrupt.as_mut(), &attack_abort, &mut interrupted).await {
+                    if let Some(files) = &mut per_target {
+                        files.write(&result)?;
+                    }
+                    writer.write(&result).await?;
+                }
+                writer.finish().await?;
+            } else if crate::socket::is_socket_url(&output) {
+                let mut writer = crate::socket::SocketWriter::connect(&output).await?;
+
+                while let Some(result) = next_result(&mut rx, interrupt.as_mut(), &attack_abort, &mut interrupted).await {
+                    if let Some(files) = &mut per_target {
+                        files.write(&result)?;
+                    }
+                    writer.write(&result).await?;
+                }
+                writer.finish().await?;
+            } else if output != "stdout" || !std::io::stdout().is_terminal() {
+                // Results piped from stdout go on to another command, like `culverin report`
+                let file = match output_append {
+                    true => crate::utils::get_append_writer(&output)?,
+                    false => crate::utils::get_writer(&output)?,
+                };
+                let mut writer = ResultWriter::new(BufWriter::new(file), encoding)?;
+                if output != "stdout" {
+                    crate::results::write_config(&output, &run_config, output_append)?;
+                }
 
-        while let Some(result) = rx.recv().await {
-            // Serialize the result to JSON and write it to the file
-            let json = serde_json::to_string(&result)?;
-            writeln!(writer, "{}", json)?;
+                // Flush regularly so `report --follow` sees the results as they come in
+                let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+                loop {
+                    tokio::select! {
+                        result = next_result(&mut rx, interrupt.as_mut(), &attack_abort, &mut interrupted) => {
+                            let Some(result) = result else { break };
+                            if let Some(files) = &mut per_target {
+                                files.write(&result)?;
+                            }
+                            writer.write(&result)?;
+                        }
+                        _ = flush.tick() => writer.flush()?,
+                    }
+                }
+                writer.finish()?;
+            } else {
+                // Results aren't written to a terminal, so just consume them
+                while let Some(result) = next_result(&mut rx, interrupt.as_mut(), &attack_abort, &mut interrupted).await {
+                    if let Some(files) = &mut per_target {
+                        files.write(&result)?;
+                    }
+                }
+            }
+            Ok::<(), anyhow::Error>(())
         }
+        .await;
+
+        // The files per target are finished even if writing the results failed
+        let finished = per_target.map_or(Ok(()), |files| files.finish());
+        written.and(finished)
+    };
+
+    // Errors are returned after the teardown, which runs whatever happened to the attack
+    let processed = process_results.await;
+
+    // Workers still running can't send any more results
+    rx.close();
+    let attacked = if interrupted || processed.is_err() {
+        attack_handle.abort();
+        Ok(())
     } else {
-        // If output is stdout, just consume the results without printing details
-        while let Some(_) = rx.recv().await {
-            // Do nothing with the result, just consume it
-        }
-    }
+        // Wait for attack to finish
+        attack_handle.await.map_err(anyhow::Error::from).and_then(|attacked| attacked)
+    };
 
-    // Wait for attack to finish
-    attack_handle.await?;
+    // Run the teardown steps once, even if the attack was interrupted
+    let teardown = match teardown_steps {
+        Some(steps) => run_phase("Teardown", &phase_client, steps, &mut phase_variables, &phase_headers, &phase_config).await,
+        None => Ok(()),
+    };
 
     // Display a summary of the attack results in the terminal
     {
         let metrics = metrics_for_shutdown.lock().unwrap();
-        println!("\nAttack Summary:");
-        println!("  Total Requests: {}", metrics.total_requests);
-        println!("  Successful Requests: {}", metrics.success_requests);
-        println!("  Failed Requests: {}", metrics.failure_requests);
+        eprintln!("\nAttack Summary:");
+        eprintln!("  Total Requests: {}", metrics.total_requests);
+        eprintln!("  Successful Requests: {}", metrics.success_requests);
+        eprintln!("  Failed Requests: {}", metrics.failure_requests);
 
         // Display timed out requests
-        println!("  Timed Out Requests: {}", metrics.timeout_requests);
+        eprintln!("  Timed Out Requests: {}", metrics.timeout_requests);
 
         // Calculate success rate
         let success_rate = if metrics.total_requests > 0 {
@@ -767,23 +1215,23 @@ pub async fn run(
         } else {
             0.0
         };
-        println!("  Success Rate: {:.2}%", success_rate);
+        eprintln!("  Success Rate: {:.2}%", success_rate);
 
         // Calculate average latency if there are any requests
         if !metrics.request_durations.is_empty() {
             let avg_latency = metrics.request_durations.iter().sum::<f64>() / metrics.request_durations.len() as f64;
-            println!("  Average Latency: {:.2}ms", avg_latency * 1000.0);
+            eprintln!("  Average Latency: {:.2}ms", avg_latency * 1000.0);
         }
 
         // Display data transfer information
-        println!("  Data Transferred:");
-        println!("    Received: {}", crate::utils::format_size(metrics.bytes_in as usize));
-        println!("    Sent: {}", crate::utils::format_size(metrics.bytes_out as usize));
+        eprintln!("  Data Transferred:");
+        eprintln!("    Received: {}", crate::utils::format_size(metrics.bytes_in as usize));
+        eprintln!("    Sent: {}", crate::utils::format_size(metrics.bytes_out as usize));
     }
 
     // If OpenTelemetry is configured, log completion and shut down providers
     if has_opentelemetry {
-        println!("\nFlushing telemetry to OpenTelemetry...");
+        eprintln!("\nFlushing telemetry to OpenTelemetry...");
 
         // Log the attack completion
         info!(
@@ -806,139 +1254,915 @@ pub async fn run(
         // Shut down the logger provider to flush logs
         global::shutdown_logger_provider();
 
-        println!("Telemetry flushed successfully.");
+        eprintln!("Telemetry flushed successfully.");
+    }
+
+    // Upload the results once they're all written. Files of an interrupted attack are marked
+    // as partial, their results end where the attack was stopped.
+    let uploaded = match &upload {
+        Some(destination) => {
+            if interrupted {
+                eprintln!("\nWarning: the attack was interrupted, uploading its results as partial");
+            }
+            let mark = |destination: crate::upload::Destination, path: &str| match interrupted {
+                true => destination.partial(path),
+                false => Ok(destination),
+            };
+
+            let mut uploaded = async {
+                let results = mark(destination.clone(), &upload_path)?;
+                eprintln!("\nUploading results to s3://{}/{}...", results.bucket, results.key);
+                let url = crate::upload::upload_file(&upload_path, &results).await?;
+                eprintln!("Uploaded results to {}", url);
+                Ok::<(), anyhow::Error>(())
+            }
+            .await;
+
+            // Files of results come with the configuration of the attack
+            if uploaded.is_ok() && !output.starts_with(crate::sqlite::SCHEME) {
+                // A prefix gets the file name appended, the name of the config file here
+                let key = match destination.key.ends_with('/') {
+                    true => destination.key.clone(),
+                    false => crate::results::config_path(&destination.key),
+                };
+                let config_path = crate::results::config_path(&upload_path);
+                let config = crate::upload::Destination { bucket: destination.bucket.clone(), key };
+                uploaded = async {
+                    let url = crate::upload::upload_file(&config_path, &mark(config, &config_path)?).await?;
+                    eprintln!("Uploaded the attack configuration to {}", url);
+                    Ok(())
+                }
+                .await;
+            }
+            uploaded
+        }
+        None => Ok(()),
+    };
+
+    processed?;
+    attacked?;
+    teardown?;
+    uploaded?;
+    if interrupted {
+        anyhow::bail!("Attack interrupted");
     }
 
     Ok(())
 }
 
+/// Receive the next result of the attack
+///
+/// When interrupted the attack is stopped and the channel closed, so only the results already
+/// sent are received. The results written so far then still end with a valid file.
+async fn next_result(
+    rx: &mut mpsc::Receiver<AttackResult>,
+    interrupt: std::pin::Pin<&mut impl std::future::Future<Output = ()>>,
+    attack: &tokio::task::AbortHandle,
+    interrupted: &mut bool,
+) -> Option<AttackResult> {
+    if !*interrupted {
+        tokio::select! {
+            result = rx.recv() => return result,
+            _ = interrupt => {
+                eprintln!("\nInterrupted, stopping the attack");
+                *interrupted = true;
+                attack.abort();
+                rx.close();
+            }
+        }
+    }
+    rx.recv().await
+}
+
+/// How often results written to a file are flushed, so they can be followed while written
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Configuration of a virtual user, which answers Digest challenges with a session of
+/// its own when it has a client of its own, so the nonce counts of its requests go up
+/// in the order they're sent
+pub(crate) fn vu_config(config: &Arc<AttackConfig>, own_client: bool) -> Arc<AttackConfig> {
+    match &config.digest_auth {
+        Some(digest) if own_client => Arc::new(AttackConfig {
+            digest_auth: Some(digest.session()),
+            ..AttackConfig::clone(config)
+        }),
+        _ => config.clone(),
+    }
+}
+
+/// How often idle virtual users check whether the stages call for them
+pub(crate) const STAGE_POLL: Duration = Duration::from_millis(100);
+
+/// Number of virtual users the stages call for at the given time since the attack started,
+/// or None to keep every virtual user active when there are no stages
+pub(crate) fn active_vus(stages: &[Stage], elapsed: Duration) -> Option<u64> {
+    let (mut start, mut from) = (Duration::ZERO, 0);
+    for stage in stages {
+        if elapsed < start + stage.duration {
+            let progress = (elapsed - start).as_secs_f64() / stage.duration.as_secs_f64();
+            return Some((from as f64 + (stage.target as f64 - from as f64) * progress).round() as u64);
+        }
+        start += stage.duration;
+        from = stage.target;
+    }
+    stages.last().map(|stage| stage.target)
+}
+
+/// One rate tick or virtual user iteration: a single target, or every step of a scenario
+pub(crate) struct Flow {
+    /// Scenario name, if the flow runs a scenario
+    scenario: Option<String>,
+    /// Number of the flow, unique within the attack
+    number: u64,
+    /// Requests made in order
+    steps: Vec<Target>,
+    /// Variables from the scenario's feeder row, or why the flow has no row
+    row: std::result::Result<Row, String>,
+}
+
+impl Flow {
+    /// Get the given flow: the next target (round-robin), or every step of the next scenario
+    ///
+    /// Scenarios are picked in turn, or by weight when their weights differ.
+    /// Returns None once a scenario's feeder has run out of rows and stops the attack.
+    pub(crate) fn next(scenarios: &[Scenario], targets: &[Target], number: u64) -> Option<Self> {
+        if scenarios.is_empty() {
+            return Some(Self {
+                scenario: None,
+                number,
+                steps: vec![targets[number as usize % targets.len()].clone()],
+                row: Ok(Row::new()),
+            });
+        }
+
+        let scenario = pick_scenario(scenarios, number);
+        let row = match scenario.feeder.as_ref().map(|feeder| feeder.next_row()) {
+            Some(Ok(Some(row))) => Ok(row.clone()),
+            Some(Ok(None)) => return None,
+            Some(Err(e)) => Err(e),
+            None => Ok(Row::new()),
+        };
+        Some(Self {
+            scenario: Some(scenario.name.clone()),
+            number,
+            steps: scenario.steps.clone(),
+            row,
+        })
+    }
+}
+
+/// Pick the scenario of the given flow
+///
+/// With differing weights, each flow number is mapped to a point of a low-discrepancy
+/// sequence, so the scenarios are interleaved and the mix matches the weights closely
+/// even over a short attack.
+fn pick_scenario(scenarios: &[Scenario], number: u64) -> &Scenario {
+    let total: u64 = scenarios.iter().map(|s| s.weight as u64).sum();
+    if total == 0 || scenarios.iter().all(|s| s.weight == scenarios[0].weight) {
+        return &scenarios[number as usize % scenarios.len()];
+    }
+
+    // The fractional part of number / golden ratio, scaled to the total weight
+    let point = ((number.wrapping_mul(0x9E37_79B9_7F4A_7C15) as u128 * total as u128) >> 64) as u64;
+    let mut cumulative = 0;
+    for scenario in scenarios {
+        cumulative += scenario.weight as u64;
+        if point < cumulative {
+            return scenario;
+        }
+    }
+    &scenarios[scenarios.len() - 1]
+}
+
+/// Check that every scenario feeder can be read and has rows, before the attack starts
+pub(crate) fn check_feeders(scenarios: &[Scenario]) -> Result<()> {
+    for feeder in scenarios.iter().filter_map(|s| s.feeder.as_ref()) {
+        match feeder.rows() {
+            Ok([]) => anyhow::bail!("Feeder {} has no rows", feeder.file),
+            Ok(_) => {}
+            Err(e) => anyhow::bail!("Failed to read feeder {}: {}", feeder.file, e),
+        }
+    }
+    Ok(())
+}
+
+/// Make the requests of a flow in order, filling in variables extracted by earlier steps
+///
+/// The flow's feeder row is added to the variables first. Steps whose conditions aren't
+/// met are skipped. The flow stops at the first step that fails, since later steps depend
+/// on earlier ones, unless a conditional step handles the failure. Every result is passed
+/// to `record` and then sent on `tx`, once the steps skipped after it are known.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn run_flow(
+    client: &Arc<Client>,
+    worker: u64,
+    flow: Flow,
+    variables: &mut BTreeMap<String, String>,
+    headers: &[Header],
+    config: &AttackConfig,
+    record: impl Fn(&AttackResult),
+    tx: &mpsc::Sender<AttackResult>,
+) {
+    // Flows of an exhausted feeder fail at their first step, without sending it
+    match flow.row {
+        Ok(row) => variables.extend(row),
+        Err(e) => {
+            if let Some(target) = flow.steps.into_iter().next() {
+                let mut result = new_result(target, config);
+                result.error = Some(e);
+                fail_checks_without_response(&mut result);
+                result.seq = config.sequence.fetch_add(1, Ordering::Relaxed);
+                result.worker = worker;
+                result.scenario = flow.scenario.map(|name| ScenarioStep {
+                    name,
+                    flow: flow.number,
+                    step: 0,
+                    skipped: 0,
+                    handled: false,
+                });
+                record(&result);
+                let _ = tx.send(result).await;
+            }
+            return;
+        }
+    }
+
+    let mut previous: Option<AttackResult> = None;
+    let mut skipped = 0;
+
+    for (step, mut target) in flow.steps.into_iter().enumerate() {
+        let failed = previous.as_ref().is_some_and(|r| !r.is_success());
+        match &target.when {
+            Some(condition) if !condition.matches(previous.as_ref().map(|r| r.status_code), variables) => {
+                match previous.as_mut().and_then(|r| r.scenario.as_mut()) {
+                    Some(previous) => previous.skipped += 1,
+                    None => skipped += 1,
+                }
+                continue;
+            }
+            Some(_) => {
+                if let Some(previous) = previous.as_mut().and_then(|r| r.scenario.as_mut()) {
+                    previous.handled = failed;
+                }
+            }
+            None if failed => break,
+            None => {}
+        }
+
+        if let Some(previous) = previous.take() {
+            record(&previous);
+            let _ = tx.send(previous).await;
+        }
+
+        // Fill in values extracted by earlier steps
+        let substituted = scenario::substitute(&mut target, variables);
+
+        debug!(
+            event = "request_start",
+            method = target.method,
+            url = target.url.to_string(),
+            message = "Starting request"
+        );
+
+        // Number the request when it's made, not when it completes
+        let seq = config.sequence.fetch_add(1, Ordering::Relaxed);
+        let mut result = match substituted {
+            Ok(()) => make_request(client.clone(), target, &scenario::substitute_headers(headers, variables), config).await,
+            Err(e) => {
+                let mut result = new_result(target, config);
+                result.error = Some(e.to_string());
+                fail_checks_without_response(&mut result);
+                result
+            }
+        };
+        result.seq = seq;
+        result.worker = worker;
+        variables.extend(result.variables.clone());
+        result.scenario = flow.scenario.as_ref().map(|name| ScenarioStep {
+            name: name.clone(),
+            flow: flow.number,
+            step,
+            skipped: std::mem::take(&mut skipped),
+            handled: false,
+        });
+
+        previous = Some(result);
+    }
+
+    if let Some(previous) = previous {
+        record(&previous);
+        let _ = tx.send(previous).await;
+    }
+}
+
+/// Run steps once, outside of the attack, adding the variables they extract
+///
+/// Their results aren't recorded. Steps whose conditions aren't met are skipped, and the
+/// phase fails at the first step that doesn't succeed.
+pub(crate) async fn run_phase(
+    phase: &str,
+    client: &Arc<Client>,
+    steps: Vec<Target>,
+    variables: &mut BTreeMap<String, String>,
+    headers: &[Header],
+    config: &AttackConfig,
+) -> Result<()> {
+    let mut status = None;
+    for (step, mut target) in steps.into_iter().enumerate() {
+        if target.when.as_ref().is_some_and(|condition| !condition.matches(status, variables)) {
+            continue;
+        }
+
+        scenario::substitute(&mut target, variables)?;
+        let (method, url) = (target.method.clone(), target.url.clone());
+
+        let result = make_request(client.clone(), target, &scenario::substitute_headers(headers, variables), config).await;
+        status = Some(result.status_code);
+        if !result.is_success() {
+            anyhow::bail!(
+                "{} step {} ({} {}) failed with status {}: {}",
+                phase,
+                step,
+                method,
+                url,
+                result.status_code,
+                result.error.as_deref().unwrap_or("unexpected status")
+            );
+        }
+        variables.extend(result.variables);
+    }
+
+    Ok(())
+}
+
+/// Log a result and add it to the attack metrics
+fn record_result(metrics: &Mutex<AttackMetrics>, result: &AttackResult) {
+    metrics.lock().unwrap().increment_requests();
+
+    // Log the result
+    if result.is_success() {
+        info!(
+            event = "request_success",
+            method = result.target.method,
+            url = result.target.url.to_string(),
+            status_code = result.status_code,
+            latency_ms = result.latency.as_millis() as u64,
+            bytes_in = result.bytes_in,
+            bytes_out = result.bytes_out,
+            message = "Request completed successfully"
+        );
+    } else if result.status_code > 0 {
+        warn!(
+            event = "request_failure",
+            method = result.target.method,
+            url = result.target.url.to_string(),
+            status_code = result.status_code,
+            latency_ms = result.latency.as_millis() as u64,
+            bytes_in = result.bytes_in,
+            bytes_out = result.bytes_out,
+            error = result.error.as_deref().unwrap_or_default(),
+            message = "Request failed with non-2xx status code or invalid response"
+        );
+    } else if let Some(error) = &result.error {
+        error!(
+            event = "request_error",
+            method = result.target.method,
+            url = result.target.url.to_string(),
+            latency_ms = result.latency.as_millis() as u64,
+            error = error,
+            message = "Request failed with error"
+        );
+    }
+
+    // Update metrics based on the result
+    {
+        let mut metrics = metrics.lock().unwrap();
+
+        // Record the request duration
+        metrics.record_duration(result.latency.as_secs_f64());
+
+        // Increment success, failure, or timeout counter based on result
+        if result.timed_out {
+            metrics.increment_timeout();
+        } else if result.is_success() {
+            metrics.increment_success();
+            if result.is_not_modified() {
+                metrics.increment_not_modified();
+            }
+        } else {
+            metrics.increment_failure();
+            if result.check_failed {
+                metrics.increment_check_failure();
+            }
+        }
+
+        // Add to bytes in/out counters
+        metrics.add_bytes_in(result.bytes_in as u64);
+        metrics.add_bytes_out(result.bytes_out as u64);
+    }
+}
+
+/// Record every named check of a request that failed without a response as failed, so
+/// checks that never ran against a response don't count as passed
+pub(crate) fn fail_checks_without_response(result: &mut AttackResult) {
+    if result.error.is_some() && result.status_code == 0 {
+        result.checks = result.target.checks.iter().map(|check| (check.name.clone(), false)).collect();
+    }
+}
+
+/// Create the result for a request to the target, before it is sent, labeled with the run
+pub(crate) fn new_result(target: Target, config: &AttackConfig) -> AttackResult {
+    AttackResult {
+        timestamp: chrono::Utc::now(),
+        latency: Duration::from_secs(0),
+        ttfb: None,
+        status_code: 0,
+        error: None,
+        target,
+        content_type: None,
+        bytes_in: 0,
+        decoded_bytes_in: None,
+        bytes_out: 0,
+        timed_out: false,
+        request_id: None,
+        trace_id: None,
+        fuzz_seed: None,
+        conditional: false,
+        check_failed: false,
+        golden_diverged: false,
+        server_timing: Vec::new(),
+        scenario: None,
+        variables: BTreeMap::new(),
+        checks: BTreeMap::new(),
+        custom: BTreeMap::new(),
+        attack: config.name.clone(),
+        run_id: Some(config.run_id.clone()),
+        metadata: config.metadata.clone(),
+        seq: 0,
+        worker: 0,
+    }
+}
+
+/// Hands out the IDs of free workers, so each request of the rate loop is made by a
+/// worker whose ID is reused once its request is done
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WorkerIds {
+    /// IDs of idle workers, and the number of IDs handed out so far
+    free: Arc<Mutex<(Vec<u64>, u64)>>,
+}
+
+impl WorkerIds {
+    /// Take the ID of an idle worker, or a new one if every worker is busy
+    pub fn acquire(&self) -> u64 {
+        let mut free = self.free.lock().unwrap();
+        free.0.pop().unwrap_or_else(|| {
+            free.1 += 1;
+            free.1 - 1
+        })
+    }
+
+    /// Return the ID of a worker that is done
+    pub fn release(&self, id: u64) {
+        self.free.lock().unwrap().0.push(id);
+    }
+}
+
 /// Make a single HTTP request
 pub async fn make_request(
     client: Arc<Client>,
     target: Target,
     headers: &[Header],
     config: &AttackConfig,
+) -> AttackResult {
+    // Build the request and let middleware and hooks modify it before it is sent
+    let mut spec = RequestSpec::new(&target, headers);
+
+    // Mutate the request, recording the seed so the mutation can be reproduced
+    let fuzz_seed = (!config.fuzz.is_empty()).then(|| {
+        let seed = rand::random::<u64>();
+        fuzz::mutate(&mut spec, &config.fuzz, seed);
+        seed
+    });
+
+    let mut result = new_result(target, config);
+    result.fuzz_seed = fuzz_seed;
+
+    // Tag the request with a unique ID
+    result.request_id = config.request_id_header.as_ref().map(|name| {
+        let id = Uuid::new_v4().to_string();
+        spec.add_header(name, &id);
+        id
+    });
+
+    // Propagate a W3C trace context so requests can be joined with the target's traces
+    if config.opentelemetry_addr.is_some() && !spec.headers.iter().any(|h| h.name.eq_ignore_ascii_case("traceparent")) {
+        let (trace_id, traceparent) = new_traceparent();
+        spec.add_header("traceparent", &traceparent);
+        result.trace_id = Some(trace_id);
+    }
+
+    // Send validators from a previous response to this target
+    result.conditional = config.conditional.as_ref().is_some_and(|cache| cache.apply(&result.target, &mut spec));
+
+    if let Err(e) = config.middleware.before_request(&mut spec).await {
+        result.error = Some(format!("Request middleware failed: {}", e));
+        fail_checks_without_response(&mut result);
+        return result;
+    }
+
+    for hook in &config.before_send {
+        hook.call(&mut spec);
+    }
+
+    let mut result = send_request(client, spec, result, config).await;
+    fail_checks_without_response(&mut result);
+    config.middleware.after_response(&result).await;
+
+    result
+}
+
+/// Send a request within the timeout, recording failures in the result
+async fn send_with_timeout(
+    request_builder: reqwest::RequestBuilder,
+    timeout_duration: Duration,
+    result: &mut AttackResult,
+) -> Option<reqwest::Response> {
+    // Use tokio::time::timeout to enforce the HTTP timeout
+    match tokio::time::timeout(timeout_duration, request_builder.send()).await {
+        // Request completed within timeout
+        Ok(Ok(response)) => Some(response),
+        Ok(Err(e)) => {
+            result.error = Some(format!("Request failed: {}", e));
+            result.timed_out = e.is_timeout();
+            None
+        }
+        // Request timed out
+        Err(_) => {
+            result.error = Some(format!("Request timed out after {:?}", timeout_duration));
+            result.timed_out = true;
+            None
+        }
+    }
+}
+
+/// Merge repeated Cookie headers into the first one, since only a single Cookie header
+/// may be sent (RFC 6265). Other repeated headers are kept as separate lines.
+fn fold_cookie_headers(headers: &[Header]) -> Vec<Header> {
+    let mut folded: Vec<Header> = Vec::with_capacity(headers.len());
+    let mut cookie_index = None;
+
+    for header in headers {
+        if header.name.eq_ignore_ascii_case("cookie") {
+            if let Some(index) = cookie_index {
+                let cookie: &mut Header = &mut folded[index];
+                cookie.value = format!("{}; {}", cookie.value, header.value);
+                continue;
+            }
+            cookie_index = Some(folded.len());
+        }
+        folded.push(header.clone());
+    }
+
+    folded
+}
+
+/// Generate a random trace ID and the traceparent header value for a new sampled trace
+fn new_traceparent() -> (String, String) {
+    // All-zero IDs are invalid
+    let trace_id = format!("{:032x}", rand::random::<u128>().max(1));
+    let span_id = format!("{:016x}", rand::random::<u64>().max(1));
+    let traceparent = format!("00-{}-{}-01", trace_id, span_id);
+    (trace_id, traceparent)
+}
+
+/// Send a prepared request and fill in its result
+async fn send_request(
+    client: Arc<Client>,
+    spec: RequestSpec,
+    mut result: AttackResult,
+    config: &AttackConfig,
 ) -> AttackResult {
     let start_time = Instant::now();
-    let timestamp = chrono::Utc::now();
-
-    let mut request_builder = match target.method.as_str() {
-        "GET" => client.get(target.url.clone()),
-        "POST" => client.post(target.url.clone()),
-        "PUT" => client.put(target.url.clone()),
-        "DELETE" => client.delete(target.url.clone()),
-        "HEAD" => client.head(target.url.clone()),
-        "OPTIONS" => client.request(reqwest::Method::OPTIONS, target.url.clone()),
-        "PATCH" => client.patch(target.url.clone()),
-        _ => client.request(reqwest::Method::from_bytes(target.method.as_bytes()).unwrap(), target.url.clone()),
+    result.timestamp = chrono::Utc::now();
+
+    let mut request_builder = match spec.method.as_str() {
+        "GET" => client.get(spec.url.clone()),
+        "POST" => client.post(spec.url.clone()),
+        "PUT" => client.put(spec.url.clone()),
+        "DELETE" => client.delete(spec.url.clone()),
+        "HEAD" => client.head(spec.url.clone()),
+        "OPTIONS" => client.request(reqwest::Method::OPTIONS, spec.url.clone()),
+        "PATCH" => client.patch(spec.url.clone()),
+        method => match reqwest::Method::from_bytes(method.as_bytes()) {
+            Ok(method) => client.request(method, spec.url.clone()),
+            Err(_) => {
+                result.error = Some(format!("Invalid request method: {}", method));
+                return result;
+            }
+        },
     };
 
-    // Add headers from target
-    for header in &target.headers {
+    // Add headers from the target and global headers in order, keeping repeated names
+    for header in fold_cookie_headers(&spec.headers) {
         request_builder = request_builder.header(&header.name, &header.value);
     }
 
-    // Add global headers
-    for header in headers {
-        request_builder = request_builder.header(&header.name, &header.value);
+    // Advertise the accepted encodings unless the request already does
+    if !config.accept_encoding.is_empty()
+        && !spec.headers.iter().any(|h| h.name.eq_ignore_ascii_case("Accept-Encoding"))
+    {
+        request_builder = request_builder.header(reqwest::header::ACCEPT_ENCODING, &config.accept_encoding);
+    }
+
+    // Add basic auth credentials
+    if let Some((user, pass)) = &config.basic_auth {
+        request_builder = request_builder.basic_auth(user, Some(pass));
+    }
+
+    // Add the current bearer token
+    if let Some(token) = &config.bearer_token {
+        request_builder = request_builder.bearer_auth(token.get());
     }
 
     // Add body if present
-    if let Some(body) = &target.body {
+    if let Some(body) = &spec.body {
         request_builder = request_builder.body(body.clone());
     }
 
-    // Make the request
-    let bytes_out = target.body.as_ref().map(|b| b.len()).unwrap_or(0);
+    // Keep a copy of the request for answering a Digest challenge, and answer a known one up front
+    let mut digest_retry = None;
+    if let Some(digest) = &config.digest_auth {
+        digest_retry = request_builder.try_clone();
+        if let Some(authorization) = digest.authorization(&spec.method, &spec.url) {
+            request_builder = request_builder.header(reqwest::header::AUTHORIZATION, authorization);
+        }
+    }
 
-    // Create a timeout future that will complete after http_timeout
+    // Make the request
+    result.bytes_out = spec.body.as_ref().map(|b| b.len()).unwrap_or(0);
     let timeout_duration = config.http_timeout;
-    let request_future = request_builder.send();
+    let mut response = send_with_timeout(request_builder, timeout_duration, &mut result).await;
+
+    // Answer a new or stale Digest challenge by retrying once with credentials
+    if let (Some(digest), Some(retry)) = (&config.digest_auth, digest_retry) {
+        let challenged = response
+            .as_ref()
+            .is_some_and(|r| r.status() == reqwest::StatusCode::UNAUTHORIZED && digest.update(&spec.url, r.headers()));
+        if challenged {
+            if let Some(authorization) = digest.authorization(&spec.method, &spec.url) {
+                let retry = retry.header(reqwest::header::AUTHORIZATION, authorization);
+                response = send_with_timeout(retry, timeout_duration, &mut result).await;
+            }
+        }
+    }
 
-    // Use tokio::time::timeout to enforce the HTTP timeout
-    let result = match tokio::time::timeout(timeout_duration, request_future).await {
-        // Request completed within timeout
-        Ok(request_result) => match request_result {
-            Ok(response) => {
-                let status_code = response.status().as_u16();
-
-                // Read the response body with timeout
-                let body_future = response.bytes();
-                let body_bytes = match tokio::time::timeout(timeout_duration, body_future).await {
-                    Ok(body_result) => match body_result {
-                        Ok(bytes) => bytes,
-                        Err(e) => {
-                            return AttackResult {
-                                timestamp,
-                                latency: start_time.elapsed(),
-                                status_code,
-                                error: Some(format!("Failed to read response body: {}", e)),
-                                target,
-                                bytes_in: 0,
-                                bytes_out,
-                                timed_out: false,
-                            };
-                        }
-                    },
-                    Err(_) => {
-                        // Body read timed out
-                        return AttackResult {
-                            timestamp,
-                            latency: start_time.elapsed(),
-                            status_code,
-                            error: Some(format!("Response body read timed out after {:?}", timeout_duration)),
-                            target,
-                            bytes_in: 0,
-                            bytes_out,
-                            timed_out: true,
-                        };
-                    }
-                };
+    // Read the response body with timeout
+    let mut received = None;
+    if let Some(response) = response {
+        result.ttfb = Some(start_time.elapsed());
+        result.status_code = response.status().as_u16();
+        let response_headers = response.headers().clone();
+        result.content_type = response_headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        match tokio::time::timeout(timeout_duration, response.bytes()).await {
+            Ok(Ok(body_bytes)) => received = Some((response_headers, body_bytes)),
+            Ok(Err(e)) => {
+                result.error = Some(format!("Failed to read response body: {}", e));
+            }
+            // Body read timed out
+            Err(_) => {
+                result.error = Some(format!("Response body read timed out after {:?}", timeout_duration));
+                result.timed_out = true;
+            }
+        }
+    }
 
-                // Limit the body size if max_body is set
-                let bytes_in = if config.max_body >= 0 && (body_bytes.len() as i64) > config.max_body {
-                    config.max_body as usize
-                } else {
-                    body_bytes.len()
-                };
+    result.latency = start_time.elapsed();
 
-                AttackResult {
-                    timestamp,
-                    latency: start_time.elapsed(),
-                    status_code,
-                    error: None,
-                    target,
-                    bytes_in,
-                    bytes_out,
-                    timed_out: false,
+    if let Some((response_headers, body_bytes)) = received {
+        result.bytes_in = body_bytes.len();
+
+        // Capture backend timings reported by the server
+        let server_timing: Vec<&str> = response_headers
+            .get_all("server-timing")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        if !server_timing.is_empty() {
+            result.server_timing = parse_server_timing(&server_timing.join(","));
+        }
+
+        // Remember validators for the next conditional request to this target
+        if let Some(cache) = &config.conditional {
+            if (200..300).contains(&result.status_code) || result.status_code == 304 {
+                cache.update(&result.target, &response_headers);
+            }
+        }
+
+        // Decode the body according to its Content-Encoding
+        let encoding = response_headers
+            .get(reqwest::header::CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("identity");
+        let body_bytes = if config.decompress {
+            match decode_body(encoding, &body_bytes) {
+                Ok(Some(decoded)) => Bytes::from(decoded),
+                Ok(None) => body_bytes,
+                Err(e) => {
+                    result.error = Some(format!("Failed to decode {} response body: {}", encoding, e));
+                    return result;
                 }
             }
-            Err(e) => {
-                let is_timeout = e.is_timeout();
-                AttackResult {
-                    timestamp,
-                    latency: start_time.elapsed(),
-                    status_code: 0,
-                    error: Some(format!("Request failed: {}", e)),
-                    target,
-                    bytes_in: 0,
-                    bytes_out,
-                    timed_out: is_timeout,
+        } else {
+            body_bytes
+        };
+        if config.decompress || is_identity(encoding) {
+            result.decoded_bytes_in = Some(body_bytes.len());
+        }
+
+        // Limit the captured body size if max_body is set
+        let captured = if config.max_body >= 0 && (body_bytes.len() as i64) > config.max_body {
+            body_bytes.slice(..config.max_body as usize)
+        } else {
+            body_bytes.clone()
+        };
+
+        // Check the response against the target's expectations, 304 responses have no body to check
+        let check = match &result.target.expect {
+            Some(expect) if !result.is_not_modified() => {
+                check_response(expect, result.status_code, &response_headers, &body_bytes)
+            }
+            _ => None,
+        };
+        if let Some(error) = check {
+            result.error = Some(error);
+            result.check_failed = true;
+        }
+
+        // Compare the body against the target's golden response
+        let golden = match result.target.expect.as_ref().and_then(|e| e.golden.as_ref()) {
+            Some(golden) if result.error.is_none() && !result.is_not_modified() => compare_golden(golden, &body_bytes),
+            _ => None,
+        };
+        if let Some((error, diverged)) = golden {
+            result.error = Some(error);
+            result.check_failed = true;
+            result.golden_diverged = diverged;
+        }
+
+        // Record whether each named check passed, without failing the request
+        if !result.target.checks.is_empty() && !result.is_not_modified() {
+            let checks = result
+                .target
+                .checks
+                .iter()
+                .map(|check| {
+                    let passed = check_response(&check.expect, result.status_code, &response_headers, &body_bytes).is_none()
+                        && check.expect.golden.as_ref().is_none_or(|golden| compare_golden(golden, &body_bytes).is_none());
+                    (check.name.clone(), passed)
+                })
+                .collect();
+            result.checks = checks;
+        }
+
+        // Capture values from the response for later steps of the scenario
+        if result.error.is_none() && !result.target.extract.is_empty() {
+            match scenario::extract(&result.target.extract, &response_headers, &body_bytes) {
+                Ok(variables) => result.variables = variables,
+                Err(e) => result.error = Some(e.to_string()),
+            }
+        }
+
+        // Check that the server echoed the request ID back
+        if result.error.is_none() && config.verify_request_id {
+            if let (Some(name), Some(id)) = (&config.request_id_header, &result.request_id) {
+                result.error = verify_request_id(&response_headers, name, id);
+            }
+        }
+
+        // Let response hooks inspect the response and annotate the result
+        if !config.on_response.is_empty() {
+            let response = ResponseData::new(result.status_code, &response_headers, captured);
+            for hook in &config.on_response {
+                hook.call(&response, &mut result);
+            }
+        }
+    }
+
+    result
+}
+
+/// Check that a response carries the ID of the request it answers, returning an error message on mismatch
+fn verify_request_id(headers: &reqwest::header::HeaderMap, name: &str, expected: &str) -> Option<String> {
+    match headers.get(name).map(|v| v.to_str().unwrap_or_default().trim()) {
+        Some(actual) if actual == expected => None,
+        Some(actual) => Some(format!("Request ID mismatch: expected {}, got {}", expected, actual)),
+        None => Some(format!("Request ID header {} missing from response", name)),
+    }
+}
+
+/// Whether a Content-Encoding value means the body isn't encoded
+fn is_identity(encoding: &str) -> bool {
+    encoding.trim().is_empty() || encoding.trim().eq_ignore_ascii_case("identity")
+}
+
+/// Decode a response body, returning None if the encoding isn't supported
+fn decode_body(encoding: &str, body: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+    // Encodings are listed in the order they were applied, so undo them in reverse
+    let mut decoded = body.to_vec();
+    for coding in encoding.rsplit(',').map(|c| c.trim().to_ascii_lowercase()) {
+        let mut buf = Vec::new();
+        match coding.as_str() {
+            "" | "identity" => continue,
+            "gzip" | "x-gzip" => {
+                flate2::read::MultiGzDecoder::new(decoded.as_slice()).read_to_end(&mut buf)?;
+            }
+            "deflate" => {
+                // Servers disagree on whether deflate means zlib-wrapped or raw deflate
+                if flate2::read::ZlibDecoder::new(decoded.as_slice()).read_to_end(&mut buf).is_err() {
+                    buf.clear();
+                    flate2::read::DeflateDecoder::new(decoded.as_slice()).read_to_end(&mut buf)?;
                 }
             }
-        },
-        // Request timed out
-        Err(_) => AttackResult {
-            timestamp,
-            latency: start_time.elapsed(),
-            status_code: 0,
-            error: Some(format!("Request timed out after {:?}", timeout_duration)),
-            target,
-            bytes_in: 0,
-            bytes_out,
-            timed_out: true,
-        },
+            "br" => {
+                brotli::Decompressor::new(decoded.as_slice(), 4096).read_to_end(&mut buf)?;
+            }
+            _ => return Ok(None),
+        }
+        decoded = buf;
+    }
+    Ok(Some(decoded))
+}
+
+/// Compare a body against a golden response, returning an error message and whether the body diverged
+fn compare_golden(golden: &Golden, body: &[u8]) -> Option<(String, bool)> {
+    let expected = match golden.contents() {
+        Ok(expected) => expected,
+        Err(e) => return Some((format!("Failed to read golden response file {}: {}", golden.file, e), false)),
     };
 
-    result
+    let matches = match golden.compare {
+        GoldenCompare::Exact => body == expected,
+        GoldenCompare::Json => {
+            let expected = match serde_json::from_slice::<serde_json::Value>(expected) {
+                Ok(expected) => expected,
+                Err(e) => return Some((format!("Golden response file {} isn't valid JSON: {}", golden.file, e), false)),
+            };
+            serde_json::from_slice::<serde_json::Value>(body).is_ok_and(|actual| actual == expected)
+        }
+    };
+
+    (!matches).then(|| (format!("Golden response mismatch: body differs from {}", golden.file), true))
+}
+
+/// Check a response against the target's expectations, returning an error message for the first mismatch
+fn check_response(expect: &Expect, status_code: u16, headers: &reqwest::header::HeaderMap, body: &[u8]) -> Option<String> {
+    if let Some(expected) = expect.status {
+        if status_code != expected {
+            return Some(format!("Check failed: expected status {}, got {}", expected, status_code));
+        }
+    }
+
+    for expected in &expect.headers {
+        let values: Vec<&str> = headers
+            .get_all(expected.name.as_str())
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        if values.is_empty() {
+            return Some(format!("Check failed: header {} missing from response", expected.name));
+        }
+        if !values.iter().any(|v| v.contains(expected.value.as_str())) {
+            return Some(format!(
+                "Check failed: expected header {} to contain {:?}, got {:?}",
+                expected.name,
+                expected.value,
+                values.join(", ")
+            ));
+        }
+    }
+
+    if let Some(expected) = &expect.body_contains {
+        if !String::from_utf8_lossy(body).contains(expected.as_str()) {
+            return Some(format!("Check failed: response body doesn't contain {:?}", expected));
+        }
+    }
+
+    if let Some(expected) = expect.content_length {
+        if body.len() as u64 != expected {
+            return Some(format!("Response size mismatch: expected {} bytes, got {} bytes", expected, body.len()));
+        }
+    }
+
+    if let Some(expected) = &expect.sha256 {
+        let actual = format!("{:x}", Sha256::digest(body));
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Some(format!("Response checksum mismatch: expected sha256 {}, got {}", expected, actual));
+        }
+    }
+
+    None
 }
@@ -0,0 +1,365 @@
+use anyhow::{Context, Result};
+use md5::Md5;
+use reqwest::header::{HeaderMap, WWW_AUTHENTICATE};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::Duration;
+use tracing::warn;
+use url::Url;
+
+use crate::utils::split_unquoted;
+
+/// Where bearer tokens are read from
+#[derive(Debug, Clone)]
+pub enum TokenSource {
+    /// Read the token from a file
+    File(String),
+    /// Run a shell command and use its standard output as the token
+    Command(String),
+}
+
+impl TokenSource {
+    /// Read the current token from the source
+    pub fn read(&self) -> Result<String> {
+        let token = match self {
+            TokenSource::File(path) => std::fs::read_to_string(path)
+                .context(format!("Failed to read bearer token file: {}", path))?,
+            TokenSource::Command(command) => {
+                let output = shell_command(command)
+                    .output()
+                    .context(format!("Failed to run bearer token command: {}", command))?;
+                if !output.status.success() {
+                    anyhow::bail!("Bearer token command exited with {}: {}", output.status, command);
+                }
+                String::from_utf8(output.stdout).context("Bearer token command produced invalid UTF-8")?
+            }
+        };
+
+        let token = token.trim().to_string();
+        if token.is_empty() {
+            anyhow::bail!("Bearer token source returned an empty token");
+        }
+
+        Ok(token)
+    }
+}
+
+/// Credentials sent to the proxy in the Proxy-Authorization header
+#[derive(Debug, Clone)]
+pub enum ProxyAuth {
+    /// Basic authentication with a username and password
+    Basic(String, String),
+    /// Bearer token authentication
+    Bearer(String),
+}
+
+/// A bearer token shared between requests that can be replaced while the attack runs
+#[derive(Debug, Clone)]
+pub struct BearerToken {
+    token: Arc<RwLock<String>>,
+}
+
+impl BearerToken {
+    /// Create a token holder with an initial value
+    pub fn new(token: String) -> Self {
+        Self {
+            token: Arc::new(RwLock::new(token)),
+        }
+    }
+
+    /// Get the current token
+    pub fn get(&self) -> String {
+        self.token.read().unwrap().clone()
+    }
+
+    fn downgrade(&self) -> Weak<RwLock<String>> {
+        Arc::downgrade(&self.token)
+    }
+}
+
+/// Read a token from the source and keep re-reading it on the given interval
+///
+/// The refresh task stops once every clone of the returned token has been dropped.
+/// If a refresh fails, the previous token is kept and a warning is logged.
+pub fn spawn_token_refresh(source: TokenSource, interval: Duration) -> Result<BearerToken> {
+    let token = BearerToken::new(source.read()?);
+    let weak = token.downgrade();
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let source = source.clone();
+            let refreshed = match tokio::task::spawn_blocking(move || source.read()).await {
+                Ok(refreshed) => refreshed,
+                Err(e) => Err(e.into()),
+            };
+
+            let Some(cell) = weak.upgrade() else {
+                break;
+            };
+
+            match refreshed {
+                Ok(value) => *cell.write().unwrap() = value,
+                Err(e) => warn!(
+                    event = "token_refresh_failed",
+                    error = e.to_string(),
+                    message = "Failed to refresh bearer token, keeping the previous one"
+                ),
+            }
+        }
+    });
+
+    Ok(token)
+}
+
+/// Settings for the OAuth2 client credentials flow
+#[derive(Debug, Clone)]
+pub struct OAuth2Config {
+    /// Token endpoint URL
+    pub token_url: String,
+    /// Client identifier
+    pub client_id: String,
+    /// Client secret
+    pub client_secret: String,
+    /// Scopes to request
+    pub scopes: Vec<String>,
+}
+
+/// Token endpoint response
+#[derive(Debug, Deserialize)]
+struct OAuth2TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+/// How long to wait before retrying a failed token refresh
+const OAUTH2_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+impl OAuth2Config {
+    /// Fetch an access token, returning it with its lifetime if the server reported one
+    pub async fn fetch_token(&self, client: &reqwest::Client) -> Result<(String, Option<Duration>)> {
+        let mut form = vec![("grant_type", "client_credentials".to_string())];
+        if !self.scopes.is_empty() {
+            form.push(("scope", self.scopes.join(" ")));
+        }
+
+        let response = client
+            .post(&self.token_url)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&form)
+            .send()
+            .await
+            .context(format!("Failed to request OAuth2 token from {}", self.token_url))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("OAuth2 token request failed with status {}: {}", status, body);
+        }
+
+        let token: OAuth2TokenResponse = response
+            .json()
+            .await
+            .context("Failed to parse OAuth2 token response")?;
+
+        Ok((token.access_token, token.expires_in.map(Duration::from_secs)))
+    }
+}
+
+/// Fetch an OAuth2 access token and keep refreshing it before it expires
+///
+/// Tokens are refreshed after 90% of their lifetime has elapsed. Tokens without an
+/// expiry are fetched once. The refresh task stops once every clone of the returned
+/// token has been dropped.
+pub async fn spawn_oauth2_refresh(oauth2: OAuth2Config, client: reqwest::Client) -> Result<BearerToken> {
+    let (access_token, mut expires_in) = oauth2.fetch_token(&client).await?;
+    let token = BearerToken::new(access_token);
+    let weak = token.downgrade();
+
+    tokio::spawn(async move {
+        while let Some(lifetime) = expires_in {
+            tokio::time::sleep(lifetime.mul_f64(0.9).max(Duration::from_secs(1))).await;
+
+            let refreshed = oauth2.fetch_token(&client).await;
+
+            let Some(cell) = weak.upgrade() else {
+                break;
+            };
+
+            match refreshed {
+                Ok((value, lifetime)) => {
+                    *cell.write().unwrap() = value;
+                    expires_in = lifetime;
+                }
+                Err(e) => {
+                    warn!(
+                        event = "token_refresh_failed",
+                        error = e.to_string(),
+                        message = "Failed to refresh OAuth2 token, keeping the previous one"
+                    );
+                    expires_in = Some(OAUTH2_RETRY_INTERVAL);
+                }
+            }
+        }
+    });
+
+    Ok(token)
+}
+
+/// Credentials for HTTP Digest authentication (RFC 7616) and the challenges received from servers
+///
+/// reqwest doesn't expose which pooled connection a request uses, so challenges are
+/// tracked per client and origin: a virtual user with a client of its own answers them
+/// with its own [`DigestAuth::session`], while workers sharing a client share them. Each
+/// request takes the next nonce count while the challenges are locked, so concurrent
+/// requests never reuse one.
+#[derive(Debug, Clone)]
+pub struct DigestAuth {
+    username: String,
+    password: String,
+    challenges: Arc<Mutex<HashMap<String, DigestChallenge>>>,
+}
+
+/// A Digest challenge from a WWW-Authenticate header
+#[derive(Debug, Clone)]
+struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: String,
+    qop: Option<&'static str>,
+    nonce_count: u32,
+}
+
+impl DigestAuth {
+    /// Create Digest credentials
+    pub fn new(username: &str, password: &str) -> Self {
+        Self {
+            username: username.to_string(),
+            password: password.to_string(),
+            challenges: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The same credentials with no challenges yet, for a client of their own
+    pub(crate) fn session(&self) -> Self {
+        Self::new(&self.username, &self.password)
+    }
+
+    /// Build the Authorization header for a request, if a challenge from the server is known
+    pub fn authorization(&self, method: &str, url: &Url) -> Option<String> {
+        let mut challenges = self.challenges.lock().unwrap();
+        let challenge = challenges.get_mut(&url.origin().ascii_serialization())?;
+        challenge.nonce_count += 1;
+
+        let uri = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let cnonce = format!("{:016x}", rand::random::<u64>());
+        let nc = format!("{:08x}", challenge.nonce_count);
+        let algorithm = challenge.algorithm.to_ascii_uppercase();
+
+        let mut ha1 = digest_hash(&algorithm, &format!("{}:{}:{}", self.username, challenge.realm, self.password));
+        if algorithm.ends_with("-SESS") {
+            ha1 = digest_hash(&algorithm, &format!("{}:{}:{}", ha1, challenge.nonce, cnonce));
+        }
+        let ha2 = digest_hash(&algorithm, &format!("{}:{}", method, uri));
+        let response = match challenge.qop {
+            Some(qop) => digest_hash(&algorithm, &format!("{}:{}:{}:{}:{}:{}", ha1, challenge.nonce, nc, cnonce, qop, ha2)),
+            None => digest_hash(&algorithm, &format!("{}:{}:{}", ha1, challenge.nonce, ha2)),
+        };
+
+        let mut header = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}, response=\"{}\"",
+            self.username, challenge.realm, challenge.nonce, uri, challenge.algorithm, response
+        );
+        if let Some(opaque) = &challenge.opaque {
+            header.push_str(&format!(", opaque=\"{}\"", opaque));
+        }
+        if let Some(qop) = challenge.qop {
+            header.push_str(&format!(", qop={}, nc={}, cnonce=\"{}\"", qop, nc, cnonce));
+        }
+
+        Some(header)
+    }
+
+    /// Remember the Digest challenge from a 401 response, returning whether one was found
+    pub fn update(&self, url: &Url, headers: &HeaderMap) -> bool {
+        let challenge = headers
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .find_map(parse_digest_challenge);
+
+        match challenge {
+            Some(challenge) => {
+                self.challenges.lock().unwrap().insert(url.origin().ascii_serialization(), challenge);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Parse a `Digest realm="...", nonce="...", qop="auth"` challenge, ignoring other schemes
+/// and challenges using an unsupported algorithm or quality of protection
+fn parse_digest_challenge(value: &str) -> Option<DigestChallenge> {
+    let value = value.trim();
+    if value.len() < 7 || !value[..7].eq_ignore_ascii_case("digest ") {
+        return None;
+    }
+
+    let mut params = HashMap::new();
+    for param in split_unquoted(&value[7..], ',') {
+        if let Some((key, value)) = param.split_once('=') {
+            params.insert(key.trim().to_ascii_lowercase(), value.trim().trim_matches('"').to_string());
+        }
+    }
+
+    let algorithm = params.remove("algorithm").unwrap_or_else(|| "MD5".to_string());
+    if !matches!(algorithm.to_ascii_uppercase().as_str(), "MD5" | "MD5-SESS" | "SHA-256" | "SHA-256-SESS") {
+        return None;
+    }
+
+    let qop = match params.get("qop") {
+        Some(qop) if qop.split(',').any(|q| q.trim().eq_ignore_ascii_case("auth")) => Some("auth"),
+        Some(_) => return None,
+        None => None,
+    };
+
+    Some(DigestChallenge {
+        realm: params.remove("realm").unwrap_or_default(),
+        nonce: params.remove("nonce")?,
+        opaque: params.remove("opaque"),
+        algorithm,
+        qop,
+        nonce_count: 0,
+    })
+}
+
+/// Hash a value with the Digest algorithm, as lowercase hex
+fn digest_hash(algorithm: &str, data: &str) -> String {
+    if algorithm.starts_with("SHA-256") {
+        format!("{:x}", Sha256::digest(data.as_bytes()))
+    } else {
+        format!("{:x}", Md5::digest(data.as_bytes()))
+    }
+}
+
+/// Build a command that runs the given string through the platform shell
+fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
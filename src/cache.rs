@@ -0,0 +1,78 @@
+use reqwest::header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::hooks::RequestSpec;
+use crate::models::Target;
+
+/// Cache validators returned by a previous response
+#[derive(Debug, Clone, Default)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// Remembers the ETag and Last-Modified validators seen for each target so that
+/// later requests to the same target can be sent as conditional requests
+#[derive(Debug, Clone, Default)]
+pub struct ValidatorCache {
+    entries: Arc<RwLock<HashMap<String, Validators>>>,
+}
+
+impl ValidatorCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add If-None-Match and If-Modified-Since headers for the target, returning
+    /// whether the request was made conditional
+    pub fn apply(&self, target: &Target, spec: &mut RequestSpec) -> bool {
+        let entries = self.entries.read().unwrap();
+        let Some(validators) = entries.get(&cache_key(target)) else {
+            return false;
+        };
+
+        let mut conditional = false;
+        if let Some(etag) = &validators.etag {
+            conditional |= add_missing_header(spec, IF_NONE_MATCH.as_str(), etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            conditional |= add_missing_header(spec, IF_MODIFIED_SINCE.as_str(), last_modified);
+        }
+        conditional
+    }
+
+    /// Remember the validators from a response to the target
+    pub fn update(&self, target: &Target, headers: &HeaderMap) {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        if etag.is_none() && last_modified.is_none() {
+            return;
+        }
+
+        let mut entries = self.entries.write().unwrap();
+        let validators = entries.entry(cache_key(target)).or_default();
+        if etag.is_some() {
+            validators.etag = etag;
+        }
+        if last_modified.is_some() {
+            validators.last_modified = last_modified;
+        }
+    }
+}
+
+/// Targets are cached by method and URL
+fn cache_key(target: &Target) -> String {
+    format!("{} {}", target.method, target.url)
+}
+
+/// Add a header unless the request already sets it
+fn add_missing_header(spec: &mut RequestSpec, name: &str, value: &str) -> bool {
+    if spec.headers.iter().any(|h| h.name.eq_ignore_ascii_case(name)) {
+        return false;
+    }
+    spec.add_header(name, value);
+    true
+}
@@ -0,0 +1,170 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::time::Duration;
+
+use crate::models::{Metrics, Result as AttackResult};
+use crate::report::{calculate_metrics, MetricsOptions};
+use crate::results::{read_results_as, InputEncoding};
+use crate::utils::{format_duration, get_reader, get_writer};
+
+/// Options of the compare command, one field per flag
+pub struct CompareOptions {
+    pub baseline: String,
+    pub current: String,
+    pub input_encoding: String,
+    pub latency_tolerance: String,
+    pub output: String,
+    pub success_tolerance: String,
+    pub throughput_tolerance: String,
+}
+
+/// Run the compare command with the given options
+pub async fn run(options: CompareOptions) -> Result<()> {
+    let CompareOptions {
+        baseline, current, input_encoding, latency_tolerance, output, success_tolerance, throughput_tolerance,
+    } = options;
+
+    let input_encoding: InputEncoding = input_encoding.parse()?;
+    let tolerances = Tolerances {
+        latency: parse_tolerance(&latency_tolerance)?,
+        success: parse_tolerance(&success_tolerance)?,
+        throughput: parse_tolerance(&throughput_tolerance)?,
+    };
+
+    let baseline_metrics = read_metrics(&baseline, input_encoding)?;
+    let current_metrics = read_metrics(&current, input_encoding)?;
+
+    let mut writer = get_writer(&output)?;
+    let regressions = write_comparison(&baseline_metrics, &current_metrics, &tolerances, &mut writer)?;
+    writer.flush()?;
+
+    if !regressions.is_empty() {
+        anyhow::bail!("{} regressed beyond tolerance: {}", current, regressions.join(", "));
+    }
+
+    Ok(())
+}
+
+/// How much worse the current run may be than the baseline before it counts as a
+/// regression, as fractions
+struct Tolerances {
+    /// Relative increase of p50, p95 and p99 latency
+    latency: f64,
+    /// Drop of the success rate, in percentage points
+    success: f64,
+    /// Relative drop of throughput
+    throughput: f64,
+}
+
+/// Parse a tolerance like "10%" or "10" into a fraction
+fn parse_tolerance(s: &str) -> Result<f64> {
+    let percent = s.trim().trim_end_matches('%').trim();
+    let percent: f64 = percent.parse().context(format!("Invalid tolerance: {}. Expected a percentage like 10%", s))?;
+    if !(percent >= 0.0 && percent.is_finite()) {
+        anyhow::bail!("Tolerance must be a percentage of at least 0%: {}", s);
+    }
+    Ok(percent / 100.0)
+}
+
+/// Read a result file and calculate its metrics
+fn read_metrics(input: &str, encoding: InputEncoding) -> Result<Metrics> {
+    let results = read_results_as(get_reader(input)?, encoding)?
+        .collect::<Result<Vec<AttackResult>>>()
+        .context(format!("Failed to read results: {}", input))?;
+    if results.is_empty() {
+        anyhow::bail!("No results to compare in {}", input);
+    }
+    calculate_metrics(&results, &MetricsOptions::default())
+}
+
+/// Write a table of the baseline and current metrics and the change between them,
+/// returning the names of the metrics that regressed beyond tolerance
+fn write_comparison<W: Write>(
+    baseline: &Metrics,
+    current: &Metrics,
+    tolerances: &Tolerances,
+    writer: &mut W,
+) -> Result<Vec<String>> {
+    let mut regressions = Vec::new();
+
+    writeln!(writer, "{:<16}{:>16}{:>16}{:>12}", "Metric", "Baseline", "Current", "Change")?;
+
+    let latencies: [(&str, Duration, Duration); 3] = [
+        ("p50", baseline.p50, current.p50),
+        ("p95", baseline.p95, current.p95),
+        ("p99", baseline.p99, current.p99),
+    ];
+    for (name, baseline, current) in latencies {
+        let change = relative_change(baseline.as_secs_f64(), current.as_secs_f64());
+        let regressed = change > tolerances.latency;
+        writeln!(
+            writer,
+            "{:<16}{:>16}{:>16}{:>12}{}",
+            name,
+            format_duration(baseline),
+            format_duration(current),
+            format!("{:+.2}%", change * 100.0),
+            verdict(regressed, format!("+{:.2}%", tolerances.latency * 100.0))
+        )?;
+        if regressed {
+            regressions.push(name.to_string());
+        }
+    }
+
+    let change = current.success_rate - baseline.success_rate;
+    let regressed = -change > tolerances.success;
+    writeln!(
+        writer,
+        "{:<16}{:>16}{:>16}{:>12}{}",
+        "Success rate",
+        format!("{:.2}%", baseline.success_rate * 100.0),
+        format!("{:.2}%", current.success_rate * 100.0),
+        format!("{:+.2}pp", change * 100.0),
+        verdict(regressed, format!("-{:.2}pp", tolerances.success * 100.0))
+    )?;
+    if regressed {
+        regressions.push("success rate".to_string());
+    }
+
+    let change = relative_change(baseline.throughput, current.throughput);
+    let regressed = -change > tolerances.throughput;
+    writeln!(
+        writer,
+        "{:<16}{:>16}{:>16}{:>12}{}",
+        "Throughput",
+        format!("{:.2}/s", baseline.throughput),
+        format!("{:.2}/s", current.throughput),
+        format!("{:+.2}%", change * 100.0),
+        verdict(regressed, format!("-{:.2}%", tolerances.throughput * 100.0))
+    )?;
+    if regressed {
+        regressions.push("throughput".to_string());
+    }
+
+    writeln!(writer)?;
+    if regressions.is_empty() {
+        writeln!(writer, "No regressions")?;
+    } else {
+        writeln!(writer, "Regressions: {}", regressions.join(", "))?;
+    }
+
+    Ok(regressions)
+}
+
+/// Change from the baseline to the current value, relative to the baseline
+fn relative_change(baseline: f64, current: f64) -> f64 {
+    if baseline > 0.0 {
+        (current - baseline) / baseline
+    } else {
+        0.0
+    }
+}
+
+/// Mark a metric that regressed beyond its tolerance
+fn verdict(regressed: bool, tolerance: String) -> String {
+    if regressed {
+        format!("  ✗ regression (tolerance {})", tolerance)
+    } else {
+        String::new()
+    }
+}
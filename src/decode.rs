@@ -0,0 +1,29 @@
+use anyhow::{Context, Result};
+use std::io::BufWriter;
+
+use crate::results::{read_results, Encoding, ResultWriter};
+use crate::utils::{get_reader, get_writer};
+
+/// Options of the decode command, one field per flag
+pub struct DecodeOptions {
+    pub inputs: Vec<String>,
+    pub output: String,
+}
+
+/// Run the decode command with the given options
+pub async fn run(options: DecodeOptions) -> Result<()> {
+    let DecodeOptions { inputs, output } = options;
+
+    // Read from stdin if no files are given
+    let inputs = if inputs.is_empty() { vec!["stdin".to_string()] } else { inputs };
+
+    let mut writer = ResultWriter::new(BufWriter::new(get_writer(&output)?), Encoding::Json)?;
+    for input in &inputs {
+        for result in read_results(get_reader(input)?) {
+            writer.write(&result.context(format!("Failed to decode results: {}", input))?)?;
+        }
+    }
+    writer.finish()?;
+
+    Ok(())
+}
@@ -1,76 +1,218 @@
 use anyhow::Result;
-use std::io::{BufRead, Write};
+use prost::Message;
+use std::io::Write;
 
+use crate::gob::GobWriter;
 use crate::models::Result as AttackResult;
+use crate::proto;
+use crate::results::{dedup_results, read_results_as, sort_results, Encoding, ResultWriter};
+use crate::sample::Sampler;
 use crate::utils::{get_reader, get_writer};
 
-/// Run the encode command with the given arguments
-pub async fn run(
-    output: String,
-    to: String,
-) -> Result<()> {
+/// Options of the encode command, one field per flag
+pub struct EncodeOptions {
+    pub columns: Option<String>,
+    pub dedup: bool,
+    pub from: String,
+    pub output: String,
+    pub sample: Option<String>,
+    pub seed: u64,
+    pub sort: bool,
+    pub to: String,
+}
+
+/// Run the encode command with the given options
+pub async fn run(options: EncodeOptions) -> Result<()> {
+    let EncodeOptions { columns, dedup, from, output, sample, seed, sort, to } = options;
+
+    // Check the columns before reading anything
+    if columns.is_some() && to != "csv" {
+        anyhow::bail!("--columns only applies to --to=csv");
+    }
+    let columns = parse_csv_columns(columns.as_deref().unwrap_or(CSV_COLUMNS))?;
+
     // Get reader and writer
-    let reader = get_reader("stdin")?;
+    let reader = read_results_as(get_reader("stdin")?, from.parse()?)?;
     let mut writer = get_writer(&output)?;
 
+    // Sorting needs every result in memory, otherwise results are streamed
+    let results: Box<dyn Iterator<Item = Result<AttackResult>>> = if sort || dedup {
+        let mut results = reader.collect::<Result<Vec<_>>>()?;
+        sort_results(&mut results);
+        if dedup {
+            dedup_results(&mut results)?;
+        }
+        Box::new(results.into_iter().map(Ok))
+    } else {
+        Box::new(reader)
+    };
+
+    // Keep only a sample of the results
+    let results = match sample {
+        Some(sample) => {
+            let mut sampler = Sampler::new(sample.parse()?, seed);
+            let mut sampled = Vec::new();
+            for result in results {
+                sampled.extend(sampler.offer(result?));
+            }
+            sampled.extend(sampler.finish());
+            Box::new(sampled.into_iter().map(Ok))
+        }
+        None => results,
+    };
+
     // Encode based on the specified format
     match to.as_str() {
-        "json" => encode_json(reader, &mut writer)?,
-        "csv" => encode_csv(reader, &mut writer)?,
+        "json" => encode_json(results, &mut writer)?,
+        "csv" => encode_csv(results, &mut writer, &columns)?,
+        "binary" => encode_results(results, &mut writer, Encoding::Binary)?,
+        "gob" => encode_gob(results, &mut writer)?,
+        "msgpack" => encode_msgpack(results, &mut writer)?,
+        "ndjson" => encode_results(results, &mut writer, Encoding::Json)?,
+        "proto" => encode_proto(results, &mut writer)?,
         _ => anyhow::bail!("Unsupported encoding format: {}", to),
     }
 
     Ok(())
 }
 
-/// Encode attack results to JSON
-fn encode_json<R: BufRead, W: Write>(reader: R, writer: &mut W) -> Result<()> {
-    let results: Vec<AttackResult> = reader
-        .lines()
-        .filter_map(|line| {
-            let line = line.ok()?;
-            serde_json::from_str(&line).ok()
-        })
-        .collect();
+/// Encode attack results to a pretty-printed JSON array
+fn encode_json<I: Iterator<Item = Result<AttackResult>>, W: Write>(results: I, writer: &mut W) -> Result<()> {
+    let results: Vec<AttackResult> = results.filter_map(|result| result.ok()).collect();
 
     serde_json::to_writer_pretty(writer, &results)?;
 
     Ok(())
 }
 
-/// Encode attack results to CSV
-fn encode_csv<R: BufRead, W: Write>(reader: R, writer: &mut W) -> Result<()> {
+/// Encode attack results in one of the encodings the attack command writes
+fn encode_results<I: Iterator<Item = Result<AttackResult>>, W: Write>(results: I, writer: &mut W, encoding: Encoding) -> Result<()> {
+    let mut writer = ResultWriter::new(writer, encoding)?;
+    for result in results {
+        writer.write(&result?)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Encode attack results to a gob stream of Vegeta results, readable by `vegeta report`
+fn encode_gob<I: Iterator<Item = Result<AttackResult>>, W: Write>(results: I, writer: &mut W) -> Result<()> {
+    let mut writer = GobWriter::new(writer)?;
+    for result in results {
+        writer.write(&result?)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Encode attack results to a stream of MessagePack maps, one per result, with the
+/// same field names as the JSON encoding
+fn encode_msgpack<I: Iterator<Item = Result<AttackResult>>, W: Write>(results: I, writer: &mut W) -> Result<()> {
+    for result in results {
+        rmp_serde::encode::write_named(writer, &result?)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Encode attack results to a stream of length-delimited protobuf Result messages,
+/// as described by proto/culverin.proto
+fn encode_proto<I: Iterator<Item = Result<AttackResult>>, W: Write>(results: I, writer: &mut W) -> Result<()> {
+    let mut buffer = Vec::new();
+    for result in results {
+        buffer.clear();
+        proto::Result::from(&result?).encode_length_delimited(&mut buffer)?;
+        writer.write_all(&buffer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Columns written by `encode --to=csv` unless `--columns` picks others
+const CSV_COLUMNS: &str = "timestamp,latency,status_code,error,method,url,bytes_in,bytes_out";
+
+/// Every column `encode --to=csv` can write
+const CSV_COLUMN_NAMES: [&str; 23] = [
+    "timestamp",
+    "timestamp_ms",
+    "latency",
+    "latency_ms",
+    "latency_us",
+    "latency_ns",
+    "ttfb",
+    "status_code",
+    "status",
+    "error",
+    "timed_out",
+    "check_failed",
+    "method",
+    "url",
+    "bytes_in",
+    "bytes_out",
+    "content_type",
+    "attack",
+    "run_id",
+    "seq",
+    "worker",
+    "request_id",
+    "trace_id",
+];
+
+/// Parse a comma-separated list of CSV columns
+fn parse_csv_columns(columns: &str) -> Result<Vec<&str>> {
+    let columns: Vec<&str> = columns.split(',').map(str::trim).filter(|column| !column.is_empty()).collect();
+    if columns.is_empty() {
+        anyhow::bail!("No CSV columns given");
+    }
+    if let Some(column) = columns.iter().find(|column| !CSV_COLUMN_NAMES.contains(column)) {
+        anyhow::bail!("Unknown CSV column: {}. Expected one of {}", column, CSV_COLUMN_NAMES.join(", "));
+    }
+    Ok(columns)
+}
+
+/// The value of a CSV column for a result
+fn csv_value(column: &str, result: &AttackResult) -> String {
+    match column {
+        "timestamp" => result.timestamp.to_rfc3339(),
+        "timestamp_ms" => result.timestamp.timestamp_millis().to_string(),
+        "latency" => crate::utils::format_duration(result.latency),
+        "latency_ms" => (result.latency.as_secs_f64() * 1000.0).to_string(),
+        "latency_us" => result.latency.as_micros().to_string(),
+        "latency_ns" => result.latency.as_nanos().to_string(),
+        "ttfb" => result.ttfb.map(crate::utils::format_duration).unwrap_or_default(),
+        "status_code" | "status" => result.status_code.to_string(),
+        "error" => result.error.clone().unwrap_or_default(),
+        "timed_out" => result.timed_out.to_string(),
+        "check_failed" => result.check_failed.to_string(),
+        "method" => result.target.method.clone(),
+        "url" => result.target.url.to_string(),
+        "bytes_in" => result.bytes_in.to_string(),
+        "bytes_out" => result.bytes_out.to_string(),
+        "content_type" => result.content_type.clone().unwrap_or_default(),
+        "attack" => result.attack.clone().unwrap_or_default(),
+        "run_id" => result.run_id.clone().unwrap_or_default(),
+        "seq" => result.seq.to_string(),
+        "worker" => result.worker.to_string(),
+        "request_id" => result.request_id.clone().unwrap_or_default(),
+        "trace_id" => result.trace_id.clone().unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// Encode attack results to CSV with the given columns
+fn encode_csv<I: Iterator<Item = Result<AttackResult>>, W: Write>(results: I, writer: &mut W, columns: &[&str]) -> Result<()> {
     // Create CSV writer
     let mut csv_writer = csv::Writer::from_writer(writer);
 
     // Write header
-    csv_writer.write_record(&[
-        "timestamp",
-        "latency",
-        "status_code",
-        "error",
-        "method",
-        "url",
-        "bytes_in",
-        "bytes_out",
-    ])?;
-
-    // Process each line
-    for line in reader.lines() {
-        let line = line?;
-        let result: AttackResult = serde_json::from_str(&line)?;
+    csv_writer.write_record(columns)?;
+
+    // Process each result
+    for result in results {
+        let result = result?;
 
         // Write record
-        csv_writer.write_record(&[
-            result.timestamp.to_rfc3339(),
-            crate::utils::format_duration(result.latency),
-            result.status_code.to_string(),
-            result.error.unwrap_or_default(),
-            result.target.method,
-            result.target.url.to_string(),
-            result.bytes_in.to_string(),
-            result.bytes_out.to_string(),
-        ])?;
+        csv_writer.write_record(columns.iter().map(|column| csv_value(column, &result)))?;
     }
 
     // Flush the writer
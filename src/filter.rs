@@ -0,0 +1,176 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use std::io::BufWriter;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use crate::models::Result as AttackResult;
+use crate::results::{read_results, ResultWriter};
+use crate::sample::Sampler;
+use crate::utils::{get_reader, get_writer};
+
+/// Options of the filter command, one field per flag
+pub struct FilterOptions {
+    pub encoding: Option<String>,
+    pub errors: bool,
+    pub from: Option<String>,
+    pub inputs: Vec<String>,
+    pub max_latency: Option<humantime::Duration>,
+    pub min_latency: Option<humantime::Duration>,
+    pub no_errors: bool,
+    pub output: String,
+    pub sample: Option<String>,
+    pub seed: u64,
+    pub status: Option<String>,
+    pub until: Option<String>,
+    pub url: Option<String>,
+}
+
+/// Run the filter command with the given options
+pub async fn run(options: FilterOptions) -> Result<()> {
+    let FilterOptions {
+        encoding, errors, from, inputs, max_latency, min_latency, no_errors, output, sample, seed, status,
+        until, url,
+    } = options;
+
+    let mut filter = Filter {
+        status: status.as_deref().map(parse_status_codes).transpose()?.unwrap_or_default(),
+        errors: if errors { Some(true) } else if no_errors { Some(false) } else { None },
+        min_latency: min_latency.map(Duration::from),
+        max_latency: max_latency.map(Duration::from),
+        from: from.as_deref().map(parse_time_bound).transpose()?,
+        until: until.as_deref().map(parse_time_bound).transpose()?,
+        url: url.map(|u| Regex::new(&u).context(format!("Invalid URL pattern: {}", u))).transpose()?,
+        start: None,
+    };
+    let mut sampler = sample.map(|sample| sample.parse().map(|sample| Sampler::new(sample, seed))).transpose()?;
+
+    // Read from stdin if no files are given
+    let inputs = if inputs.is_empty() { vec!["stdin".to_string()] } else { inputs };
+
+    let mut writer = None;
+    for input in &inputs {
+        let results = read_results(get_reader(input)?);
+
+        // Unless told otherwise, write results in the encoding they were read in
+        if writer.is_none() {
+            let encoding = match &encoding {
+                Some(encoding) => encoding.parse()?,
+                None => results.encoding(),
+            };
+            writer = Some(ResultWriter::new(BufWriter::new(get_writer(&output)?), encoding)?);
+        }
+        let writer = writer.as_mut().expect("writer was just created");
+
+        for result in results {
+            let result = result.context(format!("Failed to read results: {}", input))?;
+            if !filter.matches(&result) {
+                continue;
+            }
+            let result = match &mut sampler {
+                Some(sampler) => sampler.offer(result),
+                None => Some(result),
+            };
+            if let Some(result) = result {
+                writer.write(&result)?;
+            }
+        }
+    }
+
+    if let Some(mut writer) = writer {
+        // A sample of a number of results is only known once every result was read
+        for result in sampler.map(Sampler::finish).unwrap_or_default() {
+            writer.write(&result)?;
+        }
+        writer.finish()?;
+    }
+
+    Ok(())
+}
+
+/// Conditions a result must meet to be kept; unset conditions match every result
+#[derive(Debug, Default)]
+pub(crate) struct Filter {
+    /// Ranges of status codes to keep
+    pub status: Vec<RangeInclusive<u16>>,
+    /// Keep only failed requests if true, or only requests without errors if false
+    pub errors: Option<bool>,
+    pub min_latency: Option<Duration>,
+    pub max_latency: Option<Duration>,
+    /// Keep results from this time on
+    pub from: Option<TimeBound>,
+    /// Keep results from before this time
+    pub until: Option<TimeBound>,
+    /// Pattern the target URL must match
+    pub url: Option<Regex>,
+    /// Timestamp of the first result, that offsets are relative to
+    start: Option<DateTime<Utc>>,
+}
+
+/// A point in time to filter results by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TimeBound {
+    /// An absolute time
+    At(DateTime<Utc>),
+    /// An offset from the first result
+    Offset(Duration),
+}
+
+impl Filter {
+    /// Whether a result meets every condition of the filter
+    pub fn matches(&mut self, result: &AttackResult) -> bool {
+        let start = *self.start.get_or_insert(result.timestamp);
+        let resolve = |bound: TimeBound| match bound {
+            TimeBound::At(time) => time,
+            TimeBound::Offset(offset) => start + chrono::Duration::from_std(offset).unwrap_or(chrono::Duration::MAX),
+        };
+
+        (self.status.is_empty() || self.status.iter().any(|range| range.contains(&result.status_code)))
+            && self.errors.is_none_or(|errors| errors == result.error.is_some())
+            && self.min_latency.is_none_or(|min| result.latency >= min)
+            && self.max_latency.is_none_or(|max| result.latency <= max)
+            && self.from.is_none_or(|from| result.timestamp >= resolve(from))
+            && self.until.is_none_or(|until| result.timestamp < resolve(until))
+            && self.url.as_ref().is_none_or(|url| url.is_match(result.target.url.as_str()))
+    }
+}
+
+/// Parse a list of status codes like "200,301-308,5xx" into ranges
+pub(crate) fn parse_status_codes(codes: &str) -> Result<Vec<RangeInclusive<u16>>> {
+    codes
+        .split(',')
+        .map(str::trim)
+        .filter(|code| !code.is_empty())
+        .map(|code| {
+            let parse = |code: &str| code.parse::<u16>().context(format!("Invalid status code: {}", code));
+            if let Some(class) = code.strip_suffix("xx").or_else(|| code.strip_suffix("XX")) {
+                let class = parse(class)?;
+                if !(1..=9).contains(&class) {
+                    anyhow::bail!("Invalid status class: {}", code);
+                }
+                Ok(class * 100..=class * 100 + 99)
+            } else if let Some((low, high)) = code.split_once('-') {
+                let (low, high) = (parse(low)?, parse(high)?);
+                if low > high {
+                    anyhow::bail!("Invalid status code range: {}", code);
+                }
+                Ok(low..=high)
+            } else {
+                let code = parse(code)?;
+                Ok(code..=code)
+            }
+        })
+        .collect()
+}
+
+/// Parse an RFC 3339 timestamp, or a duration like "1m30s" taken as an offset from the
+/// first result
+pub(crate) fn parse_time_bound(bound: &str) -> Result<TimeBound> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(bound) {
+        return Ok(TimeBound::At(time.with_timezone(&Utc)));
+    }
+    humantime::parse_duration(bound)
+        .map(TimeBound::Offset)
+        .context(format!("Invalid time: {}. Expected an RFC 3339 timestamp or an offset like 1m30s", bound))
+}
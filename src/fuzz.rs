@@ -0,0 +1,131 @@
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde_json::Value;
+
+use crate::hooks::RequestSpec;
+
+/// Parts of a request that fuzzing may mutate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FuzzStrategy {
+    /// Mutate the value of a query parameter
+    Query,
+    /// Mutate the value of a header
+    Headers,
+    /// Mutate a field of a JSON body
+    Body,
+}
+
+/// Parse a comma separated list of strategies like "query,headers,body"
+pub fn parse_strategies(strategies: &str) -> Result<Vec<FuzzStrategy>> {
+    strategies
+        .split(',')
+        .map(|s| match s.trim().to_ascii_lowercase().as_str() {
+            "query" => Ok(FuzzStrategy::Query),
+            "headers" => Ok(FuzzStrategy::Headers),
+            "body" => Ok(FuzzStrategy::Body),
+            other => anyhow::bail!("Unknown fuzz strategy: {}. Expected query, headers, or body", other),
+        })
+        .collect()
+}
+
+/// Values that commonly break parsers, validators, and storage layers
+const MUTATIONS: &[&str] = &[
+    "",
+    "0",
+    "-1",
+    "2147483648",
+    "-9223372036854775809",
+    "1e309",
+    "NaN",
+    "null",
+    "true",
+    "' OR '1'='1",
+    "\"; DROP TABLE users; --",
+    "../../../../etc/passwd",
+    "<script>alert(1)</script>",
+    "%00",
+    "%s%s%s%n",
+    "{{7*7}}",
+    "${jndi:ldap://localhost/a}",
+];
+
+/// Mutate one part of the request, chosen by the seed among the enabled strategies
+///
+/// The same seed applied to the same request always produces the same mutation.
+pub fn mutate(spec: &mut RequestSpec, strategies: &[FuzzStrategy], seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // Only pick strategies that have something to mutate
+    let json_body = spec.body.as_deref().and_then(|b| serde_json::from_slice::<Value>(b).ok());
+    let applicable: Vec<FuzzStrategy> = strategies
+        .iter()
+        .copied()
+        .filter(|strategy| match strategy {
+            FuzzStrategy::Query => spec.url.query_pairs().next().is_some(),
+            FuzzStrategy::Headers => !spec.headers.is_empty(),
+            FuzzStrategy::Body => json_body.as_ref().is_some_and(|b| b.is_object() || b.is_array()),
+        })
+        .collect();
+
+    match applicable.choose(&mut rng) {
+        Some(FuzzStrategy::Query) => mutate_query(spec, &mut rng),
+        Some(FuzzStrategy::Headers) => mutate_header(spec, &mut rng),
+        Some(FuzzStrategy::Body) => {
+            if let Some(mut body) = json_body {
+                mutate_json(&mut body, &mut rng);
+                spec.body = serde_json::to_vec(&body).ok();
+            }
+        }
+        None => {}
+    }
+}
+
+/// Replace the value of a random query parameter
+fn mutate_query(spec: &mut RequestSpec, rng: &mut StdRng) {
+    let mut pairs: Vec<(String, String)> = spec.url.query_pairs().into_owned().collect();
+    let index = rng.gen_range(0..pairs.len());
+    pairs[index].1 = mutated_string(rng);
+    spec.url.query_pairs_mut().clear().extend_pairs(&pairs);
+}
+
+/// Replace the value of a random header, keeping it a valid header value
+fn mutate_header(spec: &mut RequestSpec, rng: &mut StdRng) {
+    let index = rng.gen_range(0..spec.headers.len());
+    let value: String = mutated_string(rng).chars().filter(|c| c.is_ascii() && !c.is_ascii_control()).collect();
+    spec.headers[index].value = value;
+}
+
+/// Replace a random leaf of a JSON document with a value of a random type
+fn mutate_json(value: &mut Value, rng: &mut StdRng) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            let index = rng.gen_range(0..map.len());
+            let child = map.values_mut().nth(index).unwrap();
+            mutate_json(child, rng);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            let index = rng.gen_range(0..items.len());
+            mutate_json(&mut items[index], rng);
+        }
+        _ => {
+            *value = match rng.gen_range(0..5) {
+                0 => Value::Null,
+                1 => Value::from(rng.gen::<i64>()),
+                2 => Value::Bool(rng.gen()),
+                3 => Value::Array(Vec::new()),
+                _ => Value::String(mutated_string(rng)),
+            };
+        }
+    }
+}
+
+/// Pick a known-bad value, an oversized string, or random unicode
+fn mutated_string(rng: &mut StdRng) -> String {
+    match rng.gen_range(0..10) {
+        0 => "A".repeat(rng.gen_range(1024..=65536)),
+        1 => (0..rng.gen_range(1..32)).map(|_| rng.gen::<char>()).collect(),
+        _ => MUTATIONS.choose(rng).unwrap().to_string(),
+    }
+}
@@ -0,0 +1,561 @@
+// Go gob encoding of attack results as Vegeta results, so results can be fed to
+// `vegeta report`, `vegeta plot` and other tools that read Vegeta's default encoding,
+// and Vegeta's results can be read in turn.
+//
+// Gob is self-describing: the stream starts with the definitions of the types it
+// carries, followed by one message per value. The definitions written here are the
+// ones Go's encoder writes for `vegeta.Result`, while the reader decodes values using
+// whatever definitions the stream holds, so results of older Vegeta versions with
+// fewer fields can be read too.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+
+use crate::import::VegetaResult;
+use crate::models::Result as AttackResult;
+
+// Ids of gob's predefined types
+const BOOL: i64 = 1;
+const INT: i64 = 2;
+const UINT: i64 = 3;
+const FLOAT: i64 = 4;
+const BYTES: i64 = 5;
+const STRING: i64 = 6;
+
+// Ids of the types defined by the stream; user-defined ids start at 65
+const RESULT: i64 = 65;
+const TIME: i64 = 66;
+const HEADER: i64 = 67;
+const STRINGS: i64 = 68;
+
+/// Fields of `vegeta.Result`, in declaration order
+const RESULT_FIELDS: [(&str, i64); 12] = [
+    ("Attack", STRING),
+    ("Seq", UINT),
+    ("Code", UINT),
+    ("Timestamp", TIME),
+    ("Latency", INT),
+    ("BytesOut", UINT),
+    ("BytesIn", UINT),
+    ("Error", STRING),
+    ("Body", BYTES),
+    ("Method", STRING),
+    ("URL", STRING),
+    ("Headers", HEADER),
+];
+
+/// Seconds between the start of year 1, Go's time epoch, and the Unix epoch
+const UNIX_TO_INTERNAL: i64 = 62_135_596_800;
+
+/// Writes attack results as a gob stream of `vegeta.Result` values
+pub struct GobWriter<W: Write> {
+    writer: W,
+    message: Vec<u8>,
+}
+
+impl<W: Write> GobWriter<W> {
+    /// Start a gob stream, writing the type definitions first
+    pub fn new(mut writer: W) -> Result<Self> {
+        for definition in [
+            struct_type(RESULT, "Result", &RESULT_FIELDS),
+            gob_encoder_type(TIME, "Time"),
+            map_type(HEADER, "Header", STRING, STRINGS),
+            slice_type(STRINGS, "[]string", STRING),
+        ] {
+            write_message(&mut writer, &definition)?;
+        }
+
+        Ok(Self {
+            writer,
+            message: Vec::new(),
+        })
+    }
+
+    /// Write one result
+    pub fn write(&mut self, result: &AttackResult) -> Result<()> {
+        let latency = i64::try_from(result.latency.as_nanos()).unwrap_or(i64::MAX);
+
+        self.message.clear();
+        let buf = &mut self.message;
+        put_int(buf, RESULT);
+        let mut fields = Fields::new(buf);
+        fields.bytes(0, result.attack.as_deref().unwrap_or_default().as_bytes());
+        fields.uint(1, result.seq);
+        fields.uint(2, result.status_code as u64);
+        fields.bytes(3, &marshal_time(result.timestamp));
+        fields.int(4, latency);
+        fields.uint(5, result.bytes_out as u64);
+        fields.uint(6, result.bytes_in as u64);
+        fields.bytes(7, result.error.as_deref().unwrap_or_default().as_bytes());
+        fields.bytes(9, result.target.method.as_bytes());
+        fields.bytes(10, result.target.url.as_str().as_bytes());
+        fields.end();
+
+        write_message(&mut self.writer, &self.message)?;
+        Ok(())
+    }
+
+    /// Finish writing results, returning the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// The fields of a struct value, each prefixed with the delta from the previous field
+/// number. Zero values are left out, as Go does.
+struct Fields<'a> {
+    buf: &'a mut Vec<u8>,
+    last: i64,
+}
+
+impl<'a> Fields<'a> {
+    fn new(buf: &'a mut Vec<u8>) -> Self {
+        Self { buf, last: -1 }
+    }
+
+    fn field(&mut self, number: i64) {
+        put_uint(self.buf, (number - self.last) as u64);
+        self.last = number;
+    }
+
+    fn uint(&mut self, number: i64, value: u64) {
+        if value != 0 {
+            self.field(number);
+            put_uint(self.buf, value);
+        }
+    }
+
+    fn int(&mut self, number: i64, value: i64) {
+        if value != 0 {
+            self.field(number);
+            put_int(self.buf, value);
+        }
+    }
+
+    /// A byte slice or string field
+    fn bytes(&mut self, number: i64, value: &[u8]) {
+        if !value.is_empty() {
+            self.field(number);
+            put_bytes(self.buf, value);
+        }
+    }
+
+    /// A nested struct field, encoded by `encode`
+    fn nested(&mut self, number: i64, encode: impl FnOnce(&mut Fields)) {
+        self.field(number);
+        let mut fields = Fields::new(self.buf);
+        encode(&mut fields);
+        fields.end();
+    }
+
+    fn end(self) {
+        put_uint(self.buf, 0);
+    }
+}
+
+/// A type definition message: the negated type id followed by a `wireType` value with
+/// the field for the kind of type set
+fn definition(id: i64, kind: i64, encode: impl FnOnce(&mut Fields)) -> Vec<u8> {
+    let mut buf = Vec::new();
+    put_int(&mut buf, -id);
+    let mut wire_type = Fields::new(&mut buf);
+    wire_type.nested(kind, encode);
+    wire_type.end();
+    buf
+}
+
+/// The `CommonType` every type definition starts with
+fn common_type(fields: &mut Fields, id: i64, name: &str) {
+    fields.nested(0, |common| {
+        common.bytes(0, name.as_bytes());
+        common.int(1, id);
+    });
+}
+
+fn slice_type(id: i64, name: &str, elem: i64) -> Vec<u8> {
+    definition(id, 1, |fields| {
+        common_type(fields, id, name);
+        fields.int(1, elem);
+    })
+}
+
+fn struct_type(id: i64, name: &str, struct_fields: &[(&str, i64)]) -> Vec<u8> {
+    definition(id, 2, |fields| {
+        common_type(fields, id, name);
+        fields.field(1);
+        put_uint(fields.buf, struct_fields.len() as u64);
+        for (name, id) in struct_fields {
+            let mut field = Fields::new(fields.buf);
+            field.bytes(0, name.as_bytes());
+            field.int(1, *id);
+            field.end();
+        }
+    })
+}
+
+fn map_type(id: i64, name: &str, key: i64, elem: i64) -> Vec<u8> {
+    definition(id, 3, |fields| {
+        common_type(fields, id, name);
+        fields.int(1, key);
+        fields.int(2, elem);
+    })
+}
+
+fn gob_encoder_type(id: i64, name: &str) -> Vec<u8> {
+    definition(id, 4, |fields| common_type(fields, id, name))
+}
+
+/// A timestamp as written by Go's `time.Time.GobEncode` for a UTC time
+fn marshal_time(timestamp: chrono::DateTime<chrono::Utc>) -> Vec<u8> {
+    let sec = timestamp.timestamp() + UNIX_TO_INTERNAL;
+    let nsec = timestamp.timestamp_subsec_nanos();
+    let mut buf = Vec::with_capacity(15);
+    buf.push(1); // version
+    buf.extend_from_slice(&sec.to_be_bytes());
+    buf.extend_from_slice(&nsec.to_be_bytes());
+    buf.extend_from_slice(&(-1i16).to_be_bytes()); // UTC
+    buf
+}
+
+/// Write a message prefixed with its length
+fn write_message<W: Write>(writer: &mut W, message: &[u8]) -> Result<()> {
+    let mut len = Vec::with_capacity(9);
+    put_uint(&mut len, message.len() as u64);
+    writer.write_all(&len)?;
+    writer.write_all(message)?;
+    Ok(())
+}
+
+/// An unsigned integer: a single byte below 128, otherwise the negated byte count
+/// followed by the big-endian bytes
+fn put_uint(buf: &mut Vec<u8>, value: u64) {
+    if value < 0x80 {
+        buf.push(value as u8);
+        return;
+    }
+    let bytes = value.to_be_bytes();
+    let skip = value.leading_zeros() as usize / 8;
+    buf.push((skip as u8).wrapping_sub(8));
+    buf.extend_from_slice(&bytes[skip..]);
+}
+
+/// A signed integer, with the sign moved to the lowest bit
+fn put_int(buf: &mut Vec<u8>, value: i64) {
+    let value = if value < 0 { (!(value as u64) << 1) | 1 } else { (value as u64) << 1 };
+    put_uint(buf, value);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, value: &[u8]) {
+    put_uint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+/// Largest message accepted when reading, to fail fast on input that isn't gob
+const MAX_MESSAGE: u64 = 64 * 1024 * 1024;
+
+/// Whether a stream starts like a gob stream of user-defined types, with the definition
+/// of the first of them
+pub(crate) fn is_gob(buf: &[u8]) -> bool {
+    let mut decoder = Decoder { buf };
+    decoder.uint().is_ok_and(|len| len > 0 && len <= MAX_MESSAGE) && decoder.int().is_ok_and(|id| id == -RESULT)
+}
+
+/// Reads a gob stream of `vegeta.Result` values
+pub struct GobReader<R: Read> {
+    reader: R,
+    /// Types defined by the stream so far
+    types: HashMap<i64, WireType>,
+    message: Vec<u8>,
+}
+
+/// Definition of a user-defined type
+#[derive(Debug)]
+enum WireType {
+    /// Names and type ids of the fields of a struct
+    Struct(Vec<(String, i64)>),
+    /// Type ids of the keys and elements of a map
+    Map(i64, i64),
+    /// Type id of the elements of a slice or array
+    Slice(i64),
+    /// A type that encodes itself as bytes, like `time.Time`
+    Encoder,
+}
+
+/// A decoded value
+#[derive(Debug)]
+enum Value {
+    Int(i64),
+    Uint(u64),
+    Bytes(Vec<u8>),
+    Struct(BTreeMap<String, Value>),
+    Map(Vec<(Value, Value)>),
+    List(Vec<Value>),
+}
+
+impl<R: Read> GobReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            types: HashMap::new(),
+            message: Vec::new(),
+        }
+    }
+
+    /// Read the next result, or None at the end of the stream
+    pub fn read(&mut self) -> Result<Option<AttackResult>> {
+        loop {
+            let Some(len) = self.read_len()? else {
+                return Ok(None);
+            };
+            self.message.resize(len, 0);
+            self.reader.read_exact(&mut self.message).context("Truncated gob results")?;
+
+            let mut decoder = Decoder { buf: &self.message };
+            let id = decoder.int()?;
+            if id < 0 {
+                let definition = decoder.wire_type()?;
+                self.types.insert(-id, definition);
+                continue;
+            }
+
+            let Some(WireType::Struct(_)) = self.types.get(&id) else {
+                anyhow::bail!("Gob results hold values of unsupported type {}", id);
+            };
+            let Value::Struct(fields) = decoder.value(id, &self.types)? else {
+                unreachable!("structs decode to structs");
+            };
+            return vegeta_result(fields).into_result().map(Some);
+        }
+    }
+
+    /// Read the length of the next message, or None at the end of the stream
+    fn read_len(&mut self) -> Result<Option<usize>> {
+        let mut first = [0];
+        match self.reader.read_exact(&mut first) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut buf = [0; 9];
+        buf[0] = first[0];
+        let count = if first[0] < 0x80 { 0 } else { first[0].wrapping_neg() as usize };
+        if count > 8 {
+            anyhow::bail!("Corrupt gob results");
+        }
+        self.reader.read_exact(&mut buf[1..=count]).context("Truncated gob results")?;
+        let len = Decoder { buf: &buf[..=count] }.uint()?;
+        if len > MAX_MESSAGE {
+            anyhow::bail!("Corrupt gob results: message of {} bytes", len);
+        }
+        Ok(Some(len as usize))
+    }
+}
+
+impl<R: Read> Iterator for GobReader<R> {
+    type Item = Result<AttackResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+/// A Vegeta result from the fields of a decoded `vegeta.Result`
+fn vegeta_result(mut fields: BTreeMap<String, Value>) -> VegetaResult {
+    let mut string = |name: &str| match fields.remove(name) {
+        Some(Value::Bytes(bytes)) => String::from_utf8_lossy(&bytes).into_owned(),
+        _ => String::new(),
+    };
+    let (attack, error, method, url) = (string("Attack"), string("Error"), string("Method"), string("URL"));
+    let uint = |value: Option<&Value>| match value {
+        Some(Value::Uint(n)) => *n,
+        _ => 0,
+    };
+
+    let timestamp = match fields.get("Timestamp") {
+        Some(Value::Bytes(bytes)) => unmarshal_time(bytes),
+        _ => None,
+    };
+    let latency = match fields.get("Latency") {
+        Some(Value::Int(nanos)) => *nanos,
+        _ => 0,
+    };
+    let headers = match fields.remove("Headers") {
+        Some(Value::Map(entries)) => entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                (Value::Bytes(name), Value::List(values)) => Some((
+                    String::from_utf8_lossy(&name).into_owned(),
+                    values
+                        .into_iter()
+                        .filter_map(|value| match value {
+                            Value::Bytes(value) => Some(String::from_utf8_lossy(&value).into_owned()),
+                            _ => None,
+                        })
+                        .collect(),
+                )),
+                _ => None,
+            })
+            .collect(),
+        _ => BTreeMap::new(),
+    };
+
+    VegetaResult {
+        attack,
+        seq: uint(fields.get("Seq")),
+        code: uint(fields.get("Code")) as u16,
+        timestamp,
+        latency,
+        bytes_out: uint(fields.get("BytesOut")),
+        bytes_in: uint(fields.get("BytesIn")),
+        error,
+        method,
+        url,
+        headers: Some(headers),
+    }
+}
+
+/// A timestamp written by Go's `time.Time.GobEncode`
+fn unmarshal_time(buf: &[u8]) -> Option<chrono::DateTime<chrono::Utc>> {
+    // Version 1 ends with the zone offset in minutes, version 2 adds seconds
+    if !matches!((buf.first(), buf.len()), (Some(1), 15) | (Some(2), 16)) {
+        return None;
+    }
+    let sec = i64::from_be_bytes(buf[1..9].try_into().ok()?) - UNIX_TO_INTERNAL;
+    let nsec = u32::from_be_bytes(buf[9..13].try_into().ok()?);
+    chrono::DateTime::from_timestamp(sec, nsec)
+}
+
+/// Decodes the values of a message
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl Decoder<'_> {
+    fn uint(&mut self) -> Result<u64> {
+        let (&first, rest) = self.buf.split_first().context("Truncated gob message")?;
+        self.buf = rest;
+        if first < 0x80 {
+            return Ok(u64::from(first));
+        }
+        let count = first.wrapping_neg() as usize;
+        if count > 8 || count > self.buf.len() {
+            anyhow::bail!("Corrupt gob message");
+        }
+        let (bytes, rest) = self.buf.split_at(count);
+        self.buf = rest;
+        Ok(bytes.iter().fold(0, |value, byte| (value << 8) | u64::from(*byte)))
+    }
+
+    fn int(&mut self) -> Result<i64> {
+        let value = self.uint()?;
+        Ok(if value & 1 == 1 { !(value >> 1) as i64 } else { (value >> 1) as i64 })
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        let len = self.uint()?;
+        if len > self.buf.len() as u64 {
+            anyhow::bail!("Truncated gob message");
+        }
+        let (bytes, rest) = self.buf.split_at(len as usize);
+        self.buf = rest;
+        Ok(bytes.to_vec())
+    }
+
+    /// Decode the fields of a struct, calling `field` with the number of each field
+    fn fields(&mut self, mut field: impl FnMut(&mut Self, i64) -> Result<()>) -> Result<()> {
+        let mut number = -1;
+        loop {
+            let delta = self.uint()?;
+            if delta == 0 {
+                return Ok(());
+            }
+            number += i64::try_from(delta).context("Corrupt gob message")?;
+            field(self, number)?;
+        }
+    }
+
+    /// Skip a `CommonType`, keeping only what the definition around it says
+    fn common_type(&mut self) -> Result<()> {
+        self.fields(|decoder, number| match number {
+            0 => decoder.bytes().map(drop),
+            1 => decoder.int().map(drop),
+            _ => anyhow::bail!("Unsupported gob type definition"),
+        })
+    }
+
+    /// Decode a `wireType`, the definition of a user-defined type
+    fn wire_type(&mut self) -> Result<WireType> {
+        let mut definition = None;
+        self.fields(|decoder, kind| {
+            let (mut elem, mut key, mut struct_fields) = (0, 0, Vec::new());
+            decoder.fields(|decoder, number| match (kind, number) {
+                (_, 0) => decoder.common_type(),
+                // The element of arrays, slices and maps; an array's length is ignored
+                (0 | 1, 1) | (3, 2) => decoder.int().map(|id| elem = id),
+                (0, 2) => decoder.int().map(drop),
+                (3, 1) => decoder.int().map(|id| key = id),
+                (2, 1) => {
+                    for _ in 0..decoder.uint()? {
+                        let (mut name, mut id) = (String::new(), 0);
+                        decoder.fields(|decoder, number| match number {
+                            0 => decoder.bytes().map(|bytes| name = String::from_utf8_lossy(&bytes).into_owned()),
+                            1 => decoder.int().map(|field_id| id = field_id),
+                            _ => anyhow::bail!("Unsupported gob type definition"),
+                        })?;
+                        struct_fields.push((name, id));
+                    }
+                    Ok(())
+                }
+                _ => anyhow::bail!("Unsupported gob type definition"),
+            })?;
+            definition = Some(match kind {
+                0 | 1 => WireType::Slice(elem),
+                2 => WireType::Struct(std::mem::take(&mut struct_fields)),
+                3 => WireType::Map(key, elem),
+                4..=6 => WireType::Encoder,
+                _ => anyhow::bail!("Unsupported gob type definition"),
+            });
+            Ok(())
+        })?;
+        definition.context("Empty gob type definition")
+    }
+
+    /// Decode a value of the given type
+    fn value(&mut self, id: i64, types: &HashMap<i64, WireType>) -> Result<Value> {
+        Ok(match id {
+            // No field of a Vegeta result is a float, so floats are kept as sent
+            BOOL | UINT | FLOAT => Value::Uint(self.uint()?),
+            INT => Value::Int(self.int()?),
+            BYTES | STRING => Value::Bytes(self.bytes()?),
+            _ => match types.get(&id).context(format!("Gob value of undefined type {}", id))? {
+                WireType::Struct(struct_fields) => {
+                    let mut values = BTreeMap::new();
+                    self.fields(|decoder, number| {
+                        let (name, field_id) = struct_fields.get(number as usize).context("Corrupt gob message")?;
+                        values.insert(name.clone(), decoder.value(*field_id, types)?);
+                        Ok(())
+                    })?;
+                    Value::Struct(values)
+                }
+                WireType::Map(key, elem) => {
+                    let mut entries = Vec::new();
+                    for _ in 0..self.uint()? {
+                        entries.push((self.value(*key, types)?, self.value(*elem, types)?));
+                    }
+                    Value::Map(entries)
+                }
+                WireType::Slice(elem) => {
+                    let mut values = Vec::new();
+                    for _ in 0..self.uint()? {
+                        values.push(self.value(*elem, types)?);
+                    }
+                    Value::List(values)
+                }
+                WireType::Encoder => Value::Bytes(self.bytes()?),
+            },
+        })
+    }
+}
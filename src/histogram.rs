@@ -0,0 +1,212 @@
+// Latency aggregation on an HDR histogram, which keeps latencies to a fixed number of
+// significant digits instead of every value, so percentiles far into the tail stay
+// accurate while memory stays bounded however many results are read. Histograms of
+// separate runs can be merged into one.
+
+use anyhow::{Context, Result};
+use hdrhistogram::Histogram;
+use std::time::Duration;
+
+/// Significant digits latencies are kept to unless configured otherwise
+pub const DEFAULT_PRECISION: u8 = 3;
+
+/// How the latency at a percentile is picked from latencies in ascending order
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PercentileMethod {
+    /// The latency at rank ⌈p·n⌉, at or below which the percentile of latencies lie, as
+    /// HdrHistogram, vegeta and wrk2 report it
+    #[default]
+    NearestRank,
+    /// Interpolated linearly between the latencies at ranks ⌊p·(n-1)⌋ and the next, as
+    /// numpy and spreadsheets' PERCENTILE.INC report it
+    Linear,
+}
+
+impl std::str::FromStr for PercentileMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "nearest-rank" => Ok(PercentileMethod::NearestRank),
+            "linear" => Ok(PercentileMethod::Linear),
+            _ => anyhow::bail!("Invalid percentile method: {}. Expected nearest-rank or linear", s),
+        }
+    }
+}
+
+impl PercentileMethod {
+    /// Latency at a percentile given as a fraction of `len` latencies, given the latency
+    /// at each zero-based rank in ascending order
+    pub fn select(self, p: f64, len: u64, at_rank: impl Fn(u64) -> Duration) -> Duration {
+        if len == 0 {
+            return Duration::ZERO;
+        }
+        let p = p.clamp(0.0, 1.0);
+        match self {
+            PercentileMethod::NearestRank => at_rank(((p * len as f64).ceil() as u64).clamp(1, len) - 1),
+            PercentileMethod::Linear => {
+                let rank = p * (len - 1) as f64;
+                let lower = (rank.floor() as u64).min(len - 1);
+                let fraction = rank - lower as f64;
+                let low = at_rank(lower);
+                if fraction <= 0.0 || lower == len - 1 {
+                    return low;
+                }
+                let high = at_rank(lower + 1);
+                low + high.saturating_sub(low).mul_f64(fraction)
+            }
+        }
+    }
+
+    /// Latency at a percentile given as a fraction of latencies sorted in ascending order
+    pub fn of_sorted(self, sorted: &[Duration], p: f64) -> Duration {
+        self.select(p, sorted.len() as u64, |rank| sorted[rank as usize])
+    }
+}
+
+/// Latencies recorded in nanoseconds, with the exact minimum, maximum and sum kept
+/// alongside the histogram
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    histogram: Histogram<u64>,
+    min: Duration,
+    max: Duration,
+    sum: Duration,
+    method: PercentileMethod,
+}
+
+impl LatencyHistogram {
+    /// Create a histogram keeping latencies to `precision` significant digits, from 1 to 5
+    pub fn new(precision: u8) -> Result<Self> {
+        if !(1..=5).contains(&precision) {
+            anyhow::bail!("Invalid histogram precision: {}. Expected 1 to 5 significant digits", precision);
+        }
+
+        // Auto-resizing, so any latency can be recorded
+        let histogram = Histogram::new(precision).context("Failed to create latency histogram")?;
+
+        Ok(Self {
+            histogram,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+            sum: Duration::ZERO,
+            method: PercentileMethod::default(),
+        })
+    }
+
+    /// Pick the latencies at percentiles with the given method
+    pub fn with_method(mut self, method: PercentileMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Create a histogram of the given latencies
+    pub fn from_latencies(latencies: impl IntoIterator<Item = Duration>, precision: u8) -> Result<Self> {
+        let mut histogram = Self::new(precision)?;
+        for latency in latencies {
+            histogram.record(latency);
+        }
+        Ok(histogram)
+    }
+
+    /// Record a latency
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        if self.histogram.record(nanos).is_err() {
+            // Beyond what the histogram can grow to track
+            self.histogram.saturating_record(nanos);
+        }
+        self.min = self.min.min(latency);
+        self.max = self.max.max(latency);
+        self.sum = self.sum.saturating_add(latency);
+    }
+
+    /// Add the latencies of another histogram, e.g. of another run
+    pub fn merge(&mut self, other: &LatencyHistogram) -> Result<()> {
+        self.histogram.add(&other.histogram).context("Failed to merge latency histograms")?;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum = self.sum.saturating_add(other.sum);
+        Ok(())
+    }
+
+    /// Number of latencies recorded
+    pub fn len(&self) -> u64 {
+        self.histogram.len()
+    }
+
+    /// Whether no latency was recorded
+    pub fn is_empty(&self) -> bool {
+        self.histogram.is_empty()
+    }
+
+    /// Lowest latency recorded, exactly
+    pub fn min(&self) -> Duration {
+        if self.is_empty() {
+            Duration::ZERO
+        } else {
+            self.min
+        }
+    }
+
+    /// Highest latency recorded, exactly
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Mean latency, exactly
+    pub fn mean(&self) -> Duration {
+        if self.is_empty() {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(self.sum.as_secs_f64() / self.len() as f64)
+    }
+
+    /// Standard deviation of the latencies, to the histogram's precision
+    pub fn stddev(&self) -> Duration {
+        if self.is_empty() {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos(self.histogram.stdev() as u64)
+    }
+
+    /// Latency at a percentile given as a fraction, e.g. 0.999 for p99.9, picked with the
+    /// histogram's method
+    ///
+    /// Within the histogram's precision of the exact value, and never outside the
+    /// recorded minimum and maximum.
+    pub fn percentile(&self, p: f64) -> Duration {
+        self.method.select(p, self.len(), |rank| self.at_rank(rank)).clamp(self.min(), self.max)
+    }
+
+    /// Latency at a zero-based rank in ascending order: exact for the lowest and highest,
+    /// otherwise the highest latency equivalent to the recorded one at the histogram's precision
+    fn at_rank(&self, rank: u64) -> Duration {
+        if rank == 0 {
+            return self.min();
+        }
+        let mut count = 0;
+        for value in self.histogram.iter_recorded() {
+            count += value.count_at_value();
+            if count > rank {
+                return Duration::from_nanos(value.value_iterated_to()).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Percentile spectrum as HdrHistogram reports it: the latency, percentile (as a
+    /// fraction) and count of latencies up to it, at levels getting closer together
+    /// towards 100% with `ticks` levels per halving of the distance to 100%
+    pub fn spectrum(&self, ticks: u32) -> Vec<(Duration, f64, u64)> {
+        let mut total = 0;
+        self.histogram
+            .iter_quantiles(ticks)
+            .map(|level| {
+                total += level.count_since_last_iteration();
+                let value = Duration::from_nanos(level.value_iterated_to());
+                (value.min(self.max), level.quantile_iterated_to(), total)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,204 @@
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use reqwest::header::HeaderMap;
+use std::fmt;
+use std::sync::Arc;
+use url::Url;
+
+use crate::models::{Header, Result as AttackResult, Target};
+
+/// A request about to be sent, which hooks may modify
+#[derive(Debug, Clone)]
+pub struct RequestSpec {
+    /// The HTTP method (GET, POST, etc.)
+    pub method: String,
+    /// The URL to request
+    pub url: Url,
+    /// HTTP headers to send, in order
+    pub headers: Vec<Header>,
+    /// Request body
+    pub body: Option<Vec<u8>>,
+}
+
+impl RequestSpec {
+    /// Build a request from a target and the global headers
+    pub fn new(target: &Target, headers: &[Header]) -> Self {
+        Self {
+            method: target.method.clone(),
+            url: target.url.clone(),
+            headers: target.headers.iter().chain(headers).cloned().collect(),
+            body: target.body.clone(),
+        }
+    }
+
+    /// Append a header
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        self.headers.push(Header {
+            name: name.to_string(),
+            value: value.to_string(),
+        });
+    }
+}
+
+/// Hook invoked on every request right before it is sent
+#[derive(Clone)]
+pub struct BeforeSend(Arc<dyn Fn(&mut RequestSpec) + Send + Sync>);
+
+impl BeforeSend {
+    /// Wrap a closure as a before-send hook
+    pub fn new<F>(hook: F) -> Self
+    where
+        F: Fn(&mut RequestSpec) + Send + Sync + 'static,
+    {
+        Self(Arc::new(hook))
+    }
+
+    /// Run the hook against a request
+    pub fn call(&self, request: &mut RequestSpec) {
+        (self.0)(request)
+    }
+}
+
+impl fmt::Debug for BeforeSend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BeforeSend(..)")
+    }
+}
+
+/// A received response, as seen by response hooks
+#[derive(Debug, Clone)]
+pub struct ResponseData {
+    /// HTTP status code
+    pub status_code: u16,
+    /// Response headers, in order
+    pub headers: Vec<Header>,
+    /// Response body, truncated to the configured max body size
+    pub body: Bytes,
+}
+
+impl ResponseData {
+    /// Build the hook view of a response
+    pub fn new(status_code: u16, headers: &HeaderMap, body: Bytes) -> Self {
+        Self {
+            status_code,
+            headers: headers
+                .iter()
+                .map(|(name, value)| Header {
+                    name: name.to_string(),
+                    value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                })
+                .collect(),
+            body,
+        }
+    }
+
+    /// Get the first value of a header, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value.as_str())
+    }
+}
+
+type ResponseHookFn = dyn Fn(&ResponseData, &mut AttackResult) + Send + Sync;
+
+/// Hook invoked on every response, which may attach custom values to the result
+#[derive(Clone)]
+pub struct OnResponse(Arc<ResponseHookFn>);
+
+impl OnResponse {
+    /// Wrap a closure as a response hook
+    pub fn new<F>(hook: F) -> Self
+    where
+        F: Fn(&ResponseData, &mut AttackResult) + Send + Sync + 'static,
+    {
+        Self(Arc::new(hook))
+    }
+
+    /// Run the hook against a response and its result
+    pub fn call(&self, response: &ResponseData, result: &mut AttackResult) {
+        (self.0)(response, result)
+    }
+}
+
+impl fmt::Debug for OnResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OnResponse(..)")
+    }
+}
+
+/// Hook invoked on every result as it arrives, before the attack completes
+#[derive(Clone)]
+pub struct OnResult(Arc<dyn Fn(&AttackResult) + Send + Sync>);
+
+impl OnResult {
+    /// Wrap a closure as a result hook
+    pub fn new<F>(hook: F) -> Self
+    where
+        F: Fn(&AttackResult) + Send + Sync + 'static,
+    {
+        Self(Arc::new(hook))
+    }
+
+    /// Run the hook against a result
+    pub fn call(&self, result: &AttackResult) {
+        (self.0)(result)
+    }
+}
+
+impl fmt::Debug for OnResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OnResult(..)")
+    }
+}
+
+/// Middleware applied to every request made by an attack
+///
+/// Middleware can modify requests before they are sent (auth refresh, header
+/// injection) and observe the results afterwards (custom tracing). Returning an
+/// error from `before_request` fails the request without sending it.
+pub trait RequestMiddleware: Send + Sync {
+    /// Called before the request is sent
+    fn before_request<'a>(&'a self, request: &'a mut RequestSpec) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Called after the request completes
+    fn after_response<'a>(&'a self, _result: &'a AttackResult) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// An ordered chain of request middleware
+///
+/// `before_request` runs in the order middleware was added and `after_response`
+/// runs in reverse order.
+#[derive(Clone, Default)]
+pub struct MiddlewareChain(Vec<Arc<dyn RequestMiddleware>>);
+
+impl MiddlewareChain {
+    /// Append a middleware to the chain
+    pub fn push(&mut self, middleware: Arc<dyn RequestMiddleware>) {
+        self.0.push(middleware);
+    }
+
+    /// Run every middleware's `before_request`, stopping at the first error
+    pub async fn before_request(&self, request: &mut RequestSpec) -> anyhow::Result<()> {
+        for middleware in &self.0 {
+            middleware.before_request(request).await?;
+        }
+        Ok(())
+    }
+
+    /// Run every middleware's `after_response`
+    pub async fn after_response(&self, result: &AttackResult) {
+        for middleware in self.0.iter().rev() {
+            middleware.after_response(result).await;
+        }
+    }
+}
+
+impl fmt::Debug for MiddlewareChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MiddlewareChain({} middleware)", self.0.len())
+    }
+}
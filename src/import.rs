@@ -0,0 +1,535 @@
+// Readers of results in formats other than culverin's own: CSV written by `encode`,
+// and results written by other load testing tools, turning them into attack results.
+//
+// These formats hold fewer fields than the result model, so the fields they lack are
+// left empty, as in a result of a request that was never sent.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Read};
+use std::time::Duration;
+use url::Url;
+
+use crate::models::{Result as AttackResult, Target};
+
+/// An empty result of a request to a URL
+pub(crate) fn empty_result(method: &str, url: &str, timestamp: DateTime<Utc>) -> Result<AttackResult> {
+    let target = Target {
+        method: method.to_string(),
+        url: Url::parse(url).context(format!("Invalid URL: {}", url))?,
+        headers: Vec::new(),
+        body: None,
+        expect: None,
+        slo: None,
+        extract: Vec::new(),
+        when: None,
+        checks: Vec::new(),
+        tags: BTreeMap::new(),
+    };
+
+    Ok(AttackResult {
+        timestamp,
+        latency: Duration::ZERO,
+        ttfb: None,
+        status_code: 0,
+        error: None,
+        target,
+        content_type: None,
+        bytes_in: 0,
+        decoded_bytes_in: None,
+        bytes_out: 0,
+        timed_out: false,
+        request_id: None,
+        trace_id: None,
+        fuzz_seed: None,
+        conditional: false,
+        check_failed: false,
+        golden_diverged: false,
+        server_timing: Vec::new(),
+        scenario: None,
+        variables: BTreeMap::new(),
+        checks: BTreeMap::new(),
+        custom: BTreeMap::new(),
+        attack: None,
+        run_id: None,
+        metadata: BTreeMap::new(),
+        seq: 0,
+        worker: 0,
+    })
+}
+
+/// Reads results from CSV, finding the columns by the header row so columns can be
+/// reordered or left out
+pub(crate) struct CsvResults<R: Read> {
+    reader: csv::Reader<R>,
+    format: CsvFormat,
+    /// Index of each column in a record
+    columns: BTreeMap<String, usize>,
+    record: csv::StringRecord,
+}
+
+/// Which tool wrote CSV results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CsvFormat {
+    /// Written by `encode --to=csv`
+    Culverin,
+    /// A JMeter `.jtl` file
+    Jtl,
+}
+
+/// Columns a latency can be read from, as written by `encode --to=csv --columns`
+const LATENCY_COLUMNS: [&str; 4] = ["latency", "latency_ms", "latency_us", "latency_ns"];
+
+impl CsvFormat {
+    /// Columns every record needs, in lowercase
+    fn required(self) -> &'static [&'static str] {
+        match self {
+            Self::Culverin => &["timestamp", "latency", "method", "url"],
+            Self::Jtl => &["timestamp", "elapsed", "url"],
+        }
+    }
+}
+
+impl<R: Read> CsvResults<R> {
+    pub fn new(reader: R, format: CsvFormat) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+        let columns: BTreeMap<String, usize> = reader
+            .headers()
+            .context("Invalid CSV results")?
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name.trim().to_ascii_lowercase(), index))
+            .collect();
+        let has = |column: &str| match column {
+            "latency" => LATENCY_COLUMNS.iter().any(|latency| columns.contains_key(*latency)),
+            column => columns.contains_key(column),
+        };
+        if let Some(missing) = format.required().iter().find(|column| !has(column)) {
+            if format == CsvFormat::Jtl && *missing == "url" {
+                anyhow::bail!("Invalid JTL results: missing column URL. Save them with jmeter.save.saveservice.url=true");
+            }
+            anyhow::bail!("Invalid CSV results: missing column {}", missing);
+        }
+
+        Ok(Self {
+            reader,
+            format,
+            columns,
+            record: csv::StringRecord::new(),
+        })
+    }
+
+    /// Value of a column of the current record, or an empty string if it's missing
+    fn get(&self, column: &str) -> &str {
+        self.columns
+            .get(column)
+            .and_then(|index| self.record.get(*index))
+            .map_or("", str::trim)
+    }
+
+    /// Parse the current record, or None if it isn't the result of an HTTP request
+    fn parse(&self) -> Result<Option<AttackResult>> {
+        match self.format {
+            CsvFormat::Culverin => self.parse_culverin().map(Some),
+            CsvFormat::Jtl => JtlSample {
+                timestamp: self.get("timestamp"),
+                elapsed: self.get("elapsed"),
+                latency: self.get("latency"),
+                label: self.get("label"),
+                code: self.get("responsecode"),
+                message: self.get("responsemessage"),
+                success: self.get("success"),
+                failure: self.get("failuremessage"),
+                bytes: self.get("bytes"),
+                sent_bytes: self.get("sentbytes"),
+                url: self.get("url"),
+            }
+            .into_result(),
+        }
+    }
+
+    fn parse_culverin(&self) -> Result<AttackResult> {
+        let timestamp = DateTime::parse_from_rfc3339(self.get("timestamp"))
+            .context(format!("Invalid timestamp: {}", self.get("timestamp")))?
+            .with_timezone(&Utc);
+        let mut result = empty_result(self.get("method"), self.get("url"), timestamp)?;
+
+        // Latencies are formatted, unless `encode --columns` picked a column of a number of units
+        let latency = LATENCY_COLUMNS.into_iter().find(|column| self.columns.contains_key(*column));
+        result.latency = match latency {
+            Some("latency") | None => parse_latency(self.get("latency"))?,
+            Some(column) => {
+                let value: f64 = self.get(column).parse().context(format!("Invalid {}: {}", column, self.get(column)))?;
+                let nanos = match column {
+                    "latency_ms" => value * 1_000_000.0,
+                    "latency_us" => value * 1000.0,
+                    _ => value,
+                };
+                Duration::from_nanos(nanos.max(0.0).round() as u64)
+            }
+        };
+        let number = |column: &str| -> Result<usize> {
+            let value = self.get(column);
+            if value.is_empty() {
+                return Ok(0);
+            }
+            value.parse().context(format!("Invalid {}: {}", column, value))
+        };
+        let status = if self.columns.contains_key("status_code") { "status_code" } else { "status" };
+        result.status_code = number(status)?
+            .try_into()
+            .context(format!("Invalid {}: {}", status, self.get(status)))?;
+        result.bytes_in = number("bytes_in")?;
+        result.bytes_out = number("bytes_out")?;
+        let ttfb = self.get("ttfb");
+        result.ttfb = (!ttfb.is_empty()).then(|| parse_latency(ttfb)).transpose()?;
+        let error = self.get("error");
+        result.error = (!error.is_empty()).then(|| error.to_string());
+        // The timed_out column is only written if picked, otherwise the error tells
+        result.timed_out = match self.columns.contains_key("timed_out") {
+            true => self.get("timed_out") == "true",
+            false => error.contains("timed out"),
+        };
+        Ok(result)
+    }
+}
+
+impl<R: Read> Iterator for CsvResults<R> {
+    type Item = Result<AttackResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            return match self.reader.read_record(&mut self.record) {
+                Ok(true) => match self.parse().context("Invalid CSV result") {
+                    Ok(Some(result)) => Some(Ok(result)),
+                    Ok(None) => continue,
+                    Err(e) => Some(Err(e)),
+                },
+                Ok(false) => None,
+                // Pass read errors on as they are, so reading stops instead of skipping the record
+                Err(e) if e.is_io_error() => match e.into_kind() {
+                    csv::ErrorKind::Io(e) => Some(Err(e.into())),
+                    _ => None,
+                },
+                Err(e) => Some(Err(anyhow::Error::new(e).context("Invalid CSV result"))),
+            };
+        }
+    }
+}
+
+/// Parse a latency as written by `encode --to=csv`, like "850µs", "12.34ms", "1.50s" or
+/// "2m3.50s", or a plain number of nanoseconds
+pub(crate) fn parse_latency(latency: &str) -> Result<Duration> {
+    let invalid = || format!("Invalid latency: {}", latency);
+
+    // Minutes are followed by seconds, unlike the m of ms
+    let (minutes, rest) = match latency.split_once('m') {
+        Some((minutes, rest)) if rest != "s" && !rest.is_empty() => (minutes.parse::<u64>().context(invalid())?, rest),
+        _ => (0, latency),
+    };
+
+    let (value, scale) = if let Some(value) = rest.strip_suffix("µs").or_else(|| rest.strip_suffix("us")) {
+        (value, 1e-6)
+    } else if let Some(value) = rest.strip_suffix("ns") {
+        (value, 1e-9)
+    } else if let Some(value) = rest.strip_suffix("ms") {
+        (value, 1e-3)
+    } else if let Some(value) = rest.strip_suffix('s') {
+        (value, 1.0)
+    } else {
+        (rest, 1e-9)
+    };
+    let value: f64 = value.trim().parse().context(invalid())?;
+    let seconds = Duration::try_from_secs_f64(value * scale).context(invalid())?;
+    let minutes = minutes.checked_mul(60).map(Duration::from_secs).context(invalid())?;
+    seconds.checked_add(minutes).context(invalid())
+}
+
+/// A result as Vegeta writes it, either as JSON or decoded from gob
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct VegetaResult {
+    pub attack: String,
+    pub seq: u64,
+    pub code: u16,
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Latency in nanoseconds
+    pub latency: i64,
+    pub bytes_out: u64,
+    pub bytes_in: u64,
+    pub error: String,
+    pub method: String,
+    pub url: String,
+    pub headers: Option<BTreeMap<String, Vec<String>>>,
+}
+
+impl VegetaResult {
+    pub fn into_result(self) -> Result<AttackResult> {
+        if self.url.is_empty() {
+            anyhow::bail!("Vegeta result without a URL; results of Vegeta before 12.8 can't be read");
+        }
+        let timestamp = self.timestamp.context("Vegeta result without a timestamp")?;
+        let mut result = empty_result(&self.method, &self.url, timestamp)?;
+
+        result.latency = Duration::from_nanos(self.latency.max(0) as u64);
+        result.status_code = self.code;
+        result.bytes_in = self.bytes_in as usize;
+        result.bytes_out = self.bytes_out as usize;
+        // Vegeta doesn't record timeouts apart from other errors, only in the message
+        result.timed_out = self.error.contains("Client.Timeout") || self.error.contains("deadline exceeded");
+        result.error = (!self.error.is_empty()).then_some(self.error);
+        result.content_type = self.headers.as_ref().and_then(|headers| {
+            headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+                .and_then(|(_, values)| values.first().cloned())
+        });
+        result.attack = (!self.attack.is_empty()).then_some(self.attack);
+        result.seq = self.seq;
+        Ok(result)
+    }
+}
+
+/// Whether a line is a result written by `vegeta encode --to=json`, rather than one of
+/// culverin's JSON results
+pub(crate) fn is_vegeta_json(line: &[u8]) -> bool {
+    serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(line)
+        .is_ok_and(|object| object.contains_key("code") && !object.contains_key("status_code"))
+}
+
+/// The fields of a JMeter sample, named as in JTL CSV headers, that results are made of
+struct JtlSample<'a> {
+    /// Milliseconds since the Unix epoch
+    timestamp: &'a str,
+    /// Milliseconds
+    elapsed: &'a str,
+    /// Milliseconds to the first byte of the response, JMeter's latency
+    latency: &'a str,
+    label: &'a str,
+    code: &'a str,
+    message: &'a str,
+    success: &'a str,
+    failure: &'a str,
+    bytes: &'a str,
+    sent_bytes: &'a str,
+    url: &'a str,
+}
+
+impl JtlSample<'_> {
+    /// The result of the sample, or None if it isn't the result of an HTTP request, like
+    /// a transaction controller's
+    fn into_result(self) -> Result<Option<AttackResult>> {
+        if self.url.is_empty() || self.url == "null" {
+            return Ok(None);
+        }
+
+        let millis: i64 = self.timestamp.parse().context(format!("Invalid timeStamp: {}", self.timestamp))?;
+        let timestamp = DateTime::from_timestamp_millis(millis).context(format!("Invalid timeStamp: {}", self.timestamp))?;
+        let method = match self.label.split_once(' ') {
+            Some((method, _)) if !method.is_empty() && method.bytes().all(|b| b.is_ascii_uppercase()) => method,
+            _ => "GET",
+        };
+        let mut result = empty_result(method, self.url, timestamp)?;
+
+        let elapsed: u64 = self.elapsed.parse().context(format!("Invalid elapsed: {}", self.elapsed))?;
+        result.latency = Duration::from_millis(elapsed);
+        result.bytes_in = self.bytes.parse().unwrap_or_default();
+        result.bytes_out = self.sent_bytes.parse().unwrap_or_default();
+        if !self.label.is_empty() {
+            result.custom.insert("label".to_string(), self.label.into());
+        }
+
+        // Requests that got no response have a message in place of the status code,
+        // like "Non HTTP response code: java.net.SocketTimeoutException"
+        match self.code.parse() {
+            Ok(code) => result.status_code = code,
+            Err(_) => {
+                result.timed_out = self.code.contains("Timeout");
+                result.error = Some(if self.message.is_empty() { self.code } else { self.message }.to_string());
+            }
+        }
+        if result.status_code > 0 {
+            result.ttfb = self.latency.parse().ok().map(Duration::from_millis);
+        }
+
+        // JMeter fails samples with error status codes, which the status code already
+        // tells, and samples that fail an assertion, which are check failures
+        let success = (200..400).contains(&result.status_code);
+        if self.success.eq_ignore_ascii_case("false") && success {
+            let message = [self.failure, self.message].into_iter().find(|m| !m.is_empty()).unwrap_or("Sample failed");
+            result.error = Some(message.to_string());
+            result.check_failed = true;
+        }
+        Ok(Some(result))
+    }
+}
+
+/// Reads results from a JMeter `.jtl` file in the XML format
+///
+/// Only top-level samples are read, as the samples nested in them, like redirects and
+/// embedded resources, are already part of their parent's elapsed time.
+pub(crate) struct JtlXmlResults<R: BufRead> {
+    reader: quick_xml::Reader<R>,
+    buf: Vec<u8>,
+    /// Depth of the current element, where samples are at depth 1
+    depth: usize,
+    /// Attributes of the sample being read, by their JTL CSV names
+    attributes: BTreeMap<&'static str, String>,
+    /// URL and failure message of the sample being read
+    url: String,
+    failure: String,
+    /// Element whose text is being read
+    text: Option<&'static str>,
+    /// Whether the end of the file or invalid XML was reached
+    done: bool,
+}
+
+/// XML attributes of a sample and the JTL CSV names of their values
+const JTL_ATTRIBUTES: [(&[u8], &str); 9] = [
+    (b"ts", "timestamp"),
+    (b"t", "elapsed"),
+    (b"lt", "latency"),
+    (b"lb", "label"),
+    (b"rc", "code"),
+    (b"rm", "message"),
+    (b"s", "success"),
+    (b"by", "bytes"),
+    (b"sby", "sent_bytes"),
+];
+
+impl<R: BufRead> JtlXmlResults<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: quick_xml::Reader::from_reader(reader),
+            buf: Vec::new(),
+            depth: 0,
+            attributes: BTreeMap::new(),
+            url: String::new(),
+            failure: String::new(),
+            text: None,
+            done: false,
+        }
+    }
+
+    /// Read the next result, or None at the end of the file
+    ///
+    /// A sample that can't be turned into a result is yielded as an error and reading
+    /// goes on, while invalid XML ends the results.
+    pub fn read(&mut self) -> Result<Option<AttackResult>> {
+        if self.done {
+            return Ok(None);
+        }
+        match self.next_sample() {
+            Ok(Some(result)) => result.map(Some),
+            Ok(None) => {
+                self.done = true;
+                Ok(None)
+            }
+            Err(e) => {
+                self.done = true;
+                Err(e)
+            }
+        }
+    }
+
+    /// Read up to the end of the next top-level sample
+    fn next_sample(&mut self) -> Result<Option<Result<AttackResult>>> {
+        use quick_xml::events::Event;
+
+        loop {
+            self.buf.clear();
+            let event = self.reader.read_event_into(&mut self.buf).context("Invalid JTL results")?;
+            let empty = matches!(event, Event::Empty(_));
+            match event {
+                Event::Start(element) | Event::Empty(element) => {
+                    let name = element.local_name();
+                    let name = name.as_ref();
+                    let sample = matches!(name, b"httpSample" | b"sample");
+                    if self.depth == 1 && sample {
+                        self.attributes.clear();
+                        self.url.clear();
+                        self.failure.clear();
+                        for attribute in element.attributes() {
+                            let attribute = attribute.context("Invalid JTL results")?;
+                            if let Some((_, field)) = JTL_ATTRIBUTES.iter().find(|(key, _)| *key == attribute.key.as_ref()) {
+                                self.attributes.insert(field, attribute.unescape_value()?.into_owned());
+                            }
+                        }
+                    } else if self.depth == 2 && name == b"java.net.URL" {
+                        self.text = Some("url");
+                    } else if self.depth >= 2 && name == b"failureMessage" && self.failure.is_empty() {
+                        self.text = Some("failure");
+                    }
+
+                    if !empty {
+                        self.depth += 1;
+                    } else if self.depth == 1 && sample {
+                        if let Some(result) = self.sample().transpose() {
+                            return Ok(Some(result));
+                        }
+                    }
+                }
+                Event::Text(text) => {
+                    let text = text.xml_content()?.into_owned();
+                    self.push_text(&text);
+                }
+                Event::CData(text) => {
+                    let text = text.decode()?.into_owned();
+                    self.push_text(&text);
+                }
+                Event::GeneralRef(reference) => {
+                    let resolved = match reference.resolve_char_ref()? {
+                        Some(c) => c.to_string(),
+                        None => {
+                            let name = reference.decode()?;
+                            quick_xml::escape::resolve_xml_entity(&name).unwrap_or_default().to_string()
+                        }
+                    };
+                    self.push_text(&resolved);
+                }
+                Event::End(_) => {
+                    self.depth = self.depth.saturating_sub(1);
+                    self.text = None;
+                    if self.depth == 1 {
+                        if let Some(result) = self.sample().transpose() {
+                            return Ok(Some(result));
+                        }
+                    }
+                }
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        match self.text {
+            Some("url") => self.url.push_str(text),
+            Some("failure") => self.failure.push_str(text),
+            _ => {}
+        }
+    }
+
+    /// The result of the sample that was just read
+    fn sample(&mut self) -> Result<Option<AttackResult>> {
+        let attribute = |name: &str| self.attributes.get(name).map_or("", |value| value.trim());
+        let url = self.url.trim();
+        JtlSample {
+            timestamp: attribute("timestamp"),
+            elapsed: attribute("elapsed"),
+            latency: attribute("latency"),
+            label: attribute("label"),
+            code: attribute("code"),
+            message: attribute("message"),
+            success: attribute("success"),
+            failure: self.failure.trim(),
+            bytes: attribute("bytes"),
+            sent_bytes: attribute("sent_bytes"),
+            url,
+        }
+        .into_result()
+        .context("Invalid JTL result")
+    }
+}
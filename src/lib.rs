@@ -23,6 +23,12 @@
 //!             }
 //!         ],
 //!         body: None,
+//!         expect: None,
+//!         slo: None,
+//!         extract: Vec::new(),
+//!         when: None,
+//!         checks: Vec::new(),
+//!         tags: Default::default(),
 //!     };
 //! 
 //!     // Run the attack
@@ -42,16 +48,46 @@
 //! ```
 
 mod attack;
+mod auth;
+mod cache;
 mod encode;
+mod fuzz;
+mod gob;
+mod histogram;
+mod hooks;
+mod import;
 mod models;
+mod notify;
+mod plan;
+mod plugin;
 mod plot;
+mod proto;
 mod report;
+mod results;
+mod sample;
+mod scenario;
+mod script;
+mod socket;
+mod sqlite;
+mod threshold;
+mod tsdb;
+mod upload;
 mod utils;
 
 // Re-export the main types for library users
-pub use models::{AttackConfig, Header, Metrics, Result as AttackResult, Target};
+pub use auth::{BearerToken, DigestAuth, OAuth2Config, ProxyAuth, TokenSource};
+pub use fuzz::FuzzStrategy;
+pub use histogram::{LatencyHistogram, PercentileMethod, DEFAULT_PRECISION};
+pub use hooks::{RequestMiddleware, RequestSpec, ResponseData};
+pub use plan::Plan;
+pub use plugin::Plugin;
+pub use results::{read_results, read_results_as, Encoding, InputEncoding, ResultReader, ResultWriter};
+pub use script::Script;
+pub use models::{AttackConfig, Check, Condition, Exhaustion, Expect, Extract, ExtractSource, Feeder, Golden, GoldenCompare, Header, Metrics, Result as AttackResult, Row, RunConfig, Scenario, ScenarioStep, Stage, Target};
 
 use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
@@ -64,22 +100,48 @@ pub struct AttackBuilder {
     timeout: Duration,
     workers: u64,
     max_workers: Option<u64>,
+    vus: Option<u64>,
+    stages: Vec<Stage>,
+    vu_shared_state: bool,
     keepalive: bool,
     connections: usize,
     max_connections: Option<usize>,
     http2: bool,
     name: Option<String>,
+    run_id: Option<String>,
+    metadata: BTreeMap<String, String>,
     max_body: i64,
     dns_ttl: Duration,
     laddr: String,
     lazy: bool,
     opentelemetry_addr: Option<String>,
     targets: Vec<Target>,
+    scenarios: Vec<Scenario>,
+    setup: Vec<Target>,
+    teardown: Vec<Target>,
     headers: Vec<Header>,
     insecure: bool,
+    proxy: Option<String>,
+    proxy_auth: Option<ProxyAuth>,
     h2c: bool,
     redirects: i32,
     http_timeout: Duration,
+    cookie_jar: bool,
+    cookies: Vec<String>,
+    basic_auth: Option<(String, String)>,
+    digest_auth: Option<DigestAuth>,
+    bearer_token: Option<(TokenSource, Duration)>,
+    oauth2: Option<OAuth2Config>,
+    before_send: Vec<hooks::BeforeSend>,
+    middleware: hooks::MiddlewareChain,
+    on_response: Vec<hooks::OnResponse>,
+    on_result: Vec<hooks::OnResult>,
+    accept_encoding: String,
+    decompress: bool,
+    request_id_header: Option<String>,
+    verify_request_id: bool,
+    conditional: bool,
+    fuzz: Vec<FuzzStrategy>,
 }
 
 impl Default for AttackBuilder {
@@ -90,22 +152,48 @@ impl Default for AttackBuilder {
             timeout: Duration::from_secs(30),
             workers: 10,
             max_workers: None,
+            vus: None,
+            stages: Vec::new(),
+            vu_shared_state: false,
             keepalive: true,
             connections: 10000,
             max_connections: None,
             http2: true,
             name: None,
+            run_id: None,
+            metadata: BTreeMap::new(),
             max_body: -1,
             dns_ttl: Duration::from_secs(0),
             laddr: "0.0.0.0".to_string(),
             lazy: false,
             opentelemetry_addr: None,
             targets: Vec::new(),
+            scenarios: Vec::new(),
+            setup: Vec::new(),
+            teardown: Vec::new(),
             headers: Vec::new(),
             insecure: false,
+            proxy: None,
+            proxy_auth: None,
             h2c: false,
             redirects: 10,
             http_timeout: Duration::from_secs(30),
+            cookie_jar: false,
+            cookies: Vec::new(),
+            basic_auth: None,
+            digest_auth: None,
+            bearer_token: None,
+            oauth2: None,
+            before_send: Vec::new(),
+            middleware: Default::default(),
+            on_response: Vec::new(),
+            on_result: Vec::new(),
+            accept_encoding: "gzip, deflate, br".to_string(),
+            decompress: true,
+            request_id_header: None,
+            verify_request_id: false,
+            conditional: false,
+            fuzz: Vec::new(),
         }
     }
 }
@@ -146,6 +234,37 @@ impl AttackBuilder {
         self
     }
 
+    /// Run the given number of virtual users instead of a fixed rate
+    ///
+    /// Each virtual user repeats flows back to back until the duration has elapsed,
+    /// with its own connections, cookie jar, and extracted variables. Running fails with
+    /// zero virtual users.
+    pub fn vus(mut self, vus: u64) -> Self {
+        self.vus = Some(vus);
+        self
+    }
+
+    /// Ramp virtual users over stages instead of running a fixed number
+    ///
+    /// Over each stage the number of active virtual users moves linearly from the previous
+    /// stage's target to its own. This sets the virtual users to the highest target and
+    /// the duration to the total of the stages.
+    pub fn stages(mut self, stages: Vec<Stage>) -> Self {
+        self.vus = stages.iter().map(|s| s.target).max();
+        self.duration = Some(stages.iter().map(|s| s.duration).sum());
+        self.stages = stages;
+        self
+    }
+
+    /// Set whether virtual users share one cookie jar and connection pool
+    ///
+    /// By default each virtual user has its own, so session-affinity load balancers see
+    /// separate clients.
+    pub fn vu_shared_state(mut self, shared: bool) -> Self {
+        self.vu_shared_state = shared;
+        self
+    }
+
     /// Set whether to keep connections alive
     pub fn keepalive(mut self, keepalive: bool) -> Self {
         self.keepalive = keepalive;
@@ -170,12 +289,24 @@ impl AttackBuilder {
         self
     }
 
-    /// Set the attack name
+    /// Set the attack name, attached to every result
     pub fn name(mut self, name: String) -> Self {
         self.name = Some(name);
         self
     }
 
+    /// Set the ID of the attack run attached to every result, instead of a random one
+    pub fn run_id(mut self, run_id: &str) -> Self {
+        self.run_id = Some(run_id.to_string());
+        self
+    }
+
+    /// Add a key/value describing the run, attached to every result
+    pub fn add_metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+
     /// Set the maximum number of bytes to capture from response bodies
     pub fn max_body(mut self, max_body: i64) -> Self {
         self.max_body = max_body;
@@ -236,6 +367,63 @@ impl AttackBuilder {
         self
     }
 
+    /// Set the scenarios for the attack, replacing the targets
+    ///
+    /// Each rate tick starts one flow of the next scenario (round-robin, or by weight), which
+    /// requests the scenario's steps in order and stops at the first failed step.
+    pub fn scenarios(mut self, scenarios: Vec<Scenario>) -> Self {
+        self.scenarios = scenarios;
+        self
+    }
+
+    /// Add a single scenario to the attack
+    pub fn add_scenario(mut self, scenario: Scenario) -> Self {
+        self.scenarios.push(scenario);
+        self
+    }
+
+    /// Set steps run once before the attack starts
+    ///
+    /// Their results aren't collected, and the variables they extract are available to
+    /// every request of the attack as `{{name}}`. The attack fails if a setup step fails.
+    pub fn setup(mut self, steps: Vec<Target>) -> Self {
+        self.setup = steps;
+        self
+    }
+
+    /// Set steps run once after the attack, e.g. to delete resources it created
+    ///
+    /// Teardown steps can use the variables extracted by the setup steps, and run even
+    /// if the attack fails. Their results aren't collected.
+    pub fn teardown(mut self, steps: Vec<Target>) -> Self {
+        self.teardown = steps;
+        self
+    }
+
+    /// Apply a plan read from a scenario file
+    ///
+    /// The plan's rate, duration, virtual users, and stages replace the builder's when set, its
+    /// headers are added to the global headers, and its targets, scenarios, setup, and
+    /// teardown steps replace the builder's.
+    pub fn plan(mut self, plan: Plan) -> Self {
+        if let Some(rate) = plan.rate.as_deref().and_then(|rate| utils::parse_rate(rate).ok()) {
+            self.rate = rate;
+        }
+        if let Some(duration) = plan.duration {
+            self.duration = Some(duration.into());
+        }
+        self.vus = plan.vus.or(self.vus);
+        if !plan.stages.is_empty() {
+            self = self.stages(plan.stages);
+        }
+        self.headers.extend(plan.headers);
+        self.targets = plan.targets;
+        self.scenarios = plan.scenarios;
+        self.setup = plan.setup;
+        self.teardown = plan.teardown;
+        self
+    }
+
     /// Set the global headers for the attack
     pub fn headers(mut self, headers: Vec<Header>) -> Self {
         self.headers = headers;
@@ -257,6 +445,18 @@ impl AttackBuilder {
         self
     }
 
+    /// Send all requests through the given proxy URL
+    pub fn proxy(mut self, url: &str) -> Self {
+        self.proxy = Some(url.to_string());
+        self
+    }
+
+    /// Authenticate to the proxy, or to the proxies from the environment if no proxy URL is set
+    pub fn proxy_auth(mut self, auth: ProxyAuth) -> Self {
+        self.proxy_auth = Some(auth);
+        self
+    }
+
     /// Set whether to use HTTP/2 without TLS
     pub fn h2c(mut self, h2c: bool) -> Self {
         self.h2c = h2c;
@@ -269,12 +469,200 @@ impl AttackBuilder {
         self
     }
 
+    /// Set whether to keep a cookie jar so Set-Cookie responses are honored
+    pub fn cookies(mut self, enabled: bool) -> Self {
+        self.cookie_jar = enabled;
+        self
+    }
+
+    /// Add an initial cookie sent to every target (enables the cookie jar)
+    pub fn add_cookie(mut self, name: &str, value: &str) -> Self {
+        self.cookies.push(format!("{}={}", name, value));
+        self
+    }
+
+    /// Set basic authentication credentials for all requests
+    pub fn basic_auth(mut self, user: &str, pass: &str) -> Self {
+        self.basic_auth = Some((user.to_string(), pass.to_string()));
+        self
+    }
+
+    /// Set digest authentication credentials for all requests
+    pub fn digest_auth(mut self, user: &str, pass: &str) -> Self {
+        self.digest_auth = Some(DigestAuth::new(user, pass));
+        self
+    }
+
+    /// Read the bearer token from a file or command, re-reading it on the given interval
+    pub fn bearer_token(mut self, source: TokenSource, refresh: Duration) -> Self {
+        self.bearer_token = Some((source, refresh));
+        self
+    }
+
+    /// Fetch bearer tokens with the OAuth2 client credentials flow, refreshing them before expiry
+    pub fn oauth2(mut self, oauth2: OAuth2Config) -> Self {
+        self.oauth2 = Some(oauth2);
+        self
+    }
+
+    /// Add a hook invoked on every request right before it is sent
+    ///
+    /// Hooks run in the order they were added and can modify the method, URL,
+    /// headers, and body, e.g. to add per-request signatures, timestamps, or nonces.
+    pub fn before_send<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&mut RequestSpec) + Send + Sync + 'static,
+    {
+        self.before_send.push(hooks::BeforeSend::new(hook));
+        self
+    }
+
+    /// Add a middleware applied to every request
+    ///
+    /// Middleware runs before `before_send` hooks, in the order it was added.
+    pub fn middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: RequestMiddleware + 'static,
+    {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Add a hook invoked on every received response
+    ///
+    /// The hook sees the status, headers, and body (up to `max_body` bytes) and can
+    /// attach custom values to the result, e.g. `result.custom.insert("items".into(), 42.into())`.
+    pub fn on_response<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&ResponseData, &mut AttackResult) + Send + Sync + 'static,
+    {
+        self.on_response.push(hooks::OnResponse::new(hook));
+        self
+    }
+
+    /// Add a hook invoked on every result as it arrives
+    ///
+    /// Hooks run in the order they were added, while the attack is still running, so
+    /// results can be pushed to another metrics system or database in real time. A slow
+    /// hook delays the collection of later results, so hand heavy work off to a channel.
+    pub fn on_result<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&AttackResult) + Send + Sync + 'static,
+    {
+        self.on_result.push(hooks::OnResult::new(hook));
+        self
+    }
+
+    /// Hook a Rhai script into every request
+    ///
+    /// The script's `request` function runs as middleware, after middleware added
+    /// before it, and its `response` function fails the results it rejects.
+    pub fn script(mut self, script: Script) -> Self {
+        let script = Arc::new(script);
+        if script.has_request() {
+            self.middleware.push(script.clone());
+        }
+        if script.has_response() {
+            self.on_response
+                .push(hooks::OnResponse::new(move |response, result| script.on_response(response, result)));
+        }
+        self
+    }
+
+    /// Hook a WASM plugin into every request
+    ///
+    /// The plugin's `pre_request` runs as middleware and its `post_response` fails the
+    /// results it rejects. Targets a plugin provides are read with `Plugin::targets`.
+    pub fn plugin(mut self, plugin: Plugin) -> Self {
+        let plugin = Arc::new(plugin);
+        if plugin.has_pre_request() {
+            self.middleware.push(plugin.clone());
+        }
+        if plugin.has_post_response() {
+            self.on_response
+                .push(hooks::OnResponse::new(move |response, result| plugin.post_response(response, result)));
+        }
+        self
+    }
+
+    /// Set the Accept-Encoding header sent when a request doesn't set one (empty to send none)
+    pub fn accept_encoding(mut self, accept_encoding: &str) -> Self {
+        self.accept_encoding = accept_encoding.to_string();
+        self
+    }
+
+    /// Set whether to decode compressed response bodies
+    pub fn decompress(mut self, decompress: bool) -> Self {
+        self.decompress = decompress;
+        self
+    }
+
+    /// Send a unique ID (UUID) in the given header with every request and record it in the result
+    pub fn request_id_header(mut self, name: &str) -> Self {
+        self.request_id_header = Some(name.to_string());
+        self
+    }
+
+    /// Flag responses that don't echo the request ID back in the request ID header
+    pub fn verify_request_id(mut self, verify: bool) -> Self {
+        self.verify_request_id = verify;
+        self
+    }
+
+    /// Send conditional requests using validators from previous responses and count 304 Not Modified as success
+    pub fn conditional(mut self, enable: bool) -> Self {
+        self.conditional = enable;
+        self
+    }
+
+    /// Randomly mutate one part of each request using the given strategies, recording the seed in the result
+    pub fn fuzz(mut self, strategies: Vec<FuzzStrategy>) -> Self {
+        self.fuzz = strategies;
+        self
+    }
+
     /// Run the attack and collect results
     pub async fn run(self) -> Result<Vec<AttackResult>> {
+        // Scenario steps replace the targets
+        let targets: Vec<Target> = if self.scenarios.is_empty() {
+            self.targets
+        } else {
+            self.scenarios.iter().flat_map(|s| s.steps.clone()).collect()
+        };
+
         // Validate that we have targets
-        if self.targets.is_empty() {
+        if targets.is_empty() {
             anyhow::bail!("No targets specified");
         }
+        attack::check_feeders(&self.scenarios)?;
+
+        // Virtual users run until the duration has elapsed
+        if self.vus.is_some() && self.duration.is_none() {
+            anyhow::bail!("Virtual users require a duration");
+        }
+        if self.vus == Some(0) {
+            anyhow::bail!("At least one virtual user is required");
+        }
+        if self.vu_shared_state && self.vus.is_none() {
+            anyhow::bail!("Shared virtual user state only applies to virtual users");
+        }
+        if !self.stages.is_empty() {
+            utils::check_stages(&self.stages)?;
+        }
+
+        // Start refreshing the bearer token if a source was given
+        let mut bearer_token = self
+            .bearer_token
+            .map(|(source, refresh)| auth::spawn_token_refresh(source, refresh))
+            .transpose()?;
+
+        // Fetch an OAuth2 token if configured
+        if let Some(oauth2) = self.oauth2 {
+            let token_client = reqwest::Client::builder()
+                .danger_accept_invalid_certs(self.insecure)
+                .build()?;
+            bearer_token = Some(auth::spawn_oauth2_refresh(oauth2, token_client).await?);
+        }
 
         // Create attack config
         let config = AttackConfig {
@@ -288,65 +676,183 @@ impl AttackBuilder {
             max_connections: self.max_connections,
             http2: self.http2,
             name: self.name,
+            run_id: self.run_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            metadata: self.metadata,
             max_body: self.max_body,
             dns_ttl: self.dns_ttl,
             laddr: self.laddr,
             lazy: self.lazy,
             opentelemetry_addr: self.opentelemetry_addr,
             tolerance: Some(0.1),
-            http_timeout: self.http_timeout
+            http_timeout: self.http_timeout,
+            cookie_jar: self.cookie_jar,
+            cookies: self.cookies,
+            basic_auth: self.basic_auth,
+            digest_auth: self.digest_auth,
+            bearer_token,
+            before_send: self.before_send,
+            middleware: self.middleware,
+            on_response: self.on_response,
+            accept_encoding: self.accept_encoding,
+            decompress: self.decompress,
+            request_id_header: self.request_id_header,
+            verify_request_id: self.verify_request_id,
+            conditional: self.conditional.then(cache::ValidatorCache::new),
+            fuzz: self.fuzz,
+            stages: self.stages,
+            sequence: Arc::new(AtomicU64::new(0)),
         };
 
-        // Create HTTP client
-        let mut client_builder = reqwest::Client::builder()
-            .timeout(config.timeout)
-            .pool_max_idle_per_host(config.connections);
+        // Create HTTP clients, each with its own connection pool and optional cookie jar
+        let proxies = utils::build_proxies(self.proxy.as_deref(), self.proxy_auth.as_ref())?;
 
-        if let Some(max_conns) = config.max_connections {
-            client_builder = client_builder.pool_max_idle_per_host(max_conns);
-        }
+        // Configure local address binding
+        let local_addr = if config.laddr != "0.0.0.0" {
+            // Parse the local address
+            Some(config.laddr.parse::<std::net::IpAddr>()?)
+        } else {
+            None
+        };
 
-        if !config.keepalive {
-            client_builder = client_builder.pool_idle_timeout(None);
-        }
+        let build_client = |jar: Option<Arc<reqwest::cookie::Jar>>| -> Result<reqwest::Client> {
+            let mut client_builder = reqwest::Client::builder()
+                .timeout(config.timeout)
+                .pool_max_idle_per_host(config.connections)
+                .no_gzip()
+                .no_brotli()
+                .no_deflate();
 
-        if self.insecure {
-            client_builder = client_builder.danger_accept_invalid_certs(true);
-        }
+            if let Some(max_conns) = config.max_connections {
+                client_builder = client_builder.pool_max_idle_per_host(max_conns);
+            }
 
-        if self.h2c {
-            client_builder = client_builder.http2_prior_knowledge();
-        } else if config.http2 {
-            client_builder = client_builder.http2_adaptive_window(true);
-        }
+            if !config.keepalive {
+                client_builder = client_builder.pool_idle_timeout(None);
+            }
 
-        // Configure local address binding
-        if config.laddr != "0.0.0.0" {
-            // Parse the local address
-            let local_addr = config.laddr.parse::<std::net::IpAddr>()?;
-            client_builder = client_builder.local_address(local_addr);
-        }
+            if let Some(jar) = jar {
+                client_builder = client_builder.cookie_provider(jar);
+            }
 
-        // Set up redirects policy
-        if self.redirects >= 0 {
-            client_builder = client_builder.redirect(reqwest::redirect::Policy::limited(self.redirects as usize));
-        } else {
-            client_builder = client_builder.redirect(reqwest::redirect::Policy::none());
-        }
+            for proxy in &proxies {
+                client_builder = client_builder.proxy(proxy.clone());
+            }
+
+            if self.insecure {
+                client_builder = client_builder.danger_accept_invalid_certs(true);
+            }
+
+            if self.h2c {
+                client_builder = client_builder.http2_prior_knowledge();
+            } else if config.http2 {
+                client_builder = client_builder.http2_adaptive_window(true);
+            }
+
+            if let Some(local_addr) = local_addr {
+                client_builder = client_builder.local_address(local_addr);
+            }
+
+            // Set up redirects policy
+            if self.redirects >= 0 {
+                client_builder = client_builder.redirect(reqwest::redirect::Policy::limited(self.redirects as usize));
+            } else {
+                client_builder = client_builder.redirect(reqwest::redirect::Policy::none());
+            }
+
+            Ok(client_builder.build()?)
+        };
 
-        let client = Arc::new(client_builder.build()?);
+        let clients = match self.vus {
+            // Virtual users sharing state use one client, so one connection pool and cookie jar
+            Some(vus) if self.vu_shared_state => {
+                let client = Arc::new(build_client(Some(utils::build_cookie_jar(&config.cookies, &targets)?))?);
+                (0..vus).map(|_| client.clone()).collect()
+            }
+            // Each virtual user keeps its own session: a separate client, connections, and cookie jar
+            Some(vus) => (0..vus)
+                .map(|_| Ok(Arc::new(build_client(Some(utils::build_cookie_jar(&config.cookies, &targets)?))?)))
+                .collect::<Result<Vec<_>>>()?,
+            None => {
+                let jar = if config.cookie_jar || !config.cookies.is_empty() {
+                    Some(utils::build_cookie_jar(&config.cookies, &targets)?)
+                } else {
+                    None
+                };
+                vec![Arc::new(build_client(jar)?)]
+            }
+        };
+
+        // Run the setup steps once before load starts, making their variables available to every flow
+        let mut variables = BTreeMap::new();
+        attack::run_phase("Setup", &clients[0], self.setup, &mut variables, &self.headers, &config).await?;
+
+        // Keep what the teardown steps need, they run after the attack with the setup variables
+        let phase_client = clients[0].clone();
+        let mut phase_variables = variables.clone();
+        let phase_headers = self.headers.clone();
+        let phase_config = config.clone();
 
         // Set up channels
         let (tx, mut rx) = mpsc::channel::<AttackResult>(1000);
 
         // Start attack
         let attack_handle = {
-            let targets = Arc::new(self.targets);
+            let targets = Arc::new(targets);
+            let scenarios = Arc::new(self.scenarios);
             let headers = Arc::new(self.headers);
             let config = Arc::new(config);
             let tx = tx.clone();
+            let vus = self.vus;
+            let vu_shared_state = self.vu_shared_state;
+            let variables = Arc::new(variables);
 
             tokio::spawn(async move {
+                let start_time = std::time::Instant::now();
+
+                // Set up end time if duration is specified
+                let end_time = config.duration.map(|d| start_time + d);
+
+                // Closed loop: every virtual user runs flows back to back until the duration has elapsed
+                if let Some(end) = end_time.filter(|_| vus.is_some()) {
+                    let next_flow = Arc::new(AtomicU64::new(0));
+                    let mut handles = Vec::new();
+
+                    for (index, client) in clients.into_iter().enumerate() {
+                        let targets = targets.clone();
+                        let scenarios = scenarios.clone();
+                        let headers = headers.clone();
+                        let config = attack::vu_config(&config, !vu_shared_state);
+                        let next_flow = next_flow.clone();
+                        let tx = tx.clone();
+                        let variables = variables.clone();
+
+                        handles.push(tokio::spawn(async move {
+                            // Variables extracted by a virtual user stay with it across iterations
+                            let mut variables = BTreeMap::clone(&variables);
+                            while std::time::Instant::now() < end {
+                                // Idle while the stages call for fewer virtual users
+                                if attack::active_vus(&config.stages, start_time.elapsed()).is_some_and(|vus| index as u64 >= vus) {
+                                    let remaining = end.saturating_duration_since(std::time::Instant::now());
+                                    tokio::time::sleep(attack::STAGE_POLL.min(remaining)).await;
+                                    continue;
+                                }
+
+                                let Some(flow) = attack::Flow::next(&scenarios, &targets, next_flow.fetch_add(1, Ordering::Relaxed)) else {
+                                    break;
+                                };
+                                attack::run_flow(&client, index as u64, flow, &mut variables, &headers, &config, |_| {}, &tx).await;
+                            }
+                        }));
+                    }
+
+                    for handle in handles {
+                        let _ = handle.await;
+                    }
+                    return;
+                }
+
+                let client = clients[0].clone();
+
                 // Calculate delay between requests based on rate
                 let delay = if config.rate > 0.0 {
                     Duration::from_secs_f64(1.0 / config.rate)
@@ -354,17 +860,14 @@ impl AttackBuilder {
                     Duration::from_secs(0)
                 };
 
-                let start_time = std::time::Instant::now();
                 let mut request_count = 0;
 
-                // Set up end time if duration is specified
-                let end_time = config.duration.map(|d| start_time + d);
-
                 // Create a stream of targets with the specified rate
                 let mut interval = tokio::time::interval(delay);
 
                 // Create a semaphore to limit concurrent workers
                 let worker_semaphore = Arc::new(tokio::sync::Semaphore::new(config.workers as usize));
+                let worker_ids = attack::WorkerIds::default();
 
                 // If max_workers is set, adjust the number of workers over time
                 if let Some(max_workers) = config.max_workers {
@@ -395,9 +898,10 @@ impl AttackBuilder {
                         }
                     }
 
-                    // Get the next target (round-robin)
-                    let target_index = request_count % targets.len();
-                    let target = targets[target_index].clone();
+                    // Get the next target (round-robin), or every step of the next scenario
+                    let Some(flow) = attack::Flow::next(&scenarios, &targets, request_count as u64) else {
+                        break;
+                    };
 
                     // Clone necessary data for the request
                     let client = client.clone();
@@ -405,6 +909,7 @@ impl AttackBuilder {
                     let config_clone = config.clone();
                     let tx = tx.clone();
                     let semaphore = worker_semaphore.clone();
+                    let variables = variables.clone();
 
                     // Acquire a permit from the semaphore before spawning the task
                     let permit = match semaphore.clone().try_acquire_owned() {
@@ -417,10 +922,13 @@ impl AttackBuilder {
                         }
                     };
 
-                    // Spawn a task to make the request
+                    // Spawn a task to make the request, or every step of the scenario in order
+                    let worker_ids = worker_ids.clone();
+                    let worker = worker_ids.acquire();
                     tokio::spawn(async move {
-                        let result = attack::make_request(client, target, &headers, &config_clone).await;
-                        let _ = tx.send(result).await;
+                        let mut variables = BTreeMap::clone(&variables);
+                        attack::run_flow(&client, worker, flow, &mut variables, &headers, &config_clone, |_| {}, &tx).await;
+                        worker_ids.release(worker);
                         drop(permit);
                     });
 
@@ -431,25 +939,33 @@ impl AttackBuilder {
 
         // Collect results
 
-        // Create a separate task to collect results
+        // Create a separate task to collect results, passing each to the result hooks
+        let on_result = self.on_result;
         let collector_handle = tokio::spawn(async move {
             let mut collected_results = Vec::new();
             while let Some(result) = rx.recv().await {
+                for hook in &on_result {
+                    hook.call(&result);
+                }
                 collected_results.push(result);
             }
             collected_results
         });
 
         // Wait for attack to finish
-        attack_handle.await?;
+        let attacked = attack_handle.await;
 
         // Close the channel by dropping the sender
         drop(tx);
 
         // Wait for collector to finish and get results
-        let results = collector_handle.await?;
+        let results = collector_handle.await;
+
+        // Run the teardown steps once, even if the attack failed
+        attack::run_phase("Teardown", &phase_client, self.teardown, &mut phase_variables, &phase_headers, &phase_config).await?;
 
-        Ok(results)
+        attacked?;
+        Ok(results?)
     }
 }
 
@@ -460,6 +976,12 @@ pub fn target(method: &str, url: &str) -> Result<Target> {
         url: Url::parse(url)?,
         headers: Vec::new(),
         body: None,
+        expect: None,
+        slo: None,
+        extract: Vec::new(),
+        when: None,
+        checks: Vec::new(),
+        tags: BTreeMap::new(),
     })
 }
 
@@ -475,78 +997,12 @@ pub fn post(url: &str, body: Vec<u8>) -> Result<Target> {
     Ok(target)
 }
 
-/// Calculate metrics from attack results
+/// Calculate metrics from attack results, or None if there are none
 pub fn calculate_metrics(results: &[AttackResult]) -> Option<Metrics> {
     if results.is_empty() {
         return None;
     }
-
-    let requests = results.len();
-    let success = results.iter().filter(|r| r.status_code >= 200 && r.status_code < 300).count();
-    let timeouts = results.iter().filter(|r| r.timed_out).count();
-    let success_rate = success as f64 / requests as f64;
-
-    // Calculate duration from first to last request
-    let first_timestamp = results.first().unwrap().timestamp;
-    let last_timestamp = results.last().unwrap().timestamp;
-    let duration = (last_timestamp - first_timestamp).to_std().unwrap_or(Duration::from_secs(0));
-
-    // Calculate latency statistics
-    let mut latencies: Vec<Duration> = results.iter().map(|r| r.latency).collect();
-    latencies.sort();
-
-    let min = latencies.first().cloned().unwrap_or(Duration::from_secs(0));
-    let max = latencies.last().cloned().unwrap_or(Duration::from_secs(0));
-
-    let mean = if !latencies.is_empty() {
-        let sum: Duration = latencies.iter().sum();
-        Duration::from_secs_f64(sum.as_secs_f64() / latencies.len() as f64)
-    } else {
-        Duration::from_secs(0)
-    };
-
-    // Calculate percentiles
-    let p50 = percentile(&latencies, 0.5);
-    let p90 = percentile(&latencies, 0.9);
-    let p95 = percentile(&latencies, 0.95);
-    let p99 = percentile(&latencies, 0.99);
-
-    // Calculate rate
-    let rate = if duration.as_secs_f64() > 0.0 {
-        requests as f64 / duration.as_secs_f64()
-    } else {
-        0.0
-    };
-
-    // Calculate bytes
-    let bytes_in: usize = results.iter().map(|r| r.bytes_in).sum();
-    let bytes_out: usize = results.iter().map(|r| r.bytes_out).sum();
-
-    Some(Metrics {
-        requests,
-        success,
-        timeouts,
-        duration,
-        min,
-        max,
-        mean,
-        p50,
-        p90,
-        p95,
-        p99,
-        rate,
-        bytes_in,
-        bytes_out,
-        success_rate,
-    })
-}
-
-/// Calculate a percentile from a sorted list of durations
-fn percentile(sorted_latencies: &[Duration], percentile: f64) -> Duration {
-    if sorted_latencies.is_empty() {
-        return Duration::from_secs(0);
-    }
-
-    let index = (sorted_latencies.len() as f64 * percentile) as usize;
-    sorted_latencies[index.min(sorted_latencies.len() - 1)]
+    let mut metrics = report::calculate_metrics(results, &report::MetricsOptions::default()).ok()?;
+    metrics.run = report::calculate_run_summary(results, &[]);
+    Some(metrics)
 }
@@ -1,5 +1,5 @@
 use anyhow::{Result};
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -20,7 +20,28 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Run a load test attack
+    #[command(group(ArgGroup::new("virtual_users").args(["vus", "stages", "scenario"]).multiple(true)))]
     Attack {
+        /// Accept-Encoding header sent when a request doesn't set one [empty = none]
+        #[arg(long = "accept-encoding", value_name = "value", default_value = "gzip, deflate, br")]
+        accept_encoding: String,
+
+        /// Basic authentication credentials for all requests, as user:pass
+        #[arg(long = "basic-auth", value_name = "user:pass")]
+        basic_auth: Option<String>,
+
+        /// Run a command whose output is used as the bearer token, re-run on every refresh
+        #[arg(long = "bearer-token-cmd", value_name = "command", conflicts_with = "bearer_token_file")]
+        bearer_token_cmd: Option<String>,
+
+        /// Read the bearer token from a file, re-read on every refresh
+        #[arg(long = "bearer-token-file", value_name = "file")]
+        bearer_token_file: Option<String>,
+
+        /// Interval at which the bearer token is re-read
+        #[arg(long = "bearer-token-refresh", value_name = "duration", default_value = "1m")]
+        bearer_token_refresh: humantime::Duration,
+
         /// Requests body file
         #[arg(long)]
         body: Option<String>,
@@ -33,6 +54,10 @@ enum Commands {
         #[arg(long)]
         chunked: bool,
 
+        /// Send If-None-Match/If-Modified-Since from previous responses and count 304 Not Modified as success
+        #[arg(long)]
+        conditional: bool,
+
         /// A mapping of (ip|host):port to use instead of a target URL's (ip|host):port
         #[arg(long = "connect-to", value_name = "value")]
         connect_to: Vec<String>,
@@ -41,6 +66,18 @@ enum Commands {
         #[arg(long, default_value = "10000")]
         connections: usize,
 
+        /// Initial cookie to send, as name=value (implies --cookie-jar)
+        #[arg(long = "cookie", value_name = "value")]
+        cookies: Vec<String>,
+
+        /// Keep a cookie jar so Set-Cookie responses are honored on subsequent requests
+        #[arg(long)]
+        cookie_jar: bool,
+
+        /// Digest authentication credentials for all requests, as user:pass
+        #[arg(long = "digest-auth", value_name = "user:pass", conflicts_with = "basic_auth")]
+        digest_auth: Option<String>,
+
         /// Cache DNS lookups for the given duration [-1 = disabled, 0 = forever]
         #[arg(long = "dns-ttl", value_name = "value", default_value = "0s")]
         dns_ttl: humantime::Duration,
@@ -49,10 +86,22 @@ enum Commands {
         #[arg(long)]
         duration: Option<humantime::Duration>,
 
+        /// Results encoding written to the output file [binary, json]
+        #[arg(long, default_value = "binary")]
+        encoding: String,
+
         /// Targets format [http, json, file]
         #[arg(long, default_value = "http")]
         format: String,
 
+        /// Randomly mutate one query parameter, header value, or JSON body field per request
+        #[arg(long)]
+        fuzz: bool,
+
+        /// What fuzzing may mutate [query, headers, body]
+        #[arg(long = "fuzz-strategies", value_name = "list", default_value = "query,headers,body", requires = "fuzz")]
+        fuzz_strategies: String,
+
         /// Send HTTP/2 requests without TLS encryption
         #[arg(long)]
         h2c: bool,
@@ -97,18 +146,71 @@ enum Commands {
         #[arg(long)]
         max_workers: Option<u64>,
 
-        /// Attack name
+        /// Key/value describing the run, attached to every result, as key=value
+        #[arg(long, value_name = "key=value")]
+        metadata: Vec<String>,
+
+        /// Attack name, attached to every result
         #[arg(long)]
         name: Option<String>,
 
-        /// Output file
+        /// Don't decode compressed response bodies
+        #[arg(long = "no-decompress")]
+        no_decompress: bool,
+
+        /// OAuth2 client id for the client credentials flow
+        #[arg(long = "oauth2-client-id", value_name = "id")]
+        oauth2_client_id: Option<String>,
+
+        /// OAuth2 client secret for the client credentials flow
+        #[arg(long = "oauth2-client-secret", value_name = "secret")]
+        oauth2_client_secret: Option<String>,
+
+        /// OAuth2 scope to request (repeatable)
+        #[arg(long = "oauth2-scope", value_name = "scope")]
+        oauth2_scopes: Vec<String>,
+
+        /// OAuth2 token endpoint; fetched tokens are sent as bearer tokens and refreshed before expiry
+        #[arg(
+            long = "oauth2-token-url",
+            value_name = "url",
+            requires_all = ["oauth2_client_id", "oauth2_client_secret"],
+            conflicts_with_all = ["bearer_token_cmd", "bearer_token_file"]
+        )]
+        oauth2_token_url: Option<String>,
+
+        /// Output file, compressed if it ends in .gz or .zst, or a sqlite://, influxdb://, clickhouse://, tcp:// or udp:// URL
         #[arg(long, default_value = "stdout")]
         output: String,
 
+        /// Append results to the output file instead of truncating it
+        #[arg(long)]
+        output_append: bool,
+
+        /// Also write the results of each target to a file of its own in this directory
+        #[arg(long)]
+        output_per_target: Option<String>,
+
         /// OpenTelemetry exporter listen address [empty = disabled]
         #[arg(long)]
         opentelemetry_addr: Option<String>,
 
+        /// WASM plugin exporting pre_request, post_response, and/or targets (can be repeated)
+        #[arg(long = "plugin", value_name = "file")]
+        plugins: Vec<String>,
+
+        /// Proxy URL for all requests [empty = use HTTP_PROXY/HTTPS_PROXY]
+        #[arg(long, value_name = "url")]
+        proxy: Option<String>,
+
+        /// Proxy basic authentication credentials, as user:pass
+        #[arg(long = "proxy-auth", value_name = "user:pass", env = "CULVERIN_PROXY_AUTH")]
+        proxy_auth: Option<String>,
+
+        /// Proxy bearer token
+        #[arg(long = "proxy-bearer-token", value_name = "token", env = "CULVERIN_PROXY_BEARER_TOKEN", conflicts_with = "proxy_auth")]
+        proxy_bearer_token: Option<String>,
+
         /// Proxy CONNECT header
         #[arg(long = "proxy-header", value_name = "value")]
         proxy_headers: Vec<String>,
@@ -121,6 +223,10 @@ enum Commands {
         #[arg(long, default_value = "10")]
         redirects: i32,
 
+        /// Header used to send a unique ID (UUID) with every request, e.g. X-Request-Id
+        #[arg(long = "request-id-header", value_name = "name")]
+        request_id_header: Option<String>,
+
         /// List of addresses (ip:port) to use for DNS resolution
         #[arg(long = "resolvers", value_name = "value")]
         resolvers: Vec<String>,
@@ -129,14 +235,42 @@ enum Commands {
         #[arg(long = "root-certs", value_name = "value")]
         root_certs: Vec<String>,
 
+        /// ID of the attack run, attached to every result; share it across machines of a distributed run [default: random]
+        #[arg(long = "run-id", value_name = "id")]
+        run_id: Option<String>,
+
+        /// Scenario file (YAML or TOML) declaring the rate, duration, headers, setup, targets or scenarios, and teardown of the test in one place
+        #[arg(long, value_name = "file", conflicts_with_all = ["scenarios", "setup", "teardown"])]
+        scenario: Option<String>,
+
+        /// Scenarios file (JSON); each rate tick starts one flow that requests the scenario's steps in order
+        #[arg(long, value_name = "file")]
+        scenarios: Option<String>,
+
+        /// Rhai script defining request(req) to modify requests before they are sent and/or response(resp) to validate responses
+        #[arg(long, value_name = "file")]
+        script: Option<String>,
+
         /// Enable TLS session resumption using session tickets
         #[arg(long)]
         session_tickets: bool,
 
+        /// Setup steps file (JSON targets) run once before the attack; values they extract are available to every request as {{name}}
+        #[arg(long, value_name = "file")]
+        setup: Option<String>,
+
+        /// Ramp virtual users over stages, each a duration and the number of virtual users to reach by its end (e.g. 30s:10,1m:50,30s:0). The attack lasts as long as the stages
+        #[arg(long, value_name = "stages", conflicts_with_all = ["vus", "duration"])]
+        stages: Option<String>,
+
         /// Targets file
         #[arg(long, default_value = "stdin")]
         targets: String,
 
+        /// Teardown steps file (JSON targets) run once after the attack, even if it is interrupted with Ctrl-C
+        #[arg(long, value_name = "file")]
+        teardown: Option<String>,
+
         /// Requests timeout
         #[arg(long, default_value = "30s")]
         timeout: humantime::Duration,
@@ -149,6 +283,22 @@ enum Commands {
         #[arg(long)]
         unix_socket: Option<String>,
 
+        /// Upload the results file to S3-compatible storage when the attack completes, e.g. s3://bucket/prefix/
+        #[arg(long)]
+        upload: Option<String>,
+
+        /// Verify that responses echo the request ID back in the request ID header
+        #[arg(long = "verify-request-id", requires = "request_id_header")]
+        verify_request_id: bool,
+
+        /// Let virtual users share one cookie jar and connection pool instead of each having their own
+        #[arg(long = "vu-shared-state", requires = "virtual_users")]
+        vu_shared_state: bool,
+
+        /// Run N virtual users, each repeating flows back to back with its own connections, cookies, and variables, instead of a fixed rate
+        #[arg(long, value_name = "N", requires = "duration", value_parser = clap::value_parser!(u64).range(1..))]
+        vus: Option<u64>,
+
         /// Initial number of workers
         #[arg(long, default_value = "10")]
         workers: u64,
@@ -158,20 +308,172 @@ enum Commands {
         tolerance: f64,
     },
 
+    /// Compare the results of two runs and fail on regressions beyond tolerance
+    Compare {
+        /// Result file of the baseline run
+        baseline: String,
+
+        /// Result file of the run compared to the baseline
+        current: String,
+
+        /// Encoding of the results read [auto, binary, csv, gob, json, jtl, ndjson, vegeta-json] (json is an array)
+        #[arg(long, default_value = "auto")]
+        input_encoding: String,
+
+        /// Largest increase of p50, p95 and p99 latency that isn't a regression
+        #[arg(long, default_value = "10%")]
+        latency_tolerance: String,
+
+        /// Output file, compressed if it ends in .gz or .zst
+        #[arg(long, default_value = "stdout")]
+        output: String,
+
+        /// Largest drop of the success rate, in percentage points, that isn't a regression
+        #[arg(long, default_value = "1%")]
+        success_tolerance: String,
+
+        /// Largest drop of throughput that isn't a regression
+        #[arg(long, default_value = "10%")]
+        throughput_tolerance: String,
+    },
+
+    /// Decode attack results in any encoding to JSON lines
+    Decode {
+        /// Result files to decode, in order [default: stdin]
+        inputs: Vec<String>,
+
+        /// Output file, compressed if it ends in .gz or .zst
+        #[arg(long, default_value = "stdout")]
+        output: String,
+    },
+
     /// Encode attack results to different formats
     Encode {
-        /// Output file
+        /// Comma-separated CSV columns to write, in order, e.g.: "timestamp,latency_ms,status,url,error"
+        #[arg(long)]
+        columns: Option<String>,
+
+        /// Drop results that exactly duplicate another result (implies --sort)
+        #[arg(long)]
+        dedup: bool,
+
+        /// Input encoding [auto, binary, csv, gob, json, jtl, ndjson, vegeta-json] (json is an array)
+        #[arg(long, default_value = "auto")]
+        from: String,
+
+        /// Output file, compressed if it ends in .gz or .zst
         #[arg(long, default_value = "stdout")]
         output: String,
 
-        /// Output encoding [csv, gob, json]
+        /// Keep a random sample of results, as a percentage like 1% or a number of results
+        #[arg(long)]
+        sample: Option<String>,
+
+        /// Seed of the random sample, so the same results give the same sample
+        #[arg(long, default_value = "0")]
+        seed: u64,
+
+        /// Sort results by timestamp, reading them all into memory
+        #[arg(long)]
+        sort: bool,
+
+        /// Output encoding [binary, csv, gob, json, msgpack, ndjson, proto]
         #[arg(long, default_value = "json")]
         to: String,
     },
 
+    /// Filter attack results, keeping those that meet every condition
+    Filter {
+        /// Results encoding written [binary, json] (default: the encoding read)
+        #[arg(long)]
+        encoding: Option<String>,
+
+        /// Keep only requests that failed with an error
+        #[arg(long, conflicts_with = "no_errors")]
+        errors: bool,
+
+        /// Keep results from this time on, as an RFC 3339 timestamp or an offset from the first result
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Result files to filter, in order [default: stdin]
+        inputs: Vec<String>,
+
+        /// Keep results with at most this latency
+        #[arg(long)]
+        max_latency: Option<humantime::Duration>,
+
+        /// Keep results with at least this latency
+        #[arg(long)]
+        min_latency: Option<humantime::Duration>,
+
+        /// Keep only requests without errors
+        #[arg(long)]
+        no_errors: bool,
+
+        /// Output file, compressed if it ends in .gz or .zst
+        #[arg(long, default_value = "stdout")]
+        output: String,
+
+        /// Keep a random sample of the matching results, as a percentage like 1% or a number of results
+        #[arg(long)]
+        sample: Option<String>,
+
+        /// Seed of the random sample, so the same results give the same sample
+        #[arg(long, default_value = "0")]
+        seed: u64,
+
+        /// Status codes to keep, e.g.: "200,301-308,5xx"
+        #[arg(long)]
+        status: Option<String>,
+
+        /// Keep results from before this time, as an RFC 3339 timestamp or an offset from the first result
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Regular expression the target URL must match
+        #[arg(long)]
+        url: Option<String>,
+    },
+
+    /// Merge result files into one stream, interleaved by timestamp
+    Merge {
+        /// Drop results that exactly duplicate another result (implies --sort)
+        #[arg(long)]
+        dedup: bool,
+
+        /// Results encoding written [binary, json] (default: the encoding of the first file)
+        #[arg(long)]
+        encoding: Option<String>,
+
+        /// Result files to merge
+        #[arg(required = true)]
+        inputs: Vec<String>,
+
+        /// Output file, compressed if it ends in .gz or .zst
+        #[arg(long, default_value = "stdout")]
+        output: String,
+
+        /// Sort results by timestamp, reading them all into memory
+        #[arg(long)]
+        sort: bool,
+    },
+
     /// Generate plots from attack results
     Plot {
-        /// Output file
+        /// Plot format [html, gnuplot, svg, png]; gnuplot writes the script to --output and its data files next to it, png needs --output
+        #[arg(long, default_value = "html")]
+        format: String,
+
+        /// Encoding of the results read [auto, binary, csv, gob, json, jtl, ndjson, vegeta-json] (json is an array)
+        #[arg(long, default_value = "auto")]
+        input_encoding: String,
+
+        /// Result file to read, can be repeated; the HTML plot overlays the runs read [default: stdin]
+        #[arg(long = "input")]
+        inputs: Vec<String>,
+
+        /// Output file, compressed if it ends in .gz or .zst
         #[arg(long, default_value = "stdout")]
         output: String,
 
@@ -179,35 +481,111 @@ enum Commands {
         #[arg(long, default_value = "4000")]
         threshold: usize,
 
-        /// Title and header of the resulting HTML page
+        /// Title and header of the resulting HTML page, or title of the gnuplot figure or image
         #[arg(long, default_value = "Culverin Plot")]
         title: String,
     },
 
     /// Generate reports from attack results
     Report {
-        /// Histogram buckets, e.g.: "[0,1ms,10ms]"
+        /// Rate latencies with an Apdex score: the latency up to which requests satisfy users, optionally followed by the latency up to which they're tolerated (default 4 times the first), e.g.: "500ms" or "500ms,2s"
+        #[arg(long)]
+        apdex: Option<String>,
+
+        /// Histogram buckets of --type=hist and heatmap, e.g.: "[0,1ms,10ms]" [default: log-spaced from the lowest latency to the highest]
         #[arg(long)]
         buckets: Option<String>,
 
-        /// Report interval
+        /// Report metrics per group of results [target, url, worker, tag:<key>]; target leaves out query strings, worker groups by the worker or virtual user that made the request, tag:<key> groups by a target tag, metadata or custom key
+        #[arg(long, alias = "group-by")]
+        by: Option<String>,
+
+        /// Report interval: text and JSON reports are split into intervals starting at multiples of it on the wall clock, e.g. :00, :10 and :20 for 10s; the time interval of --type=heatmap [default: 1s]
         #[arg(long)]
         every: Option<humantime::Duration>,
 
-        /// Output file
+        /// Result file to report on while it's written, e.g. by a running attack, rendering the report anew every --every interval (default 5s) until interrupted
+        #[arg(long)]
+        follow: Option<String>,
+
+        /// Append a Markdown report, with a pass/fail badge per threshold, to the job summary of a GitHub Actions step, the file named by $GITHUB_STEP_SUMMARY
+        #[arg(long)]
+        github_summary: bool,
+
+        /// Encoding of the results read [auto, binary, csv, gob, json, jtl, ndjson, vegeta-json] (json is an array)
+        #[arg(long, default_value = "auto")]
+        input_encoding: String,
+
+        /// Result file to read, can be repeated to combine several [default: stdin]
+        #[arg(long = "input")]
+        inputs: Vec<String>,
+
+        /// Payload format of --notify-webhook [json, slack]
+        #[arg(long, default_value = "json")]
+        notify_format: String,
+
+        /// Webhook URL the summary is posted to when the report completes: the rate, success rate, p99 latency and whether each threshold passed
+        #[arg(long)]
+        notify_webhook: Option<String>,
+
+        /// Output file, compressed if it ends in .gz or .zst
         #[arg(long, default_value = "stdout")]
         output: String,
 
-        /// Report type to generate [text, json, hist[buckets], hdrplot]
+        /// How latencies at percentiles are picked [nearest-rank, linear]; nearest-rank reports a recorded latency like HdrHistogram, linear interpolates between the two closest like numpy
+        #[arg(long, default_value = "nearest-rank")]
+        percentile_method: String,
+
+        /// Percentiles to report latencies at, from 0 to 100, e.g.: "50,90,99,99.9,99.99"
+        #[arg(long, default_value = "50,90,95,99")]
+        percentiles: String,
+
+        /// Significant digits latencies are aggregated to, from 1 to 5
+        #[arg(long, default_value_t = histogram::DEFAULT_PRECISION)]
+        precision: u8,
+
+        /// Report type to generate [text, json, markdown, hist, hist[buckets], heatmap, heatmap-json, hdrplot, prom, proto, slowest, wrk2]
         #[arg(long = "type", default_value = "text")]
         report_type: String,
+
+        /// Conditions the metrics must meet, failing the report if any isn't, e.g.: "p99<500ms,success_rate>99.5%,rate>950"
+        #[arg(long)]
+        thresholds: Option<String>,
+
+        /// Number of requests listed by --type=slowest
+        #[arg(long, default_value_t = 10)]
+        top: usize,
     },
 }
 
 mod attack;
+mod auth;
+mod cache;
+mod compare;
+mod decode;
 mod encode;
+mod filter;
+mod fuzz;
+mod gob;
+mod histogram;
+mod hooks;
+mod import;
+mod merge;
+mod notify;
+mod plan;
+mod plugin;
 mod plot;
+mod proto;
 mod report;
+mod results;
+mod sample;
+mod scenario;
+mod script;
+mod socket;
+mod sqlite;
+mod threshold;
+mod tsdb;
+mod upload;
 mod models;
 mod utils;
 
@@ -217,12 +595,12 @@ async fn main() -> Result<()> {
 
     // Set number of CPUs to use
     let _cpu_count = if let Some(cpus) = cli.cpus {
-        println!("Using {} CPUs", cpus);
+        eprintln!("Using {} CPUs", cpus);
         cpus
     } else {
         // Default to the number of logical cores
         let count = num_cpus::get();
-        println!("Using default CPU count: {}", count);
+        eprintln!("Using default CPU count: {}", count);
         count
     };
 
@@ -230,7 +608,7 @@ async fn main() -> Result<()> {
     if let Some(profile_type) = cli.profile {
         match profile_type.as_str() {
             "cpu" => {
-                println!("CPU profiling enabled");
+                eprintln!("CPU profiling enabled");
                 // Implement CPU profiling
                 #[cfg(feature = "pprof")]
                 {
@@ -246,11 +624,11 @@ async fn main() -> Result<()> {
                 }
                 #[cfg(not(feature = "pprof"))]
                 {
-                    println!("CPU profiling requires the 'pprof' feature to be enabled");
+                    eprintln!("CPU profiling requires the 'pprof' feature to be enabled");
                 }
             },
             "heap" => {
-                println!("Heap profiling enabled");
+                eprintln!("Heap profiling enabled");
                 // Implement heap profiling
                 #[cfg(feature = "pprof")]
                 {
@@ -266,10 +644,10 @@ async fn main() -> Result<()> {
                 }
                 #[cfg(not(feature = "pprof"))]
                 {
-                    println!("Heap profiling requires the 'pprof' feature to be enabled");
+                    eprintln!("Heap profiling requires the 'pprof' feature to be enabled");
                 }
             },
-            _ => println!("Unknown profile type: {}", profile_type),
+            _ => eprintln!("Unknown profile type: {}", profile_type),
         }
     }
 
@@ -277,14 +655,26 @@ async fn main() -> Result<()> {
 
     match cli.command {
         Some(Commands::Attack {
+            accept_encoding,
+            basic_auth,
+            bearer_token_cmd,
+            bearer_token_file,
+            bearer_token_refresh,
             body,
             cert,
             chunked,
+            conditional,
             connect_to,
             connections,
+            cookies,
+            cookie_jar,
+            digest_auth,
             dns_ttl,
             duration,
+            encoding,
             format,
+            fuzz,
+            fuzz_strategies,
             h2c,
             headers,
             http2,
@@ -296,22 +686,65 @@ async fn main() -> Result<()> {
             max_body,
             max_connections,
             max_workers,
+            metadata,
             name,
+            no_decompress,
+            oauth2_client_id,
+            oauth2_client_secret,
+            oauth2_scopes,
+            oauth2_token_url,
             output,
+            output_append,
+            output_per_target,
             opentelemetry_addr,
+            plugins,
+            proxy,
+            proxy_auth,
+            proxy_bearer_token,
             proxy_headers,
             rate,
             redirects,
-            resolvers,
+            request_id_header,
+            resolvers: _,
             root_certs,
-            session_tickets,
+            run_id,
+            scenario,
+            scenarios,
+            script,
+            session_tickets: _,
+            setup,
+            stages,
             targets,
+            teardown,
             timeout,
-            unix_socket,
+            unix_socket: _,
+            upload,
+            verify_request_id,
+            vu_shared_state,
+            vus,
             workers,
             tolerance,
             http_timeout
         }) => {
+            // Settings in the scenario file take precedence over the flags
+            let plan = scenario.as_deref().map(plan::Plan::load).transpose()?;
+            let (mut rate, mut duration, mut vus) = (rate, duration, vus);
+            if let Some(plan) = &plan {
+                rate = plan.rate.clone().unwrap_or(rate);
+                duration = plan.duration.or(duration);
+                vus = plan.vus.or(vus);
+            }
+
+            // Stages set the number of virtual users and the duration of the attack
+            let stages = match &plan {
+                Some(plan) if !plan.stages.is_empty() => plan.stages.clone(),
+                _ => stages.as_deref().map(utils::parse_stages).transpose()?.unwrap_or_default(),
+            };
+            if !stages.is_empty() {
+                vus = stages.iter().map(|s| s.target).max();
+                duration = Some(stages.iter().map(|s| s.duration).sum::<std::time::Duration>().into());
+            }
+
             // Use the rate value directly to determine the number of workers
             // We don't need to estimate based on latency since we'll spawn requests at the exact rate
             let effective_workers = if workers == 10 { // Default value is 10
@@ -330,28 +763,57 @@ async fn main() -> Result<()> {
                 workers
             };
 
-            println!("Using {} workers for rate {}", effective_workers, rate);
-
-            attack::run(
-                body, cert, chunked, connections, dns_ttl, duration, format, h2c, 
-                headers, http2, insecure, keepalive, key, laddr, lazy, max_body, 
-                max_connections, max_workers, name, output, opentelemetry_addr, 
-                proxy_headers, rate, redirects, resolvers, root_certs, 
-                session_tickets, targets, timeout, http_timeout, unix_socket, effective_workers,
-                tolerance
-            ).await?;
+            match vus {
+                Some(vus) if !stages.is_empty() => eprintln!("Ramping up to {} virtual users over {} stages", vus, stages.len()),
+                Some(vus) => eprintln!("Using {} virtual users", vus),
+                None => eprintln!("Using {} workers for rate {}", effective_workers, rate),
+            }
+
+            attack::run(attack::AttackOptions {
+                accept_encoding, basic_auth, bearer_token_cmd, bearer_token_file, bearer_token_refresh, body,
+                cert, chunked, conditional, connections, cookies, cookie_jar, digest_auth, dns_ttl, duration,
+                encoding, format, fuzz, fuzz_strategies, h2c, headers, http2, insecure, keepalive, key, laddr,
+                lazy, max_body, max_connections, max_workers, metadata, name, no_decompress, oauth2_client_id,
+                oauth2_client_secret, oauth2_scopes, oauth2_token_url, output, output_append,
+                output_per_target, opentelemetry_addr, plan, plugins, proxy, proxy_auth, proxy_bearer_token,
+                proxy_headers, rate, redirects, request_id_header, root_certs, run_id, scenarios, script,
+                setup, stages, targets, teardown, timeout, http_timeout, upload, verify_request_id,
+                vu_shared_state, vus, workers: effective_workers, tolerance,
+            }).await?;
+        }
+        Some(Commands::Compare { baseline, current, input_encoding, latency_tolerance, output, success_tolerance, throughput_tolerance }) => {
+            compare::run(compare::CompareOptions {
+                baseline, current, input_encoding, latency_tolerance, output, success_tolerance,
+                throughput_tolerance,
+            }).await?;
+        }
+        Some(Commands::Decode { inputs, output }) => {
+            decode::run(decode::DecodeOptions { inputs, output }).await?;
+        }
+        Some(Commands::Encode { columns, dedup, from, output, sample, seed, sort, to }) => {
+            encode::run(encode::EncodeOptions { columns, dedup, from, output, sample, seed, sort, to }).await?;
+        }
+        Some(Commands::Filter { encoding, errors, from, inputs, max_latency, min_latency, no_errors, output, sample, seed, status, until, url }) => {
+            filter::run(filter::FilterOptions {
+                encoding, errors, from, inputs, max_latency, min_latency, no_errors, output, sample, seed,
+                status, until, url,
+            }).await?;
         }
-        Some(Commands::Encode { output, to }) => {
-            encode::run(output, to).await?;
+        Some(Commands::Merge { dedup, encoding, inputs, output, sort }) => {
+            merge::run(merge::MergeOptions { dedup, encoding, inputs, output, sort }).await?;
         }
-        Some(Commands::Plot { output, threshold, title }) => {
-            plot::run(output, threshold, title).await?;
+        Some(Commands::Plot { format, input_encoding, inputs, output, threshold, title }) => {
+            plot::run(plot::PlotOptions { format, input_encoding, inputs, output, threshold, title }).await?;
         }
-        Some(Commands::Report { buckets, every, output, report_type }) => {
-            report::run(buckets, every, output, report_type).await?;
+        Some(Commands::Report { apdex, buckets, by, every, follow, github_summary, input_encoding, inputs, notify_format, notify_webhook, output, percentile_method, percentiles, precision, report_type, thresholds, top }) => {
+            report::run(report::ReportOptions {
+                apdex, buckets, by, every, follow, github_summary, input_encoding, inputs, notify_format,
+                notify_webhook, output, percentile_method, percentiles, precision, report_type, thresholds,
+                top,
+            }).await?;
         }
         None => {
-            println!("No command specified. Use --help for usage information.");
+            eprintln!("No command specified. Use --help for usage information.");
         }
     }
 
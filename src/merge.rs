@@ -0,0 +1,111 @@
+use anyhow::{Context, Result};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::{BufRead, BufWriter};
+
+use crate::models::Result as AttackResult;
+use crate::results::{dedup_results, read_results, sort_results, ResultReader, ResultWriter};
+use crate::utils::{get_reader, get_writer};
+
+/// Options of the merge command, one field per flag
+pub struct MergeOptions {
+    pub dedup: bool,
+    pub encoding: Option<String>,
+    pub inputs: Vec<String>,
+    pub output: String,
+    pub sort: bool,
+}
+
+/// Run the merge command with the given options
+pub async fn run(options: MergeOptions) -> Result<()> {
+    let MergeOptions { dedup, encoding, inputs, output, sort } = options;
+
+    let mut readers = inputs
+        .iter()
+        .map(|input| Ok(read_results(get_reader(input)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Unless told otherwise, write results in the encoding of the first file
+    let encoding = match encoding {
+        Some(encoding) => encoding.parse()?,
+        None => readers.first().context("No result files to merge")?.encoding(),
+    };
+    let mut writer = ResultWriter::new(BufWriter::new(get_writer(&output)?), encoding)?;
+
+    // Sorting needs every result in memory
+    if sort || dedup {
+        let mut results = Vec::new();
+        for (reader, input) in readers.into_iter().zip(&inputs) {
+            for result in reader {
+                results.push(result.context(format!("Failed to read results: {}", input))?);
+            }
+        }
+        sort_results(&mut results);
+        if dedup {
+            dedup_results(&mut results)?;
+        }
+        for result in &results {
+            writer.write(result)?;
+        }
+        writer.finish()?;
+        return Ok(());
+    }
+
+    // Start with the first result of each file, then always write the earliest result
+    // and replace it with the next one from the same file. Results are streamed rather
+    // than sorted in memory, so each file keeps its own order.
+    let mut heap = BinaryHeap::new();
+    for (index, reader) in readers.iter_mut().enumerate() {
+        if let Some(result) = next_result(reader, &inputs[index])? {
+            heap.push(Reverse(Pending { result, index }));
+        }
+    }
+
+    while let Some(Reverse(Pending { result, index })) = heap.pop() {
+        writer.write(&result)?;
+        if let Some(result) = next_result(&mut readers[index], &inputs[index])? {
+            heap.push(Reverse(Pending { result, index }));
+        }
+    }
+
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// The next result of a file that's waiting to be written
+struct Pending {
+    result: AttackResult,
+    /// Index of the file the result was read from
+    index: usize,
+}
+
+// Order by timestamp, then by file so results with equal timestamps keep the order
+// the files were given in
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.result.timestamp, self.index).cmp(&(other.result.timestamp, other.index))
+    }
+}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Pending {}
+
+/// Read the next result of a file
+fn next_result<R: BufRead>(reader: &mut ResultReader<R>, input: &str) -> Result<Option<AttackResult>> {
+    reader
+        .next()
+        .transpose()
+        .context(format!("Failed to read results: {}", input))
+}
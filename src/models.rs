@@ -1,4 +1,11 @@
+use crate::auth::{BearerToken, DigestAuth};
+use crate::cache::ValidatorCache;
+use crate::fuzz::FuzzStrategy;
+use crate::hooks::{BeforeSend, MiddlewareChain, OnResponse};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 use url::Url;
 
@@ -13,6 +20,251 @@ pub struct Target {
     pub headers: Vec<Header>,
     /// Request body
     pub body: Option<Vec<u8>>,
+    /// Expectations the response must satisfy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect: Option<Expect>,
+    /// Latency budget for requests to this target (e.g. "250ms")
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "humantime_option")]
+    pub slo: Option<Duration>,
+    /// Values captured from the response into variables for later scenario steps
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extract: Vec<Extract>,
+    /// Condition on the previous scenario step that decides whether this step runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition>,
+    /// Named assertions on the response, counted in reports without failing the request
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<Check>,
+    /// Labels the target's results can be grouped by in reports (e.g. {"endpoint": "login"})
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+}
+
+/// Represents a named assertion on a response
+///
+/// Unlike `expect`, a failed check doesn't fail the request; the passes and failures of
+/// each check are counted in reports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Check {
+    /// Name the check is reported under
+    pub name: String,
+    /// What the response must satisfy for the check to pass
+    #[serde(flatten)]
+    pub expect: Expect,
+}
+
+/// Represents a condition a scenario step's previous step must meet for the step to run
+///
+/// A step whose condition isn't met is skipped. A conditional step can also follow a
+/// failed step, e.g. to retry after a 409, and the flow goes on if its condition is met.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Condition {
+    /// Statuses the previous step must have returned, one of which must match
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub status: Vec<u16>,
+    /// Values the flow's variables must have
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+}
+
+impl Condition {
+    /// Check the condition against the status of the previous step, if one ran, and the flow's variables
+    pub fn matches(&self, status: Option<u16>, variables: &BTreeMap<String, String>) -> bool {
+        let status_matches = self.status.is_empty() || status.is_some_and(|status| self.status.contains(&status));
+        status_matches && self.variables.iter().all(|(name, value)| variables.get(name) == Some(value))
+    }
+}
+
+/// Represents a value captured from a response into a scenario variable
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Extract {
+    /// Variable name, referenced in later steps as `{{name}}`
+    pub name: String,
+    /// Where the value is read from
+    #[serde(flatten)]
+    pub source: ExtractSource,
+}
+
+/// Where an extracted value is read from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtractSource {
+    /// A JSONPath into the response body, like `$.data.items[0].id`
+    Json(String),
+    /// A regular expression matched against the response body, capturing its first group or the whole match
+    Regex(String),
+    /// A response header
+    Header(String),
+}
+
+/// Represents a user flow whose steps are requested in order, each after the previous one completes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    /// Name used to group the scenario's flows in reports
+    pub name: String,
+    /// Requests made in order; a flow stops at the first step that fails
+    pub steps: Vec<Target>,
+    /// Data whose rows are handed out one per flow, as variables
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feeder: Option<Feeder>,
+    /// Share of flows relative to the other scenarios' weights, e.g. 70, 20, and 10
+    #[serde(default = "default_weight", skip_serializing_if = "is_default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn is_default_weight(weight: &u32) -> bool {
+    *weight == 1
+}
+
+impl Scenario {
+    /// Create a scenario with the default weight and no feeder
+    pub fn new(name: &str, steps: Vec<Target>) -> Self {
+        Self {
+            name: name.to_string(),
+            steps,
+            feeder: None,
+            weight: default_weight(),
+        }
+    }
+}
+
+/// A row of feeder data, as variable names and values
+pub type Row = BTreeMap<String, String>;
+
+/// Represents a data file whose rows are handed out one per flow, e.g. unique users or orders
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Feeder {
+    /// Path to a CSV file with a header row, or a JSON array or NDJSON file of objects
+    pub file: String,
+    /// What happens to flows once every row has been used
+    #[serde(default)]
+    pub exhausted: Exhaustion,
+    /// Rows of the file, read on first use and shared between clones
+    #[serde(skip)]
+    rows: Arc<OnceLock<std::result::Result<Vec<Row>, String>>>,
+    /// Index of the next row to hand out, shared between clones
+    #[serde(skip)]
+    next: Arc<AtomicUsize>,
+}
+
+impl Feeder {
+    /// Reference a feeder file
+    pub fn new(file: &str, exhausted: Exhaustion) -> Self {
+        Self {
+            file: file.to_string(),
+            exhausted,
+            rows: Arc::default(),
+            next: Arc::default(),
+        }
+    }
+
+    /// Get the rows of the file, reading it on first use
+    pub fn rows(&self) -> std::result::Result<&[Row], &str> {
+        self.rows
+            .get_or_init(|| crate::utils::read_feeder_rows(&self.file).map_err(|e| format!("{:#}", e)))
+            .as_ref()
+            .map(Vec::as_slice)
+            .map_err(String::as_str)
+    }
+
+    /// Take the next row, or None once the rows are used up and the feeder stops
+    pub fn next_row(&self) -> std::result::Result<Option<&Row>, String> {
+        let rows = self.rows()?;
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        match rows.get(index) {
+            Some(row) => Ok(Some(row)),
+            None if rows.is_empty() => Err(format!("Feeder {} has no rows", self.file)),
+            None => match self.exhausted {
+                Exhaustion::Stop => Ok(None),
+                Exhaustion::Recycle => Ok(Some(&rows[index % rows.len()])),
+                Exhaustion::Error => Err(format!("Feeder {} is exhausted after {} rows", self.file, rows.len())),
+            },
+        }
+    }
+}
+
+/// What happens to flows once a feeder's rows have all been used
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Exhaustion {
+    /// No more flows are started, ending the attack
+    #[default]
+    Stop,
+    /// Rows are handed out again from the first
+    Recycle,
+    /// Flows fail without sending requests
+    Error,
+}
+
+/// Represents expectations about a target's response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Expect {
+    /// Expected HTTP status code, which replaces the default 2xx success check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Headers the response must carry, each containing the given value
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<Header>,
+    /// Text the response body must contain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_contains: Option<String>,
+    /// Golden response the body must match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub golden: Option<Golden>,
+    /// Expected size of the response body in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_length: Option<u64>,
+    /// Expected hex encoded SHA-256 digest of the response body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// Represents a golden response body that responses are compared against
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Golden {
+    /// Path to the file holding the golden response body
+    pub file: String,
+    /// How bodies are compared
+    #[serde(default)]
+    pub compare: GoldenCompare,
+    /// Contents of the golden file, read on first use and shared between clones
+    #[serde(skip)]
+    contents: Arc<OnceLock<std::result::Result<Vec<u8>, String>>>,
+}
+
+impl Golden {
+    /// Reference a golden response file
+    pub fn new(file: &str, compare: GoldenCompare) -> Self {
+        Self {
+            file: file.to_string(),
+            compare,
+            contents: Arc::default(),
+        }
+    }
+
+    /// Get the golden response body, reading the file on first use
+    pub fn contents(&self) -> std::result::Result<&[u8], &str> {
+        self.contents
+            .get_or_init(|| std::fs::read(&self.file).map_err(|e| e.to_string()))
+            .as_ref()
+            .map(Vec::as_slice)
+            .map_err(String::as_str)
+    }
+}
+
+/// How response bodies are compared against a golden response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GoldenCompare {
+    /// Bodies must be byte for byte identical
+    #[default]
+    Exact,
+    /// Bodies must be equal JSON documents, ignoring formatting and key order
+    Json,
 }
 
 /// Represents an HTTP header
@@ -31,29 +283,152 @@ pub struct Result {
     pub timestamp: chrono::DateTime<chrono::Utc>,
     /// How long the request took
     pub latency: Duration,
+    /// Time to first byte: how long the request took until the response headers arrived
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttfb: Option<Duration>,
     /// HTTP status code
     pub status_code: u16,
     /// Error message if the request failed
     pub error: Option<String>,
     /// The target that was requested
     pub target: Target,
-    /// Size of the response body in bytes
+    /// Content-Type of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Size of the response body in bytes, as received on the wire
     pub bytes_in: usize,
+    /// Size of the response body in bytes after content decoding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoded_bytes_in: Option<usize>,
     /// Size of the request body in bytes
     pub bytes_out: usize,
     /// Whether the request timed out
     pub timed_out: bool,
+    /// Unique ID sent in the request ID header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// W3C trace ID propagated in the traceparent header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// Seed of the fuzzing mutation applied to the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fuzz_seed: Option<u64>,
+    /// Whether the request carried validators from a previous response
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub conditional: bool,
+    /// Whether the response failed the target's expectations
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub check_failed: bool,
+    /// Whether the response body diverged from the target's golden response
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub golden_diverged: bool,
+    /// Backend timings reported by the server in the Server-Timing header
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub server_timing: Vec<ServerTiming>,
+    /// The scenario flow and step the request belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scenario: Option<ScenarioStep>,
+    /// Variables extracted from the response
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    /// Whether each of the target's named checks passed
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<String, bool>,
+    /// Custom key/values attached by response hooks
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom: BTreeMap<String, serde_json::Value>,
+    /// Name of the attack
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attack: Option<String>,
+    /// ID of the attack run the request belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// User-supplied key/values describing the run
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// Sequence number of the request, in the order requests were made
+    #[serde(default)]
+    pub seq: u64,
+    /// ID of the worker or virtual user that made the request
+    #[serde(default)]
+    pub worker: u64,
+}
+
+/// Represents one metric from a Server-Timing response header
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerTiming {
+    /// Metric name (e.g. "db")
+    pub name: String,
+    /// Reported duration in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dur: Option<f64>,
+    /// Human readable description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub desc: Option<String>,
+}
+
+/// Identifies a request as one step of a scenario flow
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioStep {
+    /// Scenario name
+    pub name: String,
+    /// Number of the flow, unique within the attack
+    pub flow: u64,
+    /// Index of the step within the scenario
+    pub step: usize,
+    /// Number of steps skipped after this one because their conditions weren't met
+    ///
+    /// Steps skipped before the first step that ran are counted on that step.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub skipped: usize,
+    /// Whether this step failed and a conditional step ran in response, so the flow went on
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub handled: bool,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+impl Result {
+    /// Whether the request succeeded (2xx status, or 304 for a conditional request, and no error)
+    ///
+    /// Targets that expect a specific status succeed only with that status.
+    pub fn is_success(&self) -> bool {
+        let status_ok = match self.target.expect.as_ref().and_then(|e| e.status) {
+            Some(expected) => self.status_code == expected,
+            None => (self.status_code >= 200 && self.status_code < 300) || self.is_not_modified(),
+        };
+        status_ok && self.error.is_none()
+    }
+
+    /// Whether a conditional request was answered with 304 Not Modified
+    pub fn is_not_modified(&self) -> bool {
+        self.conditional && self.status_code == 304
+    }
 }
 
 /// Represents metrics from a load test
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Metrics {
+    /// The runs the results came from; absent from the metrics of a group of results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<RunSummary>,
     /// Total number of requests
     pub requests: usize,
     /// Number of successful requests (2xx status)
     pub success: usize,
+    /// Number of conditional requests answered with 304 Not Modified
+    #[serde(default)]
+    pub not_modified: usize,
     /// Number of timed out requests
     pub timeouts: usize,
+    /// Number of responses that failed their target's expectations
+    #[serde(default)]
+    pub check_failures: usize,
+    /// Number of responses whose body diverged from the target's golden response
+    #[serde(default)]
+    pub golden_divergences: usize,
     /// Total duration of the test
     pub duration: Duration,
     /// Minimum latency observed
@@ -62,6 +437,12 @@ pub struct Metrics {
     pub max: Duration,
     /// Mean latency
     pub mean: Duration,
+    /// Standard deviation of latency
+    #[serde(default)]
+    pub stddev: Duration,
+    /// Coefficient of variation of latency: the standard deviation relative to the mean
+    #[serde(default)]
+    pub cv: f64,
     /// 50th percentile latency
     pub p50: Duration,
     /// 90th percentile latency
@@ -70,14 +451,290 @@ pub struct Metrics {
     pub p95: Duration,
     /// 99th percentile latency
     pub p99: Duration,
-    /// Requests per second
+    /// Latency at each percentile reported, p50, p90, p95 and p99 unless configured
+    /// otherwise
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub percentiles: Vec<PercentileLatency>,
+    /// Time to first byte of the requests that got a response, if it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttfb: Option<TtfbSummary>,
+    /// Requests sent per second, over the time from the first request to the last
     pub rate: f64,
+    /// Successful responses per second, over the time from the first request to the
+    /// last response
+    #[serde(default)]
+    pub throughput: f64,
+    /// Apdex score, if thresholds were given to rate latencies by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apdex: Option<Apdex>,
     /// Total bytes received
     pub bytes_in: usize,
     /// Total bytes sent
     pub bytes_out: usize,
+    /// Bytes received per second, over the time from the first request to the last
+    /// response
+    #[serde(default)]
+    pub bytes_in_rate: f64,
+    /// Bytes sent per second, over the time from the first request to the last response
+    #[serde(default)]
+    pub bytes_out_rate: f64,
     /// Success rate (0.0 - 1.0)
     pub success_rate: f64,
+    /// 95% confidence intervals of the mean latency and success rate, if there were any
+    /// requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<ConfidenceIntervals>,
+    /// SLO attainment for targets that declare a latency budget
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slo: Vec<SloAttainment>,
+    /// Mean server-reported duration of each Server-Timing metric
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub server_timing: Vec<ServerTimingSummary>,
+    /// Number of responses per Content-Type media type
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub content_types: BTreeMap<String, usize>,
+    /// Flow-level success and latency of each scenario
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scenarios: Vec<ScenarioSummary>,
+    /// Passes and failures of each named check
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<CheckSummary>,
+    /// Failed requests by kind of failure, most frequent first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ErrorSummary>,
+    /// Latency of each class of response: 2xx, 3xx, 4xx, 5xx, timeout and error
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub status_classes: Vec<StatusClassLatency>,
+}
+
+/// Represents the latency of one class of response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusClassLatency {
+    /// Class of response: "2xx", "3xx", "4xx", "5xx", "timeout" for requests that timed
+    /// out, or "error" for other requests that got no response
+    pub class: String,
+    /// Number of requests in the class
+    pub requests: usize,
+    /// Mean latency
+    pub mean: Duration,
+    /// 50th percentile latency
+    pub p50: Duration,
+    /// 95th percentile latency
+    pub p95: Duration,
+    /// 99th percentile latency
+    pub p99: Duration,
+}
+
+/// Represents the time to first byte of requests: how long they took until the response
+/// headers arrived, without downloading the body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TtfbSummary {
+    /// Number of requests the time to first byte was recorded for
+    pub requests: usize,
+    /// Minimum time to first byte
+    pub min: Duration,
+    /// Mean time to first byte
+    pub mean: Duration,
+    /// Standard deviation of the time to first byte
+    pub stddev: Duration,
+    /// Maximum time to first byte
+    pub max: Duration,
+    /// Time to first byte at each percentile reported
+    pub percentiles: Vec<PercentileLatency>,
+}
+
+/// Represents confidence intervals of metrics: the ranges the metrics of the system
+/// under test likely lie in, given that a run measures only a sample of its requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfidenceIntervals {
+    /// Confidence level of the intervals, e.g. 0.95
+    pub level: f64,
+    /// Lower bound of the mean latency
+    pub mean_lower: Duration,
+    /// Upper bound of the mean latency
+    pub mean_upper: Duration,
+    /// Lower bound of the success rate (0.0 - 1.0)
+    pub success_rate_lower: f64,
+    /// Upper bound of the success rate (0.0 - 1.0)
+    pub success_rate_upper: f64,
+    /// Whether there were too few requests for the intervals to be reliable
+    pub small_sample: bool,
+}
+
+/// Represents the latency at a percentile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PercentileLatency {
+    /// Percentile, from 0 to 100, e.g. 99.9
+    pub percentile: f64,
+    /// Latency at or below which the percentile of requests completed
+    pub latency: Duration,
+}
+
+/// Represents an Apdex score: how satisfied users would be with the latency of requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Apdex {
+    /// Latency up to which successful requests satisfy users, Apdex's T
+    pub satisfied_threshold: Duration,
+    /// Latency up to which successful requests are tolerated
+    pub tolerating_threshold: Duration,
+    /// Number of successful requests within the satisfied threshold
+    pub satisfied: usize,
+    /// Number of successful requests within the tolerating threshold but not the satisfied one
+    pub tolerating: usize,
+    /// Number of slower or failed requests
+    pub frustrated: usize,
+    /// (satisfied + tolerating / 2) / requests, from 0 to 1
+    pub score: f64,
+}
+
+/// Represents the runs a report's results came from, so the report can still be
+/// interpreted long after the attack
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunSummary {
+    /// Names of the attacks, in order of first appearance
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attacks: Vec<String>,
+    /// IDs of the attack runs, in order of first appearance
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub run_ids: Vec<String>,
+    /// When the first request was sent
+    pub started: chrono::DateTime<chrono::Utc>,
+    /// When the last response arrived
+    pub ended: chrono::DateTime<chrono::Utc>,
+    /// Metadata of the results; the values of a key that differ between results are joined with ", "
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// Configurations of the attacks, read from the config files next to the result files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configs: Vec<RunConfig>,
+}
+
+/// Represents the metrics of the results of one group, e.g. one target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMetrics {
+    /// What the group's results have in common, e.g. "GET https://example.com/users"
+    pub group: String,
+    /// Metrics of the group's results
+    #[serde(flatten)]
+    pub metrics: Metrics,
+}
+
+/// Represents the latency distribution over time: the number of requests of each
+/// latency bucket in each time interval, ready to render as a heatmap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heatmap {
+    /// Length of each time interval, in seconds
+    pub interval: f64,
+    /// Upper bounds of the latency buckets, in seconds; each bucket starts where the
+    /// previous one ends, and a last, unbounded bucket holds the higher latencies
+    pub buckets: Vec<f64>,
+    /// One row per time interval, including intervals without requests
+    pub rows: Vec<HeatmapRow>,
+}
+
+/// Represents the requests of one time interval of a heatmap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeatmapRow {
+    /// Start of the interval
+    pub time: chrono::DateTime<chrono::Utc>,
+    /// Number of requests sent in the interval per latency bucket, the last being unbounded
+    pub counts: Vec<usize>,
+}
+
+/// Represents the failed requests of one kind of failure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorSummary {
+    /// Kind of failure, e.g. "timeout", "connection refused" or "HTTP 503"
+    pub kind: String,
+    /// Number of requests that failed this way
+    pub count: usize,
+    /// The first error of this kind, as reported
+    pub example: String,
+}
+
+/// Represents the outcomes of one named check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckSummary {
+    /// Check name
+    pub name: String,
+    /// Number of responses that passed the check
+    pub passes: usize,
+    /// Number of responses that failed the check
+    pub fails: usize,
+}
+
+/// Represents the flows of one scenario
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioSummary {
+    /// Scenario name
+    pub name: String,
+    /// Number of flows started
+    pub flows: usize,
+    /// Number of flows whose steps all succeeded, or failed and were handled by a conditional step
+    pub success: usize,
+    /// Number of steps skipped because their conditions weren't met
+    #[serde(default)]
+    pub skipped: usize,
+    /// Mean time from the start of a flow's first step to the end of its last step
+    pub mean: Duration,
+    /// 95th percentile flow latency
+    pub p95: Duration,
+    /// Number of requests made by the scenario's flows
+    #[serde(default)]
+    pub requests: usize,
+    /// Number of those requests that succeeded
+    #[serde(default)]
+    pub request_success: usize,
+    /// Mean request latency
+    #[serde(default)]
+    pub request_mean: Duration,
+    /// 50th percentile request latency
+    #[serde(default)]
+    pub request_p50: Duration,
+    /// 95th percentile request latency
+    #[serde(default)]
+    pub request_p95: Duration,
+    /// 99th percentile request latency
+    #[serde(default)]
+    pub request_p99: Duration,
+}
+
+/// Represents the server-reported durations of one Server-Timing metric
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerTimingSummary {
+    /// Metric name
+    pub name: String,
+    /// Number of responses that reported a duration for the metric
+    pub samples: usize,
+    /// Mean reported duration
+    pub mean: Duration,
+}
+
+/// Represents how well a target met its latency budget
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SloAttainment {
+    /// The target, as "METHOD URL"
+    pub target: String,
+    /// Latency budget declared by the target
+    pub budget: Duration,
+    /// Number of requests to the target
+    pub requests: usize,
+    /// Number of successful requests completed within the budget
+    pub within_budget: usize,
+    /// Share of requests completed within the budget (0.0 - 1.0)
+    pub attainment: f64,
+}
+
+/// Represents one stage of a virtual user ramp
+///
+/// Over the stage, the number of active virtual users moves linearly from the
+/// previous stage's target (0 for the first stage) to this stage's target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stage {
+    /// How long the stage lasts
+    pub duration: Duration,
+    /// Number of active virtual users at the end of the stage
+    pub target: u64,
 }
 
 /// Represents attack parameters
@@ -105,6 +762,10 @@ pub struct AttackConfig {
     pub http2: bool,
     /// Name of the attack
     pub name: Option<String>,
+    /// ID of the attack run, attached to every result
+    pub run_id: String,
+    /// User-supplied key/values attached to every result
+    pub metadata: BTreeMap<String, String>,
     /// Maximum number of bytes to capture from response bodies
     pub max_body: i64,
     /// Cache DNS lookups for the given duration
@@ -117,4 +778,160 @@ pub struct AttackConfig {
     pub opentelemetry_addr: Option<String>,
     /// Tolerance for request rate (percentage as decimal, e.g., 0.1 for 10%)
     pub tolerance: Option<f64>,
+    /// Keep a cookie jar across requests
+    pub cookie_jar: bool,
+    /// Initial cookies in name=value form
+    pub cookies: Vec<String>,
+    /// Basic authentication credentials (user, password)
+    pub basic_auth: Option<(String, String)>,
+    /// Digest authentication credentials
+    pub digest_auth: Option<DigestAuth>,
+    /// Bearer token refreshed in the background
+    pub bearer_token: Option<BearerToken>,
+    /// Hooks invoked on every request before it is sent
+    pub before_send: Vec<BeforeSend>,
+    /// Middleware applied to every request
+    pub middleware: MiddlewareChain,
+    /// Hooks invoked on every response
+    pub on_response: Vec<OnResponse>,
+    /// Accept-Encoding header sent when a request doesn't set one [empty = none]
+    pub accept_encoding: String,
+    /// Whether to decode compressed response bodies
+    pub decompress: bool,
+    /// Header used to send a unique ID with every request
+    pub request_id_header: Option<String>,
+    /// Whether responses must echo the request ID back
+    pub verify_request_id: bool,
+    /// Validators for sending conditional requests, if enabled
+    pub conditional: Option<ValidatorCache>,
+    /// Fuzzing strategies, empty when fuzzing is disabled
+    pub fuzz: Vec<FuzzStrategy>,
+    /// Stages ramping the number of active virtual users, empty to keep them all active
+    pub stages: Vec<Stage>,
+    /// Numbers the requests of the attack in the order they're made
+    pub sequence: Arc<AtomicU64>,
+}
+
+/// The resolved configuration of an attack, written alongside its results so any
+/// results file tells how it was made and the attack can be repeated later
+///
+/// Credentials and cookies are left out, only noting which kinds of authentication
+/// were used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunConfig {
+    /// Version of culverin that ran the attack
+    pub version: String,
+    /// ID of the attack run, as attached to every result
+    pub run_id: String,
+    /// Name of the attack
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// When the attack started
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// Number of targets attacked
+    pub targets: usize,
+    /// Rate of requests (requests per second)
+    pub rate: f64,
+    /// Duration of the attack, like "5m"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+    pub timeout: String,
+    pub http_timeout: String,
+    pub workers: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_workers: Option<u64>,
+    /// Stages ramping virtual users, like "30s:10"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<String>,
+    pub keepalive: bool,
+    pub connections: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<usize>,
+    pub http2: bool,
+    pub max_body: i64,
+    pub dns_ttl: String,
+    pub laddr: String,
+    pub lazy: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<f64>,
+    pub cookie_jar: bool,
+    /// Kinds of authentication used [basic, digest, bearer]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auth: Vec<String>,
+    pub accept_encoding: String,
+    pub decompress: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id_header: Option<String>,
+    pub verify_request_id: bool,
+    pub conditional: bool,
+    /// Fuzzing strategies [query, headers, body]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fuzz: Vec<String>,
+    /// User-supplied key/values attached to every result
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl RunConfig {
+    /// Describe an attack of the given number of targets starting now
+    pub fn new(config: &AttackConfig, targets: usize) -> Self {
+        let format = |duration: Duration| humantime::format_duration(duration).to_string();
+        let auth = [
+            ("basic", config.basic_auth.is_some()),
+            ("digest", config.digest_auth.is_some()),
+            ("bearer", config.bearer_token.is_some()),
+        ];
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            run_id: config.run_id.clone(),
+            name: config.name.clone(),
+            started_at: chrono::Utc::now(),
+            targets,
+            rate: config.rate,
+            duration: config.duration.map(format),
+            timeout: format(config.timeout),
+            http_timeout: format(config.http_timeout),
+            workers: config.workers,
+            max_workers: config.max_workers,
+            stages: config.stages.iter().map(|stage| format!("{}:{}", format(stage.duration), stage.target)).collect(),
+            keepalive: config.keepalive,
+            connections: config.connections,
+            max_connections: config.max_connections,
+            http2: config.http2,
+            max_body: config.max_body,
+            dns_ttl: format(config.dns_ttl),
+            laddr: config.laddr.clone(),
+            lazy: config.lazy,
+            tolerance: config.tolerance,
+            cookie_jar: config.cookie_jar,
+            auth: auth.iter().filter(|(_, used)| *used).map(|(kind, _)| kind.to_string()).collect(),
+            accept_encoding: config.accept_encoding.clone(),
+            decompress: config.decompress,
+            request_id_header: config.request_id_header.clone(),
+            verify_request_id: config.verify_request_id,
+            conditional: config.conditional.is_some(),
+            fuzz: config.fuzz.iter().map(|strategy| format!("{:?}", strategy).to_ascii_lowercase()).collect(),
+            metadata: config.metadata.clone(),
+        }
+    }
+}
+
+/// Serialize optional durations as human readable strings like "250ms"
+mod humantime_option {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(value: &Option<Duration>, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match value {
+            Some(duration) => serializer.serialize_str(&humantime::format_duration(*duration).to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<Duration>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| humantime::parse_duration(&s).map_err(serde::de::Error::custom))
+            .transpose()
+    }
 }
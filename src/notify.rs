@@ -0,0 +1,134 @@
+// Notification of a report's summary to a webhook once the report completes, for teams
+// that want results pushed into chat rather than read from a CI log.
+//
+// The summary is posted as JSON: either culverin's own payload, which chat services
+// that accept a `text` field display as is, or a Slack message.
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::time::Duration;
+use url::Url;
+
+use crate::models::Metrics;
+use crate::threshold::Threshold;
+use crate::utils::format_duration;
+
+/// How long posting the summary may take before giving up
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Payload formats a summary can be posted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyFormat {
+    /// The summary's metrics and thresholds, along with a `text` line
+    Json,
+    /// A Slack message with the summary in Slack's markdown
+    Slack,
+}
+
+impl std::str::FromStr for NotifyFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(NotifyFormat::Json),
+            "slack" => Ok(NotifyFormat::Slack),
+            _ => anyhow::bail!("Invalid notification format: {}. Expected json or slack", s),
+        }
+    }
+}
+
+/// Post the summary of a report to a webhook: the rate, success rate and p99 latency,
+/// and whether each threshold passed
+pub async fn notify(url: &Url, format: NotifyFormat, metrics: &Metrics, thresholds: &[Threshold]) -> Result<()> {
+    let payload = match format {
+        NotifyFormat::Json => json_payload(metrics, thresholds),
+        NotifyFormat::Slack => slack_payload(metrics, thresholds),
+    };
+
+    let response = reqwest::Client::new()
+        .post(url.clone())
+        .timeout(TIMEOUT)
+        .json(&payload)
+        .send()
+        .await
+        .context(format!("Failed to notify webhook: {}", url))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Webhook {} returned {}: {}", url, status, body.trim());
+    }
+    Ok(())
+}
+
+/// Name of the attacks the results came from, to title the summary by
+fn title(metrics: &Metrics) -> String {
+    match metrics.run.as_ref().filter(|run| !run.attacks.is_empty()) {
+        Some(run) => format!("culverin report: {}", run.attacks.join(", ")),
+        None => "culverin report".to_string(),
+    }
+}
+
+/// Whether all thresholds passed, if any were given
+fn passed(metrics: &Metrics, thresholds: &[Threshold]) -> Option<bool> {
+    (!thresholds.is_empty()).then(|| thresholds.iter().all(|threshold| threshold.evaluate(metrics).0))
+}
+
+/// The headline metrics in one line
+fn headline(metrics: &Metrics) -> String {
+    format!(
+        "{} requests, {:.2} req/s, {:.2}% success, p99 {}",
+        metrics.requests,
+        metrics.rate,
+        metrics.success_rate * 100.0,
+        format_duration(metrics.p99)
+    )
+}
+
+fn json_payload(metrics: &Metrics, thresholds: &[Threshold]) -> serde_json::Value {
+    let verdict = match passed(metrics, thresholds) {
+        Some(true) => " (thresholds passed)",
+        Some(false) => " (thresholds breached)",
+        None => "",
+    };
+    json!({
+        "text": format!("{}: {}{}", title(metrics), headline(metrics), verdict),
+        "attacks": metrics.run.as_ref().map(|run| run.attacks.clone()).unwrap_or_default(),
+        "run_ids": metrics.run.as_ref().map(|run| run.run_ids.clone()).unwrap_or_default(),
+        "requests": metrics.requests,
+        "rate": metrics.rate,
+        "success_rate": metrics.success_rate,
+        "p99": metrics.p99.as_secs_f64(),
+        "passed": passed(metrics, thresholds),
+        "thresholds": thresholds
+            .iter()
+            .map(|threshold| {
+                let (passed, value) = threshold.evaluate(metrics);
+                json!({"condition": threshold.condition(), "passed": passed, "value": value})
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn slack_payload(metrics: &Metrics, thresholds: &[Threshold]) -> serde_json::Value {
+    let icon = match passed(metrics, thresholds) {
+        Some(true) => ":white_check_mark: ",
+        Some(false) => ":x: ",
+        None => "",
+    };
+    let mut lines = vec![
+        format!("{}*{}*", icon, title(metrics)),
+        format!("Rate: {:.2} req/s", metrics.rate),
+        format!("Success: {:.2}% of {} requests", metrics.success_rate * 100.0, metrics.requests),
+        format!("p99: {}", format_duration(metrics.p99)),
+    ];
+    for threshold in thresholds {
+        let (passed, value) = threshold.evaluate(metrics);
+        lines.push(format!("{} `{}` {}", if passed { "✓" } else { "✗" }, threshold.condition(), value));
+    }
+
+    json!({
+        // Shown in notifications, where blocks aren't
+        "text": format!("{}: {}", title(metrics), headline(metrics)),
+        "blocks": [{"type": "section", "text": {"type": "mrkdwn", "text": lines.join("\n")}}],
+    })
+}
@@ -0,0 +1,226 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use url::Url;
+
+use crate::models::{Check, Condition, Expect, Extract, Feeder, Header, Scenario, Stage, Target};
+use crate::utils::{check_stages, parse_rate};
+
+/// Represents a test described in one declarative YAML or TOML file
+///
+/// ```yaml
+/// rate: 20/1s
+/// duration: 1m
+/// headers:
+///   Authorization: Bearer {{token}}
+/// setup:
+///   - method: POST
+///     url: http://goku:9090/login
+///     body: '{"user": "load"}'
+///     extract:
+///       - name: token
+///         json: $.token
+/// scenarios:
+///   - name: browse
+///     steps:
+///       - url: http://goku:9090/items
+///         expect:
+///           status: 200
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    /// Request rate, like "50/1s"
+    pub rate: Option<String>,
+    /// Duration of the attack
+    pub duration: Option<humantime::Duration>,
+    /// Number of virtual users
+    pub vus: Option<u64>,
+    /// Stages ramping the number of virtual users, instead of a fixed number and duration
+    pub stages: Vec<Stage>,
+    /// Headers sent with every request
+    pub headers: Vec<Header>,
+    /// Steps run once before the attack
+    pub setup: Vec<Target>,
+    /// Targets requested in turn, when there are no scenarios
+    pub targets: Vec<Target>,
+    /// Scenarios whose flows are started in turn
+    pub scenarios: Vec<Scenario>,
+    /// Steps run once after the attack
+    pub teardown: Vec<Target>,
+}
+
+/// The plan as written in the file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlanFile {
+    rate: Option<String>,
+    duration: Option<String>,
+    vus: Option<u64>,
+    #[serde(default)]
+    stages: Vec<PlanStage>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    setup: Vec<PlanStep>,
+    #[serde(default)]
+    targets: Vec<PlanStep>,
+    #[serde(default)]
+    scenarios: Vec<PlanScenario>,
+    #[serde(default)]
+    teardown: Vec<PlanStep>,
+}
+
+/// A virtual user stage as written in the file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlanStage {
+    duration: String,
+    target: u64,
+}
+
+/// A scenario as written in the file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlanScenario {
+    name: String,
+    steps: Vec<PlanStep>,
+    feeder: Option<Feeder>,
+    weight: Option<u32>,
+}
+
+/// A request as written in the file, with a text body and headers as a map
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlanStep {
+    #[serde(default = "default_method")]
+    method: String,
+    url: Url,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    body: Option<String>,
+    expect: Option<Expect>,
+    slo: Option<String>,
+    #[serde(default)]
+    extract: Vec<Extract>,
+    when: Option<Condition>,
+    #[serde(default)]
+    checks: Vec<Check>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+impl Plan {
+    /// Read a plan, choosing YAML or TOML by the file extension
+    pub fn load(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path).context(format!("Failed to read scenario file: {}", path))?;
+
+        let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or_default();
+        let file: PlanFile = match extension.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => serde_yaml::from_str(&content).context(format!("Failed to parse scenario file: {}", path))?,
+            "toml" => toml::from_str(&content).context(format!("Failed to parse scenario file: {}", path))?,
+            _ => anyhow::bail!("Unsupported scenario file: {}. Expected a .yaml, .yml, or .toml file", path),
+        };
+
+        if let Some(rate) = &file.rate {
+            parse_rate(rate).context(format!("Invalid rate in scenario file: {}", rate))?;
+        }
+
+        let duration = file
+            .duration
+            .as_deref()
+            .map(|d| d.parse::<humantime::Duration>().context(format!("Invalid duration: {}", d)))
+            .transpose()?;
+
+        let stages = file
+            .stages
+            .iter()
+            .map(|stage| {
+                Ok(Stage {
+                    duration: humantime::parse_duration(&stage.duration)
+                        .context(format!("Invalid stage duration: {}", stage.duration))?,
+                    target: stage.target,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if !stages.is_empty() {
+            if file.vus.is_some() || file.duration.is_some() {
+                anyhow::bail!("Scenario file can't have stages together with vus or duration");
+            }
+            check_stages(&stages)?;
+        }
+
+        let scenarios = file
+            .scenarios
+            .into_iter()
+            .map(|scenario| {
+                if scenario.steps.is_empty() {
+                    anyhow::bail!("Scenario {} has no steps", scenario.name);
+                }
+                if scenario.weight == Some(0) {
+                    anyhow::bail!("Scenario {} must have a weight of at least 1", scenario.name);
+                }
+                Ok(Scenario {
+                    name: scenario.name,
+                    steps: to_targets(scenario.steps)?,
+                    feeder: scenario.feeder,
+                    weight: scenario.weight.unwrap_or(1),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if scenarios.is_empty() == file.targets.is_empty() {
+            anyhow::bail!("Scenario file must have either targets or scenarios");
+        }
+
+        Ok(Self {
+            rate: file.rate,
+            duration,
+            vus: file.vus,
+            stages,
+            headers: to_headers(file.headers),
+            setup: to_targets(file.setup)?,
+            targets: to_targets(file.targets)?,
+            scenarios,
+            teardown: to_targets(file.teardown)?,
+        })
+    }
+}
+
+/// Convert steps as written in the file to targets
+fn to_targets(steps: Vec<PlanStep>) -> Result<Vec<Target>> {
+    steps
+        .into_iter()
+        .map(|step| {
+            let slo = step
+                .slo
+                .as_deref()
+                .map(|s| s.parse::<humantime::Duration>().map(Duration::from).context(format!("Invalid SLO: {}", s)))
+                .transpose()?;
+            let method = step.method.to_uppercase();
+            reqwest::Method::from_bytes(method.as_bytes()).context(format!("Invalid method: {}", step.method))?;
+
+            Ok(Target {
+                method,
+                url: step.url,
+                headers: to_headers(step.headers),
+                body: step.body.map(String::into_bytes),
+                expect: step.expect,
+                slo,
+                extract: step.extract,
+                when: step.when,
+                checks: step.checks,
+                tags: step.tags,
+            })
+        })
+        .collect()
+}
+
+fn to_headers(headers: BTreeMap<String, String>) -> Vec<Header> {
+    headers.into_iter().map(|(name, value)| Header { name, value }).collect()
+}
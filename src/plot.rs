@@ -1,71 +1,397 @@
-use anyhow::Result;
-use std::io::{BufRead, Write};
-// use std::time::Duration;
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
 
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+use crate::histogram::{LatencyHistogram, DEFAULT_PRECISION};
 use crate::models::Result as AttackResult;
-use crate::utils::{get_reader, get_writer};
+use crate::report::log_spaced_buckets;
+use crate::results::{collect_results, read_results_as, InputEncoding};
+use crate::utils::{format_duration, get_reader, get_writer};
+
+/// Formats a plot can be rendered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlotFormat {
+    /// An HTML page of interactive charts
+    Html,
+    /// Whitespace-delimited data files and a gnuplot script plotting them
+    Gnuplot,
+    /// A static SVG image of the charts
+    Svg,
+    /// A static PNG image of the charts
+    Png,
+}
+
+impl std::str::FromStr for PlotFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "html" => Ok(PlotFormat::Html),
+            "gnuplot" => Ok(PlotFormat::Gnuplot),
+            "svg" => Ok(PlotFormat::Svg),
+            "png" => Ok(PlotFormat::Png),
+            _ => anyhow::bail!("Invalid plot format: {}. Expected html, gnuplot, svg or png", s),
+        }
+    }
+}
+
+/// Options of the plot command, one field per flag
+pub struct PlotOptions {
+    pub format: String,
+    pub input_encoding: String,
+    pub inputs: Vec<String>,
+    pub output: String,
+    pub threshold: usize,
+    pub title: String,
+}
+
+/// Run the plot command with the given options
+pub async fn run(options: PlotOptions) -> Result<()> {
+    let PlotOptions { format, input_encoding, inputs, output, threshold, title } = options;
+
+    // Read from stdin if no files are given
+    let inputs = if inputs.is_empty() { vec!["stdin".to_string()] } else { inputs };
+    let input_encoding: InputEncoding = input_encoding.parse()?;
+    let format: PlotFormat = format.parse()?;
+    if format == PlotFormat::Gnuplot && output == "stdout" {
+        anyhow::bail!("--format=gnuplot writes data files next to the script, so it needs --output");
+    }
+    if format == PlotFormat::Png && output == "stdout" {
+        anyhow::bail!("--format=png writes an image file, so it needs --output");
+    }
+
+    // Get readers
+    let readers = inputs
+        .iter()
+        .map(|input| read_results_as(get_reader(input)?, input_encoding))
+        .collect::<Result<Vec<_>>>()?;
 
-/// Run the plot command with the given arguments
-pub async fn run(
-    output: String,
-    threshold: usize,
-    title: String,
-) -> Result<()> {
-    // Get reader and writer
-    let reader = get_reader("stdin")?;
-    let mut writer = get_writer(&output)?;
+    // Parse results, combining those of every input, in the order requests were sent
+    let mut results = collect_results(readers)?;
+    results.sort_by_key(|r| r.timestamp);
 
-    // Generate the plot
-    generate_plot(reader, &mut writer, threshold, &title)?;
+    match format {
+        PlotFormat::Html => generate_plot(&split_runs(results), &mut get_writer(&output)?, threshold, &title)?,
+        PlotFormat::Gnuplot => generate_gnuplot(&results, &output, threshold, &title)?,
+        PlotFormat::Svg => {
+            let mut svg = String::new();
+            draw_plot(SVGBackend::with_string(&mut svg, IMAGE_SIZE).into_drawing_area(), &split_runs(results), threshold, &title)?;
+            let mut writer = get_writer(&output)?;
+            writer.write_all(svg.as_bytes())?;
+            writer.flush()?;
+        }
+        PlotFormat::Png => {
+            let root = BitMapBackend::new(&output, IMAGE_SIZE).into_drawing_area();
+            draw_plot(root, &split_runs(results), threshold, &title)
+                .context(format!("Failed to write plot image: {}", output))?;
+        }
+    }
 
     Ok(())
 }
 
-/// Generate an HTML plot from attack results
-fn generate_plot<R: BufRead, W: Write>(
-    reader: R,
-    writer: &mut W,
-    threshold: usize,
-    title: &str,
-) -> Result<()> {
-    // Parse results
-    let mut results: Vec<AttackResult> = reader
-        .lines()
-        .filter_map(|line| {
-            let line = line.ok()?;
-            serde_json::from_str(&line).ok()
+/// Every n-th of the results sorted by timestamp, so that no more than about
+/// `threshold` points are plotted
+fn downsample(results: &[AttackResult], threshold: usize) -> Vec<&AttackResult> {
+    let factor = results.len().div_ceil(threshold.max(1)).max(1);
+    results.iter().step_by(factor).collect()
+}
+
+/// Counts of the requests sent in one second
+#[derive(Debug, Clone, Copy, Default)]
+struct SecondCounts {
+    requests: usize,
+    successes: usize,
+    timeouts: usize,
+    bytes_in: usize,
+    bytes_out: usize,
+}
+
+impl SecondCounts {
+    /// Percentage of the requests that failed, if any were sent
+    fn error_percent(&self) -> Option<f64> {
+        (self.requests > 0).then(|| (self.requests - self.successes) as f64 * 100.0 / self.requests as f64)
+    }
+
+    /// Percentage of the requests that timed out, if any were sent
+    fn timeout_percent(&self) -> Option<f64> {
+        (self.requests > 0).then(|| self.timeouts as f64 * 100.0 / self.requests as f64)
+    }
+}
+
+/// Counts of the requests sent in each second from the first request on, including
+/// seconds without requests
+fn per_second_series(results: &[AttackResult]) -> Vec<SecondCounts> {
+    let Some(start) = results.iter().map(|r| r.timestamp).min() else {
+        return Vec::new();
+    };
+
+    let mut series: Vec<SecondCounts> = Vec::new();
+    for result in results {
+        let second = (result.timestamp - start).num_seconds().max(0) as usize;
+        if series.len() <= second {
+            series.resize(second + 1, SecondCounts::default());
+        }
+        let counts = &mut series[second];
+        counts.requests += 1;
+        if result.is_success() {
+            counts.successes += 1;
+        }
+        if result.timed_out {
+            counts.timeouts += 1;
+        }
+        counts.bytes_in += result.bytes_in;
+        counts.bytes_out += result.bytes_out;
+    }
+    series
+}
+
+/// Number of buckets of the latency histogram
+const HISTOGRAM_BUCKETS: i32 = 50;
+
+/// Highest percentile of the percentile distribution, as a fraction; the highest latency
+/// is plotted there
+const MAX_PERCENTILE: f64 = 0.99999;
+
+/// Colors of the runs overlaid in a plot, Plotly's default palette
+const RUN_COLORS: [RGBColor; 10] = [
+    RGBColor(0x1f, 0x77, 0xb4),
+    RGBColor(0xff, 0x7f, 0x0e),
+    RGBColor(0x2c, 0xa0, 0x2c),
+    RGBColor(0xd6, 0x27, 0x28),
+    RGBColor(0x94, 0x67, 0xbd),
+    RGBColor(0x8c, 0x56, 0x4b),
+    RGBColor(0xe3, 0x77, 0xc2),
+    RGBColor(0x7f, 0x7f, 0x7f),
+    RGBColor(0xbc, 0xbd, 0x22),
+    RGBColor(0x17, 0xbe, 0xcf),
+];
+
+/// The results of one attack run, plotted as its own series in each chart
+struct Run {
+    /// Name of the run in legends: its attack name or run ID
+    label: String,
+    /// The run's results, sorted by timestamp
+    results: Vec<AttackResult>,
+}
+
+/// Attack name and run ID of a run
+type RunKey = (Option<String>, Option<String>);
+
+/// Split results sorted by timestamp into runs by attack name and run ID, in the order
+/// runs started. Results of the machines of a distributed attack share a run ID, so they
+/// stay in one run
+fn split_runs(results: Vec<AttackResult>) -> Vec<Run> {
+    let mut runs: Vec<(RunKey, Vec<AttackResult>)> = Vec::new();
+    for result in results {
+        let key = (result.attack.clone(), result.run_id.clone());
+        match runs.iter_mut().find(|(run, _)| *run == key) {
+            Some((_, run_results)) => run_results.push(result),
+            None => runs.push((key, vec![result])),
+        }
+    }
+
+    let name = |(attack, run_id): &RunKey| {
+        attack.clone().or_else(|| run_id.clone()).unwrap_or_else(|| "results".to_string())
+    };
+    let names: Vec<String> = runs.iter().map(|(key, _)| name(key)).collect();
+    runs.into_iter()
+        .zip(&names)
+        .map(|((key, results), label)| {
+            // Tell apart runs of the same attack by their run IDs
+            let label = match &key {
+                (Some(_), Some(run_id)) if names.iter().filter(|name| *name == label).count() > 1 => {
+                    format!("{} ({})", label, run_id)
+                }
+                _ => label.clone(),
+            };
+            Run { label, results }
         })
-        .collect();
-
-    // Sort results by timestamp
-    results.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
-
-    // Downsample if necessary
-    if results.len() > threshold {
-        let factor = results.len() / threshold;
-        results = results
-            .into_iter()
-            .enumerate()
-            .filter(|(i, _)| i % factor == 0)
-            .map(|(_, r)| r)
-            .collect();
+        .collect()
+}
+
+/// Histogram buckets shared by runs: the lowest latency and the upper bounds of buckets
+/// spaced on a log scale up to the highest, so that a long tail doesn't squash the fast
+/// requests
+fn histogram_buckets(runs: &[Run]) -> (Duration, Vec<Duration>) {
+    let latencies = runs.iter().flat_map(|run| &run.results).map(|r| r.latency);
+    let lowest = latencies.clone().min().unwrap_or_default();
+    (lowest, log_spaced_buckets(lowest, latencies.max().unwrap_or_default(), HISTOGRAM_BUCKETS))
+}
+
+/// Share of the results in each bucket in percent, at the lower bound of each bucket in
+/// milliseconds and once more at the upper bound of the last, to be drawn as steps
+fn latency_distribution(results: &[AttackResult], lowest: Duration, buckets: &[Duration]) -> Vec<(f64, f64)> {
+    let mut counts = vec![0usize; buckets.len()];
+    for result in results {
+        let bucket = buckets.iter().position(|&bucket| result.latency < bucket).unwrap_or(buckets.len() - 1);
+        counts[bucket] += 1;
     }
+    let shares = counts.iter().chain(counts.last()).map(|&count| count as f64 * 100.0 / results.len() as f64);
+    std::iter::once(lowest)
+        .chain(buckets.iter().copied())
+        .map(|bound| bound.as_secs_f64() * 1000.0)
+        .zip(shares)
+        .collect()
+}
 
-    // Extract data for plotting
-    let timestamps: Vec<f64> = results
-        .iter()
-        .map(|r| r.timestamp.timestamp_millis() as f64 / 1000.0)
-        .collect();
+/// Latency in milliseconds by percentile as HdrHistogram plots it, over 1 / (1 - percentile)
+/// so that on a log scale each nine of the tail gets as much room as the one before
+fn percentile_distribution(results: &[AttackResult]) -> Result<Vec<(f64, f64)>> {
+    let histogram = LatencyHistogram::from_latencies(results.iter().map(|r| r.latency), DEFAULT_PRECISION)?;
+    Ok(histogram
+        .spectrum(5)
+        .into_iter()
+        .map(|(value, level, _)| (1.0 / (1.0 - level.min(MAX_PERCENTILE)), value.as_secs_f64() * 1000.0))
+        .collect())
+}
 
-    let latencies: Vec<f64> = results
+/// Seconds since the first of the results sorted by timestamp
+fn elapsed_seconds(results: &[&AttackResult]) -> Vec<f64> {
+    let start = results.first().map(|r| r.timestamp);
+    results
         .iter()
-        .map(|r| r.latency.as_secs_f64() * 1000.0) // Convert to milliseconds
-        .collect();
+        .map(|r| start.map_or(0.0, |start| (r.timestamp - start).num_milliseconds() as f64 / 1000.0))
+        .collect()
+}
 
-    let status_codes: Vec<u16> = results
-        .iter()
-        .map(|r| r.status_code)
-        .collect();
+/// Durations in milliseconds of each metric servers reported in Server-Timing headers,
+/// for each of the results
+fn server_timings<'a>(results: &[&'a AttackResult]) -> Vec<(&'a str, Vec<Option<f64>>)> {
+    let mut names: Vec<&str> = Vec::new();
+    for timing in results.iter().flat_map(|r| &r.server_timing) {
+        if timing.dur.is_some() && !names.contains(&timing.name.as_str()) {
+            names.push(&timing.name);
+        }
+    }
+    names
+        .into_iter()
+        .map(|name| {
+            let durations = results
+                .iter()
+                .map(|r| r.server_timing.iter().find(|t| t.name == name).and_then(|t| t.dur))
+                .collect();
+            (name, durations)
+        })
+        .collect()
+}
+
+/// Generate an HTML plot from runs of attack results, overlaying the runs in each chart
+/// with the time since each run started
+fn generate_plot<W: Write>(runs: &[Run], writer: &mut W, threshold: usize, title: &str) -> Result<()> {
+    let overlay = runs.len() > 1;
+    let mut latency_traces: Vec<serde_json::Value> = Vec::new();
+    let mut throughput_traces: Vec<serde_json::Value> = Vec::new();
+    let mut error_traces: Vec<serde_json::Value> = Vec::new();
+    let mut bandwidth_traces: Vec<serde_json::Value> = Vec::new();
+    let mut status_traces: Vec<serde_json::Value> = Vec::new();
+    let mut histogram_traces: Vec<serde_json::Value> = Vec::new();
+    let mut percentile_traces: Vec<serde_json::Value> = Vec::new();
+
+    let (lowest, buckets) = histogram_buckets(runs);
+
+    for (index, run) in runs.iter().enumerate() {
+        // Runs are told apart by color, and the series of a run by dashes
+        let color = overlay.then(|| {
+            let RGBColor(red, green, blue) = RUN_COLORS[index % RUN_COLORS.len()];
+            format!("#{:02x}{:02x}{:02x}", red, green, blue)
+        });
+        let name = |series: &str| if overlay { format!("{}: {}", run.label, series) } else { series.to_string() };
+        let colored = |mut style: serde_json::Value| {
+            if let Some(color) = &color {
+                style["color"] = color.as_str().into();
+            }
+            style
+        };
+        let line = |dash: &str, shape: &str| colored(serde_json::json!({"dash": dash, "shape": shape}));
+
+        // Count requests per second over all results, so throughput and error rates don't
+        // depend on downsampling
+        let per_second = per_second_series(&run.results);
+        let seconds: Vec<usize> = (0..per_second.len()).collect();
+        let per_second_trace = |series: &str, dash: &str, values: Vec<Option<f64>>| {
+            serde_json::json!({
+                "x": seconds,
+                // Seconds without requests have no error rate, and are left as gaps
+                "y": values,
+                "type": "scatter",
+                "mode": "lines",
+                "line": line(dash, "hv"),
+                "name": name(series),
+            })
+        };
+        let counts = |count: fn(&SecondCounts) -> Option<f64>| per_second.iter().map(count).collect::<Vec<_>>();
+        throughput_traces.push(per_second_trace("Requests/s", "solid", counts(|c| Some(c.requests as f64))));
+        throughput_traces.push(per_second_trace("Successes/s", "dot", counts(|c| Some(c.successes as f64))));
+        error_traces.push(per_second_trace("Errors (%)", "solid", counts(SecondCounts::error_percent)));
+        error_traces.push(per_second_trace("Timeouts (%)", "dot", counts(SecondCounts::timeout_percent)));
+        bandwidth_traces.push(per_second_trace("Received (MB/s)", "solid", counts(|c| Some(c.bytes_in as f64 / 1e6))));
+        bandwidth_traces.push(per_second_trace("Sent (MB/s)", "dot", counts(|c| Some(c.bytes_out as f64 / 1e6))));
+
+        let (bounds, shares): (Vec<f64>, Vec<f64>) = latency_distribution(&run.results, lowest, &buckets).into_iter().unzip();
+        histogram_traces.push(serde_json::json!({
+            "x": bounds,
+            "y": shares,
+            "type": "scatter",
+            "mode": "lines",
+            "line": line("solid", "hv"),
+            "fill": "tozeroy",
+            "name": name("Requests (%)"),
+        }));
+
+        let (levels, percentiles): (Vec<f64>, Vec<f64>) = percentile_distribution(&run.results)?.into_iter().unzip();
+        percentile_traces.push(serde_json::json!({
+            "x": levels,
+            "y": percentiles,
+            "type": "scatter",
+            "mode": "lines",
+            "line": line("solid", "linear"),
+            "name": name("Latency (ms)"),
+        }));
+
+        // Downsample if necessary
+        let results = downsample(&run.results, threshold);
+        let timestamps = elapsed_seconds(&results);
+        let latencies: Vec<f64> = results
+            .iter()
+            .map(|r| r.latency.as_secs_f64() * 1000.0) // Convert to milliseconds
+            .collect();
+        let status_codes: Vec<u16> = results.iter().map(|r| r.status_code).collect();
+
+        latency_traces.push(serde_json::json!({
+            "x": timestamps,
+            "y": latencies,
+            "type": "scatter",
+            "mode": "lines",
+            "line": line("solid", "linear"),
+            "name": name("Latency (ms)"),
+        }));
+
+        // Plot server-reported timings next to the client-observed latency
+        for (server_timing_name, durations) in server_timings(&results) {
+            latency_traces.push(serde_json::json!({
+                "x": timestamps,
+                "y": durations,
+                "type": "scatter",
+                "mode": "lines",
+                "line": line("dash", "linear"),
+                "name": name(&format!("Server: {} (ms)", server_timing_name)),
+            }));
+        }
+
+        status_traces.push(serde_json::json!({
+            "x": timestamps,
+            "y": status_codes,
+            "type": "scatter",
+            "mode": "markers",
+            "marker": colored(serde_json::json!({"size": 5})),
+            "name": name("Status Codes"),
+        }));
+    }
 
     // Generate HTML
     let html = format!(
@@ -85,50 +411,93 @@ fn generate_plot<R: BufRead, W: Write>(
     <h1>{title}</h1>
 
     <div id="latency-plot" class="plot"></div>
+    <div id="throughput-plot" class="plot"></div>
+    <div id="error-plot" class="plot"></div>
+    <div id="bandwidth-plot" class="plot"></div>
     <div id="status-plot" class="plot"></div>
+    <div id="histogram-plot" class="plot"></div>
+    <div id="percentile-plot" class="plot"></div>
 
     <script>
         // Latency plot
-        var latencyData = {{
-            x: {timestamps:?},
-            y: {latencies:?},
-            type: 'scatter',
-            mode: 'lines',
-            name: 'Latency (ms)'
-        }};
-
         var latencyLayout = {{
             title: 'Request Latencies',
             xaxis: {{ title: 'Time (s)' }},
             yaxis: {{ title: 'Latency (ms)' }}
         }};
 
-        Plotly.newPlot('latency-plot', [latencyData], latencyLayout);
+        Plotly.newPlot('latency-plot', {latency_traces}, latencyLayout);
 
-        // Status code plot
-        var statusData = {{
-            x: {timestamps:?},
-            y: {status_codes:?},
-            type: 'scatter',
-            mode: 'markers',
-            marker: {{ size: 5 }},
-            name: 'Status Codes'
+        // Throughput plot
+        var throughputLayout = {{
+            title: 'Throughput',
+            xaxis: {{ title: 'Time (s)' }},
+            yaxis: {{ title: 'Requests per second', rangemode: 'tozero' }}
+        }};
+
+        Plotly.newPlot('throughput-plot', {throughput_traces}, throughputLayout);
+
+        // Error rate plot
+        var errorLayout = {{
+            title: 'Error Rate',
+            xaxis: {{ title: 'Time (s)' }},
+            yaxis: {{ title: 'Requests (%)', range: [0, 100] }}
         }};
 
+        Plotly.newPlot('error-plot', {error_traces}, errorLayout);
+
+        // Bandwidth plot
+        var bandwidthLayout = {{
+            title: 'Bandwidth',
+            xaxis: {{ title: 'Time (s)' }},
+            yaxis: {{ title: 'MB/s', rangemode: 'tozero' }}
+        }};
+
+        Plotly.newPlot('bandwidth-plot', {bandwidth_traces}, bandwidthLayout);
+
+        // Status code plot
         var statusLayout = {{
             title: 'Response Status Codes',
             xaxis: {{ title: 'Time (s)' }},
             yaxis: {{ title: 'Status Code' }}
         }};
 
-        Plotly.newPlot('status-plot', [statusData], statusLayout);
+        Plotly.newPlot('status-plot', {status_traces}, statusLayout);
+
+        // Latency histogram
+        var histogramLayout = {{
+            title: 'Latency Distribution',
+            xaxis: {{ title: 'Latency (ms)', type: 'log' }},
+            yaxis: {{ title: 'Requests (%)', rangemode: 'tozero' }}
+        }};
+
+        Plotly.newPlot('histogram-plot', {histogram_traces}, histogramLayout);
+
+        // Latency by percentile distribution
+        var percentileLayout = {{
+            title: 'Latency by Percentile Distribution',
+            xaxis: {{
+                title: 'Percentile',
+                type: 'log',
+                range: [0, 5],
+                tickvals: [1, 2, 10, 100, 1000, 10000, 100000],
+                ticktext: ['0%', '50%', '90%', '99%', '99.9%', '99.99%', '99.999%']
+            }},
+            yaxis: {{ title: 'Latency (ms)', rangemode: 'tozero' }}
+        }};
+
+        Plotly.newPlot('percentile-plot', {percentile_traces}, percentileLayout);
     </script>
 </body>
 </html>"#,
         title = title,
-        timestamps = timestamps,
-        latencies = latencies,
-        status_codes = status_codes
+        latency_traces = serde_json::to_string(&latency_traces)?,
+        throughput_traces = serde_json::to_string(&throughput_traces)?,
+        error_traces = serde_json::to_string(&error_traces)?,
+        bandwidth_traces = serde_json::to_string(&bandwidth_traces)?,
+        status_traces = serde_json::to_string(&status_traces)?,
+        histogram_traces = serde_json::to_string(&histogram_traces)?,
+        percentile_traces = serde_json::to_string(&percentile_traces)?
     );
 
     // Write HTML to output
@@ -136,3 +505,259 @@ fn generate_plot<R: BufRead, W: Write>(
 
     Ok(())
 }
+
+/// Size of static plot images in pixels
+const IMAGE_SIZE: (u32, u32) = (1600, 2000);
+
+/// How a series is drawn in a static image
+#[derive(Debug, Clone, Copy)]
+enum SeriesStyle {
+    Line,
+    Dashed,
+    Points,
+}
+
+/// A series of a chart in a static image
+struct Series {
+    label: String,
+    color: RGBColor,
+    style: SeriesStyle,
+    points: Vec<(f64, f64)>,
+}
+
+/// Horizontal axes of the charts in a static image
+#[derive(Debug, Clone, Copy)]
+enum XAxis {
+    /// Seconds since the run started
+    Seconds,
+    /// Latency in milliseconds, plotted as its logarithm
+    LogLatency,
+    /// Percentile, plotted as the logarithm of 1 / (1 - percentile)
+    LogPercentile,
+}
+
+impl XAxis {
+    fn label(&self, x: f64) -> String {
+        // Labels have up to three decimals, without trailing zeros
+        let decimals = |value: f64| format!("{:.3}", value).trim_end_matches('0').trim_end_matches('.').to_string();
+        match self {
+            XAxis::Seconds => decimals(x),
+            XAxis::LogLatency => format_duration(Duration::from_secs_f64(10f64.powf(x) / 1000.0)),
+            XAxis::LogPercentile => format!("{}%", decimals((1.0 - 10f64.powf(-x)) * 100.0)),
+        }
+    }
+}
+
+/// Draw the charts of the HTML plot onto a static image, the latencies over time across
+/// the top and the other charts in a grid below, overlaying the runs in each chart
+fn draw_plot<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, runs: &[Run], threshold: usize, title: &str) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let overlay = runs.len() > 1;
+    let mut latency: Vec<Series> = Vec::new();
+    let mut throughput: Vec<Series> = Vec::new();
+    let mut errors: Vec<Series> = Vec::new();
+    let mut bandwidth: Vec<Series> = Vec::new();
+    let mut status: Vec<Series> = Vec::new();
+    let mut histogram: Vec<Series> = Vec::new();
+    let mut percentiles: Vec<Series> = Vec::new();
+
+    let (lowest, buckets) = histogram_buckets(runs);
+    for (index, run) in runs.iter().enumerate() {
+        // Runs are told apart by color, and the series of a run by dashes
+        let color = RUN_COLORS[index % RUN_COLORS.len()];
+        let series = |name: &str, style: SeriesStyle, points: Vec<(f64, f64)>| Series {
+            label: if overlay { format!("{}: {}", run.label, name) } else { name.to_string() },
+            color,
+            style,
+            points,
+        };
+
+        let per_second = per_second_series(&run.results);
+        // Counts are drawn as steps lasting their second. Seconds without requests have no
+        // error rate, and are skipped
+        let counts = |count: fn(&SecondCounts) -> Option<f64>| {
+            per_second
+                .iter()
+                .enumerate()
+                .filter_map(|(second, counts)| Some((second as f64, count(counts)?)))
+                .flat_map(|(second, value)| [(second, value), (second + 1.0, value)])
+                .collect::<Vec<_>>()
+        };
+        throughput.push(series("Requests/s", SeriesStyle::Line, counts(|c| Some(c.requests as f64))));
+        throughput.push(series("Successes/s", SeriesStyle::Dashed, counts(|c| Some(c.successes as f64))));
+        errors.push(series("Errors (%)", SeriesStyle::Line, counts(SecondCounts::error_percent)));
+        errors.push(series("Timeouts (%)", SeriesStyle::Dashed, counts(SecondCounts::timeout_percent)));
+        bandwidth.push(series("Received (MB/s)", SeriesStyle::Line, counts(|c| Some(c.bytes_in as f64 / 1e6))));
+        bandwidth.push(series("Sent (MB/s)", SeriesStyle::Dashed, counts(|c| Some(c.bytes_out as f64 / 1e6))));
+
+        // Log scales are drawn as the logarithms of the values on linear axes
+        let log = |points: Vec<(f64, f64)>| points.into_iter().map(|(x, y)| (x.log10(), y)).collect();
+        let steps = latency_distribution(&run.results, lowest, &buckets)
+            .windows(2)
+            .flat_map(|bucket| [(bucket[0].0, bucket[0].1), (bucket[1].0, bucket[0].1)])
+            .collect();
+        histogram.push(series("Requests (%)", SeriesStyle::Line, log(steps)));
+        percentiles.push(series("Latency (ms)", SeriesStyle::Line, log(percentile_distribution(&run.results)?)));
+
+        let results = downsample(&run.results, threshold);
+        let timestamps = elapsed_seconds(&results);
+        let points = |values: Vec<Option<f64>>| {
+            timestamps.iter().zip(values).filter_map(|(&x, y)| Some((x, y?))).collect::<Vec<_>>()
+        };
+        latency.push(series(
+            "Latency (ms)",
+            SeriesStyle::Line,
+            points(results.iter().map(|r| Some(r.latency.as_secs_f64() * 1000.0)).collect()),
+        ));
+        for (server_timing_name, durations) in server_timings(&results) {
+            latency.push(series(&format!("Server: {} (ms)", server_timing_name), SeriesStyle::Dashed, points(durations)));
+        }
+        status.push(series(
+            "Status Codes",
+            SeriesStyle::Points,
+            points(results.iter().map(|r| Some(r.status_code as f64)).collect()),
+        ));
+    }
+
+    root.fill(&WHITE)?;
+    let root = root.titled(title, ("sans-serif", 32))?;
+    let (top, bottom) = root.split_vertically(25.percent_height());
+    draw_chart(&top, "Request Latencies", "Time (s)", "Latency (ms)", XAxis::Seconds, &latency)?;
+    let charts = bottom.split_evenly((3, 2));
+    draw_chart(&charts[0], "Throughput", "Time (s)", "Requests per second", XAxis::Seconds, &throughput)?;
+    draw_chart(&charts[1], "Error Rate", "Time (s)", "Requests (%)", XAxis::Seconds, &errors)?;
+    draw_chart(&charts[2], "Bandwidth", "Time (s)", "MB/s", XAxis::Seconds, &bandwidth)?;
+    draw_chart(&charts[3], "Response Status Codes", "Time (s)", "Status Code", XAxis::Seconds, &status)?;
+    draw_chart(&charts[4], "Latency Distribution", "Latency", "Requests (%)", XAxis::LogLatency, &histogram)?;
+    draw_chart(&charts[5], "Latency by Percentile Distribution", "Percentile", "Latency (ms)", XAxis::LogPercentile, &percentiles)?;
+    root.present()?;
+
+    Ok(())
+}
+
+/// Draw a chart of the given series, with the vertical axis starting at zero
+fn draw_chart<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    title: &str,
+    x_desc: &str,
+    y_desc: &str,
+    x_axis: XAxis,
+    series: &[Series],
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let points = || series.iter().flat_map(|series| &series.points);
+    let x_min = points().map(|&(x, _)| x).fold(f64::INFINITY, f64::min);
+    let x_max = points().map(|&(x, _)| x).fold(f64::NEG_INFINITY, f64::max);
+    let y_max = points().map(|&(_, y)| y).fold(0.0, f64::max);
+    // Keep the ranges of charts with no or a single point from being empty
+    let (x_min, x_max) = if x_min < x_max { (x_min, x_max) } else if x_min.is_finite() { (x_min, x_min + 1.0) } else { (0.0, 1.0) };
+    let y_max = if y_max > 0.0 { y_max * 1.05 } else { 1.0 };
+
+    let mut chart = ChartBuilder::on(area)
+        .caption(title, ("sans-serif", 22))
+        .margin(15)
+        .x_label_area_size(40)
+        .y_label_area_size(70)
+        .build_cartesian_2d(x_min..x_max, 0.0..y_max)?;
+    chart
+        .configure_mesh()
+        .x_desc(x_desc)
+        .y_desc(y_desc)
+        .x_label_formatter(&|&x| x_axis.label(x))
+        .draw()?;
+
+    for series in series {
+        let color = series.color;
+        let points = series.points.iter().copied();
+        let annotation = match series.style {
+            SeriesStyle::Line => chart.draw_series(LineSeries::new(points, color.stroke_width(2)))?,
+            SeriesStyle::Dashed => chart.draw_series(DashedLineSeries::new(points, 6, 4, color.stroke_width(2)))?,
+            SeriesStyle::Points => chart.draw_series(points.map(|point| Circle::new(point, 2, color.filled())))?,
+        };
+        annotation
+            .label(&series.label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+    }
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+/// Path of a data file written next to a gnuplot script, e.g. "plot.latency.dat" for "plot.gp"
+fn data_path(script: &str, series: &str) -> String {
+    let path = Path::new(script);
+    let stem = path.file_stem().map_or_else(|| script.into(), |stem| stem.to_string_lossy());
+    path.with_file_name(format!("{}.{}.dat", stem, series)).to_string_lossy().into_owned()
+}
+
+/// Write attack results sorted by timestamp as whitespace-delimited data files, latencies
+/// and throughput over the seconds since the first request, and a gnuplot script that
+/// plots them to an SVG named after the script
+fn generate_gnuplot(results: &[AttackResult], output: &str, threshold: usize, title: &str) -> Result<()> {
+    let start = results.first().map(|r| r.timestamp);
+    let seconds = |r: &AttackResult| start.map_or(0.0, |start| (r.timestamp - start).num_microseconds().unwrap_or_default() as f64 / 1e6);
+
+    let latency_path = data_path(output, "latency");
+    let mut writer = get_writer(&latency_path)?;
+    writeln!(writer, "# time_s latency_ms status")?;
+    for result in downsample(results, threshold) {
+        writeln!(writer, "{:.6} {:.3} {}", seconds(result), result.latency.as_secs_f64() * 1000.0, result.status_code)?;
+    }
+    writer.flush()?;
+
+    let throughput_path = data_path(output, "throughput");
+    let mut writer = get_writer(&throughput_path)?;
+    writeln!(writer, "# time_s requests_per_s successes_per_s")?;
+    for (second, counts) in per_second_series(results).into_iter().enumerate() {
+        writeln!(writer, "{} {} {}", second, counts.requests, counts.successes)?;
+    }
+    writer.flush()?;
+
+    // The script refers to the data files by name, so it runs from the directory it's in
+    let name = |path: &str| Path::new(path).file_name().map_or_else(|| path.to_string(), |name| name.to_string_lossy().into_owned());
+    let image = Path::new(output).with_extension("svg");
+    let quote = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+    let mut writer = get_writer(output)?;
+    write!(
+        writer,
+        r#"# Plot of culverin results; run with: gnuplot {script}
+set terminal svg size 1000,800 dynamic noenhanced font "Arial,12"
+set output "{image}"
+set multiplot layout 2,1 title "{title}"
+set grid
+set key top left
+
+set title "Request Latencies"
+set xlabel "Time (s)"
+set ylabel "Latency (ms)"
+plot "{latency}" using 1:2 with points pointtype 7 pointsize 0.3 title "Latency (ms)"
+
+set title "Throughput"
+set xlabel "Time (s)"
+set ylabel "Requests per second"
+set yrange [0:*]
+plot "{throughput}" using 1:2 with steps title "Requests/s", \
+     "{throughput}" using 1:3 with steps title "Successes/s"
+
+unset multiplot
+"#,
+        script = name(output),
+        image = quote(&name(&image.to_string_lossy())),
+        title = quote(title),
+        latency = quote(&name(&latency_path)),
+        throughput = quote(&name(&throughput_path)),
+    )
+    .context(format!("Failed to write gnuplot script: {}", output))?;
+    writer.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,303 @@
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+use url::Url;
+use wasmi::{Config, Engine, Instance, Linker, Memory, Module, Store, TypedFunc};
+
+use crate::hooks::{RequestMiddleware, RequestSpec, ResponseData};
+use crate::models::{Header, Result as AttackResult, Target};
+
+/// Fuel given to each plugin call, roughly the number of instructions it may execute
+const FUEL_PER_CALL: u64 = 100_000_000;
+
+/// A sandboxed WASM plugin hooked into every request of an attack
+///
+/// Plugins have no imports, so they can only compute on the data they are given.
+/// A plugin module exports its `memory` and `alloc(len: i32) -> i32`, which returns
+/// where culverin may write `len` bytes of input, plus any of:
+///
+/// - `pre_request(ptr: i32, len: i32) -> i64`: receives a request as JSON
+///   (`{"method", "url", "headers": [{"name", "value"}], "body": [bytes] | null}`) and returns
+///   the request to send instead, `{"error": "..."}` to fail it, or nothing to send it as is
+/// - `post_response(ptr: i32, len: i32) -> i64`: receives a response as JSON
+///   (`{"status", "headers", "body"}`) and returns `{"error": "..."}` to fail the request,
+///   `{"custom": {...}}` to attach values to its result, or nothing to accept it
+/// - `targets() -> i64`: returns targets in the JSON target format, used instead of the targets file
+///
+/// Functions return their output as `(ptr << 32) | len`, or 0 for nothing. If the plugin
+/// exports `dealloc(ptr: i32, len: i32)`, it is called once culverin is done with a buffer.
+/// Calls to a plugin are serialized, and each may run about 100 million instructions.
+pub struct Plugin {
+    name: String,
+    instance: Mutex<PluginInstance>,
+    has_pre_request: bool,
+    has_post_response: bool,
+    has_targets: bool,
+}
+
+/// A plugin module instantiated in its own store
+struct PluginInstance {
+    store: Store<()>,
+    instance: Instance,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    dealloc: Option<TypedFunc<(i32, i32), ()>>,
+}
+
+/// A request as exchanged with plugins
+#[derive(Debug, Serialize, Deserialize)]
+struct PluginRequest {
+    method: String,
+    url: Url,
+    #[serde(default)]
+    headers: Vec<Header>,
+    #[serde(default)]
+    body: Option<Vec<u8>>,
+}
+
+/// What `pre_request` may return
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PreRequestOutput {
+    Error { error: String },
+    Request(PluginRequest),
+}
+
+/// A response as given to plugins
+#[derive(Debug, Serialize)]
+struct PluginResponse<'a> {
+    status: u16,
+    headers: &'a [Header],
+    body: &'a [u8],
+}
+
+/// What `post_response` may return
+#[derive(Debug, Default, Deserialize)]
+struct PostResponseOutput {
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    custom: BTreeMap<String, Value>,
+}
+
+impl Plugin {
+    /// Load and instantiate a plugin from WASM bytes
+    pub fn new(name: &str, wasm: &[u8]) -> Result<Self> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+
+        let module = Module::new(&engine, wasm).map_err(|e| anyhow::anyhow!("Invalid WASM module: {}", e))?;
+        let mut store = Store::new(&engine, ());
+        let instance = Linker::<()>::new(&engine)
+            .instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|e| anyhow::anyhow!("Failed to instantiate plugin (plugins can't have imports): {}", e))?;
+
+        let memory = instance.get_memory(&store, "memory").context("Plugin must export its memory")?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&store, "alloc")
+            .map_err(|e| anyhow::anyhow!("Plugin must export alloc(len: i32) -> i32: {}", e))?;
+        let dealloc = instance.get_typed_func::<(i32, i32), ()>(&store, "dealloc").ok();
+
+        let has_pre_request = instance.get_typed_func::<(i32, i32), i64>(&store, "pre_request").is_ok();
+        let has_post_response = instance.get_typed_func::<(i32, i32), i64>(&store, "post_response").is_ok();
+        let has_targets = instance.get_typed_func::<(), i64>(&store, "targets").is_ok();
+        if !has_pre_request && !has_post_response && !has_targets {
+            anyhow::bail!("Plugin must export pre_request, post_response, or targets");
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            instance: Mutex::new(PluginInstance {
+                store,
+                instance,
+                memory,
+                alloc,
+                dealloc,
+            }),
+            has_pre_request,
+            has_post_response,
+            has_targets,
+        })
+    }
+
+    /// Load a plugin from a .wasm file, named after the file
+    pub fn load(path: &str) -> Result<Self> {
+        let wasm = std::fs::read(path).context(format!("Failed to read plugin: {}", path))?;
+        let name = Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or(path);
+        Self::new(name, &wasm).context(format!("Invalid plugin: {}", path))
+    }
+
+    /// Name of the plugin
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the plugin exports `pre_request`
+    pub fn has_pre_request(&self) -> bool {
+        self.has_pre_request
+    }
+
+    /// Whether the plugin exports `post_response`
+    pub fn has_post_response(&self) -> bool {
+        self.has_post_response
+    }
+
+    /// Whether the plugin exports `targets`
+    pub fn has_targets(&self) -> bool {
+        self.has_targets
+    }
+
+    /// Get the targets the plugin provides
+    pub fn targets(&self) -> Result<Vec<Target>> {
+        if !self.has_targets {
+            return Ok(Vec::new());
+        }
+
+        match self.call("targets", None)? {
+            Some(output) => serde_json::from_slice(&output).context(format!("Plugin {} returned invalid targets", self.name)),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Let the plugin's `pre_request` modify or reject a request
+    pub fn pre_request(&self, request: &mut RequestSpec) -> Result<()> {
+        if !self.has_pre_request {
+            return Ok(());
+        }
+
+        let input = serde_json::to_vec(&PluginRequest {
+            method: request.method.clone(),
+            url: request.url.clone(),
+            headers: request.headers.clone(),
+            body: request.body.clone(),
+        })?;
+
+        let Some(output) = self.call("pre_request", Some(&input))? else {
+            return Ok(());
+        };
+        match serde_json::from_slice(&output).context(format!("Plugin {} returned an invalid request", self.name))? {
+            PreRequestOutput::Error { error } => anyhow::bail!("Plugin {} rejected the request: {}", self.name, error),
+            PreRequestOutput::Request(replacement) => {
+                reqwest::Method::from_bytes(replacement.method.as_bytes())
+                    .context(format!("Plugin {} returned an invalid method: {}", self.name, replacement.method))?;
+                request.method = replacement.method;
+                request.url = replacement.url;
+                request.headers = replacement.headers;
+                request.body = replacement.body;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Let the plugin's `post_response` validate a response and annotate its result
+    pub fn post_response(&self, response: &ResponseData, result: &mut AttackResult) {
+        if !self.has_post_response {
+            return;
+        }
+
+        let outcome = serde_json::to_vec(&PluginResponse {
+            status: response.status_code,
+            headers: &response.headers,
+            body: &response.body,
+        })
+        .map_err(anyhow::Error::from)
+        .and_then(|input| self.call("post_response", Some(&input)))
+        .and_then(|output| match output {
+            Some(output) => serde_json::from_slice::<PostResponseOutput>(&output)
+                .context(format!("Plugin {} returned an invalid response outcome", self.name)),
+            None => Ok(PostResponseOutput::default()),
+        });
+
+        let error = match outcome {
+            Ok(outcome) => {
+                result.custom.extend(outcome.custom);
+                outcome.error.map(|e| format!("Plugin {} rejected the response: {}", self.name, e))
+            }
+            Err(e) => Some(e.to_string()),
+        };
+
+        if let Some(error) = error {
+            result.check_failed = true;
+            result.error.get_or_insert(error);
+        }
+    }
+
+    /// Call an exported function, passing the input through the plugin's memory
+    fn call(&self, export: &str, input: Option<&[u8]>) -> Result<Option<Vec<u8>>> {
+        let mut guard = self.instance.lock().unwrap();
+        let plugin = &mut *guard;
+        plugin.store.set_fuel(FUEL_PER_CALL).map_err(|e| anyhow::anyhow!("{}", e))?;
+
+        let fail = |e: wasmi::Error| anyhow::anyhow!("Plugin {} {} failed: {}", self.name, export, e);
+
+        let packed = match input {
+            Some(input) => {
+                let len = i32::try_from(input.len()).context("Plugin input is too large")?;
+                let ptr = plugin.alloc.call(&mut plugin.store, len).map_err(fail)?;
+                plugin
+                    .memory
+                    .write(&mut plugin.store, ptr as u32 as usize, input)
+                    .map_err(|e| anyhow::anyhow!("Plugin {} alloc returned an invalid buffer: {}", self.name, e))?;
+
+                let func = plugin
+                    .instance
+                    .get_typed_func::<(i32, i32), i64>(&plugin.store, export)
+                    .map_err(fail)?;
+                let packed = func.call(&mut plugin.store, (ptr, len)).map_err(fail)?;
+                if let Some(dealloc) = &plugin.dealloc {
+                    dealloc.call(&mut plugin.store, (ptr, len)).map_err(fail)?;
+                }
+                packed
+            }
+            None => {
+                let func = plugin.instance.get_typed_func::<(), i64>(&plugin.store, export).map_err(fail)?;
+                func.call(&mut plugin.store, ()).map_err(fail)?
+            }
+        };
+
+        if packed == 0 {
+            return Ok(None);
+        }
+
+        let (ptr, len) = ((packed >> 32) as u32, packed as u32);
+        // Check the buffer is in the plugin's memory before allocating a copy of it
+        if ptr as usize + len as usize > plugin.memory.data(&plugin.store).len() {
+            anyhow::bail!("Plugin {} {} returned a buffer outside its memory", self.name, export);
+        }
+        let mut output = vec![0; len as usize];
+        plugin
+            .memory
+            .read(&plugin.store, ptr as usize, &mut output)
+            .map_err(|e| anyhow::anyhow!("Plugin {} {} returned an invalid buffer: {}", self.name, export, e))?;
+        if let Some(dealloc) = &plugin.dealloc {
+            dealloc.call(&mut plugin.store, (ptr as i32, len as i32)).map_err(fail)?;
+        }
+
+        Ok(Some(output))
+    }
+}
+
+impl RequestMiddleware for Plugin {
+    fn before_request<'a>(&'a self, request: &'a mut RequestSpec) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.pre_request(request) })
+    }
+}
+
+impl fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Plugin")
+            .field("name", &self.name)
+            .field("pre_request", &self.has_pre_request)
+            .field("post_response", &self.has_post_response)
+            .field("targets", &self.has_targets)
+            .finish()
+    }
+}
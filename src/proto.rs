@@ -0,0 +1,570 @@
+// Protobuf messages of `proto/culverin.proto`, for encoding results and metrics
+//
+// The messages are written out by hand rather than generated, so building culverin
+// doesn't need protoc. Keep them in sync with the schema.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::models;
+
+/// The result of a single request
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Result {
+    #[prost(int64, tag = "1")]
+    pub timestamp: i64,
+    #[prost(uint64, tag = "2")]
+    pub latency: u64,
+    #[prost(uint32, tag = "3")]
+    pub status_code: u32,
+    #[prost(string, optional, tag = "4")]
+    pub error: Option<String>,
+    #[prost(message, optional, tag = "5")]
+    pub target: Option<Target>,
+    #[prost(string, optional, tag = "6")]
+    pub content_type: Option<String>,
+    #[prost(uint64, tag = "7")]
+    pub bytes_in: u64,
+    #[prost(uint64, optional, tag = "8")]
+    pub decoded_bytes_in: Option<u64>,
+    #[prost(uint64, tag = "9")]
+    pub bytes_out: u64,
+    #[prost(bool, tag = "10")]
+    pub timed_out: bool,
+    #[prost(string, optional, tag = "11")]
+    pub request_id: Option<String>,
+    #[prost(string, optional, tag = "12")]
+    pub trace_id: Option<String>,
+    #[prost(uint64, optional, tag = "13")]
+    pub fuzz_seed: Option<u64>,
+    #[prost(bool, tag = "14")]
+    pub conditional: bool,
+    #[prost(bool, tag = "15")]
+    pub check_failed: bool,
+    #[prost(bool, tag = "16")]
+    pub golden_diverged: bool,
+    #[prost(message, repeated, tag = "17")]
+    pub server_timing: Vec<ServerTiming>,
+    #[prost(message, optional, tag = "18")]
+    pub scenario: Option<ScenarioStep>,
+    #[prost(btree_map = "string, string", tag = "19")]
+    pub variables: BTreeMap<String, String>,
+    #[prost(btree_map = "string, bool", tag = "20")]
+    pub checks: BTreeMap<String, bool>,
+    #[prost(btree_map = "string, string", tag = "21")]
+    pub custom: BTreeMap<String, String>,
+    #[prost(string, optional, tag = "22")]
+    pub attack: Option<String>,
+    #[prost(string, optional, tag = "23")]
+    pub run_id: Option<String>,
+    #[prost(btree_map = "string, string", tag = "24")]
+    pub metadata: BTreeMap<String, String>,
+    #[prost(uint64, tag = "25")]
+    pub seq: u64,
+    #[prost(uint64, tag = "26")]
+    pub worker: u64,
+    #[prost(uint64, optional, tag = "27")]
+    pub ttfb: Option<u64>,
+}
+
+/// A request target
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Target {
+    #[prost(string, tag = "1")]
+    pub method: String,
+    #[prost(string, tag = "2")]
+    pub url: String,
+    #[prost(message, repeated, tag = "3")]
+    pub headers: Vec<Header>,
+    #[prost(bytes = "vec", optional, tag = "4")]
+    pub body: Option<Vec<u8>>,
+    #[prost(btree_map = "string, string", tag = "5")]
+    pub tags: BTreeMap<String, String>,
+}
+
+/// An HTTP header
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Header {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+/// One metric from a Server-Timing response header
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerTiming {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(double, optional, tag = "2")]
+    pub dur: Option<f64>,
+    #[prost(string, optional, tag = "3")]
+    pub desc: Option<String>,
+}
+
+/// Identifies a request as one step of a scenario flow
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScenarioStep {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(uint64, tag = "2")]
+    pub flow: u64,
+    #[prost(uint64, tag = "3")]
+    pub step: u64,
+    #[prost(uint64, tag = "4")]
+    pub skipped: u64,
+    #[prost(bool, tag = "5")]
+    pub handled: bool,
+}
+
+/// Metrics calculated from the results of an attack
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Metrics {
+    #[prost(uint64, tag = "1")]
+    pub requests: u64,
+    #[prost(uint64, tag = "2")]
+    pub success: u64,
+    #[prost(uint64, tag = "3")]
+    pub not_modified: u64,
+    #[prost(uint64, tag = "4")]
+    pub timeouts: u64,
+    #[prost(uint64, tag = "5")]
+    pub check_failures: u64,
+    #[prost(uint64, tag = "6")]
+    pub golden_divergences: u64,
+    #[prost(uint64, tag = "7")]
+    pub duration: u64,
+    #[prost(uint64, tag = "8")]
+    pub min: u64,
+    #[prost(uint64, tag = "9")]
+    pub max: u64,
+    #[prost(uint64, tag = "10")]
+    pub mean: u64,
+    #[prost(uint64, tag = "11")]
+    pub p50: u64,
+    #[prost(uint64, tag = "12")]
+    pub p90: u64,
+    #[prost(uint64, tag = "13")]
+    pub p95: u64,
+    #[prost(uint64, tag = "14")]
+    pub p99: u64,
+    #[prost(double, tag = "15")]
+    pub rate: f64,
+    #[prost(uint64, tag = "16")]
+    pub bytes_in: u64,
+    #[prost(uint64, tag = "17")]
+    pub bytes_out: u64,
+    #[prost(double, tag = "18")]
+    pub success_rate: f64,
+    #[prost(message, repeated, tag = "19")]
+    pub slo: Vec<SloAttainment>,
+    #[prost(message, repeated, tag = "20")]
+    pub server_timing: Vec<ServerTimingSummary>,
+    #[prost(btree_map = "string, uint64", tag = "21")]
+    pub content_types: BTreeMap<String, u64>,
+    #[prost(message, repeated, tag = "22")]
+    pub scenarios: Vec<ScenarioSummary>,
+    #[prost(message, repeated, tag = "23")]
+    pub checks: Vec<CheckSummary>,
+    #[prost(message, repeated, tag = "24")]
+    pub errors: Vec<ErrorSummary>,
+    #[prost(double, tag = "25")]
+    pub throughput: f64,
+    #[prost(message, repeated, tag = "26")]
+    pub status_classes: Vec<StatusClassLatency>,
+    #[prost(message, optional, tag = "27")]
+    pub apdex: Option<Apdex>,
+    #[prost(uint64, tag = "28")]
+    pub stddev: u64,
+    #[prost(double, tag = "29")]
+    pub cv: f64,
+    #[prost(message, repeated, tag = "30")]
+    pub percentiles: Vec<PercentileLatency>,
+    #[prost(double, tag = "31")]
+    pub bytes_in_rate: f64,
+    #[prost(double, tag = "32")]
+    pub bytes_out_rate: f64,
+    #[prost(message, optional, tag = "33")]
+    pub ttfb: Option<TtfbSummary>,
+    #[prost(message, optional, tag = "34")]
+    pub confidence: Option<ConfidenceIntervals>,
+    #[prost(message, optional, tag = "35")]
+    pub run: Option<RunSummary>,
+}
+
+/// The runs a report's results came from
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RunSummary {
+    #[prost(string, repeated, tag = "1")]
+    pub attacks: Vec<String>,
+    #[prost(string, repeated, tag = "2")]
+    pub run_ids: Vec<String>,
+    #[prost(int64, tag = "3")]
+    pub started: i64,
+    #[prost(int64, tag = "4")]
+    pub ended: i64,
+    #[prost(btree_map = "string, string", tag = "5")]
+    pub metadata: BTreeMap<String, String>,
+    #[prost(string, repeated, tag = "6")]
+    pub configs: Vec<String>,
+}
+
+/// Ranges the mean latency and success rate likely lie in
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ConfidenceIntervals {
+    #[prost(double, tag = "1")]
+    pub level: f64,
+    #[prost(uint64, tag = "2")]
+    pub mean_lower: u64,
+    #[prost(uint64, tag = "3")]
+    pub mean_upper: u64,
+    #[prost(double, tag = "4")]
+    pub success_rate_lower: f64,
+    #[prost(double, tag = "5")]
+    pub success_rate_upper: f64,
+    #[prost(bool, tag = "6")]
+    pub small_sample: bool,
+}
+
+/// Time to first byte of the requests that got a response
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TtfbSummary {
+    #[prost(uint64, tag = "1")]
+    pub requests: u64,
+    #[prost(uint64, tag = "2")]
+    pub min: u64,
+    #[prost(uint64, tag = "3")]
+    pub mean: u64,
+    #[prost(uint64, tag = "4")]
+    pub stddev: u64,
+    #[prost(uint64, tag = "5")]
+    pub max: u64,
+    #[prost(message, repeated, tag = "6")]
+    pub percentiles: Vec<PercentileLatency>,
+}
+
+/// Latency at a percentile, from 0 to 100
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PercentileLatency {
+    #[prost(double, tag = "1")]
+    pub percentile: f64,
+    #[prost(uint64, tag = "2")]
+    pub latency: u64,
+}
+
+/// How satisfied users would be with the latency of requests
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Apdex {
+    #[prost(uint64, tag = "1")]
+    pub satisfied_threshold: u64,
+    #[prost(uint64, tag = "2")]
+    pub tolerating_threshold: u64,
+    #[prost(uint64, tag = "3")]
+    pub satisfied: u64,
+    #[prost(uint64, tag = "4")]
+    pub tolerating: u64,
+    #[prost(uint64, tag = "5")]
+    pub frustrated: u64,
+    #[prost(double, tag = "6")]
+    pub score: f64,
+}
+
+/// How well a target met its latency budget
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SloAttainment {
+    #[prost(string, tag = "1")]
+    pub target: String,
+    #[prost(uint64, tag = "2")]
+    pub budget: u64,
+    #[prost(uint64, tag = "3")]
+    pub requests: u64,
+    #[prost(uint64, tag = "4")]
+    pub within_budget: u64,
+    #[prost(double, tag = "5")]
+    pub attainment: f64,
+}
+
+/// Server-reported durations of one Server-Timing metric
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerTimingSummary {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(uint64, tag = "2")]
+    pub samples: u64,
+    #[prost(uint64, tag = "3")]
+    pub mean: u64,
+}
+
+/// The flows and requests of one scenario
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScenarioSummary {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(uint64, tag = "2")]
+    pub flows: u64,
+    #[prost(uint64, tag = "3")]
+    pub success: u64,
+    #[prost(uint64, tag = "4")]
+    pub skipped: u64,
+    #[prost(uint64, tag = "5")]
+    pub mean: u64,
+    #[prost(uint64, tag = "6")]
+    pub p95: u64,
+    #[prost(uint64, tag = "7")]
+    pub requests: u64,
+    #[prost(uint64, tag = "8")]
+    pub request_success: u64,
+    #[prost(uint64, tag = "9")]
+    pub request_mean: u64,
+    #[prost(uint64, tag = "10")]
+    pub request_p50: u64,
+    #[prost(uint64, tag = "11")]
+    pub request_p95: u64,
+    #[prost(uint64, tag = "12")]
+    pub request_p99: u64,
+}
+
+/// Passes and failures of one named check
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CheckSummary {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(uint64, tag = "2")]
+    pub passes: u64,
+    #[prost(uint64, tag = "3")]
+    pub fails: u64,
+}
+
+/// Latency of one class of response
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StatusClassLatency {
+    #[prost(string, tag = "1")]
+    pub class: String,
+    #[prost(uint64, tag = "2")]
+    pub requests: u64,
+    #[prost(uint64, tag = "3")]
+    pub mean: u64,
+    #[prost(uint64, tag = "4")]
+    pub p50: u64,
+    #[prost(uint64, tag = "5")]
+    pub p95: u64,
+    #[prost(uint64, tag = "6")]
+    pub p99: u64,
+}
+
+/// Failed requests of one kind of failure
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ErrorSummary {
+    #[prost(string, tag = "1")]
+    pub kind: String,
+    #[prost(uint64, tag = "2")]
+    pub count: u64,
+    #[prost(string, tag = "3")]
+    pub example: String,
+}
+
+/// A duration in nanoseconds, saturating at u64::MAX
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+impl From<&models::Result> for Result {
+    fn from(result: &models::Result) -> Self {
+        Self {
+            timestamp: result.timestamp.timestamp_nanos_opt().unwrap_or_default(),
+            latency: nanos(result.latency),
+            status_code: result.status_code as u32,
+            error: result.error.clone(),
+            target: Some(Target {
+                method: result.target.method.clone(),
+                url: result.target.url.to_string(),
+                headers: result
+                    .target
+                    .headers
+                    .iter()
+                    .map(|h| Header {
+                        name: h.name.clone(),
+                        value: h.value.clone(),
+                    })
+                    .collect(),
+                body: result.target.body.clone(),
+                tags: result.target.tags.clone(),
+            }),
+            content_type: result.content_type.clone(),
+            bytes_in: result.bytes_in as u64,
+            decoded_bytes_in: result.decoded_bytes_in.map(|b| b as u64),
+            bytes_out: result.bytes_out as u64,
+            timed_out: result.timed_out,
+            request_id: result.request_id.clone(),
+            trace_id: result.trace_id.clone(),
+            fuzz_seed: result.fuzz_seed,
+            conditional: result.conditional,
+            check_failed: result.check_failed,
+            golden_diverged: result.golden_diverged,
+            server_timing: result
+                .server_timing
+                .iter()
+                .map(|t| ServerTiming {
+                    name: t.name.clone(),
+                    dur: t.dur,
+                    desc: t.desc.clone(),
+                })
+                .collect(),
+            scenario: result.scenario.as_ref().map(|s| ScenarioStep {
+                name: s.name.clone(),
+                flow: s.flow,
+                step: s.step as u64,
+                skipped: s.skipped as u64,
+                handled: s.handled,
+            }),
+            variables: result.variables.clone(),
+            checks: result.checks.clone(),
+            custom: result.custom.iter().map(|(k, v)| (k.clone(), v.to_string())).collect(),
+            attack: result.attack.clone(),
+            run_id: result.run_id.clone(),
+            metadata: result.metadata.clone(),
+            seq: result.seq,
+            worker: result.worker,
+            ttfb: result.ttfb.map(nanos),
+        }
+    }
+}
+
+fn percentile_latency(p: &models::PercentileLatency) -> PercentileLatency {
+    PercentileLatency {
+        percentile: p.percentile,
+        latency: nanos(p.latency),
+    }
+}
+
+impl From<&models::Metrics> for Metrics {
+    fn from(metrics: &models::Metrics) -> Self {
+        Self {
+            run: metrics.run.as_ref().map(|r| RunSummary {
+                attacks: r.attacks.clone(),
+                run_ids: r.run_ids.clone(),
+                started: r.started.timestamp_nanos_opt().unwrap_or_default(),
+                ended: r.ended.timestamp_nanos_opt().unwrap_or_default(),
+                metadata: r.metadata.clone(),
+                configs: r.configs.iter().filter_map(|c| serde_json::to_string(c).ok()).collect(),
+            }),
+            requests: metrics.requests as u64,
+            success: metrics.success as u64,
+            not_modified: metrics.not_modified as u64,
+            timeouts: metrics.timeouts as u64,
+            check_failures: metrics.check_failures as u64,
+            golden_divergences: metrics.golden_divergences as u64,
+            duration: nanos(metrics.duration),
+            min: nanos(metrics.min),
+            max: nanos(metrics.max),
+            mean: nanos(metrics.mean),
+            stddev: nanos(metrics.stddev),
+            cv: metrics.cv,
+            p50: nanos(metrics.p50),
+            p90: nanos(metrics.p90),
+            p95: nanos(metrics.p95),
+            p99: nanos(metrics.p99),
+            percentiles: metrics.percentiles.iter().map(percentile_latency).collect(),
+            rate: metrics.rate,
+            throughput: metrics.throughput,
+            apdex: metrics.apdex.as_ref().map(|a| Apdex {
+                satisfied_threshold: nanos(a.satisfied_threshold),
+                tolerating_threshold: nanos(a.tolerating_threshold),
+                satisfied: a.satisfied as u64,
+                tolerating: a.tolerating as u64,
+                frustrated: a.frustrated as u64,
+                score: a.score,
+            }),
+            bytes_in: metrics.bytes_in as u64,
+            bytes_out: metrics.bytes_out as u64,
+            bytes_in_rate: metrics.bytes_in_rate,
+            bytes_out_rate: metrics.bytes_out_rate,
+            ttfb: metrics.ttfb.as_ref().map(|t| TtfbSummary {
+                requests: t.requests as u64,
+                min: nanos(t.min),
+                mean: nanos(t.mean),
+                stddev: nanos(t.stddev),
+                max: nanos(t.max),
+                percentiles: t.percentiles.iter().map(percentile_latency).collect(),
+            }),
+            confidence: metrics.confidence.as_ref().map(|c| ConfidenceIntervals {
+                level: c.level,
+                mean_lower: nanos(c.mean_lower),
+                mean_upper: nanos(c.mean_upper),
+                success_rate_lower: c.success_rate_lower,
+                success_rate_upper: c.success_rate_upper,
+                small_sample: c.small_sample,
+            }),
+            success_rate: metrics.success_rate,
+            slo: metrics
+                .slo
+                .iter()
+                .map(|s| SloAttainment {
+                    target: s.target.clone(),
+                    budget: nanos(s.budget),
+                    requests: s.requests as u64,
+                    within_budget: s.within_budget as u64,
+                    attainment: s.attainment,
+                })
+                .collect(),
+            server_timing: metrics
+                .server_timing
+                .iter()
+                .map(|t| ServerTimingSummary {
+                    name: t.name.clone(),
+                    samples: t.samples as u64,
+                    mean: nanos(t.mean),
+                })
+                .collect(),
+            content_types: metrics.content_types.iter().map(|(k, v)| (k.clone(), *v as u64)).collect(),
+            scenarios: metrics
+                .scenarios
+                .iter()
+                .map(|s| ScenarioSummary {
+                    name: s.name.clone(),
+                    flows: s.flows as u64,
+                    success: s.success as u64,
+                    skipped: s.skipped as u64,
+                    mean: nanos(s.mean),
+                    p95: nanos(s.p95),
+                    requests: s.requests as u64,
+                    request_success: s.request_success as u64,
+                    request_mean: nanos(s.request_mean),
+                    request_p50: nanos(s.request_p50),
+                    request_p95: nanos(s.request_p95),
+                    request_p99: nanos(s.request_p99),
+                })
+                .collect(),
+            checks: metrics
+                .checks
+                .iter()
+                .map(|c| CheckSummary {
+                    name: c.name.clone(),
+                    passes: c.passes as u64,
+                    fails: c.fails as u64,
+                })
+                .collect(),
+            errors: metrics
+                .errors
+                .iter()
+                .map(|e| ErrorSummary {
+                    kind: e.kind.clone(),
+                    count: e.count as u64,
+                    example: e.example.clone(),
+                })
+                .collect(),
+            status_classes: metrics
+                .status_classes
+                .iter()
+                .map(|c| StatusClassLatency {
+                    class: c.class.clone(),
+                    requests: c.requests as u64,
+                    mean: nanos(c.mean),
+                    p50: nanos(c.p50),
+                    p95: nanos(c.p95),
+                    p99: nanos(c.p99),
+                })
+                .collect(),
+        }
+    }
+}
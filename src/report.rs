@@ -1,151 +1,1362 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use prost::Message;
 // use serde::{Deserialize, Serialize};
-use std::io::{BufRead, Write};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{IsTerminal, Read, Seek, SeekFrom, Write};
 use std::time::Duration;
+use url::Url;
 
-use crate::models::{Metrics, Result as AttackResult};
-use crate::utils::{format_duration, format_size, get_reader, get_writer};
+use crate::histogram::{LatencyHistogram, PercentileMethod, DEFAULT_PRECISION};
+use crate::models::{Apdex, CheckSummary, ConfidenceIntervals, ErrorSummary, GroupMetrics, Heatmap, HeatmapRow, Metrics, PercentileLatency, Result as AttackResult, RunConfig, RunSummary, ScenarioSummary, ServerTimingSummary, SloAttainment, StatusClassLatency, TtfbSummary};
+use crate::proto;
+use crate::results::{collect_results, read_configs, read_results_as, target_key, BinaryFollower, InputEncoding, MAGIC};
+use crate::notify::{notify, NotifyFormat};
+use crate::threshold::{check_thresholds, parse_thresholds, Threshold};
+use crate::utils::{format_duration, format_size, get_reader, get_writer, is_compressed};
 
-/// Run the report command with the given arguments
-pub async fn run(
-    buckets: Option<String>,
-    every: Option<humantime::Duration>,
-    output: String,
-    report_type: String,
-) -> Result<()> {
-    // Get reader and writer
-    let reader = get_reader("stdin")?;
+/// Options of the report command, one field per flag
+pub struct ReportOptions {
+    pub apdex: Option<String>,
+    pub buckets: Option<String>,
+    pub by: Option<String>,
+    pub every: Option<humantime::Duration>,
+    pub follow: Option<String>,
+    pub github_summary: bool,
+    pub input_encoding: String,
+    pub inputs: Vec<String>,
+    pub notify_format: String,
+    pub notify_webhook: Option<String>,
+    pub output: String,
+    pub percentile_method: String,
+    pub percentiles: String,
+    pub precision: u8,
+    pub report_type: String,
+    pub thresholds: Option<String>,
+    pub top: usize,
+}
+
+/// Run the report command with the given options
+pub async fn run(options: ReportOptions) -> Result<()> {
+    let ReportOptions {
+        apdex, buckets, by, every, follow, github_summary, input_encoding, inputs, notify_format,
+        notify_webhook, output, percentile_method, percentiles, precision, report_type, thresholds, top,
+    } = options;
+
+    // Fail on invalid options before reading any results
+    LatencyHistogram::new(precision)?;
+    let options = MetricsOptions {
+        precision,
+        percentiles: parse_percentiles(&percentiles)?,
+        percentile_method: percentile_method.parse()?,
+        apdex: apdex.map(|apdex| apdex.parse()).transpose()?,
+    };
+    let mut by: Option<GroupBy> = by.map(|by| by.parse()).transpose()?;
+    let thresholds = thresholds.as_deref().map(parse_thresholds).transpose()?;
+    let buckets = buckets.as_deref().map(parse_buckets).transpose()?;
+    let input_encoding: InputEncoding = input_encoding.parse()?;
+    let notify_format: NotifyFormat = notify_format.parse()?;
+    let notify_webhook = notify_webhook
+        .map(|url| Url::parse(&url).context(format!("Invalid webhook URL: {}", url)))
+        .transpose()?;
+
+    // Intervals of text and JSON reports, unless --every sets how often a followed file is reported on
+    if let (Some(every), None, "text" | "json") = (every, &follow, report_type.as_str()) {
+        if by.is_some() {
+            anyhow::bail!("--every splits the report into intervals, so it can't be combined with --by");
+        }
+        if every.is_zero() {
+            anyhow::bail!("The report interval (--every) must be greater than zero");
+        }
+        by = Some(GroupBy::Interval(every.into()));
+    }
+
+    if let Some(path) = follow {
+        if !inputs.is_empty() {
+            anyhow::bail!("--follow reads a single result file, so it can't be combined with --input");
+        }
+        if thresholds.is_some() {
+            anyhow::bail!("--thresholds can't be checked with --follow, which reports until interrupted");
+        }
+        if notify_webhook.is_some() {
+            anyhow::bail!("--notify-webhook can't be used with --follow, which reports until interrupted");
+        }
+        if github_summary {
+            anyhow::bail!("--github-summary can't be used with --follow, which reports until interrupted");
+        }
+        let interval = every.map_or(DEFAULT_FOLLOW_INTERVAL, Into::into);
+        return follow_results(&path, input_encoding, interval, &output, |results, writer| {
+            // Read anew each time, as the attack may only just have started
+            let configs = read_input_configs(std::slice::from_ref(&path));
+            write_report(results, writer, &report_type, buckets.as_deref(), by.as_ref(), every, top, &configs, &options)
+        })
+        .await;
+    }
+
+    // Read from stdin if no files are given
+    let inputs = if inputs.is_empty() { vec!["stdin".to_string()] } else { inputs };
+
+    // Get readers and writer
+    let readers = inputs
+        .iter()
+        .map(|input| read_results_as(get_reader(input)?, input_encoding))
+        .collect::<Result<Vec<_>>>()?;
     let mut writer = get_writer(&output)?;
 
-    // Parse buckets if provided
-    let _buckets = match buckets {
-        Some(b) => parse_buckets(&b)?,
-        None => vec![],
+    // Parse results, combining those of every input
+    let results = collect_results(readers)?;
+
+    let configs = read_input_configs(&inputs);
+    write_report(&results, &mut writer, &report_type, buckets.as_deref(), by.as_ref(), every, top, &configs, &options)?;
+
+    // Finish the report before summarizing, notifying or failing on breached thresholds
+    if thresholds.is_none() && notify_webhook.is_none() && !github_summary {
+        return Ok(());
+    }
+    writer.flush()?;
+    drop(writer);
+    let mut metrics = calculate_metrics(&results, &options)?;
+    metrics.run = calculate_run_summary(&results, &configs);
+
+    if github_summary {
+        write_github_summary(&results, &configs, &options, &metrics, thresholds.as_deref().unwrap_or_default())?;
+    }
+
+    // Notify of breached thresholds too, before failing on them
+    let notified = match &notify_webhook {
+        Some(url) => notify(url, notify_format, &metrics, thresholds.as_deref().unwrap_or_default()).await,
+        None => Ok(()),
     };
+    if let Some(thresholds) = thresholds {
+        check_thresholds(&thresholds, &metrics)?;
+    }
+    notified
+}
 
-    // Generate report based on the specified type
-    if report_type.starts_with("hist[") && report_type.ends_with("]") {
+/// Read the configurations of the attacks that wrote the given result files, warning
+/// about unreadable ones rather than failing the report
+fn read_input_configs(inputs: &[String]) -> Vec<RunConfig> {
+    inputs
+        .iter()
+        .filter(|input| *input != "stdin")
+        .flat_map(|input| {
+            read_configs(input).unwrap_or_else(|e| {
+                eprintln!("Warning: {:#}", e);
+                Vec::new()
+            })
+        })
+        .collect()
+}
+
+/// Generate a report of the given type
+#[allow(clippy::too_many_arguments)]
+fn write_report<W: Write>(
+    results: &[AttackResult],
+    writer: &mut W,
+    report_type: &str,
+    buckets: Option<&[Duration]>,
+    by: Option<&GroupBy>,
+    every: Option<humantime::Duration>,
+    top: usize,
+    configs: &[RunConfig],
+    options: &MetricsOptions,
+) -> Result<()> {
+    if let Some(by) = by {
+        match report_type {
+            "text" => generate_grouped_text_report(results, writer, by, options)?,
+            "json" => generate_grouped_json_report(results, writer, by, options)?,
+            _ => anyhow::bail!("--by is only supported by text and json reports, not {}", report_type),
+        }
+    } else if report_type.starts_with("hist[") && report_type.ends_with("]") {
         // Extract buckets from report type
         let buckets_str = &report_type[5..report_type.len() - 1];
         let buckets = parse_buckets(buckets_str)?;
-        generate_histogram_report(reader, &mut writer, &buckets)?;
+        generate_histogram_report(results, writer, &buckets)?;
+    } else if report_type == "hist" {
+        // Buckets spanning the latencies unless given
+        let buckets = buckets.map_or_else(|| log_buckets(results), <[Duration]>::to_vec);
+        generate_histogram_report(results, writer, &buckets)?;
+    } else if report_type == "heatmap" || report_type == "heatmap-json" {
+        let buckets = buckets.map_or_else(|| log_buckets(results), <[Duration]>::to_vec);
+        let interval = every.map_or(DEFAULT_HEATMAP_INTERVAL, Into::into);
+        let heatmap = calculate_heatmap(results, &buckets, interval)?;
+        if report_type == "heatmap" {
+            generate_heatmap_csv_report(&heatmap, writer)?;
+        } else {
+            serde_json::to_writer_pretty(&mut *writer, &heatmap)?;
+            writeln!(writer)?;
+        }
     } else {
-        match report_type.as_str() {
-            "text" => generate_text_report(reader, &mut writer, every)?,
-            "json" => generate_json_report(reader, &mut writer, every)?,
-            "hdrplot" => generate_hdrplot_report(reader, &mut writer)?,
+        match report_type {
+            "text" => generate_text_report(results, writer, configs, options)?,
+            "json" => generate_json_report(results, writer, configs, options)?,
+            "markdown" => generate_markdown_report(results, writer, configs, options)?,
+            "prom" => generate_prometheus_report(results, writer, options)?,
+            "hdrplot" => generate_hdrplot_report(results, writer, options)?,
+            "wrk2" => generate_wrk2_report(results, writer, options)?,
+            "proto" => generate_proto_report(results, writer, configs, options)?,
+            "slowest" => generate_slowest_report(results, writer, top)?,
             _ => anyhow::bail!("Unsupported report type: {}", report_type),
         }
     }
-
+
+    Ok(())
+}
+
+/// How often a followed result file is reported on unless configured otherwise
+const DEFAULT_FOLLOW_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Report on a result file while it's still being written, e.g. by a running attack,
+/// rendering the report anew every interval until interrupted
+///
+/// A terminal is cleared before each report, and an output file rewritten, so they show
+/// the latest; piped output gets every report in turn.
+async fn follow_results<F>(path: &str, encoding: InputEncoding, interval: Duration, output: &str, render: F) -> Result<()>
+where
+    F: Fn(&[AttackResult], &mut Box<dyn Write>) -> Result<()>,
+{
+    let mut followed = FollowedResults::new(path, encoding);
+    let terminal = output == "stdout" && std::io::stdout().is_terminal();
+
+    loop {
+        followed.refresh()?;
+
+        let mut writer = get_writer(output)?;
+        if terminal {
+            write!(writer, "\x1b[2J\x1b[H")?;
+            writeln!(writer, "Following {} every {}: {} results\n", path, humantime::format_duration(interval), followed.results.len())?;
+        }
+        render(&followed.results, &mut writer)?;
+        writer.flush()?;
+        drop(writer);
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+}
+
+/// Results of a file that's still being written, read as it grows
+struct FollowedResults {
+    path: String,
+    encoding: InputEncoding,
+    results: Vec<AttackResult>,
+    /// How the file is read, or None until it has enough content to tell
+    reader: Option<FollowedReader>,
+    /// Bytes of the file read so far
+    offset: u64,
+}
+
+/// How a followed file is read as it grows
+enum FollowedReader {
+    /// JSON lines, read from the end of the last complete line
+    Lines,
+    /// Binary results, decoded from where the last refresh stopped
+    Binary(BinaryFollower),
+}
+
+impl FollowedResults {
+    fn new(path: &str, encoding: InputEncoding) -> Self {
+        Self {
+            path: path.to_string(),
+            encoding,
+            results: Vec::new(),
+            reader: None,
+            offset: 0,
+        }
+    }
+
+    /// Read the results written since the last refresh
+    fn refresh(&mut self) -> Result<()> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            // Not written yet
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(anyhow::Error::new(e).context(format!("Failed to open file: {}", self.path))),
+        };
+
+        // Start over if the file was truncated, e.g. by a new attack
+        let len = file.metadata()?.len();
+        if len < self.offset {
+            self.results.clear();
+            self.reader = None;
+            self.offset = 0;
+        }
+
+        if self.reader.is_none() {
+            let mut start = Vec::new();
+            (&mut file).take(64).read_to_end(&mut start)?;
+            self.reader = self.open(&start)?;
+        }
+
+        match &mut self.reader {
+            Some(FollowedReader::Lines) => {
+                let mut appended = Vec::new();
+                file.seek(SeekFrom::Start(self.offset))?;
+                file.read_to_end(&mut appended)?;
+
+                // Leave a line still being written for the next refresh
+                let complete = appended.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+                let results = read_results_as(&appended[..complete], self.encoding)?;
+                self.results.extend(results.filter_map(|result| result.ok()));
+                self.offset += complete as u64;
+            }
+            Some(FollowedReader::Binary(follower)) => {
+                self.results.extend(follower.read()?);
+                self.offset = len;
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    /// Tell how the file is read from its first bytes, or None until there are enough
+    fn open(&self, start: &[u8]) -> Result<Option<FollowedReader>> {
+        if is_compressed(start) {
+            anyhow::bail!("Can't follow compressed results: {}. Write the results uncompressed to follow them", self.path);
+        }
+
+        let binary = matches!(self.encoding, InputEncoding::Auto | InputEncoding::Binary);
+        if binary && start.starts_with(MAGIC) {
+            return Ok(Some(FollowedReader::Binary(BinaryFollower::open(&self.path)?)));
+        }
+        if binary && start.len() < MAGIC.len() && MAGIC.starts_with(start) {
+            // The header is still being written
+            return Ok(None);
+        }
+
+        let lines = matches!(self.encoding, InputEncoding::Auto | InputEncoding::Ndjson | InputEncoding::VegetaJson);
+        match start.iter().find(|b| !b.is_ascii_whitespace()) {
+            None => Ok(None),
+            Some(b'{') if lines => Ok(Some(FollowedReader::Lines)),
+            Some(_) => anyhow::bail!("--follow reads JSON lines or binary results, which {} doesn't hold", self.path),
+        }
+    }
+}
+
+/// How metrics are calculated
+#[derive(Debug, Clone)]
+pub(crate) struct MetricsOptions {
+    /// Significant digits latencies are aggregated to
+    pub precision: u8,
+    /// Percentiles latencies are reported at, from 0 to 100
+    pub percentiles: Vec<f64>,
+    /// How latencies at percentiles are picked
+    pub percentile_method: PercentileMethod,
+    /// Latencies the Apdex score is calculated with, if it's wanted
+    pub apdex: Option<ApdexThresholds>,
+}
+
+impl Default for MetricsOptions {
+    fn default() -> Self {
+        Self {
+            precision: DEFAULT_PRECISION,
+            percentiles: DEFAULT_PERCENTILES.to_vec(),
+            percentile_method: PercentileMethod::default(),
+            apdex: None,
+        }
+    }
+}
+
+impl MetricsOptions {
+    /// Aggregate latencies to the configured precision, picking percentiles with the
+    /// configured method
+    pub(crate) fn histogram(&self, latencies: impl IntoIterator<Item = Duration>) -> Result<LatencyHistogram> {
+        Ok(LatencyHistogram::from_latencies(latencies, self.precision)?.with_method(self.percentile_method))
+    }
+}
+
+/// Percentiles latencies are reported at unless configured otherwise
+pub(crate) const DEFAULT_PERCENTILES: [f64; 4] = [50.0, 90.0, 95.0, 99.0];
+
+/// Parse a comma separated list of percentiles like "50,90,99,99.9", in ascending order
+pub(crate) fn parse_percentiles(s: &str) -> Result<Vec<f64>> {
+    let mut percentiles = s
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            p.trim_start_matches('p')
+                .parse::<f64>()
+                .ok()
+                .filter(|percentile| (0.0..=100.0).contains(percentile))
+                .context(format!("Invalid percentile: {}. Expected a number from 0 to 100", p))
+        })
+        .collect::<Result<Vec<f64>>>()?;
+    if percentiles.is_empty() {
+        anyhow::bail!("No percentiles given: {}", s);
+    }
+    percentiles.sort_by(f64::total_cmp);
+    percentiles.dedup();
+    Ok(percentiles)
+}
+
+/// Latency at each of the percentiles, given from 0 to 100
+pub(crate) fn calculate_percentiles(latencies: &LatencyHistogram, percentiles: &[f64]) -> Vec<PercentileLatency> {
+    percentiles
+        .iter()
+        .map(|&percentile| PercentileLatency {
+            percentile,
+            latency: latencies.percentile(percentile / 100.0),
+        })
+        .collect()
+}
+
+/// Summarize the time to first byte of the results that recorded it, or None if none did
+pub(crate) fn calculate_ttfb(results: &[AttackResult], options: &MetricsOptions) -> Result<Option<TtfbSummary>> {
+    let ttfbs = options.histogram(results.iter().filter_map(|r| r.ttfb))?;
+    if ttfbs.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(TtfbSummary {
+        requests: ttfbs.len() as usize,
+        min: ttfbs.min(),
+        mean: ttfbs.mean(),
+        stddev: ttfbs.stddev(),
+        max: ttfbs.max(),
+        percentiles: calculate_percentiles(&ttfbs, &options.percentiles),
+    }))
+}
+
+/// Quantile of a percentile, formatted to the digits given, e.g. 0.999 rather than
+/// 0.9990000000000001
+fn quantile(percentile: f64) -> String {
+    let quantile = format!("{:.10}", percentile / 100.0);
+    quantile.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Ordinal of a percentile, e.g. "99.9th" or "1st"
+fn ordinal(percentile: f64) -> String {
+    let number = percentile.to_string();
+    let suffix = if ["11", "12", "13"].iter().any(|teen| number.ends_with(teen)) {
+        "th"
+    } else if number.ends_with('1') {
+        "st"
+    } else if number.ends_with('2') {
+        "nd"
+    } else if number.ends_with('3') {
+        "rd"
+    } else {
+        "th"
+    };
+    format!("{}{}", number, suffix)
+}
+
+/// Latencies successful requests are rated by in an Apdex score
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ApdexThresholds {
+    /// Latency up to which requests satisfy users, Apdex's T
+    pub satisfied: Duration,
+    /// Latency up to which requests are tolerated, 4T unless configured otherwise
+    pub tolerating: Duration,
+}
+
+impl std::str::FromStr for ApdexThresholds {
+    type Err = anyhow::Error;
+
+    /// Parse thresholds like "500ms", or "500ms,2s" to tolerate requests up to 2s
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |latency: &str| -> Result<Duration> {
+            let latency = humantime::parse_duration(latency.trim()).map_err(|_| anyhow::anyhow!("Invalid Apdex threshold: {}", latency))?;
+            if latency.is_zero() {
+                anyhow::bail!("Apdex thresholds must be greater than 0: {}", s);
+            }
+            Ok(latency)
+        };
+
+        let (satisfied, tolerating) = match s.split_once(',') {
+            Some((satisfied, tolerating)) => (parse(satisfied)?, parse(tolerating)?),
+            None => {
+                let satisfied = parse(s)?;
+                (satisfied, satisfied * 4)
+            }
+        };
+        if tolerating < satisfied {
+            anyhow::bail!("The tolerating Apdex threshold must be at least the satisfied one: {}", s);
+        }
+        Ok(Self { satisfied, tolerating })
+    }
+}
+
+/// What results are grouped by in a report of metrics per group
+enum GroupBy {
+    /// Method, scheme, host, port and path, so requests differing only in their query
+    /// string are grouped together
+    Target,
+    /// Method and full URL
+    Url,
+    /// Value of a metadata key, or of a custom key set by a response hook or imported
+    /// from a JTL label
+    Tag(String),
+    /// Worker or virtual user that made the request
+    Worker,
+    /// Interval the request was sent in, aligned to the wall clock
+    Interval(Duration),
+}
+
+impl std::str::FromStr for GroupBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "target" => Ok(GroupBy::Target),
+            "url" => Ok(GroupBy::Url),
+            "worker" => Ok(GroupBy::Worker),
+            _ => match s.strip_prefix("tag:") {
+                Some(key) if !key.is_empty() => Ok(GroupBy::Tag(key.to_string())),
+                _ => anyhow::bail!("Invalid grouping: {}. Expected target, url, worker or tag:<key>", s),
+            },
+        }
+    }
+}
+
+impl GroupBy {
+    /// The group of a result
+    fn group(&self, result: &AttackResult) -> String {
+        match self {
+            GroupBy::Target => target_key(&result.target),
+            GroupBy::Url => format!("{} {}", result.target.method, result.target.url),
+            GroupBy::Tag(key) => match result.target.tags.get(key).or_else(|| result.metadata.get(key)) {
+                Some(value) => value.clone(),
+                None => match result.custom.get(key) {
+                    Some(serde_json::Value::String(value)) => value.clone(),
+                    Some(value) => value.to_string(),
+                    None => "(none)".to_string(),
+                },
+            },
+            GroupBy::Worker => format!("worker {}", result.worker),
+            GroupBy::Interval(interval) => {
+                let format = match interval.subsec_nanos() {
+                    0 => chrono::SecondsFormat::Secs,
+                    _ => chrono::SecondsFormat::Millis,
+                };
+                interval_start(result.timestamp, *interval).to_rfc3339_opts(format, true)
+            }
+        }
+    }
+}
+
+/// Start of the interval a time falls in, intervals starting at multiples of their length
+/// since the Unix epoch, so 10s intervals start at :00, :10, :20 and so on, and line up
+/// with server-side dashboards and logs
+fn interval_start(time: chrono::DateTime<chrono::Utc>, interval: Duration) -> chrono::DateTime<chrono::Utc> {
+    let nanos = time.timestamp_nanos_opt().unwrap_or_default();
+    let interval_nanos = i64::try_from(interval.as_nanos()).unwrap_or(i64::MAX).max(1);
+    chrono::DateTime::from_timestamp_nanos(nanos - nanos.rem_euclid(interval_nanos))
+}
+
+/// Calculate the metrics of each group of results, in order of first appearance
+fn calculate_group_metrics(results: &[AttackResult], by: &GroupBy, options: &MetricsOptions) -> Result<Vec<GroupMetrics>> {
+    let mut groups: Vec<(String, Vec<AttackResult>)> = Vec::new();
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    for result in results {
+        let group = by.group(result);
+        let index = *indexes.entry(group.clone()).or_insert_with(|| {
+            groups.push((group, Vec::new()));
+            groups.len() - 1
+        });
+        groups[index].1.push(result.clone());
+    }
+    // Workers in order of their IDs rather than of who happened to finish first, and
+    // intervals in order of time whatever the order of the results
+    match by {
+        GroupBy::Worker => groups.sort_by_key(|(_, results)| results[0].worker),
+        GroupBy::Interval(interval) => groups.sort_by_key(|(_, results)| interval_start(results[0].timestamp, *interval)),
+        _ => {}
+    }
+
+    groups
+        .into_iter()
+        .map(|(group, results)| {
+            Ok(GroupMetrics {
+                group,
+                metrics: calculate_metrics(&results, options)?,
+            })
+        })
+        .collect()
+}
+
+/// Generate a text report of the metrics of each group of results, as a table
+fn generate_grouped_text_report<W: Write>(
+    results: &[AttackResult],
+    writer: &mut W,
+    by: &GroupBy,
+    options: &MetricsOptions,
+) -> Result<()> {
+    if results.is_empty() {
+        writeln!(writer, "No results to report")?;
+        return Ok(());
+    }
+
+    let heading = if let GroupBy::Interval(_) = by { "Interval" } else { "Group" };
+    writeln!(writer, "{}\tRequests\tRate\tThroughput\tSuccess\tp50\tp95\tp99", heading)?;
+    let groups = calculate_group_metrics(results, by, options)?;
+    let total = GroupMetrics {
+        group: "Total".to_string(),
+        metrics: calculate_metrics(results, options)?,
+    };
+    for GroupMetrics { group, metrics } in groups.iter().chain([&total]) {
+        writeln!(
+            writer,
+            "{}\t{}\t{:.2} req/s\t{:.2} success/s\t{:.2}%\t{}\t{}\t{}",
+            group,
+            metrics.requests,
+            metrics.rate,
+            metrics.throughput,
+            metrics.success_rate * 100.0,
+            format_duration(metrics.p50),
+            format_duration(metrics.p95),
+            format_duration(metrics.p99)
+        )?;
+    }
+
+    if let GroupBy::Worker = by {
+        write_starved_workers(&groups, writer)?;
+    }
+
+    Ok(())
+}
+
+/// Point out workers that made far fewer requests than the median worker, a sign of a
+/// bottleneck in the client rather than the server under test, like a worker starved of
+/// CPU, which skews the measurement
+fn write_starved_workers<W: Write>(workers: &[GroupMetrics], writer: &mut W) -> Result<()> {
+    let mut requests: Vec<usize> = workers.iter().map(|worker| worker.metrics.requests).collect();
+    requests.sort_unstable();
+    let Some(&median) = requests.get(requests.len() / 2) else {
+        return Ok(());
+    };
+
+    let starved: Vec<String> = workers
+        .iter()
+        .filter(|worker| worker.metrics.requests * 2 < median)
+        .map(|worker| format!("{} ({} requests)", worker.group, worker.metrics.requests))
+        .collect();
+    if !starved.is_empty() {
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "Warning: {} made less than half the {} requests of the median worker, the client may be a bottleneck",
+            starved.join(", "),
+            median
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Generate a JSON report of the metrics of each group of results, as an array
+fn generate_grouped_json_report<W: Write>(
+    results: &[AttackResult],
+    writer: &mut W,
+    by: &GroupBy,
+    options: &MetricsOptions,
+) -> Result<()> {
+    let groups = calculate_group_metrics(results, by, options)?;
+    serde_json::to_writer_pretty(writer, &groups)?;
+
+    Ok(())
+}
+
+/// Parse histogram buckets from a string like "[0,1ms,10ms]"
+fn parse_buckets(buckets_str: &str) -> Result<Vec<Duration>> {
+    let inner = buckets_str.trim_start_matches('[').trim_end_matches(']');
+    let parts: Vec<&str> = inner.split(',').collect();
+
+    let mut buckets = Vec::new();
+    for part in parts {
+        let part = part.trim();
+        if part == "0" {
+            buckets.push(Duration::from_secs(0));
+        } else {
+            let duration = humantime::parse_duration(part)
+                .map_err(|_| anyhow::anyhow!("Invalid duration: {}", part))?;
+            buckets.push(duration.into());
+        }
+    }
+
+    Ok(buckets)
+}
+
+/// Number of buckets generated for a histogram report without explicit buckets
+const LOG_BUCKETS: i32 = 10;
+
+/// Histogram buckets spaced evenly on a log scale from the lowest latency to the highest,
+/// rounded to two significant digits, so fast and slow requests are both told apart
+/// without knowing the latencies in advance
+fn log_buckets(results: &[AttackResult]) -> Vec<Duration> {
+    let (Some(min), Some(max)) = (results.iter().map(|r| r.latency).min(), results.iter().map(|r| r.latency).max()) else {
+        return Vec::new();
+    };
+    log_spaced_buckets(min, max, LOG_BUCKETS)
+}
+
+/// Upper bounds of `count` histogram buckets spaced evenly on a log scale from `min` to
+/// `max`, rounded to two significant digits
+pub(crate) fn log_spaced_buckets(min: Duration, max: Duration, count: i32) -> Vec<Duration> {
+    let min = min.max(Duration::from_micros(1)).as_nanos() as f64;
+    let max = max.max(Duration::from_micros(1)).as_nanos() as f64;
+
+    let step = (max / min).powf(1.0 / count as f64);
+    let mut buckets: Vec<Duration> = (1..=count)
+        .map(|i| {
+            let nanos = min * step.powi(i);
+            let magnitude = 10f64.powi(nanos.log10().floor() as i32 - 1);
+            // The last bucket is rounded up so it holds the highest latency
+            let digits = if i == count { (nanos / magnitude).ceil() } else { (nanos / magnitude).round() };
+            Duration::from_nanos((digits * magnitude) as u64)
+        })
+        .collect();
+    buckets.dedup();
+    buckets
+}
+
+/// Generate a text report from attack results
+fn generate_text_report<W: Write>(
+    results: &[AttackResult],
+    writer: &mut W,
+    configs: &[RunConfig],
+    options: &MetricsOptions,
+) -> Result<()> {
+    if results.is_empty() {
+        writeln!(writer, "No results to report")?;
+        return Ok(());
+    }
+
+    // Calculate metrics
+    let mut metrics = calculate_metrics(results, options)?;
+    metrics.run = calculate_run_summary(results, configs);
+
+    // Write report, headed by what the results came from
+    if let Some(run) = &metrics.run {
+        for (label, value) in run_summary_lines(run) {
+            let tabs = if label.len() < 7 { "\t\t" } else { "\t" };
+            writeln!(writer, "{}:{}{}", label, tabs, value)?;
+        }
+        writeln!(writer)?;
+    }
+    writeln!(writer, "Requests:\t{}", metrics.requests)?;
+    if metrics.confidence.as_ref().is_some_and(|confidence| confidence.small_sample) {
+        writeln!(writer, "Warning:\t{}", small_sample_warning(metrics.requests))?;
+    }
+    writeln!(writer, "Duration:\t{}", format_duration(metrics.duration))?;
+    writeln!(writer, "Rate:\t\t{:.2} req/s", metrics.rate)?;
+    writeln!(writer, "Throughput:\t{:.2} success/s", metrics.throughput)?;
+    match &metrics.confidence {
+        Some(confidence) => writeln!(
+            writer,
+            "Success:\t{} ({:.2}%, {}% CI {:.2}% to {:.2}%)",
+            metrics.success,
+            metrics.success_rate * 100.0,
+            confidence.level * 100.0,
+            confidence.success_rate_lower * 100.0,
+            confidence.success_rate_upper * 100.0
+        )?,
+        None => writeln!(writer, "Success:\t{} ({:.2}%)", metrics.success, metrics.success_rate * 100.0)?,
+    }
+    // Timeouts apart from other failed requests
+    let percent = |count: usize| count as f64 / metrics.requests as f64 * 100.0;
+    let failures = metrics.requests.saturating_sub(metrics.success + metrics.timeouts);
+    writeln!(writer, "Timeouts:\t{} ({:.2}%)", metrics.timeouts, percent(metrics.timeouts))?;
+    writeln!(writer, "Failures:\t{} ({:.2}%)", failures, percent(failures))?;
+    if let Some(apdex) = &metrics.apdex {
+        writeln!(
+            writer,
+            "Apdex:\t\t{:.2} (T={}; {} satisfied, {} tolerating, {} frustrated)",
+            apdex.score,
+            format_duration(apdex.satisfied_threshold),
+            apdex.satisfied,
+            apdex.tolerating,
+            apdex.frustrated
+        )?;
+    }
+    if metrics.not_modified > 0 {
+        writeln!(writer, "Not Modified:\t{}", metrics.not_modified)?;
+    }
+    if metrics.check_failures > 0 {
+        writeln!(writer, "Check failures:\t{}", metrics.check_failures)?;
+    }
+    if metrics.golden_divergences > 0 {
+        writeln!(writer, "Golden divergences:\t{}", metrics.golden_divergences)?;
+    }
+    writeln!(writer, "Min:\t\t{}", format_duration(metrics.min))?;
+    match &metrics.confidence {
+        Some(confidence) => writeln!(
+            writer,
+            "Mean:\t\t{} ({}% CI {} to {})",
+            format_duration(metrics.mean),
+            confidence.level * 100.0,
+            format_duration(confidence.mean_lower),
+            format_duration(confidence.mean_upper)
+        )?,
+        None => writeln!(writer, "Mean:\t\t{}", format_duration(metrics.mean))?,
+    }
+    writeln!(writer, "Std Dev:\t{} (CV {:.2})", format_duration(metrics.stddev), metrics.cv)?;
+    for p in &metrics.percentiles {
+        writeln!(writer, "{} percentile:\t{}", ordinal(p.percentile), format_duration(p.latency))?;
+    }
+    writeln!(writer, "Max:\t\t{}", format_duration(metrics.max))?;
+    if let Some(ttfb) = &metrics.ttfb {
+        let percentiles: Vec<String> = ttfb.percentiles.iter().map(|p| format!("p{} {}", p.percentile, format_duration(p.latency))).collect();
+        writeln!(
+            writer,
+            "TTFB:\t\tmean {}\t{}\tmax {}",
+            format_duration(ttfb.mean),
+            percentiles.join("\t"),
+            format_duration(ttfb.max)
+        )?;
+    }
+    writeln!(writer, "Bytes in:\t{} ({}/s)", format_size(metrics.bytes_in), format_size(metrics.bytes_in_rate as usize))?;
+    writeln!(writer, "Bytes out:\t{} ({}/s)", format_size(metrics.bytes_out), format_size(metrics.bytes_out_rate as usize))?;
+
+    // One class would only repeat the latencies above
+    if metrics.status_classes.len() > 1 {
+        writeln!(writer)?;
+        writeln!(writer, "Latency by status:")?;
+        for class in &metrics.status_classes {
+            writeln!(
+                writer,
+                "  {}\t{} ({:.2}%)\tmean {}\tp50 {}\tp95 {}\tp99 {}",
+                class.class,
+                class.requests,
+                class.requests as f64 / metrics.requests as f64 * 100.0,
+                format_duration(class.mean),
+                format_duration(class.p50),
+                format_duration(class.p95),
+                format_duration(class.p99)
+            )?;
+        }
+    }
+
+    if !metrics.content_types.is_empty() {
+        let responses: usize = metrics.content_types.values().sum();
+        writeln!(writer)?;
+        writeln!(writer, "Content types:")?;
+        for (content_type, count) in &metrics.content_types {
+            writeln!(
+                writer,
+                "  {}\t{} ({:.2}%)",
+                content_type,
+                count,
+                *count as f64 / responses as f64 * 100.0
+            )?;
+        }
+    }
+
+    if !metrics.server_timing.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "Server timing (mean):")?;
+        for timing in &metrics.server_timing {
+            writeln!(
+                writer,
+                "  {}\t{} ({} samples)",
+                timing.name,
+                format_duration(timing.mean),
+                timing.samples
+            )?;
+        }
+    }
+
+    if !metrics.slo.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "SLO attainment:")?;
+        for slo in &metrics.slo {
+            writeln!(
+                writer,
+                "  {} (budget {})\t{}/{} ({:.2}%)",
+                slo.target,
+                format_duration(slo.budget),
+                slo.within_budget,
+                slo.requests,
+                slo.attainment * 100.0
+            )?;
+        }
+    }
+
+    let flows: usize = metrics.scenarios.iter().map(|s| s.flows).sum();
+    for scenario in &metrics.scenarios {
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "Scenario {} ({:.2}% of flows):",
+            scenario.name,
+            scenario.flows as f64 / flows as f64 * 100.0
+        )?;
+        writeln!(
+            writer,
+            "  Flows:\t{}/{} succeeded ({:.2}%)\tmean {}\tp95 {}",
+            scenario.success,
+            scenario.flows,
+            scenario.success as f64 / scenario.flows as f64 * 100.0,
+            format_duration(scenario.mean),
+            format_duration(scenario.p95)
+        )?;
+        writeln!(
+            writer,
+            "  Requests:\t{}/{} succeeded ({:.2}%)",
+            scenario.request_success,
+            scenario.requests,
+            scenario.request_success as f64 / scenario.requests as f64 * 100.0
+        )?;
+        writeln!(
+            writer,
+            "  Latency:\tmean {}\tp50 {}\tp95 {}\tp99 {}",
+            format_duration(scenario.request_mean),
+            format_duration(scenario.request_p50),
+            format_duration(scenario.request_p95),
+            format_duration(scenario.request_p99)
+        )?;
+        if scenario.skipped > 0 {
+            writeln!(writer, "  Skipped steps:\t{}", scenario.skipped)?;
+        }
+    }
+
+    if !metrics.checks.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "Checks:")?;
+        for check in &metrics.checks {
+            let total = check.passes + check.fails;
+            writeln!(
+                writer,
+                "  {} {}\t{}/{} passed ({:.2}%)",
+                if check.fails == 0 { "✓" } else { "✗" },
+                check.name,
+                check.passes,
+                total,
+                check.passes as f64 / total as f64 * 100.0
+            )?;
+        }
+    }
+
+    if !metrics.errors.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "Errors:")?;
+        for error in &metrics.errors {
+            writeln!(
+                writer,
+                "  {}\t{} ({:.2}%)",
+                error.kind,
+                error.count,
+                error.count as f64 / metrics.requests as f64 * 100.0
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Generate a JSON report from attack results
+fn generate_json_report<W: Write>(
+    results: &[AttackResult],
+    writer: &mut W,
+    configs: &[RunConfig],
+    options: &MetricsOptions,
+) -> Result<()> {
+    if results.is_empty() {
+        writeln!(writer, "{{}}")?;
+        return Ok(());
+    }
+
+    // Calculate metrics
+    let mut metrics = calculate_metrics(results, options)?;
+    metrics.run = calculate_run_summary(results, configs);
+
+    // Write report
+    serde_json::to_writer_pretty(writer, &metrics)?;
+
+    Ok(())
+}
+
+/// Generate a Markdown report from attack results, with tables ready to paste into pull
+/// requests, wikis and chat
+fn generate_markdown_report<W: Write>(results: &[AttackResult], writer: &mut W, configs: &[RunConfig], options: &MetricsOptions) -> Result<()> {
+    writeln!(writer, "## Culverin Report")?;
+    writeln!(writer)?;
+    if results.is_empty() {
+        writeln!(writer, "No results to report")?;
+        return Ok(());
+    }
+
+    let mut metrics = calculate_metrics(results, options)?;
+    metrics.run = calculate_run_summary(results, configs);
+    if let Some(run) = &metrics.run {
+        for (label, value) in run_summary_lines(run) {
+            writeln!(writer, "- **{}:** {}", label, escape_markdown(&value))?;
+        }
+        writeln!(writer)?;
+    }
+    let share = |count: usize| format!("{:.2}%", count as f64 / metrics.requests as f64 * 100.0);
+
+    writeln!(writer, "| Requests | Duration | Rate | Throughput | Success | Timeouts | Failures | Bytes in | Bytes out |")?;
+    writeln!(writer, "|---:|---:|---:|---:|---:|---:|---:|---:|---:|")?;
+    writeln!(
+        writer,
+        "| {} | {} | {:.2} req/s | {:.2} success/s | {} | {} | {} | {} ({}/s) | {} ({}/s) |",
+        metrics.requests,
+        format_duration(metrics.duration),
+        metrics.rate,
+        metrics.throughput,
+        share(metrics.success),
+        share(metrics.timeouts),
+        share(metrics.requests.saturating_sub(metrics.success + metrics.timeouts)),
+        format_size(metrics.bytes_in),
+        format_size(metrics.bytes_in_rate as usize),
+        format_size(metrics.bytes_out),
+        format_size(metrics.bytes_out_rate as usize)
+    )?;
+
+    if let Some(confidence) = &metrics.confidence {
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "**{}% confidence intervals:** mean latency {} to {}, success rate {:.2}% to {:.2}%",
+            confidence.level * 100.0,
+            format_duration(confidence.mean_lower),
+            format_duration(confidence.mean_upper),
+            confidence.success_rate_lower * 100.0,
+            confidence.success_rate_upper * 100.0
+        )?;
+        if confidence.small_sample {
+            writeln!(writer)?;
+            writeln!(writer, "> **Warning:** {}", small_sample_warning(metrics.requests))?;
+        }
+    }
+
+    if let Some(apdex) = &metrics.apdex {
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "**Apdex:** {:.2} (T={}; {} satisfied, {} tolerating, {} frustrated)",
+            apdex.score,
+            format_duration(apdex.satisfied_threshold),
+            apdex.satisfied,
+            apdex.tolerating,
+            apdex.frustrated
+        )?;
+    }
+
+    writeln!(writer)?;
+    writeln!(writer, "### Latency")?;
+    writeln!(writer)?;
+    let headers: Vec<String> = metrics.percentiles.iter().map(|p| format!("p{}", p.percentile)).collect();
+    let latencies: Vec<String> = metrics.percentiles.iter().map(|p| format_duration(p.latency)).collect();
+    writeln!(writer, "| Min | Mean | Std Dev | {} | Max |", headers.join(" | "))?;
+    writeln!(writer, "|---:|---:|---:|{}---:|", "---:|".repeat(headers.len()))?;
+    writeln!(
+        writer,
+        "| {} | {} | {} | {} | {} |",
+        format_duration(metrics.min),
+        format_duration(metrics.mean),
+        format_duration(metrics.stddev),
+        latencies.join(" | "),
+        format_duration(metrics.max)
+    )?;
+
+    if let Some(ttfb) = &metrics.ttfb {
+        let latencies: Vec<String> = ttfb.percentiles.iter().map(|p| format_duration(p.latency)).collect();
+        writeln!(writer)?;
+        writeln!(writer, "### Time to First Byte")?;
+        writeln!(writer)?;
+        writeln!(writer, "| Min | Mean | Std Dev | {} | Max |", headers.join(" | "))?;
+        writeln!(writer, "|---:|---:|---:|{}---:|", "---:|".repeat(headers.len()))?;
+        writeln!(
+            writer,
+            "| {} | {} | {} | {} | {} |",
+            format_duration(ttfb.min),
+            format_duration(ttfb.mean),
+            format_duration(ttfb.stddev),
+            latencies.join(" | "),
+            format_duration(ttfb.max)
+        )?;
+    }
+
+    if metrics.status_classes.len() > 1 {
+        writeln!(writer)?;
+        writeln!(writer, "### Latency by Status")?;
+        writeln!(writer)?;
+        writeln!(writer, "| Class | Requests | Share | Mean | p50 | p95 | p99 |")?;
+        writeln!(writer, "|---|---:|---:|---:|---:|---:|---:|")?;
+        for class in &metrics.status_classes {
+            writeln!(
+                writer,
+                "| {} | {} | {} | {} | {} | {} | {} |",
+                class.class,
+                class.requests,
+                share(class.requests),
+                format_duration(class.mean),
+                format_duration(class.p50),
+                format_duration(class.p95),
+                format_duration(class.p99)
+            )?;
+        }
+    }
+
+    let mut status_codes: BTreeMap<u16, usize> = BTreeMap::new();
+    for result in results {
+        *status_codes.entry(result.status_code).or_insert(0) += 1;
+    }
+    writeln!(writer)?;
+    writeln!(writer, "### Status Codes")?;
+    writeln!(writer)?;
+    writeln!(writer, "| Status | Requests | Share |")?;
+    writeln!(writer, "|---|---:|---:|")?;
+    for (status_code, count) in status_codes {
+        let status = match status_code {
+            0 => "No response".to_string(),
+            _ => status_code.to_string(),
+        };
+        writeln!(writer, "| {} | {} | {} |", status, count, share(count))?;
+    }
+
+    if !metrics.errors.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "### Errors")?;
+        writeln!(writer)?;
+        writeln!(writer, "| Error | Requests | Share |")?;
+        writeln!(writer, "|---|---:|---:|")?;
+        for error in &metrics.errors {
+            writeln!(writer, "| {} | {} | {} |", escape_markdown(&error.kind), error.count, share(error.count))?;
+        }
+    }
+
+    if !metrics.checks.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "### Checks")?;
+        writeln!(writer)?;
+        writeln!(writer, "| Check | Passed | Failed | Pass rate |")?;
+        writeln!(writer, "|---|---:|---:|---:|")?;
+        for check in &metrics.checks {
+            writeln!(
+                writer,
+                "| {} {} | {} | {} | {:.2}% |",
+                if check.fails == 0 { "✓" } else { "✗" },
+                escape_markdown(&check.name),
+                check.passes,
+                check.fails,
+                check.passes as f64 / (check.passes + check.fails) as f64 * 100.0
+            )?;
+        }
+    }
+
+    if !metrics.slo.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "### SLO Attainment")?;
+        writeln!(writer)?;
+        writeln!(writer, "| Target | Budget | Within budget | Attainment |")?;
+        writeln!(writer, "|---|---:|---:|---:|")?;
+        for slo in &metrics.slo {
+            writeln!(
+                writer,
+                "| {} | {} | {}/{} | {:.2}% |",
+                escape_markdown(&slo.target),
+                format_duration(slo.budget),
+                slo.within_budget,
+                slo.requests,
+                slo.attainment * 100.0
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Append a Markdown report to the job summary of a GitHub Actions step, the file named
+/// by GITHUB_STEP_SUMMARY, followed by whether each threshold passed
+fn write_github_summary(
+    results: &[AttackResult],
+    configs: &[RunConfig],
+    options: &MetricsOptions,
+    metrics: &Metrics,
+    thresholds: &[Threshold],
+) -> Result<()> {
+    let Some(path) = std::env::var_os("GITHUB_STEP_SUMMARY").filter(|path| !path.is_empty()) else {
+        eprintln!("Warning: GITHUB_STEP_SUMMARY isn't set, so there's no job summary to write to");
+        return Ok(());
+    };
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context(format!("Failed to open job summary: {}", path.to_string_lossy()))?;
+    let mut writer = std::io::BufWriter::new(file);
+
+    generate_markdown_report(results, &mut writer, configs, options)?;
+    if !thresholds.is_empty() {
+        writeln!(writer)?;
+        write_markdown_thresholds(thresholds, metrics, &mut writer)?;
+    }
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write a Markdown table of whether each threshold passed, headed by the verdict
+fn write_markdown_thresholds<W: Write>(thresholds: &[Threshold], metrics: &Metrics, writer: &mut W) -> Result<()> {
+    let outcomes: Vec<(bool, String)> = thresholds.iter().map(|threshold| threshold.evaluate(metrics)).collect();
+    let breached = outcomes.iter().filter(|(passed, _)| !passed).count();
+
+    writeln!(writer, "### Thresholds")?;
+    writeln!(writer)?;
+    if breached == 0 {
+        writeln!(writer, "**✅ All {} thresholds passed**", thresholds.len())?;
+    } else {
+        writeln!(writer, "**❌ {} of {} thresholds breached**", breached, thresholds.len())?;
+    }
+    writeln!(writer)?;
+    writeln!(writer, "| | Threshold | Value |")?;
+    writeln!(writer, "|:-:|---|---:|")?;
+    for (threshold, (passed, value)) in thresholds.iter().zip(&outcomes) {
+        writeln!(
+            writer,
+            "| {} | `{}` | {} |",
+            if *passed { "✅" } else { "❌" },
+            threshold.condition(),
+            escape_markdown(value)
+        )?;
+    }
     Ok(())
 }
 
-/// Parse histogram buckets from a string like "[0,1ms,10ms]"
-fn parse_buckets(buckets_str: &str) -> Result<Vec<Duration>> {
-    let inner = buckets_str.trim_start_matches('[').trim_end_matches(']');
-    let parts: Vec<&str> = inner.split(',').collect();
-
-    let mut buckets = Vec::new();
-    for part in parts {
-        let part = part.trim();
-        if part == "0" {
-            buckets.push(Duration::from_secs(0));
-        } else {
-            let duration = humantime::parse_duration(part)
-                .map_err(|_| anyhow::anyhow!("Invalid duration: {}", part))?;
-            buckets.push(duration.into());
+/// Escape text for a Markdown table cell, so pipes don't split the cell and markup in
+/// error messages or names isn't rendered
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '|' | '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push(' '),
+            _ => escaped.push(c),
         }
     }
-
-    Ok(buckets)
+    escaped
 }
 
-/// Generate a text report from attack results
-fn generate_text_report<R: BufRead, W: Write>(
-    reader: R,
-    writer: &mut W,
-    interval: Option<humantime::Duration>,
-) -> Result<()> {
-    // Parse results
-    let results: Vec<AttackResult> = reader
-        .lines()
-        .filter_map(|line| {
-            let line = line.ok()?;
-            serde_json::from_str(&line).ok()
-        })
-        .collect();
+/// Generate a report of the metrics in the Prometheus text exposition format, labelled
+/// with the attack name and target, to push to a Pushgateway or scrape from a file
+fn generate_prometheus_report<W: Write>(results: &[AttackResult], writer: &mut W, options: &MetricsOptions) -> Result<()> {
+    // Group results by attack name and target, in order of first appearance
+    let mut groups: Vec<(String, Vec<AttackResult>)> = Vec::new();
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    for result in results {
+        let target = target_key(&result.target);
+        let labels = match &result.attack {
+            Some(attack) => format!("attack=\"{}\",target=\"{}\"", escape_label(attack), escape_label(&target)),
+            None => format!("target=\"{}\"", escape_label(&target)),
+        };
+        let index = *indexes.entry(labels.clone()).or_insert_with(|| {
+            groups.push((labels, Vec::new()));
+            groups.len() - 1
+        });
+        groups[index].1.push(result.clone());
+    }
+    let groups = groups
+        .into_iter()
+        .map(|(labels, results)| Ok((labels, calculate_metrics(&results, options)?, results)))
+        .collect::<Result<Vec<_>>>()?;
 
-    if results.is_empty() {
-        writeln!(writer, "No results to report")?;
-        return Ok(());
+    // Name, type, help and value of each metric family
+    type Family = (&'static str, &'static str, &'static str, fn(&Metrics) -> f64);
+    let families: [Family; 13] = [
+        ("culverin_requests_total", "counter", "Requests made", |m| m.requests as f64),
+        ("culverin_success_total", "counter", "Requests that succeeded", |m| m.success as f64),
+        ("culverin_timeouts_total", "counter", "Requests that timed out", |m| m.timeouts as f64),
+        ("culverin_success_ratio", "gauge", "Share of requests that succeeded, from 0 to 1", |m| m.success_rate),
+        ("culverin_rate", "gauge", "Requests sent per second", |m| m.rate),
+        ("culverin_throughput", "gauge", "Successful responses per second", |m| m.throughput),
+        ("culverin_latency_min_seconds", "gauge", "Lowest request latency", |m| m.min.as_secs_f64()),
+        ("culverin_latency_max_seconds", "gauge", "Highest request latency", |m| m.max.as_secs_f64()),
+        ("culverin_latency_stddev_seconds", "gauge", "Standard deviation of request latency", |m| m.stddev.as_secs_f64()),
+        ("culverin_bytes_in_total", "counter", "Response body bytes received", |m| m.bytes_in as f64),
+        ("culverin_bytes_out_total", "counter", "Request body bytes sent", |m| m.bytes_out as f64),
+        ("culverin_bytes_in_per_second", "gauge", "Response body bytes received per second", |m| m.bytes_in_rate),
+        ("culverin_bytes_out_per_second", "gauge", "Request body bytes sent per second", |m| m.bytes_out_rate),
+    ];
+    for (name, kind, help, value) in families {
+        writeln!(writer, "# HELP {} {}", name, help)?;
+        writeln!(writer, "# TYPE {} {}", name, kind)?;
+        for (labels, metrics, _) in &groups {
+            writeln!(writer, "{}{{{}}} {}", name, labels, value(metrics))?;
+        }
     }
 
-    // Calculate metrics
-    let metrics = calculate_metrics(&results);
+    if groups.iter().any(|(_, metrics, _)| metrics.apdex.is_some()) {
+        writeln!(writer, "# HELP culverin_apdex Apdex score, from 0 to 1")?;
+        writeln!(writer, "# TYPE culverin_apdex gauge")?;
+        for (labels, metrics, _) in &groups {
+            if let Some(apdex) = &metrics.apdex {
+                writeln!(writer, "culverin_apdex{{{}}} {}", labels, apdex.score)?;
+            }
+        }
+    }
 
-    // Write report
-    writeln!(writer, "Requests:\t{}", metrics.requests)?;
-    writeln!(writer, "Duration:\t{}", format_duration(metrics.duration))?;
-    writeln!(writer, "Rate:\t\t{:.2} req/s", metrics.rate)?;
-    writeln!(writer, "Success:\t{} ({:.2}%)", metrics.success, metrics.success_rate * 100.0)?;
-    writeln!(writer, "Min:\t\t{}", format_duration(metrics.min))?;
-    writeln!(writer, "Mean:\t\t{}", format_duration(metrics.mean))?;
-    writeln!(writer, "50th percentile:\t{}", format_duration(metrics.p50))?;
-    writeln!(writer, "90th percentile:\t{}", format_duration(metrics.p90))?;
-    writeln!(writer, "95th percentile:\t{}", format_duration(metrics.p95))?;
-    writeln!(writer, "99th percentile:\t{}", format_duration(metrics.p99))?;
-    writeln!(writer, "Max:\t\t{}", format_duration(metrics.max))?;
-    writeln!(writer, "Bytes in:\t{}", format_size(metrics.bytes_in))?;
-    writeln!(writer, "Bytes out:\t{}", format_size(metrics.bytes_out))?;
+    writeln!(writer, "# HELP culverin_latency_seconds Request latency")?;
+    writeln!(writer, "# TYPE culverin_latency_seconds summary")?;
+    for (labels, metrics, results) in &groups {
+        for p in &metrics.percentiles {
+            writeln!(writer, "culverin_latency_seconds{{{},quantile=\"{}\"}} {}", labels, quantile(p.percentile), p.latency.as_secs_f64())?;
+        }
+        let sum: f64 = results.iter().map(|r| r.latency.as_secs_f64()).sum();
+        writeln!(writer, "culverin_latency_seconds_sum{{{}}} {}", labels, sum)?;
+        writeln!(writer, "culverin_latency_seconds_count{{{}}} {}", labels, metrics.requests)?;
+    }
+
+    if groups.iter().any(|(_, metrics, _)| metrics.ttfb.is_some()) {
+        writeln!(writer, "# HELP culverin_ttfb_seconds Time to first byte of requests that got a response")?;
+        writeln!(writer, "# TYPE culverin_ttfb_seconds summary")?;
+        for (labels, metrics, _) in &groups {
+            if let Some(ttfb) = &metrics.ttfb {
+                for p in &ttfb.percentiles {
+                    writeln!(writer, "culverin_ttfb_seconds{{{},quantile=\"{}\"}} {}", labels, quantile(p.percentile), p.latency.as_secs_f64())?;
+                }
+                writeln!(writer, "culverin_ttfb_seconds_sum{{{}}} {}", labels, ttfb.mean.as_secs_f64() * ttfb.requests as f64)?;
+                writeln!(writer, "culverin_ttfb_seconds_count{{{}}} {}", labels, ttfb.requests)?;
+            }
+        }
+    }
+
+    writeln!(writer, "# HELP culverin_responses_total Responses by status code, 0 for requests that got no response")?;
+    writeln!(writer, "# TYPE culverin_responses_total counter")?;
+    for (labels, _, results) in &groups {
+        let mut status_codes: BTreeMap<u16, usize> = BTreeMap::new();
+        for result in results {
+            *status_codes.entry(result.status_code).or_insert(0) += 1;
+        }
+        for (status_code, count) in status_codes {
+            writeln!(writer, "culverin_responses_total{{{},code=\"{}\"}} {}", labels, status_code, count)?;
+        }
+    }
 
     Ok(())
 }
 
-/// Generate a JSON report from attack results
-fn generate_json_report<R: BufRead, W: Write>(
-    reader: R,
-    writer: &mut W,
-    interval: Option<humantime::Duration>,
-) -> Result<()> {
-    // Parse results
-    let results: Vec<AttackResult> = reader
-        .lines()
-        .filter_map(|line| {
-            let line = line.ok()?;
-            serde_json::from_str(&line).ok()
-        })
-        .collect();
-
-    if results.is_empty() {
-        writeln!(writer, "{{}}")?;
-        return Ok(());
-    }
+/// Escape a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
 
-    // Calculate metrics
-    let metrics = calculate_metrics(&results);
+/// Generate a report of the metrics as a protobuf Metrics message, as described by proto/culverin.proto
+fn generate_proto_report<W: Write>(results: &[AttackResult], writer: &mut W, configs: &[RunConfig], options: &MetricsOptions) -> Result<()> {
+    let mut metrics = calculate_metrics(results, options)?;
+    metrics.run = calculate_run_summary(results, configs);
 
-    // Write report
-    serde_json::to_writer_pretty(writer, &metrics)?;
+    writer.write_all(&proto::Metrics::from(&metrics).encode_to_vec())?;
+    writer.flush()?;
 
     Ok(())
 }
 
 /// Generate a histogram report from attack results
-fn generate_histogram_report<R: BufRead, W: Write>(
-    reader: R,
+fn generate_histogram_report<W: Write>(
+    results: &[AttackResult],
     writer: &mut W,
     buckets: &[Duration],
 ) -> Result<()> {
-    // Parse results
-    let results: Vec<AttackResult> = reader
-        .lines()
-        .filter_map(|line| {
-            let line = line.ok()?;
-            serde_json::from_str(&line).ok()
-        })
-        .collect();
-
     if results.is_empty() {
         writeln!(writer, "No results to report")?;
         return Ok(());
@@ -158,7 +1369,7 @@ fn generate_histogram_report<R: BufRead, W: Write>(
         .collect();
 
     // Write header
-    writeln!(writer, "Bucket\t\tCount\t\tPercentage")?;
+    writeln!(writer, "Bucket\t\tCount\t\tPercentage\tHistogram")?;
 
     // Write buckets
     let mut prev_bucket = 0;
@@ -174,11 +1385,12 @@ fn generate_histogram_report<R: BufRead, W: Write>(
 
         writeln!(
             writer,
-            "[{} - {}]\t{}\t\t{:.2}%",
+            "[{} - {}]\t{}\t\t{:.2}%\t\t{}",
             format_duration(Duration::from_micros(prev_bucket)),
             format_duration(*bucket),
             count,
-            percentage
+            percentage,
+            histogram_bar(count, results.len())
         )?;
 
         prev_bucket = micros;
@@ -193,37 +1405,126 @@ fn generate_histogram_report<R: BufRead, W: Write>(
 
     writeln!(
         writer,
-        "[{} - inf]\t{}\t\t{:.2}%",
+        "[{} - inf]\t{}\t\t{:.2}%\t\t{}",
         format_duration(Duration::from_micros(prev_bucket)),
         count,
-        percentage
+        percentage,
+        histogram_bar(count, results.len())
     )?;
 
     Ok(())
 }
 
+/// Width of the bar of a histogram bucket holding every result
+const HISTOGRAM_BAR_WIDTH: usize = 75;
+
+/// Bar of a histogram bucket, as long relative to the full width as the bucket's share of
+/// the results, like vegeta draws it
+fn histogram_bar(count: usize, total: usize) -> String {
+    "#".repeat(count * HISTOGRAM_BAR_WIDTH / total.max(1))
+}
+
+/// Length of the time intervals of a heatmap report unless configured otherwise
+const DEFAULT_HEATMAP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Count the requests of each latency bucket in each time interval
+///
+/// Intervals are aligned to multiples of their length since the Unix epoch, so the
+/// heatmaps of runs on several machines line up, and results are placed by the time
+/// their request was sent.
+fn calculate_heatmap(results: &[AttackResult], buckets: &[Duration], interval: Duration) -> Result<Heatmap> {
+    if interval.is_zero() {
+        anyhow::bail!("The heatmap interval (--every) must be greater than zero");
+    }
+    let interval_nanos = i64::try_from(interval.as_nanos()).context("The heatmap interval (--every) is too long")?;
+
+    let mut counts: BTreeMap<i64, Vec<usize>> = BTreeMap::new();
+    for result in results {
+        let nanos = result.timestamp.timestamp_nanos_opt().context("Result timestamp is out of range")?;
+        let bucket = buckets.iter().position(|&bucket| result.latency < bucket).unwrap_or(buckets.len());
+        counts.entry(nanos.div_euclid(interval_nanos)).or_insert_with(|| vec![0; buckets.len() + 1])[bucket] += 1;
+    }
+
+    // Fill in the intervals without requests so the time axis has no gaps
+    let rows = match (counts.keys().next(), counts.keys().next_back()) {
+        (Some(&first), Some(&last)) => (first..=last)
+            .map(|index| HeatmapRow {
+                time: chrono::DateTime::from_timestamp_nanos(index * interval_nanos),
+                counts: counts.remove(&index).unwrap_or_else(|| vec![0; buckets.len() + 1]),
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    Ok(Heatmap {
+        interval: interval.as_secs_f64(),
+        buckets: buckets.iter().map(Duration::as_secs_f64).collect(),
+        rows,
+    })
+}
+
+/// Generate a heatmap report as CSV: a row per time interval and a column per latency
+/// bucket, named after its upper bound in seconds like Prometheus' `le` label, which
+/// Grafana's heatmap panel reads as time series buckets
+fn generate_heatmap_csv_report<W: Write>(heatmap: &Heatmap, writer: &mut W) -> Result<()> {
+    let mut header = vec!["time".to_string()];
+    header.extend(heatmap.buckets.iter().map(f64::to_string));
+    header.push("+Inf".to_string());
+    writeln!(writer, "{}", header.join(","))?;
+
+    for row in &heatmap.rows {
+        let counts: Vec<String> = row.counts.iter().map(usize::to_string).collect();
+        writeln!(writer, "{},{}", row.time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true), counts.join(","))?;
+    }
+
+    Ok(())
+}
+
+/// Generate a report of the slowest requests, slowest first, with what's needed to find
+/// them in server logs: when they were sent, their target and their request ID
+fn generate_slowest_report<W: Write>(results: &[AttackResult], writer: &mut W, top: usize) -> Result<()> {
+    if results.is_empty() {
+        writeln!(writer, "No results to report")?;
+        return Ok(());
+    }
+
+    let mut slowest: Vec<&AttackResult> = results.iter().collect();
+    slowest.sort_by_key(|result| std::cmp::Reverse(result.latency));
+
+    writeln!(writer, "Timestamp\t\t\tLatency\tStatus\tTarget\tRequest ID")?;
+    for result in slowest.into_iter().take(top) {
+        let status = match result.status_code {
+            _ if result.timed_out => "timeout".to_string(),
+            0 => "error".to_string(),
+            status => status.to_string(),
+        };
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{} {}\t{}",
+            result.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            format_duration(result.latency),
+            status,
+            result.target.method,
+            result.target.url,
+            result.request_id.as_deref().unwrap_or("-")
+        )?;
+    }
+
+    Ok(())
+}
+
 /// Generate an HDR plot report from attack results
-fn generate_hdrplot_report<R: BufRead, W: Write>(
-    reader: R,
+fn generate_hdrplot_report<W: Write>(
+    results: &[AttackResult],
     writer: &mut W,
+    options: &MetricsOptions,
 ) -> Result<()> {
-    // Parse results
-    let results: Vec<AttackResult> = reader
-        .lines()
-        .filter_map(|line| {
-            let line = line.ok()?;
-            serde_json::from_str(&line).ok()
-        })
-        .collect();
-
     if results.is_empty() {
         writeln!(writer, "No results to report")?;
         return Ok(());
     }
 
-    // Extract and sort latencies
-    let mut latencies: Vec<Duration> = results.iter().map(|r| r.latency).collect();
-    latencies.sort();
+    let latencies = options.histogram(results.iter().map(|r| r.latency))?;
 
     // Generate percentiles
     let percentiles = [
@@ -235,7 +1536,7 @@ fn generate_hdrplot_report<R: BufRead, W: Write>(
 
     // Write percentiles
     for p in percentiles {
-        let value = percentile(&latencies, p / 100.0);
+        let value = latencies.percentile(p / 100.0);
         writeln!(
             writer,
             "{:.2}%\t\t{}",
@@ -247,57 +1548,131 @@ fn generate_hdrplot_report<R: BufRead, W: Write>(
     Ok(())
 }
 
+/// Generate a latency distribution report in the format wrk2 prints with `--latency`:
+/// the summary percentiles, then HdrHistogram's percentile spectrum with latencies in
+/// milliseconds
+fn generate_wrk2_report<W: Write>(results: &[AttackResult], writer: &mut W, options: &MetricsOptions) -> Result<()> {
+    if results.is_empty() {
+        writeln!(writer, "No results to report")?;
+        return Ok(());
+    }
+
+    let latencies = options.histogram(results.iter().map(|r| r.latency))?;
+    let millis = |latency: Duration| latency.as_secs_f64() * 1000.0;
+
+    writeln!(writer, "  Latency Distribution (HdrHistogram - Recorded Latency)")?;
+    for p in [50.0, 75.0, 90.0, 99.0, 99.9, 99.99, 99.999, 100.0] {
+        // Units are padded so the numbers line up, as wrk2 does
+        let value = format_wrk2_latency(latencies.percentile(p / 100.0));
+        let pad = value.bytes().rev().take(2).filter(|b| !b.is_ascii_alphabetic()).count();
+        writeln!(writer, "{:7.3}%{:>width$}{}", p, value, &"  "[..pad], width = 10 - pad)?;
+    }
+
+    writeln!(writer)?;
+    writeln!(writer, "  Detailed Percentile spectrum:")?;
+    writeln!(writer, "{:>12} {:>12} {:>12} {:>12}", "Value", "Percentile", "TotalCount", "1/(1-Percentile)")?;
+    writeln!(writer)?;
+
+    // 5 levels per halving of the distance to 100%, as wrk2 prints
+    for (value, level, total) in latencies.spectrum(5) {
+        writeln!(
+            writer,
+            "{:>12.3} {:>12.6} {:>12} {:>12.2}",
+            millis(value),
+            level,
+            total,
+            1.0 / (1.0 - level)
+        )?;
+    }
+
+    writeln!(writer, "#[Mean    = {:>12.3}, StdDeviation   = {:>12.3}]", millis(latencies.mean()), millis(latencies.stddev()))?;
+    writeln!(writer, "#[Max     = {:>12.3}, Total count    = {:>12}]", millis(latencies.max()), latencies.len())?;
+    // The layout of wrk2's histogram, which tracks up to 24 hours with 3 significant digits
+    writeln!(writer, "#[Buckets = {:>12}, SubBuckets     = {:>12}]", 27, 2048)?;
+    writeln!(writer, "----------------------------------------------------------")?;
+
+    Ok(())
+}
+
+/// Format a latency the way wrk does, in the largest of us, ms or s that keeps it at
+/// least 1
+fn format_wrk2_latency(latency: Duration) -> String {
+    let micros = latency.as_secs_f64() * 1_000_000.0;
+    if micros < 1000.0 {
+        format!("{:.2}us", micros)
+    } else if micros < 1_000_000.0 {
+        format!("{:.2}ms", micros / 1000.0)
+    } else {
+        format!("{:.2}s", micros / 1_000_000.0)
+    }
+}
+
 /// Calculate metrics from attack results
-fn calculate_metrics(results: &[AttackResult]) -> Metrics {
+pub(crate) fn calculate_metrics(results: &[AttackResult], options: &MetricsOptions) -> Result<Metrics> {
     if results.is_empty() {
-        return Metrics {
+        return Ok(Metrics {
             requests: 0,
             success: 0,
+            not_modified: 0,
             timeouts: 0,
+            check_failures: 0,
+            golden_divergences: 0,
             duration: Duration::from_secs(0),
             min: Duration::from_secs(0),
             max: Duration::from_secs(0),
             mean: Duration::from_secs(0),
+            stddev: Duration::from_secs(0),
+            cv: 0.0,
             p50: Duration::from_secs(0),
             p90: Duration::from_secs(0),
             p95: Duration::from_secs(0),
             p99: Duration::from_secs(0),
+            percentiles: Vec::new(),
+            ttfb: None,
             rate: 0.0,
+            throughput: 0.0,
+            apdex: None,
             bytes_in: 0,
             bytes_out: 0,
+            bytes_in_rate: 0.0,
+            bytes_out_rate: 0.0,
             success_rate: 0.0,
-        };
+            confidence: None,
+            slo: Vec::new(),
+            server_timing: Vec::new(),
+            content_types: BTreeMap::new(),
+            scenarios: Vec::new(),
+            checks: Vec::new(),
+            errors: Vec::new(),
+            status_classes: Vec::new(),
+            run: None,
+        });
     }
 
-    // Sort results by latency for percentile calculations
-    let mut sorted_latencies: Vec<Duration> = results.iter().map(|r| r.latency).collect();
-    sorted_latencies.sort();
+    let latencies = options.histogram(results.iter().map(|r| r.latency))?;
 
     // Calculate basic metrics
     let requests = results.len();
-    let success = results.iter().filter(|r| r.status_code >= 200 && r.status_code < 300).count();
+    let success = results.iter().filter(|r| r.is_success()).count();
+    let not_modified = results.iter().filter(|r| r.is_success() && r.is_not_modified()).count();
 
-    // Calculate duration (time between first request and last response)
+    // Calculate duration (time between first and last request)
     let first_timestamp = results.iter().map(|r| r.timestamp).min().unwrap();
     let last_timestamp = results.iter().map(|r| r.timestamp).max().unwrap();
-    let duration = Duration::from_secs((last_timestamp - first_timestamp).num_seconds() as u64);
+    let duration = (last_timestamp - first_timestamp).to_std().unwrap_or_default();
 
     // Calculate latency metrics
-    let min = *sorted_latencies.first().unwrap();
-    let max = *sorted_latencies.last().unwrap();
-
-    let mean = if requests > 0 {
-        let sum: Duration = sorted_latencies.iter().sum();
-        sum / requests as u32
-    } else {
-        Duration::from_secs(0)
-    };
+    let min = latencies.min();
+    let max = latencies.max();
+    let mean = latencies.mean();
+    let stddev = latencies.stddev();
+    let cv = coefficient_of_variation(stddev, mean);
 
     // Calculate percentiles
-    let p50 = percentile(&sorted_latencies, 0.5);
-    let p90 = percentile(&sorted_latencies, 0.9);
-    let p95 = percentile(&sorted_latencies, 0.95);
-    let p99 = percentile(&sorted_latencies, 0.99);
+    let p50 = latencies.percentile(0.5);
+    let p90 = latencies.percentile(0.9);
+    let p95 = latencies.percentile(0.95);
+    let p99 = latencies.percentile(0.99);
 
     // Calculate rate
     let rate = if duration.as_secs_f64() > 0.0 {
@@ -317,31 +1692,522 @@ fn calculate_metrics(results: &[AttackResult]) -> Metrics {
         0.0
     };
 
-    Metrics {
+    Ok(Metrics {
+        run: None,
         requests,
         success,
+        not_modified,
         timeouts: results.iter().filter(|r| r.timed_out).count(),
+        check_failures: results.iter().filter(|r| r.check_failed).count(),
+        golden_divergences: results.iter().filter(|r| r.golden_diverged).count(),
         duration,
         min,
         max,
         mean,
+        stddev,
+        cv,
         p50,
         p90,
         p95,
         p99,
+        percentiles: calculate_percentiles(&latencies, &options.percentiles),
+        ttfb: calculate_ttfb(results, options)?,
         rate,
+        throughput: calculate_throughput(results, success),
+        apdex: options.apdex.map(|thresholds| calculate_apdex(results, thresholds)),
         bytes_in,
         bytes_out,
+        bytes_in_rate: per_second(bytes_in, response_window(results)),
+        bytes_out_rate: per_second(bytes_out, response_window(results)),
         success_rate,
+        confidence: Some(calculate_confidence(&latencies, success, requests)),
+        slo: calculate_slo_attainment(results),
+        server_timing: calculate_server_timing(results),
+        content_types: calculate_content_types(results),
+        scenarios: calculate_scenarios(results, options)?,
+        checks: calculate_checks(results),
+        errors: calculate_errors(results),
+        status_classes: calculate_status_classes(results, options)?,
+    })
+}
+
+/// Summarize the runs results came from: the attacks and run IDs, when the first request
+/// was sent and the last response arrived, the metadata and the attacks' configurations
+pub(crate) fn calculate_run_summary(results: &[AttackResult], configs: &[RunConfig]) -> Option<RunSummary> {
+    let started = results.iter().map(|r| r.timestamp).min()?;
+    let ended = results
+        .iter()
+        .map(|r| r.timestamp + chrono::Duration::from_std(r.latency).unwrap_or_else(|_| chrono::Duration::zero()))
+        .max()?;
+
+    let mut attacks: Vec<String> = Vec::new();
+    let mut run_ids: Vec<String> = Vec::new();
+    let mut metadata: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for result in results {
+        if let Some(attack) = result.attack.as_ref().filter(|attack| !attacks.contains(attack)) {
+            attacks.push(attack.clone());
+        }
+        if let Some(run_id) = result.run_id.as_ref().filter(|run_id| !run_ids.contains(run_id)) {
+            run_ids.push(run_id.clone());
+        }
+        for (key, value) in &result.metadata {
+            let values = metadata.entry(key.clone()).or_default();
+            if !values.contains(value) {
+                values.push(value.clone());
+            }
+        }
+    }
+
+    Some(RunSummary {
+        attacks,
+        run_ids,
+        started,
+        ended,
+        metadata: metadata.into_iter().map(|(key, values)| (key, values.join(", "))).collect(),
+        configs: configs.to_vec(),
+    })
+}
+
+/// Label and value of each line of a report header describing the runs the results came from
+fn run_summary_lines(run: &RunSummary) -> Vec<(&'static str, String)> {
+    let mut lines = Vec::new();
+    if !run.attacks.is_empty() {
+        lines.push(("Attack", run.attacks.join(", ")));
+    }
+    if !run.run_ids.is_empty() {
+        lines.push(("Run ID", run.run_ids.join(", ")));
+    }
+    let mut versions: Vec<&str> = Vec::new();
+    for config in &run.configs {
+        if !versions.contains(&config.version.as_str()) {
+            versions.push(&config.version);
+        }
+    }
+    if !versions.is_empty() {
+        lines.push(("Version", format!("culverin {}", versions.join(", "))));
+    }
+    lines.push(("Started", run.started.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)));
+    lines.push(("Ended", run.ended.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)));
+    if !run.metadata.is_empty() {
+        let metadata: Vec<String> = run.metadata.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        lines.push(("Metadata", metadata.join(", ")));
+    }
+    for config in &run.configs {
+        lines.push(("Config", config_summary(config)));
+    }
+    lines
+}
+
+/// The settings of an attack that shape its results, in one line
+fn config_summary(config: &RunConfig) -> String {
+    let mut settings = vec![format!("{} target{}", config.targets, if config.targets == 1 { "" } else { "s" })];
+    if config.stages.is_empty() {
+        settings.push(format!("rate {}/s", config.rate));
+    } else {
+        settings.push(format!("stages {}", config.stages.join(",")));
+    }
+    if let Some(duration) = &config.duration {
+        settings.push(format!("duration {}", duration));
+    }
+    settings.push(format!("workers {}", config.workers));
+    settings.push(format!("http timeout {}", config.http_timeout));
+    if config.http2 {
+        settings.push("http2".to_string());
+    }
+    if !config.keepalive {
+        settings.push("no keepalive".to_string());
+    }
+    settings.join(", ")
+}
+
+/// Calculate successful responses per second, over the time from the first request to
+/// the last response
+///
+/// Unlike the rate requests were sent at, this is what the server managed to answer: it
+/// falls below the rate as requests fail or responses lag behind.
+pub(crate) fn calculate_throughput(results: &[AttackResult], success: usize) -> f64 {
+    per_second(success, response_window(results))
+}
+
+/// Time from the first request to the last response
+pub(crate) fn response_window(results: &[AttackResult]) -> Duration {
+    let Some(first_timestamp) = results.iter().map(|r| r.timestamp).min() else {
+        return Duration::ZERO;
+    };
+    let last_response = results
+        .iter()
+        .map(|r| r.timestamp + chrono::Duration::from_std(r.latency).unwrap_or_else(|_| chrono::Duration::zero()))
+        .max()
+        .unwrap();
+    (last_response - first_timestamp).to_std().unwrap_or_default()
+}
+
+/// An amount per second over the elapsed time, 0 if no time elapsed
+pub(crate) fn per_second(amount: usize, elapsed: Duration) -> f64 {
+    if elapsed.as_secs_f64() > 0.0 {
+        amount as f64 / elapsed.as_secs_f64()
+    } else {
+        0.0
+    }
+}
+
+/// Warn that there were too few requests to judge metrics by
+fn small_sample_warning(requests: usize) -> String {
+    format!(
+        "only {} requests, too few for reliable confidence intervals. Run at least {} before comparing runs",
+        requests, MIN_SAMPLE
+    )
+}
+
+/// Fewer requests than this make confidence intervals unreliable, as the normal
+/// approximation they rest on no longer holds
+const MIN_SAMPLE: usize = 30;
+
+/// Calculate 95% confidence intervals of the mean latency and the success rate
+///
+/// The mean's interval is the normal approximation, mean ± 1.96 standard errors, and the
+/// success rate's the Wilson score interval, which stays within 0% to 100% and holds up
+/// for success rates close to either.
+pub(crate) fn calculate_confidence(latencies: &LatencyHistogram, success: usize, requests: usize) -> ConfidenceIntervals {
+    const LEVEL: f64 = 0.95;
+    const Z: f64 = 1.959964;
+
+    let n = requests.max(1) as f64;
+    let mean = latencies.mean().as_secs_f64();
+    let margin = Z * latencies.stddev().as_secs_f64() / n.sqrt();
+
+    let rate = success as f64 / n;
+    let z2 = Z * Z;
+    let denominator = 1.0 + z2 / n;
+    let center = (rate + z2 / (2.0 * n)) / denominator;
+    let spread = Z * (rate * (1.0 - rate) / n + z2 / (4.0 * n * n)).sqrt() / denominator;
+
+    ConfidenceIntervals {
+        level: LEVEL,
+        mean_lower: Duration::from_secs_f64((mean - margin).max(0.0)),
+        mean_upper: Duration::from_secs_f64(mean + margin),
+        success_rate_lower: (center - spread).max(0.0),
+        success_rate_upper: (center + spread).min(1.0),
+        small_sample: requests < MIN_SAMPLE,
+    }
+}
+
+/// Calculate the Apdex score of the results: the share of requests that satisfied users,
+/// plus half the share of those that were tolerated
+///
+/// Failed requests frustrate users, however fast they were.
+pub(crate) fn calculate_apdex(results: &[AttackResult], thresholds: ApdexThresholds) -> Apdex {
+    let (mut satisfied, mut tolerating) = (0, 0);
+    for result in results.iter().filter(|r| r.is_success()) {
+        if result.latency <= thresholds.satisfied {
+            satisfied += 1;
+        } else if result.latency <= thresholds.tolerating {
+            tolerating += 1;
+        }
+    }
+
+    let score = if results.is_empty() {
+        0.0
+    } else {
+        (satisfied as f64 + tolerating as f64 / 2.0) / results.len() as f64
+    };
+    Apdex {
+        satisfied_threshold: thresholds.satisfied,
+        tolerating_threshold: thresholds.tolerating,
+        satisfied,
+        tolerating,
+        frustrated: results.len() - satisfied - tolerating,
+        score,
+    }
+}
+
+/// Count responses per Content-Type media type, ignoring parameters like charset
+pub(crate) fn calculate_content_types(results: &[AttackResult]) -> BTreeMap<String, usize> {
+    let mut content_types = BTreeMap::new();
+
+    for result in results.iter().filter(|r| r.status_code > 0) {
+        let media_type = match &result.content_type {
+            Some(content_type) => content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase(),
+            None => "(none)".to_string(),
+        };
+        *content_types.entry(media_type).or_insert(0) += 1;
+    }
+
+    content_types
+}
+
+/// Standard deviation relative to the mean, 0 without a mean to relate it to
+pub(crate) fn coefficient_of_variation(stddev: Duration, mean: Duration) -> f64 {
+    if mean.is_zero() {
+        0.0
+    } else {
+        stddev.as_secs_f64() / mean.as_secs_f64()
+    }
+}
+
+/// Calculate the mean server-reported duration of each Server-Timing metric, in order of first appearance
+pub(crate) fn calculate_server_timing(results: &[AttackResult]) -> Vec<ServerTimingSummary> {
+    let mut totals: Vec<(String, usize, f64)> = Vec::new();
+
+    for timing in results.iter().flat_map(|r| &r.server_timing) {
+        let Some(dur) = timing.dur else {
+            continue;
+        };
+        match totals.iter_mut().find(|(name, _, _)| *name == timing.name) {
+            Some((_, samples, sum)) => {
+                *samples += 1;
+                *sum += dur;
+            }
+            None => totals.push((timing.name.clone(), 1, dur)),
+        }
+    }
+
+    // Results recorded before durations were checked may hold ones too large to report
+    totals
+        .into_iter()
+        .filter_map(|(name, samples, sum)| {
+            let mean = Duration::try_from_secs_f64((sum / samples as f64).max(0.0) / 1000.0).ok()?;
+            Some(ServerTimingSummary { name, samples, mean })
+        })
+        .collect()
+}
+
+/// Calculate the share of requests completed within budget for each target that declares an SLO
+pub(crate) fn calculate_slo_attainment(results: &[AttackResult]) -> Vec<SloAttainment> {
+    let mut attainment: Vec<SloAttainment> = Vec::new();
+
+    for result in results {
+        let Some(budget) = result.target.slo else {
+            continue;
+        };
+
+        let target = format!("{} {}", result.target.method, result.target.url);
+        let index = match attainment.iter().position(|a| a.target == target && a.budget == budget) {
+            Some(index) => index,
+            None => {
+                attainment.push(SloAttainment {
+                    target,
+                    budget,
+                    requests: 0,
+                    within_budget: 0,
+                    attainment: 0.0,
+                });
+                attainment.len() - 1
+            }
+        };
+
+        let entry = &mut attainment[index];
+        entry.requests += 1;
+        if result.is_success() && result.latency <= budget {
+            entry.within_budget += 1;
+        }
+    }
+
+    for entry in &mut attainment {
+        entry.attainment = entry.within_budget as f64 / entry.requests as f64;
+    }
+
+    attainment
+}
+
+/// Calculate flow-level success and latency of each scenario, in order of first appearance
+///
+/// A flow's latency runs from the start of its first step to the end of its last step,
+/// and a flow succeeds when every step it made succeeded.
+pub(crate) fn calculate_scenarios(results: &[AttackResult], options: &MetricsOptions) -> Result<Vec<ScenarioSummary>> {
+    type Flow = (chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>, bool, usize);
+
+    let mut names: Vec<&str> = Vec::new();
+    let mut flows: HashMap<(&str, u64), Flow> = HashMap::new();
+
+    for result in results {
+        let Some(step) = &result.scenario else {
+            continue;
+        };
+        let end = result.timestamp + chrono::Duration::from_std(result.latency).unwrap_or_else(|_| chrono::Duration::zero());
+        let flow = flows.entry((&step.name, step.flow)).or_insert((result.timestamp, end, true, 0));
+        flow.0 = flow.0.min(result.timestamp);
+        flow.1 = flow.1.max(end);
+        flow.2 &= result.is_success() || step.handled;
+        flow.3 += step.skipped;
+        if !names.contains(&step.name.as_str()) {
+            names.push(&step.name);
+        }
+    }
+
+    names
+        .into_iter()
+        .map(|name| {
+            let requests: Vec<&AttackResult> = results
+                .iter()
+                .filter(|r| r.scenario.as_ref().is_some_and(|s| s.name == name))
+                .collect();
+            let request_latencies = options.histogram(requests.iter().map(|r| r.latency))?;
+
+            let mut latencies = options.histogram([])?;
+            let (mut success, mut skipped) = (0, 0);
+            for (_, (start, end, ok, flow_skipped)) in flows.iter().filter(|((n, _), _)| *n == name) {
+                latencies.record((*end - *start).to_std().unwrap_or_default());
+                if *ok {
+                    success += 1;
+                }
+                skipped += flow_skipped;
+            }
+
+            Ok(ScenarioSummary {
+                name: name.to_string(),
+                flows: latencies.len() as usize,
+                success,
+                skipped,
+                mean: latencies.mean(),
+                p95: latencies.percentile(0.95),
+                requests: requests.len(),
+                request_success: requests.iter().filter(|r| r.is_success()).count(),
+                request_mean: request_latencies.mean(),
+                request_p50: request_latencies.percentile(0.5),
+                request_p95: request_latencies.percentile(0.95),
+                request_p99: request_latencies.percentile(0.99),
+            })
+        })
+        .collect()
+}
+
+/// Count the passes and failures of each named check, in the order checks first appear
+pub(crate) fn calculate_checks(results: &[AttackResult]) -> Vec<CheckSummary> {
+    let mut checks: Vec<CheckSummary> = Vec::new();
+
+    for (name, passed) in results.iter().flat_map(|r| &r.checks) {
+        let index = match checks.iter().position(|c| &c.name == name) {
+            Some(index) => index,
+            None => {
+                checks.push(CheckSummary {
+                    name: name.clone(),
+                    passes: 0,
+                    fails: 0,
+                });
+                checks.len() - 1
+            }
+        };
+        if *passed {
+            checks[index].passes += 1;
+        } else {
+            checks[index].fails += 1;
+        }
+    }
+
+    checks
+}
+
+/// Calculate the latency of each class of response, so fast errors don't hide how slow
+/// successful responses are
+///
+/// Requests that timed out count as "timeout", even if the response started, and other
+/// requests that got no response as "error". Classes are in a fixed order and only included if a request fell in them.
+pub(crate) fn calculate_status_classes(results: &[AttackResult], options: &MetricsOptions) -> Result<Vec<StatusClassLatency>> {
+    const CLASSES: [&str; 7] = ["1xx", "2xx", "3xx", "4xx", "5xx", "timeout", "error"];
+
+    let mut histograms: Vec<Option<LatencyHistogram>> = vec![None; CLASSES.len()];
+    for result in results {
+        let index = match result.status_code {
+            _ if result.timed_out => 5,
+            100..=599 => result.status_code as usize / 100 - 1,
+            _ => 6,
+        };
+        let histogram = match &mut histograms[index] {
+            Some(histogram) => histogram,
+            histogram => histogram.insert(options.histogram([])?),
+        };
+        histogram.record(result.latency);
+    }
+
+    Ok(CLASSES
+        .iter()
+        .zip(histograms)
+        .filter_map(|(class, histogram)| {
+            let histogram = histogram?;
+            Some(StatusClassLatency {
+                class: class.to_string(),
+                requests: histogram.len() as usize,
+                mean: histogram.mean(),
+                p50: histogram.percentile(0.5),
+                p95: histogram.percentile(0.95),
+                p99: histogram.percentile(0.99),
+            })
+        })
+        .collect())
+}
+
+/// Count failed requests by kind of failure, most frequent first
+///
+/// Errors are normalized so failures of the same kind count together however their
+/// messages differ, e.g. by URL or address. Failures without an error are counted by
+/// status code.
+pub(crate) fn calculate_errors(results: &[AttackResult]) -> Vec<ErrorSummary> {
+    let mut errors: Vec<ErrorSummary> = Vec::new();
+
+    for result in results.iter().filter(|r| !r.is_success()) {
+        let example = match &result.error {
+            Some(error) => error.clone(),
+            None => format!("HTTP {}", result.status_code),
+        };
+        let kind = normalize_error(result, &example);
+        match errors.iter_mut().find(|e| e.kind == kind) {
+            Some(summary) => summary.count += 1,
+            None => errors.push(ErrorSummary { kind, count: 1, example }),
+        }
     }
+
+    // Stable, so kinds as frequent as each other stay in order of first appearance
+    errors.sort_by_key(|e| std::cmp::Reverse(e.count));
+    errors
 }
 
-/// Calculate a percentile from a sorted list of durations
-fn percentile(sorted: &[Duration], p: f64) -> Duration {
-    if sorted.is_empty() {
-        return Duration::from_secs(0);
+/// The kind of failure of a failed request
+fn normalize_error(result: &AttackResult, error: &str) -> String {
+    if result.golden_diverged {
+        return "golden divergence".to_string();
+    }
+    if result.check_failed {
+        return "check failed".to_string();
+    }
+    if result.timed_out {
+        return "timeout".to_string();
+    }
+    if result.error.is_none() {
+        return error.to_string();
+    }
+
+    let lowercase = error.to_ascii_lowercase();
+    let kinds = [
+        ("timeout", &["timed out", "timeout"][..]),
+        ("connection refused", &["connection refused"]),
+        ("connection reset", &["connection reset", "broken pipe"]),
+        ("connection closed", &["connection closed", "unexpected eof", "incomplete message"]),
+        ("DNS failure", &["dns error", "failed to lookup address", "name or service not known", "no such host"]),
+        ("TLS error", &["certificate", "tls", "ssl", "handshake"]),
+        ("too many redirects", &["too many redirects"]),
+        ("response body", &["failed to read response body", "response body read", "failed to decode"]),
+    ];
+    for (kind, patterns) in kinds {
+        if patterns.iter().any(|pattern| lowercase.contains(pattern)) {
+            return kind.to_string();
+        }
     }
 
-    let index = (sorted.len() as f64 * p).ceil() as usize - 1;
-    sorted[index.min(sorted.len() - 1)]
+    // Anything else counts by its message, without the URL and parenthesized details
+    // that differ between requests
+    let mut message = String::new();
+    let mut depth = 0;
+    for c in error.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = (depth - 1).max(0),
+            _ if depth == 0 => message.push(c),
+            _ => {}
+        }
+    }
+    let message = message.replace(result.target.url.as_str(), "");
+    let message = message.split_whitespace().collect::<Vec<_>>().join(" ");
+    message.trim_end_matches([':', ' ']).to_string()
 }
+
@@ -0,0 +1,665 @@
+use anyhow::{Context, Result};
+use flate2::bufread::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Decompress, FlushDecompress, Status};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufWriter, Read, Write};
+
+use crate::gob::{is_gob, GobReader};
+use crate::import::{is_vegeta_json, CsvFormat, CsvResults, JtlXmlResults, VegetaResult};
+use crate::models::{Result as AttackResult, RunConfig, Target};
+
+/// Bytes that start every file of results in the binary encoding
+pub const MAGIC: &[u8; 8] = b"CULVRES1";
+
+/// Largest record accepted when reading the binary encoding, to fail fast on corrupt input
+const MAX_RECORD: usize = 64 * 1024 * 1024;
+
+/// Encoding of attack results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Compact binary records: a deflate stream of MessagePack records, each prefixed
+    /// with its length as a little-endian u32
+    Binary,
+    /// One JSON object per line
+    Json,
+}
+
+impl std::str::FromStr for Encoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "binary" => Ok(Self::Binary),
+            "json" | "ndjson" => Ok(Self::Json),
+            _ => anyhow::bail!("Unsupported results encoding: {}. Expected binary or json", s),
+        }
+    }
+}
+
+/// Encoding of attack results to read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputEncoding {
+    /// Tell the encoding apart by the start of the input
+    #[default]
+    Auto,
+    /// The binary encoding written by the attack command
+    Binary,
+    /// One JSON object per line
+    Ndjson,
+    /// A JSON array of results, as written by `encode --to=json`
+    JsonArray,
+    /// CSV with a header row, as written by `encode --to=csv`
+    Csv,
+    /// A gob stream of Vegeta results, as written by Vegeta or `encode --to=gob`
+    Gob,
+    /// Vegeta results as JSON lines, as written by `vegeta encode --to=json`
+    VegetaJson,
+    /// A JMeter `.jtl` file, in either its CSV or its XML format
+    Jtl,
+}
+
+impl std::str::FromStr for InputEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "binary" => Ok(Self::Binary),
+            "ndjson" => Ok(Self::Ndjson),
+            "json" => Ok(Self::JsonArray),
+            "csv" => Ok(Self::Csv),
+            "gob" => Ok(Self::Gob),
+            "vegeta-json" => Ok(Self::VegetaJson),
+            "jtl" => Ok(Self::Jtl),
+            _ => anyhow::bail!(
+                "Unsupported results encoding: {}. Expected auto, binary, ndjson, json, csv, gob, vegeta-json or jtl",
+                s
+            ),
+        }
+    }
+}
+
+/// Writes attack results one at a time in the given encoding
+pub struct ResultWriter<W: Write> {
+    sink: Sink<W>,
+    buffer: Vec<u8>,
+}
+
+/// Where a [`ResultWriter`] writes to
+enum Sink<W: Write> {
+    Binary(DeflateEncoder<W>),
+    Json(W),
+}
+
+impl<W: Write> ResultWriter<W> {
+    /// Start writing results, writing the binary header first if needed
+    pub fn new(mut writer: W, encoding: Encoding) -> Result<Self> {
+        let sink = match encoding {
+            Encoding::Binary => {
+                writer.write_all(MAGIC)?;
+                Sink::Binary(DeflateEncoder::new(writer, Compression::fast()))
+            }
+            Encoding::Json => Sink::Json(writer),
+        };
+        Ok(Self {
+            sink,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Write one result
+    pub fn write(&mut self, result: &AttackResult) -> Result<()> {
+        match &mut self.sink {
+            Sink::Binary(writer) => {
+                self.buffer.clear();
+                rmp_serde::encode::write_named(&mut self.buffer, result)?;
+                let len = u32::try_from(self.buffer.len()).context("Result is too large to encode")?;
+                writer.write_all(&len.to_le_bytes())?;
+                writer.write_all(&self.buffer)?;
+            }
+            Sink::Json(writer) => {
+                serde_json::to_writer(&mut *writer, result)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush the results written so far to the underlying writer
+    pub fn flush(&mut self) -> Result<()> {
+        match &mut self.sink {
+            Sink::Binary(writer) => writer.flush()?,
+            Sink::Json(writer) => writer.flush()?,
+        }
+        Ok(())
+    }
+
+    /// Finish writing results, returning the underlying writer
+    pub fn finish(self) -> Result<W> {
+        let mut writer = match self.sink {
+            Sink::Binary(writer) => writer.finish()?,
+            Sink::Json(writer) => writer,
+        };
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+/// Path of the file holding the configuration of the attack that wrote results to a
+/// file, next to it
+pub fn config_path(output: &str) -> String {
+    format!("{}.config.json", output)
+}
+
+/// Write the configuration of an attack next to the file its results are written to,
+/// after the configurations of earlier attacks when appending to the file
+pub fn write_config(output: &str, config: &RunConfig, append: bool) -> Result<()> {
+    let path = config_path(output);
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(&path)
+        .context(format!("Failed to create config file: {}", path))?;
+    let mut writer = std::io::BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, config)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read the configurations of the attacks that wrote results to a file, from the file
+/// next to it, in the order the attacks ran; none if the file is missing
+pub fn read_configs(output: &str) -> Result<Vec<RunConfig>> {
+    let path = config_path(output);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(format!("Failed to read config file: {}", path)),
+    };
+    serde_json::Deserializer::from_str(&contents)
+        .into_iter::<RunConfig>()
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .context(format!("Failed to parse config file: {}", path))
+}
+
+/// Check that results in the given encoding can be appended to a file, which is the
+/// case if it's missing, empty, or holds results in the same encoding
+pub(crate) fn check_append(path: &str, encoding: Encoding) -> Result<()> {
+    if std::fs::metadata(path).map_or(true, |metadata| metadata.len() == 0) {
+        return Ok(());
+    }
+
+    let mut reader = crate::utils::get_reader(path)?;
+    let start = reader.fill_buf().context(format!("Failed to read: {}", path))?;
+    let found = if start.starts_with(MAGIC) { Encoding::Binary } else { Encoding::Json };
+    if !start.is_empty() && found != encoding {
+        anyhow::bail!(
+            "Can't append {} results to {}, which holds {} results",
+            format!("{:?}", encoding).to_lowercase(),
+            path,
+            format!("{:?}", found).to_lowercase()
+        );
+    }
+    Ok(())
+}
+
+/// Most files [`TargetFiles`] writes, so requests to ever-changing paths don't exhaust
+/// file descriptors
+const MAX_TARGET_FILES: usize = 256;
+
+/// Writes results to a file per target in a directory, a target being the method, host,
+/// and path of the request, so query strings that vary between requests don't split
+/// results up
+///
+/// Files are named after their target, like `GET_https_api.example.com_users.bin`. Once
+/// `MAX_TARGET_FILES` files are open, the results of further targets go to
+/// `other_targets.bin`.
+pub struct TargetFiles {
+    dir: std::path::PathBuf,
+    encoding: Encoding,
+    append: bool,
+    /// Writer of each target's results
+    writers: HashMap<String, ResultWriter<BufWriter<std::fs::File>>>,
+    /// Names of the files written, to keep targets whose names clash apart
+    names: HashSet<String>,
+}
+
+/// Name of the file of results of targets beyond `MAX_TARGET_FILES`, without extension
+const OTHER_TARGETS: &str = "other_targets";
+
+impl TargetFiles {
+    /// Start writing results to files in a directory, creating it if needed
+    pub fn create(dir: &str, encoding: Encoding, append: bool) -> Result<Self> {
+        std::fs::create_dir_all(dir).context(format!("Failed to create directory: {}", dir))?;
+        Ok(Self {
+            dir: dir.into(),
+            encoding,
+            append,
+            writers: HashMap::new(),
+            names: HashSet::new(),
+        })
+    }
+
+    /// Write one result to the file of its target
+    pub fn write(&mut self, result: &AttackResult) -> Result<()> {
+        let mut target = target_key(&result.target);
+        if !self.writers.contains_key(&target) && self.writers.len() >= MAX_TARGET_FILES {
+            target = OTHER_TARGETS.to_string();
+        }
+
+        if !self.writers.contains_key(&target) {
+            let writer = self.open(&target)?;
+            self.writers.insert(target.clone(), writer);
+        }
+        self.writers.get_mut(&target).unwrap().write(result)
+    }
+
+    /// Finish writing every file
+    pub fn finish(self) -> Result<()> {
+        for writer in self.writers.into_values() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Open the file of a target's results
+    fn open(&mut self, target: &str) -> Result<ResultWriter<BufWriter<std::fs::File>>> {
+        // Keep file names short and portable, numbering names that clash
+        let base: String = target
+            .trim_end_matches('/')
+            .replace("://", "_")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .take(120)
+            .collect();
+        let mut name = base.clone();
+        for n in 2.. {
+            if self.names.insert(name.clone()) {
+                break;
+            }
+            name = format!("{}-{}", base, n);
+        }
+
+        let extension = match self.encoding {
+            Encoding::Binary => "bin",
+            Encoding::Json => "jsonl",
+        };
+        let path = self.dir.join(format!("{}.{}", name, extension));
+        let path_str = path.to_string_lossy();
+        if self.append {
+            check_append(&path_str, self.encoding)?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(self.append)
+            .truncate(!self.append)
+            .open(&path)
+            .context(format!("Failed to create file: {}", path_str))?;
+        ResultWriter::new(BufWriter::new(file), self.encoding)
+    }
+}
+
+/// The target of a request as its method, scheme, host, port and path, leaving out the
+/// query string and fragment
+pub(crate) fn target_key(target: &Target) -> String {
+    let url = &target.url;
+    let mut key = format!("{} {}://{}", target.method, url.scheme(), url.host_str().unwrap_or_default());
+    if let Some(port) = url.port() {
+        key.push_str(&format!(":{}", port));
+    }
+    key.push_str(url.path());
+    key
+}
+
+/// Sort results by timestamp, keeping the order of results with the same timestamp
+pub(crate) fn sort_results(results: &mut [AttackResult]) {
+    results.sort_by_key(|result| result.timestamp);
+}
+
+/// Drop results that exactly duplicate an earlier result, returning how many were
+/// dropped. Results must be sorted by timestamp, as duplicates are only looked for
+/// among results with the same timestamp.
+pub(crate) fn dedup_results(results: &mut Vec<AttackResult>) -> Result<usize> {
+    let before = results.len();
+    let mut kept: Vec<AttackResult> = Vec::with_capacity(results.len());
+    let mut group = HashSet::new();
+    for result in results.drain(..) {
+        if kept.last().is_some_and(|last| last.timestamp != result.timestamp) {
+            group.clear();
+        }
+        // Results are compared by their encoding, which covers every field
+        if group.insert(rmp_serde::to_vec_named(&result)?) {
+            kept.push(result);
+        }
+    }
+    *results = kept;
+    Ok(before - results.len())
+}
+
+/// Read attack results in any encoding, telling them apart by the start of the input:
+/// the binary header, a JSON array, a CSV header row, a gob stream, Vegeta's JSON, a
+/// JMeter `.jtl` file, or otherwise JSON lines
+///
+/// JSON lines, CSV records and JMeter samples that can't be parsed are yielded as errors
+/// and reading goes on, while a corrupt binary record, gob message, JSON array or XML
+/// document ends the results.
+pub fn read_results<R: BufRead>(reader: R) -> ResultReader<R> {
+    let (source, error) = match open(reader, InputEncoding::Auto) {
+        Ok(source) => (source, None),
+        Err(e) => (Source::Array(Vec::new().into_iter()), Some(e)),
+    };
+
+    ResultReader {
+        source,
+        line: String::new(),
+        error,
+        done: false,
+    }
+}
+
+/// Read attack results in the given encoding, failing if the input isn't in it
+pub fn read_results_as<R: BufRead>(reader: R, encoding: InputEncoding) -> Result<ResultReader<R>> {
+    Ok(ResultReader {
+        source: open(reader, encoding)?,
+        line: String::new(),
+        error: None,
+        done: false,
+    })
+}
+
+/// Start reading results in the given encoding, or the one the input starts with
+fn open<R: BufRead>(mut reader: R, encoding: InputEncoding) -> Result<Source<R>> {
+    let (binary, array, csv, gob, vegeta, jtl, xml, empty) = match reader.fill_buf() {
+        Ok(buf) => {
+            let start = buf.trim_ascii_start();
+            (
+                buf.starts_with(MAGIC),
+                start.first() == Some(&b'['),
+                buf.starts_with(b"timestamp,"),
+                is_gob(buf),
+                buf.split(|b| *b == b'\n').find(|line| !line.trim_ascii().is_empty()).is_some_and(is_vegeta_json),
+                buf.starts_with(b"timeStamp,") || start.starts_with(b"<?xml") || start.starts_with(b"<testResults"),
+                start.first() == Some(&b'<'),
+                buf.trim_ascii().is_empty(),
+            )
+        }
+        Err(_) => (false, false, false, false, false, false, false, true),
+    };
+    let encoding = match encoding {
+        InputEncoding::Auto if binary => InputEncoding::Binary,
+        InputEncoding::Auto if array => InputEncoding::JsonArray,
+        InputEncoding::Auto if csv => InputEncoding::Csv,
+        InputEncoding::Auto if gob => InputEncoding::Gob,
+        InputEncoding::Auto if vegeta => InputEncoding::VegetaJson,
+        InputEncoding::Auto if jtl => InputEncoding::Jtl,
+        InputEncoding::Auto => InputEncoding::Ndjson,
+        encoding => encoding,
+    };
+
+    match encoding {
+        InputEncoding::Binary if binary => {
+            reader.consume(MAGIC.len());
+            Ok(Source::Binary(DeflateDecoder::new(reader)))
+        }
+        InputEncoding::Binary => anyhow::bail!("Not binary results: the header is missing"),
+        InputEncoding::JsonArray => {
+            let results: Vec<AttackResult> = serde_json::from_reader(reader).context("Invalid JSON array of results")?;
+            Ok(Source::Array(results.into_iter()))
+        }
+        InputEncoding::Csv => Ok(Source::Csv(CsvResults::new(reader, CsvFormat::Culverin)?)),
+        InputEncoding::Jtl if xml => Ok(Source::JtlXml(JtlXmlResults::new(reader))),
+        InputEncoding::Jtl => Ok(Source::Csv(CsvResults::new(reader, CsvFormat::Jtl)?)),
+        InputEncoding::Gob if gob => Ok(Source::Gob(GobReader::new(reader))),
+        InputEncoding::Gob => anyhow::bail!("Not gob results: the input doesn't start with a Vegeta result type"),
+        InputEncoding::VegetaJson if vegeta || empty => Ok(Source::VegetaJson(reader)),
+        InputEncoding::VegetaJson => anyhow::bail!("Not Vegeta JSON results: the first result has no code"),
+        InputEncoding::Ndjson if binary => anyhow::bail!("Not JSON lines: the input is binary results"),
+        InputEncoding::Ndjson | InputEncoding::Auto => Ok(Source::Json(reader)),
+    }
+}
+
+/// Iterator over results read by [`read_results`]
+pub struct ResultReader<R: BufRead> {
+    source: Source<R>,
+    line: String,
+    /// Why the input can't be read at all, yielded as the only item
+    error: Option<anyhow::Error>,
+    done: bool,
+}
+
+/// Where a [`ResultReader`] reads from
+enum Source<R: BufRead> {
+    Binary(DeflateDecoder<R>),
+    Json(R),
+    /// Results of a JSON array, which is parsed as a whole
+    Array(std::vec::IntoIter<AttackResult>),
+    Csv(CsvResults<R>),
+    Gob(GobReader<R>),
+    VegetaJson(R),
+    JtlXml(JtlXmlResults<R>),
+}
+
+impl<R: BufRead> ResultReader<R> {
+    /// Encoding the results are read in
+    ///
+    /// Results in other encodings than culverin's own are written back as JSON lines.
+    pub fn encoding(&self) -> Encoding {
+        match self.source {
+            Source::Binary(_) => Encoding::Binary,
+            Source::Json(_) | Source::Array(_) | Source::Csv(_) | Source::Gob(_) | Source::VegetaJson(_) | Source::JtlXml(_) => {
+                Encoding::Json
+            }
+        }
+    }
+
+    /// Go on reading the results appended after the end of a binary stream, which follow
+    /// in a stream of their own after another header
+    fn next_binary_stream(&mut self) -> Result<Option<AttackResult>> {
+        let Source::Binary(decoder) = &mut self.source else {
+            return Ok(None);
+        };
+        if !decoder.get_mut().fill_buf()?.starts_with(MAGIC) {
+            return Ok(None);
+        }
+
+        let Source::Binary(decoder) = std::mem::replace(&mut self.source, Source::Array(Vec::new().into_iter())) else {
+            unreachable!()
+        };
+        let mut reader = decoder.into_inner();
+        reader.consume(MAGIC.len());
+        self.source = Source::Binary(DeflateDecoder::new(reader));
+        self.read_next()
+    }
+
+    fn read_next(&mut self) -> Result<Option<AttackResult>> {
+        match &mut self.source {
+            Source::Binary(reader) => {
+                let mut len = [0; 4];
+                match reader.read_exact(&mut len) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return self.next_binary_stream(),
+                    Err(e) => return Err(e.into()),
+                }
+
+                let len = u32::from_le_bytes(len) as usize;
+                if len > MAX_RECORD {
+                    anyhow::bail!("Corrupt binary results: record of {} bytes", len);
+                }
+                let mut record = vec![0; len];
+                reader.read_exact(&mut record).context("Truncated binary results")?;
+                Ok(Some(rmp_serde::from_slice(&record).context("Corrupt binary results")?))
+            }
+            Source::Array(results) => Ok(results.next()),
+            Source::Csv(results) => results.next().transpose(),
+            Source::Gob(results) => results.read(),
+            Source::JtlXml(results) => results.read(),
+            Source::Json(reader) => loop {
+                self.line.clear();
+                if reader.read_line(&mut self.line)? == 0 {
+                    return Ok(None);
+                }
+                let line = self.line.trim();
+                if !line.is_empty() {
+                    return Ok(Some(serde_json::from_str(line).context("Invalid JSON result")?));
+                }
+            },
+            Source::VegetaJson(reader) => loop {
+                self.line.clear();
+                if reader.read_line(&mut self.line)? == 0 {
+                    return Ok(None);
+                }
+                let line = self.line.trim();
+                if !line.is_empty() {
+                    let result: VegetaResult = serde_json::from_str(line).context("Invalid Vegeta JSON result")?;
+                    return Ok(Some(result.into_result()?));
+                }
+            },
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ResultReader<R> {
+    type Item = Result<AttackResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Some(e) = self.error.take() {
+            self.done = true;
+            return Some(Err(e));
+        }
+
+        match self.read_next() {
+            Ok(Some(result)) => Some(Ok(result)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                // An unreadable line can be skipped, but a binary stream can't be resynchronized
+                // JMeter's XML reader ends by itself on invalid XML
+                self.done = !matches!(
+                    self.source,
+                    Source::Json(_) | Source::Csv(_) | Source::VegetaJson(_) | Source::JtlXml(_)
+                )
+                    || e.downcast_ref::<std::io::Error>().is_some();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Read every result of the given readers, skipping lines that can't be parsed with a
+/// warning of how many there were
+///
+/// A binary stream ends at a corrupt or truncated record, e.g. one of an attack that was
+/// killed, so reading it fails rather than leaving out the results after the record.
+pub(crate) fn collect_results<R: BufRead>(readers: Vec<ResultReader<R>>) -> Result<Vec<AttackResult>> {
+    let mut results = Vec::new();
+    let mut unreadable = 0;
+    for reader in readers {
+        let binary = reader.encoding() == Encoding::Binary;
+        for result in reader {
+            match result {
+                Ok(result) => results.push(result),
+                Err(e) if binary => return Err(e.context("Failed to read binary results")),
+                Err(_) => unreadable += 1,
+            }
+        }
+    }
+
+    if unreadable > 0 {
+        eprintln!("Warning: {} results could not be read", unreadable);
+    }
+    Ok(results)
+}
+
+/// Decompressed bytes [`BinaryFollower`] makes room for at a time
+const FOLLOW_CHUNK: usize = 64 * 1024;
+
+/// Reads binary results from a file that's still being written, going on from where the
+/// last read stopped instead of reading the whole file again
+///
+/// Records still being written at the end of the file are kept until they're complete.
+pub(crate) struct BinaryFollower {
+    file: std::fs::File,
+    /// Bytes read from the file that weren't decompressed yet
+    input: Vec<u8>,
+    /// Decompressor of the current stream, or None before its header is read
+    inflate: Option<Decompress>,
+    /// Decompressed bytes of records that aren't complete yet
+    pending: Vec<u8>,
+}
+
+impl BinaryFollower {
+    /// Start following a file of binary results
+    pub(crate) fn open(path: &str) -> Result<Self> {
+        Ok(Self {
+            file: std::fs::File::open(path).context(format!("Failed to open file: {}", path))?,
+            input: Vec::new(),
+            inflate: None,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Read the results written since the last read
+    pub(crate) fn read(&mut self) -> Result<Vec<AttackResult>> {
+        self.file.read_to_end(&mut self.input)?;
+
+        let mut consumed = 0;
+        loop {
+            let input = &self.input[consumed..];
+            let Some(inflate) = &mut self.inflate else {
+                // Every stream starts with the header, including those appended by later attacks
+                if input.len() < MAGIC.len() {
+                    if !MAGIC.starts_with(input) {
+                        anyhow::bail!("Corrupt binary results: the header is missing");
+                    }
+                    break;
+                }
+                if !input.starts_with(MAGIC) {
+                    anyhow::bail!("Corrupt binary results: the header is missing");
+                }
+                consumed += MAGIC.len();
+                self.inflate = Some(Decompress::new(false));
+                continue;
+            };
+
+            let (total_in, total_out) = (inflate.total_in(), inflate.total_out());
+            self.pending.reserve(FOLLOW_CHUNK);
+            let status = inflate
+                .decompress_vec(input, &mut self.pending, FlushDecompress::None)
+                .context("Corrupt binary results")?;
+            consumed += (inflate.total_in() - total_in) as usize;
+            if status == Status::StreamEnd {
+                self.inflate = None;
+            } else if inflate.total_in() == total_in && inflate.total_out() == total_out {
+                // Everything written so far is decompressed
+                break;
+            }
+        }
+        self.input.drain(..consumed);
+
+        let mut results = Vec::new();
+        let mut start = 0;
+        while let Some(len) = self.pending.get(start..start + 4) {
+            let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+            if len > MAX_RECORD {
+                anyhow::bail!("Corrupt binary results: record of {} bytes", len);
+            }
+            let Some(record) = self.pending.get(start + 4..start + 4 + len) else {
+                break;
+            };
+            results.push(rmp_serde::from_slice(record).context("Corrupt binary results")?);
+            start += 4 + len;
+        }
+        self.pending.drain(..start);
+
+        Ok(results)
+    }
+}
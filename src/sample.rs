@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::models::Result as AttackResult;
+
+/// How many results to sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Sample {
+    /// Keep each result with the given probability
+    Fraction(f64),
+    /// Keep this many results, chosen uniformly
+    Count(usize),
+}
+
+impl std::str::FromStr for Sample {
+    type Err = anyhow::Error;
+
+    /// Parse a sample size like "1%" or "100000"
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(percent) = s.trim().strip_suffix('%') {
+            let percent: f64 = percent.trim().parse().context(format!("Invalid sample percentage: {}", s))?;
+            if !(percent > 0.0 && percent <= 100.0) {
+                anyhow::bail!("Sample percentage must be greater than 0% and at most 100%: {}", s);
+            }
+            Ok(Self::Fraction(percent / 100.0))
+        } else {
+            let count: usize = s.trim().parse().context(format!("Invalid sample size: {}. Expected a percentage like 1% or a number of results", s))?;
+            if count == 0 {
+                anyhow::bail!("Sample size must be at least 1");
+            }
+            Ok(Self::Count(count))
+        }
+    }
+}
+
+/// Takes a reproducible random sample of a stream of results: the same results and
+/// seed always give the same sample
+///
+/// A fraction is sampled as results arrive. A count is sampled with a reservoir, so
+/// the sample is only known once every result has been offered, and is returned by
+/// [`Sampler::finish`] in the order the results arrived.
+pub(crate) struct Sampler {
+    sample: Sample,
+    rng: StdRng,
+    /// Number of results offered so far
+    seen: usize,
+    /// Results sampled so far when sampling a count, with the order they arrived in
+    reservoir: Vec<(usize, AttackResult)>,
+}
+
+impl Sampler {
+    pub fn new(sample: Sample, seed: u64) -> Self {
+        Self {
+            sample,
+            rng: StdRng::seed_from_u64(seed),
+            seen: 0,
+            reservoir: Vec::new(),
+        }
+    }
+
+    /// Offer a result to the sample, returning it if it can be written right away
+    pub fn offer(&mut self, result: AttackResult) -> Option<AttackResult> {
+        let index = self.seen;
+        self.seen += 1;
+
+        match self.sample {
+            Sample::Fraction(fraction) => self.rng.gen_bool(fraction).then_some(result),
+            Sample::Count(count) => {
+                if self.reservoir.len() < count {
+                    self.reservoir.push((index, result));
+                } else {
+                    let slot = self.rng.gen_range(0..self.seen);
+                    if slot < count {
+                        self.reservoir[slot] = (index, result);
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// The results held back until every result was offered
+    pub fn finish(mut self) -> Vec<AttackResult> {
+        self.reservoir.sort_by_key(|(index, _)| *index);
+        self.reservoir.into_iter().map(|(_, result)| result).collect()
+    }
+}
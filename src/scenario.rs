@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use url::Url;
+
+use crate::models::{Extract, ExtractSource, Header, Target};
+
+/// Capture the values a step extracts from its response
+pub fn extract(extracts: &[Extract], headers: &HeaderMap, body: &[u8]) -> Result<BTreeMap<String, String>> {
+    let mut variables = BTreeMap::new();
+    let mut json: Option<Value> = None;
+
+    for extract in extracts {
+        let value = match &extract.source {
+            ExtractSource::Json(path) => {
+                if json.is_none() {
+                    json = Some(serde_json::from_slice(body).context("Response body isn't valid JSON")?);
+                }
+                json_path(json.as_ref().unwrap(), path)?.map(|value| match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+            }
+            ExtractSource::Regex(pattern) => {
+                let regex = Regex::new(pattern).context(format!("Invalid regex: {}", pattern))?;
+                let body = String::from_utf8_lossy(body);
+                regex
+                    .captures(&body)
+                    .and_then(|captures| captures.get(1).or_else(|| captures.get(0)))
+                    .map(|m| m.as_str().to_string())
+            }
+            ExtractSource::Header(name) => headers.get(name.as_str()).and_then(|v| v.to_str().ok()).map(str::to_string),
+        };
+
+        match value {
+            Some(value) => variables.insert(extract.name.clone(), value),
+            None => anyhow::bail!("Failed to extract {}: no match in the response", extract.name),
+        };
+    }
+
+    Ok(variables)
+}
+
+/// Replace `{{name}}` placeholders in the target's URL, headers, and body with variable values
+///
+/// Placeholders without a matching variable are left as they are.
+pub fn substitute(target: &mut Target, variables: &BTreeMap<String, String>) -> Result<()> {
+    if variables.is_empty() {
+        return Ok(());
+    }
+
+    // Braces in URLs are percent-encoded when parsed, so match both forms
+    let mut url = target.url.to_string();
+    for (name, value) in variables {
+        url = url
+            .replace(&format!("{{{{{}}}}}", name), value)
+            .replace(&format!("%7B%7B{}%7D%7D", name), value);
+    }
+    target.url = Url::parse(&url).context(format!("Invalid URL after substituting variables: {}", url))?;
+
+    for header in &mut target.headers {
+        header.name = substitute_str(&header.name, variables);
+        header.value = substitute_str(&header.value, variables);
+    }
+
+    if let Some(body) = &target.body {
+        if let Ok(text) = std::str::from_utf8(body) {
+            target.body = Some(substitute_str(text, variables).into_bytes());
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace `{{name}}` placeholders in headers sent with every request, like those of a
+/// plan, with variable values
+pub fn substitute_headers<'a>(headers: &'a [Header], variables: &BTreeMap<String, String>) -> Cow<'a, [Header]> {
+    if variables.is_empty() || !headers.iter().any(|h| h.name.contains("{{") || h.value.contains("{{")) {
+        return Cow::Borrowed(headers);
+    }
+
+    Cow::Owned(
+        headers
+            .iter()
+            .map(|header| Header {
+                name: substitute_str(&header.name, variables),
+                value: substitute_str(&header.value, variables),
+            })
+            .collect(),
+    )
+}
+
+/// Replace `{{name}}` placeholders in a string
+fn substitute_str(text: &str, variables: &BTreeMap<String, String>) -> String {
+    if !text.contains("{{") {
+        return text.to_string();
+    }
+
+    let mut text = text.to_string();
+    for (name, value) in variables {
+        text = text.replace(&format!("{{{{{}}}}}", name), value);
+    }
+    text
+}
+
+/// Look up a value with a JSONPath like `$.data.items[0].id` or `$['content-type']`
+///
+/// Only child member and array index selectors are supported.
+fn json_path<'a>(root: &'a Value, path: &str) -> Result<Option<&'a Value>> {
+    let rest = path.trim().strip_prefix('$').context(format!("JSONPath must start with $: {}", path))?;
+    let mut current = root;
+    let mut chars = rest.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let next = match c {
+            '.' => {
+                let mut key = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == '.' || c == '[' {
+                        break;
+                    }
+                    key.push(c);
+                    chars.next();
+                }
+                current.get(key.as_str())
+            }
+            '[' => {
+                let mut selector = String::new();
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    selector.push(c);
+                }
+                let selector = selector.trim();
+                if let Some(key) = selector.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+                    current.get(key)
+                } else {
+                    let index: usize = selector.parse().context(format!("Invalid JSONPath selector: [{}]", selector))?;
+                    current.get(index)
+                }
+            }
+            _ => anyhow::bail!("Invalid JSONPath: {}", path),
+        };
+
+        match next {
+            Some(value) => current = value,
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(current))
+}
@@ -0,0 +1,196 @@
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use rand::Rng;
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use std::fmt;
+use url::Url;
+
+use crate::hooks::{RequestMiddleware, RequestSpec, ResponseData};
+use crate::models::{Header, Result as AttackResult};
+
+/// A Rhai script whose functions hook into every request of an attack
+///
+/// The script may define either or both of:
+///
+/// ```text
+/// // Called before each request is sent. Change the method, url, headers (a map),
+/// // or body, and return the request; returning nothing leaves it as it was.
+/// fn request(req) {
+///     req.body = `{"id": "${uuid()}", "qty": ${random(1, 5)}}`;
+///     if random(0, 9) == 0 { req.url = "http://goku:9090/slow-path"; }
+///     req
+/// }
+///
+/// // Called with each response (status, headers, body). Return false or an
+/// // error message to fail the request; true or nothing to accept it.
+/// fn response(resp) {
+///     if resp.status == 200 && !resp.body.contains("\"ok\"") { return "missing ok"; }
+///     true
+/// }
+/// ```
+///
+/// Scripts also have `uuid()`, `random(min, max)`, and `timestamp()` (Unix milliseconds).
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    has_request: bool,
+    has_response: bool,
+}
+
+impl Script {
+    /// Compile a script from source
+    pub fn new(source: &str) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine.register_fn("uuid", || uuid::Uuid::new_v4().to_string());
+        engine.register_fn("random", |min: i64, max: i64| {
+            if min >= max {
+                min
+            } else {
+                rand::thread_rng().gen_range(min..=max)
+            }
+        });
+        engine.register_fn("timestamp", || chrono::Utc::now().timestamp_millis());
+
+        let ast = engine.compile(source).map_err(|e| anyhow::anyhow!("Failed to compile script: {}", e))?;
+        let defines = |name: &str| ast.iter_functions().any(|f| f.name == name && f.params.len() == 1);
+        let (has_request, has_response) = (defines("request"), defines("response"));
+        if !has_request && !has_response {
+            anyhow::bail!("Script must define a request(req) or response(resp) function");
+        }
+
+        Ok(Self {
+            engine,
+            ast,
+            has_request,
+            has_response,
+        })
+    }
+
+    /// Read and compile a script file
+    pub fn load(path: &str) -> Result<Self> {
+        let source = std::fs::read_to_string(path).context(format!("Failed to read script: {}", path))?;
+        Self::new(&source).context(format!("Invalid script: {}", path))
+    }
+
+    /// Whether the script defines a `request` function
+    pub fn has_request(&self) -> bool {
+        self.has_request
+    }
+
+    /// Whether the script defines a `response` function
+    pub fn has_response(&self) -> bool {
+        self.has_response
+    }
+
+    /// Let the script's `request` function modify a request
+    pub fn on_request(&self, request: &mut RequestSpec) -> Result<()> {
+        if !self.has_request {
+            return Ok(());
+        }
+
+        let body = request.body.as_deref().map(|b| String::from_utf8_lossy(b).into_owned());
+        let mut map = Map::new();
+        map.insert("method".into(), request.method.clone().into());
+        map.insert("url".into(), request.url.to_string().into());
+        map.insert("headers".into(), headers_map(&request.headers).into());
+        map.insert("body".into(), body.clone().map_or(Dynamic::UNIT, Dynamic::from));
+
+        let returned = self.call("request", map)?;
+        if returned.is_unit() {
+            return Ok(());
+        }
+        let map = returned
+            .try_cast::<Map>()
+            .context("Script request function must return the request or nothing")?;
+
+        if let Some(method) = map.get("method") {
+            let method = method.to_string().to_uppercase();
+            reqwest::Method::from_bytes(method.as_bytes()).context(format!("Script returned an invalid method: {}", method))?;
+            request.method = method;
+        }
+        if let Some(url) = map.get("url").map(|u| u.to_string()) {
+            if url != request.url.as_str() {
+                request.url = Url::parse(&url).context(format!("Script returned an invalid URL: {}", url))?;
+            }
+        }
+        if let Some(headers) = map.get("headers").and_then(|h| h.read_lock::<Map>()) {
+            // Keep repeated headers in order unless the script changed them
+            let returned = header_pairs(&headers);
+            if returned != header_pairs(&headers_map(&request.headers)) {
+                request.headers = returned.into_iter().map(|(name, value)| Header { name, value }).collect();
+            }
+        }
+        match map.get("body") {
+            Some(value) if value.is_unit() => request.body = None,
+            Some(value) => {
+                let value = value.to_string();
+                if body.as_deref() != Some(value.as_str()) {
+                    request.body = Some(value.into_bytes());
+                }
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    /// Let the script's `response` function validate a response, failing the result if it rejects it
+    pub fn on_response(&self, response: &ResponseData, result: &mut AttackResult) {
+        if !self.has_response {
+            return;
+        }
+
+        let mut map = Map::new();
+        map.insert("status".into(), (response.status_code as i64).into());
+        map.insert("headers".into(), headers_map(&response.headers).into());
+        map.insert("body".into(), String::from_utf8_lossy(&response.body).into_owned().into());
+
+        let error = match self.call("response", map) {
+            Ok(value) if value.is_unit() || value.as_bool() == Ok(true) => None,
+            Ok(value) if value.as_bool() == Ok(false) => Some("Script check failed".to_string()),
+            Ok(value) => Some(format!("Script check failed: {}", value)),
+            Err(e) => Some(e.to_string()),
+        };
+
+        if let Some(error) = error {
+            result.check_failed = true;
+            result.error.get_or_insert(error);
+        }
+    }
+
+    /// Call one of the script's functions with a single argument
+    fn call(&self, name: &str, arg: Map) -> Result<Dynamic> {
+        let options = CallFnOptions::new().eval_ast(false);
+        self.engine
+            .call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &self.ast, name, (arg,))
+            .map_err(|e| anyhow::anyhow!("Script {} function failed: {}", name, e))
+    }
+}
+
+impl RequestMiddleware for Script {
+    fn before_request<'a>(&'a self, request: &'a mut RequestSpec) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.on_request(request) })
+    }
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Script")
+            .field("request", &self.has_request)
+            .field("response", &self.has_response)
+            .finish()
+    }
+}
+
+/// Headers as a script map, where a repeated header keeps its last value
+fn headers_map(headers: &[Header]) -> Map {
+    headers
+        .iter()
+        .map(|h| (h.name.as_str().into(), h.value.clone().into()))
+        .collect()
+}
+
+/// Headers of a script map as name and value pairs
+fn header_pairs(headers: &Map) -> Vec<(String, String)> {
+    headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+}
@@ -0,0 +1,151 @@
+// Socket sink for attack results, streaming them as JSON lines to a remote collector
+// while the attack runs, so results can be processed off the load generator.
+//
+// Over TCP, results are sent in batches at least every 100ms, reconnecting if the
+// connection drops. Over UDP, every result is a datagram of its own, and results the
+// collector doesn't receive are lost.
+
+use anyhow::{Context, Result};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+
+use crate::models::Result as AttackResult;
+
+/// Bytes of results written before sending them, unless `SEND_INTERVAL` passes first
+const BATCH_SIZE: usize = 64 * 1024;
+
+/// Longest time results are held before sending them
+const SEND_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Attempts at sending a batch over TCP, reconnecting in between, before giving up
+const ATTEMPTS: u32 = 3;
+
+/// Whether an output is a tcp:// or udp:// URL
+pub fn is_socket_url(output: &str) -> bool {
+    output.starts_with("tcp://") || output.starts_with("udp://")
+}
+
+/// Streams attack results over a socket
+pub struct SocketWriter {
+    /// Host and port of the collector
+    address: String,
+    transport: Transport,
+    /// Results waiting to be sent, as JSON lines
+    batch: Vec<u8>,
+    last_send: Instant,
+}
+
+/// The socket results are sent over
+enum Transport {
+    /// A TCP connection, or None after it dropped and reconnecting failed
+    Tcp(Option<TcpStream>),
+    Udp(UdpSocket),
+}
+
+impl SocketWriter {
+    /// Connect to the collector of a `tcp://host:port` or `udp://host:port` output URL
+    pub async fn connect(output: &str) -> Result<Self> {
+        let (scheme, address) = output.split_once("://").context(format!("Invalid output URL: {}", output))?;
+        let address = address.trim_end_matches('/');
+        if address.is_empty() {
+            anyhow::bail!("Missing host and port in output: {}", output);
+        }
+
+        let transport = match scheme {
+            "tcp" => {
+                let stream = TcpStream::connect(address)
+                    .await
+                    .context(format!("Failed to connect to {}", output))?;
+                stream.set_nodelay(true)?;
+                Transport::Tcp(Some(stream))
+            }
+            "udp" => {
+                let collector = tokio::net::lookup_host(address)
+                    .await
+                    .context(format!("Failed to resolve {}", output))?
+                    .next()
+                    .context(format!("Failed to resolve {}", output))?;
+                let local = if collector.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+                let socket = UdpSocket::bind(local).await?;
+                socket.connect(collector).await.context(format!("Failed to connect to {}", output))?;
+                Transport::Udp(socket)
+            }
+            _ => anyhow::bail!("Unsupported socket scheme: {}. Expected tcp or udp", scheme),
+        };
+
+        Ok(Self {
+            address: address.to_string(),
+            transport,
+            batch: Vec::new(),
+            last_send: Instant::now(),
+        })
+    }
+
+    /// Write one result
+    pub async fn write(&mut self, result: &AttackResult) -> Result<()> {
+        serde_json::to_writer(&mut self.batch, result)?;
+        self.batch.push(b'\n');
+
+        match &self.transport {
+            Transport::Udp(socket) => {
+                let sent = socket.send(&self.batch).await;
+                self.batch.clear();
+                match sent {
+                    // Nothing listens on the collector's port yet, or anymore
+                    Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => Ok(()),
+                    Err(e) => Err(e).context(format!("Failed to send a result to udp://{}", self.address)),
+                    Ok(_) => Ok(()),
+                }
+            }
+            Transport::Tcp(_) => {
+                if self.batch.len() >= BATCH_SIZE || self.last_send.elapsed() >= SEND_INTERVAL {
+                    self.send().await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Send the remaining results and close the socket
+    pub async fn finish(mut self) -> Result<()> {
+        self.send().await?;
+        if let Transport::Tcp(Some(stream)) = &mut self.transport {
+            stream.shutdown().await?;
+        }
+        Ok(())
+    }
+
+    /// Send the batch over TCP, reconnecting if the connection dropped
+    async fn send(&mut self) -> Result<()> {
+        let Transport::Tcp(stream) = &mut self.transport else {
+            return Ok(());
+        };
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let sent = match stream {
+                Some(stream) => stream.write_all(&self.batch).await,
+                None => Err(std::io::ErrorKind::NotConnected.into()),
+            };
+            match sent {
+                Ok(()) => break,
+                Err(e) if attempt >= ATTEMPTS => {
+                    return Err(e).context(format!("Failed to send results to tcp://{}", self.address));
+                }
+                Err(_) => {
+                    tokio::time::sleep(Duration::from_millis(100) * attempt).await;
+                    *stream = TcpStream::connect(&self.address).await.ok();
+                }
+            }
+        }
+
+        self.batch.clear();
+        self.last_send = Instant::now();
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+// SQLite sink for attack results, so results can be queried with SQL during and right
+// after an attack.
+//
+// Results are written to a `results` table, one row per request, with indexes on the
+// columns queries usually filter or group by, and the configuration of each attack to a
+// `runs` table. The database uses write-ahead logging
+// and results are committed in batches, so other connections can read the results
+// written so far while the attack is still running.
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::time::{Duration, Instant};
+
+use crate::models::{Result as AttackResult, RunConfig};
+
+/// Scheme of output URLs that write results to SQLite
+pub const SCHEME: &str = "sqlite://";
+
+/// Results written before committing them, unless `COMMIT_INTERVAL` passes first
+const BATCH_SIZE: usize = 1000;
+
+/// Longest time results are held in an open transaction
+const COMMIT_INTERVAL: Duration = Duration::from_secs(1);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS results (
+    id INTEGER PRIMARY KEY,
+    run_id TEXT,
+    attack TEXT,
+    seq INTEGER NOT NULL,
+    worker INTEGER NOT NULL,
+    timestamp TEXT NOT NULL,
+    latency_ms REAL NOT NULL,
+    status_code INTEGER NOT NULL,
+    error TEXT,
+    timed_out INTEGER NOT NULL,
+    method TEXT NOT NULL,
+    url TEXT NOT NULL,
+    bytes_in INTEGER NOT NULL,
+    bytes_out INTEGER NOT NULL,
+    content_type TEXT,
+    request_id TEXT,
+    trace_id TEXT,
+    scenario TEXT,
+    step INTEGER,
+    check_failed INTEGER NOT NULL,
+    metadata TEXT,
+    custom TEXT
+);
+CREATE INDEX IF NOT EXISTS results_timestamp ON results (timestamp);
+CREATE INDEX IF NOT EXISTS results_status_code ON results (status_code);
+CREATE INDEX IF NOT EXISTS results_url ON results (url);
+CREATE INDEX IF NOT EXISTS results_run_id ON results (run_id);
+CREATE TABLE IF NOT EXISTS runs (
+    run_id TEXT PRIMARY KEY,
+    started_at TEXT NOT NULL,
+    version TEXT NOT NULL,
+    targets INTEGER NOT NULL,
+    config TEXT NOT NULL
+);
+";
+
+const INSERT: &str = "
+INSERT INTO results (
+    run_id, attack, seq, worker, timestamp, latency_ms, status_code, error, timed_out,
+    method, url, bytes_in, bytes_out, content_type, request_id, trace_id, scenario,
+    step, check_failed, metadata, custom
+) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)
+";
+
+/// Writes attack results to a SQLite database
+pub struct SqliteWriter {
+    conn: Connection,
+    /// Results written since the last commit
+    pending: usize,
+    last_commit: Instant,
+}
+
+impl SqliteWriter {
+    /// Open the database at the path of a `sqlite://` output URL, creating it and the
+    /// results table if needed
+    pub fn open(output: &str) -> Result<Self> {
+        let path = output.strip_prefix(SCHEME).unwrap_or(output);
+        if path.is_empty() {
+            anyhow::bail!("Missing database path in output: {}", output);
+        }
+
+        let conn = Connection::open(path).context(format!("Failed to open SQLite database: {}", path))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(SCHEMA).context("Failed to create the results table")?;
+        conn.execute_batch("BEGIN")?;
+
+        Ok(Self {
+            conn,
+            pending: 0,
+            last_commit: Instant::now(),
+        })
+    }
+
+    /// Record the configuration of the attack whose results are written, replacing any
+    /// earlier record of a run with the same ID
+    pub fn write_config(&mut self, config: &RunConfig) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO runs (run_id, started_at, version, targets, config) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                config.run_id,
+                config.started_at.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true),
+                config.version,
+                config.targets as i64,
+                serde_json::to_string(config)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Write one result
+    pub fn write(&mut self, result: &AttackResult) -> Result<()> {
+        let json = |value: String| (value != "{}").then_some(value);
+        let metadata = json(serde_json::to_string(&result.metadata)?);
+        let custom = json(serde_json::to_string(&result.custom)?);
+
+        self.conn.prepare_cached(INSERT)?.execute(params![
+            result.run_id,
+            result.attack,
+            result.seq as i64,
+            result.worker as i64,
+            result.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true),
+            result.latency.as_secs_f64() * 1000.0,
+            result.status_code,
+            result.error,
+            result.timed_out,
+            result.target.method,
+            result.target.url.as_str(),
+            result.bytes_in as i64,
+            result.bytes_out as i64,
+            result.content_type,
+            result.request_id,
+            result.trace_id,
+            result.scenario.as_ref().map(|step| step.name.as_str()),
+            result.scenario.as_ref().map(|step| step.step as i64),
+            result.check_failed,
+            metadata,
+            custom,
+        ])?;
+
+        self.pending += 1;
+        if self.pending >= BATCH_SIZE || self.last_commit.elapsed() >= COMMIT_INTERVAL {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Commit the results written so far, making them visible to other connections
+    pub fn commit(&mut self) -> Result<()> {
+        self.conn.execute_batch("COMMIT; BEGIN")?;
+        self.pending = 0;
+        self.last_commit = Instant::now();
+        Ok(())
+    }
+
+    /// Commit the remaining results and close the database
+    pub fn finish(self) -> Result<()> {
+        self.conn.execute_batch("COMMIT")?;
+        self.conn.close().map_err(|(_, e)| e)?;
+        Ok(())
+    }
+}
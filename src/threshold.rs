@@ -0,0 +1,261 @@
+use anyhow::{Context, Result};
+use std::time::Duration;
+
+use crate::models::Metrics;
+use crate::utils::{format_duration, format_size};
+
+/// A condition on a report metric, like "p99<500ms" or "success_rate>99.5%"
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Threshold {
+    /// The condition as given, to report it by
+    condition: String,
+    metric: Metric,
+    operator: Operator,
+    /// Bound the metric is compared to, in seconds for latencies and as a fraction for
+    /// ratios
+    bound: f64,
+}
+
+/// Metrics thresholds can be set on
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Metric {
+    Min,
+    Mean,
+    P50,
+    P90,
+    P95,
+    P99,
+    Max,
+    /// Any other percentile reported with --percentiles, from 0 to 100
+    Percentile(f64),
+    Stddev,
+    Cv,
+    TtfbMean,
+    TtfbMax,
+    /// Time to first byte at a percentile, from 0 to 100
+    TtfbPercentile(f64),
+    SuccessRate,
+    ErrorRate,
+    Rate,
+    Throughput,
+    BytesInRate,
+    BytesOutRate,
+    Requests,
+    Errors,
+    Timeouts,
+    Apdex,
+}
+
+/// Names thresholds refer to metrics by
+const METRICS: [(&str, Metric); 21] = [
+    ("min", Metric::Min),
+    ("mean", Metric::Mean),
+    ("p50", Metric::P50),
+    ("p90", Metric::P90),
+    ("p95", Metric::P95),
+    ("p99", Metric::P99),
+    ("max", Metric::Max),
+    ("stddev", Metric::Stddev),
+    ("cv", Metric::Cv),
+    ("ttfb_mean", Metric::TtfbMean),
+    ("ttfb_max", Metric::TtfbMax),
+    ("success_rate", Metric::SuccessRate),
+    ("error_rate", Metric::ErrorRate),
+    ("rate", Metric::Rate),
+    ("throughput", Metric::Throughput),
+    ("bytes_in_rate", Metric::BytesInRate),
+    ("bytes_out_rate", Metric::BytesOutRate),
+    ("requests", Metric::Requests),
+    ("errors", Metric::Errors),
+    ("timeouts", Metric::Timeouts),
+    ("apdex", Metric::Apdex),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Metric {
+    fn is_latency(self) -> bool {
+        matches!(
+            self,
+            Metric::Min
+                | Metric::Mean
+                | Metric::P50
+                | Metric::P90
+                | Metric::P95
+                | Metric::P99
+                | Metric::Max
+                | Metric::Percentile(_)
+                | Metric::Stddev
+                | Metric::TtfbMean
+                | Metric::TtfbMax
+                | Metric::TtfbPercentile(_)
+        )
+    }
+
+    fn is_ratio(self) -> bool {
+        matches!(self, Metric::SuccessRate | Metric::ErrorRate)
+    }
+
+    /// The metric's value, in seconds for latencies and as a fraction for ratios
+    fn value(self, metrics: &Metrics) -> f64 {
+        match self {
+            Metric::Min => metrics.min.as_secs_f64(),
+            Metric::Mean => metrics.mean.as_secs_f64(),
+            Metric::P50 => metrics.p50.as_secs_f64(),
+            Metric::P90 => metrics.p90.as_secs_f64(),
+            Metric::P95 => metrics.p95.as_secs_f64(),
+            Metric::P99 => metrics.p99.as_secs_f64(),
+            Metric::Max => metrics.max.as_secs_f64(),
+            // Fails every condition unless the percentile was reported
+            Metric::Percentile(percentile) => metrics
+                .percentiles
+                .iter()
+                .find(|p| p.percentile == percentile)
+                .map_or(f64::NAN, |p| p.latency.as_secs_f64()),
+            Metric::Stddev => metrics.stddev.as_secs_f64(),
+            Metric::Cv => metrics.cv,
+            // Fails every condition unless the results recorded the time to first byte
+            Metric::TtfbMean => metrics.ttfb.as_ref().map_or(f64::NAN, |ttfb| ttfb.mean.as_secs_f64()),
+            Metric::TtfbMax => metrics.ttfb.as_ref().map_or(f64::NAN, |ttfb| ttfb.max.as_secs_f64()),
+            Metric::TtfbPercentile(percentile) => metrics
+                .ttfb
+                .iter()
+                .flat_map(|ttfb| &ttfb.percentiles)
+                .find(|p| p.percentile == percentile)
+                .map_or(f64::NAN, |p| p.latency.as_secs_f64()),
+            Metric::SuccessRate => metrics.success_rate,
+            Metric::ErrorRate if metrics.requests == 0 => 0.0,
+            Metric::ErrorRate => 1.0 - metrics.success_rate,
+            Metric::Rate => metrics.rate,
+            Metric::Throughput => metrics.throughput,
+            Metric::BytesInRate => metrics.bytes_in_rate,
+            Metric::BytesOutRate => metrics.bytes_out_rate,
+            Metric::Requests => metrics.requests as f64,
+            Metric::Errors => (metrics.requests - metrics.success) as f64,
+            Metric::Timeouts => metrics.timeouts as f64,
+            // Fails every condition without an Apdex score to compare
+            Metric::Apdex => metrics.apdex.as_ref().map_or(f64::NAN, |apdex| apdex.score),
+        }
+    }
+
+    /// Format a value of the metric the way reports show it
+    fn format(self, value: f64) -> String {
+        if value.is_nan() {
+            match self {
+                Metric::Apdex => "n/a (no --apdex)".to_string(),
+                Metric::TtfbMean | Metric::TtfbMax => "n/a (no time to first byte recorded)".to_string(),
+                _ => "n/a (not in --percentiles)".to_string(),
+            }
+        } else if self.is_latency() {
+            format_duration(Duration::from_secs_f64(value))
+        } else if self.is_ratio() {
+            format!("{:.2}%", value * 100.0)
+        } else if matches!(self, Metric::Rate | Metric::Throughput) {
+            format!("{:.2}/s", value)
+        } else if matches!(self, Metric::BytesInRate | Metric::BytesOutRate) {
+            format!("{}/s", format_size(value as usize))
+        } else if matches!(self, Metric::Cv | Metric::Apdex) {
+            format!("{:.2}", value)
+        } else {
+            format!("{}", value)
+        }
+    }
+}
+
+impl std::str::FromStr for Threshold {
+    type Err = anyhow::Error;
+
+    /// Parse a condition like "p99<500ms", "success_rate>=99.5%" or "rate>950"
+    fn from_str(s: &str) -> Result<Self> {
+        let condition = s.trim();
+        let (position, operator, length) = [("<=", Operator::LessOrEqual), (">=", Operator::GreaterOrEqual), ("<", Operator::Less), (">", Operator::Greater)]
+            .into_iter()
+            .find_map(|(symbol, operator)| condition.find(symbol).map(|position| (position, operator, symbol.len())))
+            .context(format!("Invalid threshold: {}. Expected a condition like p99<500ms", condition))?;
+
+        let name = condition[..position].trim();
+        let bound = condition[position + length..].trim();
+        let metric = METRICS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, metric)| *metric)
+            .or_else(|| name.strip_prefix("ttfb_p").and_then(parse_percentile).map(Metric::TtfbPercentile))
+            .or_else(|| name.strip_prefix('p').and_then(parse_percentile).map(Metric::Percentile))
+            .context(format!(
+                "Unknown threshold metric: {}. Expected a percentile like p99.9 or one of {}",
+                name,
+                METRICS.iter().map(|(n, _)| *n).collect::<Vec<_>>().join(", ")
+            ))?;
+
+        let bound = if metric.is_latency() {
+            humantime::parse_duration(bound)
+                .context(format!("Invalid latency in threshold {}: {}", condition, bound))?
+                .as_secs_f64()
+        } else if let Some(percent) = bound.strip_suffix('%').filter(|_| metric.is_ratio()) {
+            percent.trim().parse::<f64>().context(format!("Invalid percentage in threshold {}: {}", condition, bound))? / 100.0
+        } else {
+            bound.parse::<f64>().context(format!("Invalid number in threshold {}: {}", condition, bound))?
+        };
+
+        Ok(Self {
+            condition: condition.to_string(),
+            metric,
+            operator,
+            bound,
+        })
+    }
+}
+
+impl Threshold {
+    /// Whether the metrics meet the condition, and the value of its metric, formatted
+    pub fn evaluate(&self, metrics: &Metrics) -> (bool, String) {
+        let value = self.metric.value(metrics);
+        let passed = match self.operator {
+            Operator::Less => value < self.bound,
+            Operator::LessOrEqual => value <= self.bound,
+            Operator::Greater => value > self.bound,
+            Operator::GreaterOrEqual => value >= self.bound,
+        };
+        (passed, self.metric.format(value))
+    }
+
+    /// The condition as given
+    pub fn condition(&self) -> &str {
+        &self.condition
+    }
+}
+
+/// Parse a percentile from 0 to 100
+fn parse_percentile(s: &str) -> Option<f64> {
+    s.parse::<f64>().ok().filter(|percentile| (0.0..=100.0).contains(percentile))
+}
+
+/// Parse a comma separated list of thresholds
+pub(crate) fn parse_thresholds(s: &str) -> Result<Vec<Threshold>> {
+    s.split(',').filter(|condition| !condition.trim().is_empty()).map(|condition| condition.parse()).collect()
+}
+
+/// Print a pass/fail table of the thresholds to stderr, failing if any isn't met
+pub(crate) fn check_thresholds(thresholds: &[Threshold], metrics: &Metrics) -> Result<()> {
+    let mut breached = Vec::new();
+
+    eprintln!("Thresholds:");
+    for threshold in thresholds {
+        let (passed, value) = threshold.evaluate(metrics);
+        eprintln!("  {} {}\t{}", if passed { "✓" } else { "✗" }, threshold.condition(), value);
+        if !passed {
+            breached.push(threshold.condition());
+        }
+    }
+
+    if !breached.is_empty() {
+        anyhow::bail!("{} of {} thresholds breached: {}", breached.len(), thresholds.len(), breached.join(", "));
+    }
+    Ok(())
+}
@@ -0,0 +1,457 @@
+// Time-series database sink for attack results, streaming them to InfluxDB or
+// ClickHouse over HTTP while the attack runs.
+//
+// Either every result is written, or with `aggregate=<interval>` one row per interval
+// summarizing the results that completed in it, which keeps soak tests of millions
+// of requests down to a row per second. Rows are sent in batches.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+use url::Url;
+
+use crate::models::Result as AttackResult;
+use crate::histogram::PercentileMethod;
+
+/// Rows written before sending them, unless `SEND_INTERVAL` passes first
+const BATCH_SIZE: usize = 5000;
+
+/// Longest time rows are held before sending them
+const SEND_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Attempts at sending a batch before giving up
+const ATTEMPTS: u32 = 3;
+
+/// Default table or measurement of per-request results
+const RESULTS_TABLE: &str = "culverin_results";
+
+/// Default table or measurement of aggregates
+const AGGREGATES_TABLE: &str = "culverin_aggregates";
+
+/// Whether an output is a time-series database URL
+pub fn is_tsdb_url(output: &str) -> bool {
+    Database::from_scheme(output.split_once("://").map_or("", |(scheme, _)| scheme)).is_some()
+}
+
+/// The database results are written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Database {
+    Influx,
+    ClickHouse,
+}
+
+impl Database {
+    /// The database and whether to use TLS for a URL scheme like "influxdb+https"
+    fn from_scheme(scheme: &str) -> Option<(Self, bool)> {
+        let (database, transport) = scheme.split_once('+').unwrap_or((scheme, "http"));
+        let database = match database {
+            "influxdb" => Self::Influx,
+            "clickhouse" => Self::ClickHouse,
+            _ => return None,
+        };
+        match transport {
+            "http" => Some((database, false)),
+            "https" => Some((database, true)),
+            _ => None,
+        }
+    }
+}
+
+/// Streams attack results to a time-series database
+pub struct TsdbWriter {
+    client: reqwest::Client,
+    database: Database,
+    /// Where batches are sent, with the query naming the database and table
+    endpoint: Url,
+    /// Username and password from the URL, sent with basic authentication
+    credentials: Option<(String, String)>,
+    /// InfluxDB API token
+    token: Option<String>,
+    /// Table or measurement rows are written to
+    table: String,
+    /// Length of the aggregation interval, if results are aggregated
+    aggregate: Option<Duration>,
+    window: Option<Window>,
+    /// Rows waiting to be sent
+    batch: String,
+    rows: usize,
+    last_send: Instant,
+}
+
+/// Results that completed within one aggregation interval
+struct Window {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    /// Labels of the first result, shared by every result of a run
+    attack: Option<String>,
+    run_id: Option<String>,
+    metadata: BTreeMap<String, String>,
+    latencies: Vec<Duration>,
+    errors: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+impl TsdbWriter {
+    /// Connect to the database of an output URL like
+    /// `influxdb://localhost:8086?org=acme&bucket=loadtests` or
+    /// `clickhouse://localhost:8123?database=loadtests`, creating the ClickHouse table
+    /// if needed
+    pub async fn open(output: &str) -> Result<Self> {
+        let url = Url::parse(output).context(format!("Invalid database URL: {}", output))?;
+        let (database, tls) = Database::from_scheme(url.scheme()).context(format!(
+            "Unsupported database URL: {}. Expected an influxdb:// or clickhouse:// URL",
+            output
+        ))?;
+        let host = url.host_str().context(format!("Missing host in database URL: {}", output))?;
+        let query: BTreeMap<String, String> = url.query_pairs().into_owned().collect();
+
+        let aggregate = query
+            .get("aggregate")
+            .map(|interval| humantime::parse_duration(interval).context(format!("Invalid aggregation interval: {}", interval)))
+            .transpose()?;
+        if aggregate == Some(Duration::ZERO) {
+            anyhow::bail!("Aggregation interval must be greater than zero");
+        }
+        let table = query
+            .get(if database == Database::Influx { "measurement" } else { "table" })
+            .cloned()
+            .unwrap_or_else(|| if aggregate.is_some() { AGGREGATES_TABLE } else { RESULTS_TABLE }.to_string());
+
+        let scheme = if tls { "https" } else { "http" };
+        let port = url.port().unwrap_or(if database == Database::Influx { 8086 } else { 8123 });
+        let mut endpoint = Url::parse(&format!("{}://{}:{}/", scheme, host, port))?;
+        let credentials = (!url.username().is_empty())
+            .then(|| (url.username().to_string(), url.password().unwrap_or_default().to_string()));
+        let mut token = None;
+
+        match database {
+            Database::Influx => {
+                // InfluxDB 2 writes to a bucket of an organization with a token, 1.x to a
+                // database with a username and password
+                if let Some(bucket) = query.get("bucket") {
+                    endpoint.set_path("/api/v2/write");
+                    let mut pairs = endpoint.query_pairs_mut();
+                    pairs.append_pair("bucket", bucket);
+                    if let Some(org) = query.get("org") {
+                        pairs.append_pair("org", org);
+                    }
+                    pairs.append_pair("precision", "ns");
+                    drop(pairs);
+                    token = std::env::var("INFLUX_TOKEN").ok();
+                } else {
+                    let db = query.get("db").context("Missing bucket or db in InfluxDB URL")?;
+                    endpoint.set_path("/write");
+                    endpoint.query_pairs_mut().append_pair("db", db).append_pair("precision", "ns");
+                }
+            }
+            Database::ClickHouse => {
+                if let Some(db) = query.get("database") {
+                    endpoint.query_pairs_mut().append_pair("database", db);
+                }
+            }
+        }
+
+        let writer = Self {
+            client: reqwest::Client::new(),
+            database,
+            endpoint,
+            credentials,
+            token,
+            table,
+            aggregate,
+            window: None,
+            batch: String::new(),
+            rows: 0,
+            last_send: Instant::now(),
+        };
+        if database == Database::ClickHouse {
+            writer.create_table().await?;
+        }
+        Ok(writer)
+    }
+
+    /// Write one result, or add it to the aggregate of its interval
+    pub async fn write(&mut self, result: &AttackResult) -> Result<()> {
+        match self.aggregate {
+            Some(interval) => {
+                // Results arrive in the order they complete, so a result completing
+                // after the current interval closes it
+                let completed = result.timestamp + chrono::Duration::from_std(result.latency)?;
+                if self.window.as_ref().is_some_and(|window| completed >= window.end) {
+                    self.close_window()?;
+                }
+                let window = match &mut self.window {
+                    Some(window) => window,
+                    None => self.window.insert(Window::new(completed, interval, result)?),
+                };
+                window.add(result);
+            }
+            None => {
+                match self.database {
+                    Database::Influx => write_result_line(&mut self.batch, &self.table, result),
+                    Database::ClickHouse => write_result_row(&mut self.batch, result)?,
+                }
+                self.rows += 1;
+            }
+        }
+
+        if self.rows >= BATCH_SIZE || (self.rows > 0 && self.last_send.elapsed() >= SEND_INTERVAL) {
+            self.send().await?;
+        }
+        Ok(())
+    }
+
+    /// Write the last aggregate and send the remaining rows
+    pub async fn finish(mut self) -> Result<()> {
+        if self.window.is_some() {
+            self.close_window()?;
+        }
+        self.send().await
+    }
+
+    /// Add the aggregate of the current interval to the batch
+    fn close_window(&mut self) -> Result<()> {
+        let Some(mut window) = self.window.take() else {
+            return Ok(());
+        };
+        window.latencies.sort();
+        match self.database {
+            Database::Influx => write_aggregate_line(&mut self.batch, &self.table, &window),
+            Database::ClickHouse => write_aggregate_row(&mut self.batch, &window)?,
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Send the rows written so far
+    async fn send(&mut self) -> Result<()> {
+        self.last_send = Instant::now();
+        if self.rows == 0 {
+            return Ok(());
+        }
+
+        let mut endpoint = self.endpoint.clone();
+        if self.database == Database::ClickHouse {
+            endpoint
+                .query_pairs_mut()
+                .append_pair("query", &format!("INSERT INTO {} FORMAT JSONEachRow", quote_identifier(&self.table)));
+        }
+        let body = std::mem::take(&mut self.batch);
+        self.rows = 0;
+        self.post(endpoint, body).await
+    }
+
+    /// Create the ClickHouse table for the rows written, if it doesn't exist
+    async fn create_table(&self) -> Result<()> {
+        let columns = if self.aggregate.is_some() {
+            "timestamp DateTime64(9, 'UTC'), attack String, run_id String, metadata Map(String, String), \
+             requests UInt64, errors UInt64, latency_mean_ms Float64, latency_min_ms Float64, \
+             latency_p50_ms Float64, latency_p90_ms Float64, latency_p99_ms Float64, latency_max_ms Float64, \
+             bytes_in UInt64, bytes_out UInt64"
+        } else {
+            "timestamp DateTime64(9, 'UTC'), attack String, run_id String, metadata Map(String, String), \
+             seq UInt64, worker UInt64, method String, url String, status_code UInt16, latency_ms Float64, \
+             bytes_in UInt64, bytes_out UInt64, error String, timed_out Bool"
+        };
+        let query = format!(
+            "CREATE TABLE IF NOT EXISTS {} ({}) ENGINE = MergeTree ORDER BY timestamp",
+            quote_identifier(&self.table),
+            columns
+        );
+        self.post(self.endpoint.clone(), query)
+            .await
+            .context(format!("Failed to create ClickHouse table: {}", self.table))
+    }
+
+    /// POST a body to the database, retrying failed requests
+    async fn post(&self, endpoint: Url, body: String) -> Result<()> {
+        let mut attempt = 1;
+        loop {
+            let mut request = self.client.post(endpoint.clone()).body(body.clone());
+            if let Some((username, password)) = &self.credentials {
+                request = request.basic_auth(username, Some(password));
+            }
+            if let Some(token) = &self.token {
+                request = request.header(reqwest::header::AUTHORIZATION, format!("Token {}", token));
+            }
+
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    let text = response.text().await.unwrap_or_default();
+                    anyhow::anyhow!("{}: {}", status, text.trim())
+                }
+                Err(e) => e.into(),
+            };
+            if attempt == ATTEMPTS {
+                return Err(error.context(format!("Failed to write results to {}", self.endpoint.as_str())));
+            }
+            tokio::time::sleep(Duration::from_millis(500 * u64::from(attempt))).await;
+            attempt += 1;
+        }
+    }
+}
+
+impl Window {
+    /// The interval containing a completion time, labeled with the result's labels
+    fn new(completed: DateTime<Utc>, interval: Duration, result: &AttackResult) -> Result<Self> {
+        let nanos = completed.timestamp_nanos_opt().context("Result timestamp is out of range")?;
+        let interval_nanos = i64::try_from(interval.as_nanos()).context("Aggregation interval is too long")?;
+        let start = DateTime::from_timestamp_nanos(nanos - nanos.rem_euclid(interval_nanos));
+        Ok(Self {
+            start,
+            end: start + chrono::Duration::from_std(interval)?,
+            attack: result.attack.clone(),
+            run_id: result.run_id.clone(),
+            metadata: result.metadata.clone(),
+            latencies: Vec::new(),
+            errors: 0,
+            bytes_in: 0,
+            bytes_out: 0,
+        })
+    }
+
+    fn add(&mut self, result: &AttackResult) {
+        self.latencies.push(result.latency);
+        self.errors += u64::from(result.error.is_some());
+        self.bytes_in += result.bytes_in as u64;
+        self.bytes_out += result.bytes_out as u64;
+    }
+
+    fn mean(&self) -> Duration {
+        self.latencies.iter().sum::<Duration>() / self.latencies.len().max(1) as u32
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Write the InfluxDB line protocol measurement and tags shared by results and aggregates
+fn write_line_tags(line: &mut String, measurement: &str, attack: Option<&str>, run_id: Option<&str>, metadata: &BTreeMap<String, String>) {
+    line.push_str(&escape_line(measurement, ", "));
+    let tags = [("attack", attack), ("run_id", run_id)];
+    for (key, value) in tags.into_iter().filter_map(|(key, value)| Some((key, value?))) {
+        let _ = write!(line, ",{}={}", key, escape_line(value, ",= "));
+    }
+    for (key, value) in metadata {
+        if !key.is_empty() && !value.is_empty() {
+            let _ = write!(line, ",{}={}", escape_line(key, ",= "), escape_line(value, ",= "));
+        }
+    }
+}
+
+/// Write a result as an InfluxDB line protocol point
+fn write_result_line(batch: &mut String, measurement: &str, result: &AttackResult) {
+    write_line_tags(batch, measurement, result.attack.as_deref(), result.run_id.as_deref(), &result.metadata);
+    let _ = write!(
+        batch,
+        ",method={},url={},status={} latency_ms={},bytes_in={}i,bytes_out={}i,seq={}i,worker={}i,timed_out={}",
+        escape_line(&result.target.method, ",= "),
+        escape_line(result.target.url.as_str(), ",= "),
+        result.status_code,
+        millis(result.latency),
+        result.bytes_in,
+        result.bytes_out,
+        result.seq,
+        result.worker,
+        result.timed_out,
+    );
+    if let Some(error) = &result.error {
+        let _ = write!(batch, ",error=\"{}\"", error.replace('\\', "\\\\").replace('"', "\\\""));
+    }
+    let _ = writeln!(batch, " {}", result.timestamp.timestamp_nanos_opt().unwrap_or_default());
+}
+
+/// Write an aggregate as an InfluxDB line protocol point
+fn write_aggregate_line(batch: &mut String, measurement: &str, window: &Window) {
+    write_line_tags(batch, measurement, window.attack.as_deref(), window.run_id.as_deref(), &window.metadata);
+    let _ = writeln!(
+        batch,
+        " requests={}i,errors={}i,latency_mean_ms={},latency_min_ms={},latency_p50_ms={},latency_p90_ms={},latency_p99_ms={},latency_max_ms={},bytes_in={}i,bytes_out={}i {}",
+        window.latencies.len(),
+        window.errors,
+        millis(window.mean()),
+        millis(window.latencies.first().copied().unwrap_or_default()),
+        millis(PercentileMethod::NearestRank.of_sorted(&window.latencies, 0.5)),
+        millis(PercentileMethod::NearestRank.of_sorted(&window.latencies, 0.9)),
+        millis(PercentileMethod::NearestRank.of_sorted(&window.latencies, 0.99)),
+        millis(window.latencies.last().copied().unwrap_or_default()),
+        window.bytes_in,
+        window.bytes_out,
+        window.start.timestamp_nanos_opt().unwrap_or_default(),
+    );
+}
+
+/// Escape the given characters of a line protocol measurement, tag key, or tag value
+fn escape_line(value: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(c) || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A timestamp as ClickHouse parses it into a DateTime64
+fn clickhouse_time(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%d %H:%M:%S%.9f").to_string()
+}
+
+/// Write a result as a ClickHouse JSONEachRow row
+fn write_result_row(batch: &mut String, result: &AttackResult) -> Result<()> {
+    let row = serde_json::json!({
+        "timestamp": clickhouse_time(result.timestamp),
+        "attack": result.attack,
+        "run_id": result.run_id,
+        "metadata": result.metadata,
+        "seq": result.seq,
+        "worker": result.worker,
+        "method": result.target.method,
+        "url": result.target.url.as_str(),
+        "status_code": result.status_code,
+        "latency_ms": millis(result.latency),
+        "bytes_in": result.bytes_in,
+        "bytes_out": result.bytes_out,
+        "error": result.error,
+        "timed_out": result.timed_out,
+    });
+    writeln!(batch, "{}", serde_json::to_string(&row)?)?;
+    Ok(())
+}
+
+/// Write an aggregate as a ClickHouse JSONEachRow row
+fn write_aggregate_row(batch: &mut String, window: &Window) -> Result<()> {
+    let row = serde_json::json!({
+        "timestamp": clickhouse_time(window.start),
+        "attack": window.attack,
+        "run_id": window.run_id,
+        "metadata": window.metadata,
+        "requests": window.latencies.len(),
+        "errors": window.errors,
+        "latency_mean_ms": millis(window.mean()),
+        "latency_min_ms": millis(window.latencies.first().copied().unwrap_or_default()),
+        "latency_p50_ms": millis(PercentileMethod::NearestRank.of_sorted(&window.latencies, 0.5)),
+        "latency_p90_ms": millis(PercentileMethod::NearestRank.of_sorted(&window.latencies, 0.9)),
+        "latency_p99_ms": millis(PercentileMethod::NearestRank.of_sorted(&window.latencies, 0.99)),
+        "latency_max_ms": millis(window.latencies.last().copied().unwrap_or_default()),
+        "bytes_in": window.bytes_in,
+        "bytes_out": window.bytes_out,
+    });
+    writeln!(batch, "{}", serde_json::to_string(&row)?)?;
+    Ok(())
+}
+
+/// Quote a ClickHouse identifier, keeping a `database.table` name as two identifiers
+fn quote_identifier(name: &str) -> String {
+    name.split('.')
+        .map(|part| format!("`{}`", part.replace('\\', "\\\\").replace('`', "\\`")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
@@ -0,0 +1,331 @@
+// Upload of results files to S3-compatible object storage once an attack completes,
+// since load generators often run on instances whose disk doesn't outlive them.
+//
+// Requests are signed with AWS Signature Version 4 using the standard AWS_* environment
+// variables. Files larger than one part are sent with a multipart upload.
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::time::Duration;
+use url::Url;
+
+/// Size of the parts of a multipart upload, and the largest file sent in one request
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Attempts at sending a request before giving up
+const ATTEMPTS: u32 = 3;
+
+/// Where results are uploaded to, parsed from a URL like `s3://bucket/prefix/results.bin`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    pub bucket: String,
+    /// Object key; a key ending in `/` is a prefix the file name is appended to
+    pub key: String,
+}
+
+impl std::str::FromStr for Destination {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (bucket, key) = s
+            .strip_prefix("s3://")
+            .and_then(|rest| rest.split_once('/').or(Some((rest, ""))))
+            .filter(|(bucket, _)| !bucket.is_empty())
+            .context(format!("Invalid upload destination: {}. Expected a URL like s3://bucket/key", s))?;
+        Ok(Self {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+}
+
+impl Destination {
+    /// Key of the object the file at the given path is uploaded to
+    fn object_key(&self, path: &str) -> Result<String> {
+        if self.key.is_empty() || self.key.ends_with('/') {
+            let name = std::path::Path::new(path)
+                .file_name()
+                .context(format!("Invalid results file: {}", path))?;
+            Ok(format!("{}{}", self.key, name.to_string_lossy()))
+        } else {
+            Ok(self.key.clone())
+        }
+    }
+
+    /// Destination of the file at the given path marked as partial, for the results of an
+    /// interrupted attack
+    pub fn partial(&self, path: &str) -> Result<Self> {
+        Ok(Self {
+            bucket: self.bucket.clone(),
+            key: format!("{}{}", self.object_key(path)?, PARTIAL_SUFFIX),
+        })
+    }
+}
+
+/// Suffix of the keys of the files of an interrupted attack
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Upload a results file, returning the `s3://` URL it was uploaded to
+pub async fn upload_file(path: &str, destination: &Destination) -> Result<String> {
+    let key = destination.object_key(path)?;
+
+    let client = S3Client::from_env(&destination.bucket)?;
+    let mut file = std::fs::File::open(path).context(format!("Failed to open results file: {}", path))?;
+    let first = read_part(&mut file)?;
+
+    if first.len() < PART_SIZE {
+        client.send(reqwest::Method::PUT, &key, &[], first).await?;
+    } else {
+        client.multipart_upload(&key, first, &mut file).await?;
+    }
+
+    Ok(format!("s3://{}/{}", destination.bucket, key))
+}
+
+/// Read up to one part of a file
+fn read_part(file: &mut std::fs::File) -> Result<Vec<u8>> {
+    let mut part = Vec::with_capacity(PART_SIZE);
+    file.take(PART_SIZE as u64).read_to_end(&mut part)?;
+    Ok(part)
+}
+
+/// Signs and sends requests to a bucket
+struct S3Client {
+    client: reqwest::Client,
+    /// URL of the bucket, which object keys are appended to
+    bucket_url: Url,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl S3Client {
+    /// Configure a client from AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY,
+    /// AWS_SESSION_TOKEN, AWS_REGION, and AWS_ENDPOINT_URL for other S3-compatible stores
+    fn from_env(bucket: &str) -> Result<Self> {
+        let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let access_key = env("AWS_ACCESS_KEY_ID").context("AWS_ACCESS_KEY_ID must be set to upload results")?;
+        let secret_key = env("AWS_SECRET_ACCESS_KEY").context("AWS_SECRET_ACCESS_KEY must be set to upload results")?;
+        let region = env("AWS_REGION").or_else(|| env("AWS_DEFAULT_REGION")).unwrap_or_else(|| "us-east-1".to_string());
+
+        // Other stores are addressed by path, AWS by virtual host
+        let bucket_url = match env("AWS_ENDPOINT_URL_S3").or_else(|| env("AWS_ENDPOINT_URL")) {
+            Some(endpoint) => {
+                let endpoint = Url::parse(&endpoint).context(format!("Invalid S3 endpoint: {}", endpoint))?;
+                endpoint.join(&format!("{}/", encode_key(bucket)))?
+            }
+            None => Url::parse(&format!("https://{}.s3.{}.amazonaws.com/", bucket, region))?,
+        };
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            bucket_url,
+            region,
+            access_key,
+            secret_key,
+            session_token: env("AWS_SESSION_TOKEN"),
+        })
+    }
+
+    /// Upload a file in parts, starting with the part already read
+    async fn multipart_upload(&self, key: &str, first: Vec<u8>, file: &mut std::fs::File) -> Result<()> {
+        let response = self.send(reqwest::Method::POST, key, &[("uploads", "")], Vec::new()).await?;
+        let upload_id = xml_element(&response, "UploadId")
+            .context("S3 didn't return an upload ID")?
+            .to_string();
+
+        let upload = async {
+            let mut etags = Vec::new();
+            let mut part = first;
+            while !part.is_empty() {
+                let number = (etags.len() + 1).to_string();
+                let query = [("partNumber", number.as_str()), ("uploadId", upload_id.as_str())];
+                let etag = self.send_for_etag(key, &query, part).await?;
+                etags.push(etag);
+                part = read_part(file)?;
+            }
+
+            let mut complete = String::from("<CompleteMultipartUpload>");
+            for (index, etag) in etags.iter().enumerate() {
+                complete.push_str(&format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", index + 1, etag));
+            }
+            complete.push_str("</CompleteMultipartUpload>");
+            let response = self
+                .send(reqwest::Method::POST, key, &[("uploadId", upload_id.as_str())], complete.into_bytes())
+                .await?;
+            // Completing can fail after the request succeeded
+            if let Some(message) = xml_element(&response, "Message").filter(|_| response.contains("<Error>")) {
+                anyhow::bail!("Failed to complete upload: {}", message);
+            }
+            Ok(())
+        };
+
+        let uploaded: Result<()> = upload.await;
+        if uploaded.is_err() {
+            // Don't leave the uploaded parts behind to be billed for
+            let _ = self.send(reqwest::Method::DELETE, key, &[("uploadId", upload_id.as_str())], Vec::new()).await;
+        }
+        uploaded
+    }
+
+    /// Upload one part, returning its ETag
+    async fn send_for_etag(&self, key: &str, query: &[(&str, &str)], body: Vec<u8>) -> Result<String> {
+        let response = self.request(reqwest::Method::PUT, key, query, body).await?;
+        response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_string)
+            .context("S3 didn't return an ETag for an uploaded part")
+    }
+
+    /// Send a signed request, returning the response body
+    async fn send(&self, method: reqwest::Method, key: &str, query: &[(&str, &str)], body: Vec<u8>) -> Result<String> {
+        let response = self.request(method, key, query, body).await?;
+        Ok(response.text().await?)
+    }
+
+    /// Send a signed request, retrying failed ones
+    async fn request(&self, method: reqwest::Method, key: &str, query: &[(&str, &str)], body: Vec<u8>) -> Result<reqwest::Response> {
+        let mut url = self.bucket_url.join(&encode_key(key))?;
+        let canonical_query = canonical_query(query);
+        url.set_query((!canonical_query.is_empty()).then_some(canonical_query.as_str()));
+
+        let mut attempt = 1;
+        loop {
+            let error = match self.sign(method.clone(), &url, &canonical_query, body.clone()).send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status();
+                    let text = response.text().await.unwrap_or_default();
+                    let message = xml_element(&text, "Message").unwrap_or(text.trim()).to_string();
+                    anyhow::anyhow!("{}: {}", status, message)
+                }
+                Err(e) => e.into(),
+            };
+            if attempt == ATTEMPTS {
+                return Err(error.context(format!("S3 request failed: {} {}", method, url.path())));
+            }
+            tokio::time::sleep(Duration::from_millis(500 * u64::from(attempt))).await;
+            attempt += 1;
+        }
+    }
+
+    /// Build a request signed with AWS Signature Version 4
+    fn sign(&self, method: reqwest::Method, url: &Url, canonical_query: &str, body: Vec<u8>) -> reqwest::RequestBuilder {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex(&Sha256::digest(&body));
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            url.path(),
+            canonical_query,
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut signing_key = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac_sha256(&signing_key, part.as_bytes());
+        }
+        let signature = hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+        let mut request = self.client.request(method, url.clone()).body(body).header(
+            reqwest::header::AUTHORIZATION,
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key, scope, signed_headers, signature
+            ),
+        );
+        for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+            request = request.header(name, value);
+        }
+        request
+    }
+}
+
+/// URI-encode an object key the way S3 signs it, keeping the slashes between segments
+fn encode_key(key: &str) -> String {
+    key.split('/').map(uri_encode).collect::<Vec<_>>().join("/")
+}
+
+/// Percent-encode everything but unreserved characters
+fn uri_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Query parameters sorted and encoded as they're signed
+fn canonical_query(query: &[(&str, &str)]) -> String {
+    let mut pairs: Vec<_> = query.iter().map(|(name, value)| (uri_encode(name), uri_encode(value))).collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Text of the first element with the given name in an XML response
+fn xml_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(&xml[start..end])
+}
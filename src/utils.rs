@@ -1,12 +1,22 @@
 use anyhow::{Context, Result};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::Arc;
 // use std::path::Path;
 use std::time::Duration;
 
-use crate::models::{Header, Target};
+use crate::auth::ProxyAuth;
+use crate::models::{Expect, Golden, GoldenCompare, Header, Row, Scenario, ServerTiming, Stage, Target};
+use reqwest::cookie::Jar;
 use url::Url;
 
+/// Bytes that start a gzip stream
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Bytes that start a zstd frame
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
 /// Parse a rate string like "50/1s" into requests per second
 pub fn parse_rate(rate_str: &str) -> Result<f64> {
     let parts: Vec<&str> = rate_str.split('/').collect();
@@ -29,6 +39,37 @@ pub fn parse_rate(rate_str: &str) -> Result<f64> {
     Ok(requests / duration_secs)
 }
 
+/// Parse virtual user stages like "30s:10,1m:50,30s:0" into a duration and target for each stage
+pub fn parse_stages(stages_str: &str) -> Result<Vec<Stage>> {
+    let stages = stages_str
+        .split(',')
+        .map(|stage| {
+            let (duration, target) = stage
+                .trim()
+                .split_once(':')
+                .context(format!("Invalid stage: {}. Expected format: <duration>:<target> (e.g., 30s:10)", stage))?;
+            Ok(Stage {
+                duration: humantime::parse_duration(duration.trim()).context(format!("Invalid stage duration: {}", duration))?,
+                target: target.trim().parse().context(format!("Invalid stage target: {}", target))?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    check_stages(&stages)?;
+    Ok(stages)
+}
+
+/// Check that stages last a while and ramp up to at least one virtual user
+pub(crate) fn check_stages(stages: &[Stage]) -> Result<()> {
+    if stages.iter().all(|s| s.duration.is_zero()) {
+        anyhow::bail!("Stages must last longer than 0s");
+    }
+    if stages.iter().all(|s| s.target == 0) {
+        anyhow::bail!("Stages must ramp up to at least one virtual user");
+    }
+    Ok(())
+}
+
 /// Parse HTTP targets from a reader in HTTP format
 pub fn parse_http_targets<R: BufRead>(reader: R) -> Result<Vec<Target>> {
     let mut targets = Vec::new();
@@ -55,6 +96,12 @@ pub fn parse_http_targets<R: BufRead>(reader: R) -> Result<Vec<Target>> {
             url,
             headers: Vec::new(),
             body: None,
+            expect: None,
+            slo: None,
+            extract: Vec::new(),
+            when: None,
+            checks: Vec::new(),
+            tags: BTreeMap::new(),
         });
     }
 
@@ -68,6 +115,89 @@ pub fn parse_json_targets<R: Read>(reader: R) -> Result<Vec<Target>> {
     Ok(targets)
 }
 
+/// Parse scenarios from a reader in JSON format
+///
+/// The file holds an array of scenarios, each with a name, its steps in the
+/// JSON target format, and an optional weight:
+///
+/// ```text
+/// [
+///   {
+///     "name": "checkout",
+///     "steps": [
+///       {"method": "POST", "url": "http://goku:9090/login", "headers": [], "body": null},
+///       {"method": "GET", "url": "http://goku:9090/cart", "headers": [], "body": null}
+///     ]
+///   }
+/// ]
+/// ```
+pub fn parse_scenarios<R: Read>(reader: R) -> Result<Vec<Scenario>> {
+    let scenarios: Vec<Scenario> = serde_json::from_reader(reader)
+        .context("Failed to parse scenarios")?;
+
+    for scenario in &scenarios {
+        if scenario.steps.is_empty() {
+            anyhow::bail!("Scenario {} has no steps", scenario.name);
+        }
+        if scenario.weight == 0 {
+            anyhow::bail!("Scenario {} must have a weight of at least 1", scenario.name);
+        }
+    }
+
+    Ok(scenarios)
+}
+
+/// Read the rows of a feeder file
+///
+/// `.json` files hold an array of objects and `.ndjson` or `.jsonl` files one object per
+/// line. Any other file is read as CSV, whose header row names the variables.
+pub fn read_feeder_rows(path: &str) -> Result<Vec<Row>> {
+    let extension = std::path::Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    let objects: Vec<serde_json::Value> = match extension.as_str() {
+        "json" => serde_json::from_reader(get_reader(path)?).context(format!("Failed to parse feeder: {}", path))?,
+        "ndjson" | "jsonl" => BufReader::new(get_reader(path)?)
+            .lines()
+            .filter(|line| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect::<Result<_>>()
+            .context(format!("Failed to parse feeder: {}", path))?,
+        _ => {
+            let mut reader = csv::Reader::from_reader(get_reader(path)?);
+            let headers = reader.headers().context(format!("Failed to parse feeder: {}", path))?.clone();
+            return reader
+                .records()
+                .map(|record| {
+                    let record = record.context(format!("Failed to parse feeder: {}", path))?;
+                    Ok(headers.iter().zip(record.iter()).map(|(h, v)| (h.to_string(), v.to_string())).collect())
+                })
+                .collect();
+        }
+    };
+
+    objects
+        .into_iter()
+        .map(|object| match object {
+            serde_json::Value::Object(fields) => Ok(fields
+                .into_iter()
+                .map(|(name, value)| {
+                    let value = match value {
+                        serde_json::Value::String(s) => s,
+                        serde_json::Value::Null => String::new(),
+                        other => other.to_string(),
+                    };
+                    (name, value)
+                })
+                .collect()),
+            other => anyhow::bail!("Feeder {} rows must be objects, got: {}", path, other),
+        })
+        .collect()
+}
+
 /// Parse HTTP headers from strings like "Name: Value"
 pub fn parse_headers(headers: &[String]) -> Result<Vec<Header>> {
     let mut result = Vec::new();
@@ -87,22 +217,202 @@ pub fn parse_headers(headers: &[String]) -> Result<Vec<Header>> {
     Ok(result)
 }
 
-/// Get a reader for a file or stdin
+/// Parse run metadata given as key=value pairs
+pub fn parse_metadata(metadata: &[String]) -> Result<BTreeMap<String, String>> {
+    metadata
+        .iter()
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.trim().to_string())),
+            _ => anyhow::bail!("Invalid metadata: {}. Expected key=value", pair),
+        })
+        .collect()
+}
+
+/// Parse credentials from a string like "user:pass"
+pub fn parse_credentials(credentials: &str) -> Result<(String, String)> {
+    match credentials.split_once(':') {
+        Some((user, pass)) if !user.is_empty() => Ok((user.to_string(), pass.to_string())),
+        _ => anyhow::bail!("Invalid credentials format. Expected format: <user>:<pass>"),
+    }
+}
+
+/// Build a cookie jar seeded with "name=value" cookies for every target URL
+pub fn build_cookie_jar(cookies: &[String], targets: &[Target]) -> Result<Arc<Jar>> {
+    let jar = Jar::default();
+
+    for cookie in cookies {
+        if !cookie.contains('=') {
+            anyhow::bail!("Invalid cookie format: {}", cookie);
+        }
+
+        for target in targets {
+            jar.add_cookie_str(cookie, &target.url);
+        }
+    }
+
+    Ok(Arc::new(jar))
+}
+
+/// Build the proxies for the client, attaching Proxy-Authorization credentials
+///
+/// Without an explicit proxy URL, credentials are attached to the proxies from the
+/// standard HTTP_PROXY, HTTPS_PROXY and ALL_PROXY environment variables.
+pub fn build_proxies(proxy: Option<&str>, auth: Option<&ProxyAuth>) -> Result<Vec<reqwest::Proxy>> {
+    let mut proxies = Vec::new();
+
+    if let Some(url) = proxy {
+        proxies.push(reqwest::Proxy::all(url).context(format!("Invalid proxy URL: {}", url))?);
+    } else if auth.is_some() {
+        let env_proxy = |names: [&str; 2]| names.iter().find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty()));
+        if let Some(url) = env_proxy(["HTTP_PROXY", "http_proxy"]) {
+            proxies.push(reqwest::Proxy::http(&url).context(format!("Invalid proxy URL: {}", url))?);
+        }
+        if let Some(url) = env_proxy(["HTTPS_PROXY", "https_proxy"]) {
+            proxies.push(reqwest::Proxy::https(&url).context(format!("Invalid proxy URL: {}", url))?);
+        }
+        if let Some(url) = env_proxy(["ALL_PROXY", "all_proxy"]) {
+            proxies.push(reqwest::Proxy::all(&url).context(format!("Invalid proxy URL: {}", url))?);
+        }
+        if proxies.is_empty() {
+            anyhow::bail!("Proxy credentials were given but no proxy is configured");
+        }
+        proxies = proxies.into_iter().map(|p| p.no_proxy(reqwest::NoProxy::from_env())).collect();
+    }
+
+    match auth {
+        Some(ProxyAuth::Basic(user, pass)) => {
+            proxies = proxies.into_iter().map(|p| p.basic_auth(user, pass)).collect();
+        }
+        Some(ProxyAuth::Bearer(token)) => {
+            let mut value = reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token))
+                .context("Invalid proxy bearer token")?;
+            value.set_sensitive(true);
+            proxies = proxies.into_iter().map(|p| p.custom_http_auth(value.clone())).collect();
+        }
+        None => {}
+    }
+
+    Ok(proxies)
+}
+
+/// Parse a Server-Timing header value like `db;dur=53.2, cache;desc="Cache Read";dur=23.2`
+///
+/// Malformed parameters are ignored rather than failing the whole header, as are durations
+/// that aren't finite or don't fit a `Duration`.
+pub fn parse_server_timing(value: &str) -> Vec<ServerTiming> {
+    split_unquoted(value, ',')
+        .into_iter()
+        .filter_map(|metric| {
+            let mut parts = split_unquoted(metric, ';').into_iter();
+            let name = parts.next()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+
+            let mut timing = ServerTiming {
+                name: name.to_string(),
+                dur: None,
+                desc: None,
+            };
+            for param in parts {
+                let Some((key, value)) = param.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches('"');
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "dur" => {
+                        timing.dur = value
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|dur| dur.is_finite() && Duration::try_from_secs_f64(dur.max(0.0) / 1000.0).is_ok())
+                    }
+                    "desc" => timing.desc = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            Some(timing)
+        })
+        .collect()
+}
+
+/// Split a header value on a delimiter, ignoring delimiters inside quoted strings
+pub(crate) fn split_unquoted(value: &str, delimiter: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+
+    for (i, c) in value.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == delimiter && !in_quotes {
+            parts.push(&value[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&value[start..]);
+
+    parts
+}
+
+/// Get a reader for a file or stdin, decompressing gzip and zstd input on the fly
 pub fn get_reader(path: &str) -> Result<Box<dyn BufRead>> {
-    if path == "stdin" {
-        Ok(Box::new(BufReader::new(io::stdin())))
+    let mut reader: Box<dyn BufRead> = if path == "stdin" {
+        Box::new(BufReader::new(io::stdin()))
     } else {
         let file = File::open(path).context(format!("Failed to open file: {}", path))?;
-        Ok(Box::new(BufReader::new(file)))
+        Box::new(BufReader::new(file))
+    };
+
+    // Tell compressed input apart by its magic bytes, so piped input is decompressed too
+    let header = reader.fill_buf().context(format!("Failed to read: {}", path))?;
+    if header.starts_with(GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))))
+    } else if header.starts_with(ZSTD_MAGIC) {
+        let decoder = zstd::Decoder::with_buffer(reader).context(format!("Failed to decompress: {}", path))?;
+        Ok(Box::new(BufReader::new(decoder)))
+    } else {
+        Ok(reader)
     }
 }
 
-/// Get a writer for a file or stdout
+/// Whether input starting with the given bytes is gzip or zstd compressed
+pub(crate) fn is_compressed(start: &[u8]) -> bool {
+    start.starts_with(GZIP_MAGIC) || start.starts_with(ZSTD_MAGIC)
+}
+
+/// Get a writer for a file or stdout, compressing output to files ending in `.gz` or `.zst`
+///
+/// Compressed output is finished when the writer is dropped.
 pub fn get_writer(path: &str) -> Result<Box<dyn Write>> {
     if path == "stdout" {
-        Ok(Box::new(io::stdout()))
+        return Ok(Box::new(io::stdout()));
+    }
+
+    let file = File::create(path).context(format!("Failed to create file: {}", path))?;
+    compress(path, file)
+}
+
+/// Get a writer appending to a file, creating it if needed
+///
+/// Compressed output is appended as a new gzip member or zstd frame, which readers
+/// decompress as if the file had been written at once.
+pub fn get_append_writer(path: &str) -> Result<Box<dyn Write>> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("Failed to open file: {}", path))?;
+    compress(path, file)
+}
+
+/// Compress output to a file ending in `.gz` or `.zst`
+fn compress(path: &str, file: File) -> Result<Box<dyn Write>> {
+    if path.ends_with(".gz") {
+        Ok(Box::new(flate2::write::GzEncoder::new(file, flate2::Compression::default())))
+    } else if path.ends_with(".zst") {
+        let encoder = zstd::Encoder::new(file, 0).context(format!("Failed to compress: {}", path))?;
+        Ok(Box::new(encoder.auto_finish()))
     } else {
-        let file = File::create(path).context(format!("Failed to create file: {}", path))?;
         Ok(Box::new(file))
     }
 }
@@ -153,9 +463,11 @@ pub fn format_size(size: usize) -> String {
 /// - URL line (e.g., "POST http://goku:9090/things" or "POST /api/things HTTP/1.1")
 /// - Headers (e.g., "Header1: asdasd")
 /// - Body in JSON or HTTP param format (e.g., {"key": "value"} or file path)
+/// - An optional `Expect:` section after the headers and body, with "Status:", "Header:",
+///   "Body:", "Golden:" and "Golden-JSON:" lines the response must match
 /// 
 /// Example 1 (Simple format):
-/// ```
+/// ```text
 /// POST http://goku:9090/things
 /// Header1: asdasd
 /// Body:
@@ -166,13 +478,23 @@ pub fn format_size(size: usize) -> String {
 /// ```
 ///
 /// Example 2 (HTTP/1.1 format):
-/// ```
+/// ```text
 /// POST /api/things HTTP/1.1
 /// Host: goku:9090
 /// Content-Type: application/json
 /// 
 /// {"key": "value"}
 /// ```
+///
+/// Example 3 (Expectations):
+/// ```text
+/// GET http://goku:9090/health
+/// Expect:
+/// Status: 200
+/// Header: Content-Type: application/json
+/// Body: "status":"ok"
+/// Golden-JSON: golden/health.json
+/// ```
 pub fn parse_file_targets<R: BufRead>(reader: R) -> Result<Vec<Target>> {
     let mut targets = Vec::new();
     let mut lines = reader.lines();
@@ -187,6 +509,8 @@ pub fn parse_file_targets<R: BufRead>(reader: R) -> Result<Vec<Target>> {
     let mut body_content = String::new();
     let mut is_http_format = false;
     let mut found_empty_line = false;
+    let mut current_expect: Option<Expect> = None;
+    let mut reading_expect = false;
 
     while let Some(line_result) = lines.next() {
         let line = line_result?;
@@ -203,6 +527,7 @@ pub fn parse_file_targets<R: BufRead>(reader: R) -> Result<Vec<Target>> {
             if is_http_format && current_method.is_some() && current_path.is_some() && !found_empty_line {
                 found_empty_line = true;
                 reading_body = true;
+                reading_expect = false;
                 continue;
             }
 
@@ -235,8 +560,15 @@ pub fn parse_file_targets<R: BufRead>(reader: R) -> Result<Vec<Target>> {
                     url: current_url.take().unwrap(),
                     headers: std::mem::take(&mut current_headers),
                     body: current_body.take(),
+                    expect: current_expect.take(),
+                    slo: None,
+                    extract: Vec::new(),
+                    when: None,
+                    checks: Vec::new(),
+                    tags: BTreeMap::new(),
                 });
                 reading_body = false;
+                reading_expect = false;
                 body_content.clear();
                 is_http_format = false;
                 found_empty_line = false;
@@ -287,6 +619,12 @@ pub fn parse_file_targets<R: BufRead>(reader: R) -> Result<Vec<Target>> {
                                 url: current_url.take().unwrap(),
                                 headers: std::mem::take(&mut current_headers),
                                 body: current_body.take(),
+                                expect: current_expect.take(),
+                                slo: None,
+                                extract: Vec::new(),
+                                when: None,
+                                checks: Vec::new(),
+                                tags: BTreeMap::new(),
                             });
                             body_content.clear();
                             current_path.take();
@@ -300,6 +638,7 @@ pub fn parse_file_targets<R: BufRead>(reader: R) -> Result<Vec<Target>> {
                         current_path = Some(path);
                         is_http_format = true;
                         found_empty_line = false;
+                        reading_expect = false;
                         continue;
                     }
                 } else {
@@ -333,6 +672,12 @@ pub fn parse_file_targets<R: BufRead>(reader: R) -> Result<Vec<Target>> {
                             url: current_url.take().unwrap(),
                             headers: std::mem::take(&mut current_headers),
                             body: current_body.take(),
+                            expect: current_expect.take(),
+                            slo: None,
+                            extract: Vec::new(),
+                            when: None,
+                            checks: Vec::new(),
+                            tags: BTreeMap::new(),
                         });
                         body_content.clear();
                         current_path.take();
@@ -346,6 +691,7 @@ pub fn parse_file_targets<R: BufRead>(reader: R) -> Result<Vec<Target>> {
                             current_url = Some(url);
                             is_http_format = false;
                             found_empty_line = false;
+                            reading_expect = false;
                         },
                         Err(e) => {
                             anyhow::bail!("Failed to parse URL {}: {}", method_url_parts[1], e);
@@ -356,6 +702,25 @@ pub fn parse_file_targets<R: BufRead>(reader: R) -> Result<Vec<Target>> {
             }
         }
 
+        // A bare "Expect:" line starts the expectations for the current target
+        if trimmed_line.eq_ignore_ascii_case("Expect:") && current_method.is_some() && !(is_http_format && found_empty_line) {
+            if reading_body && !body_content.is_empty() {
+                current_body = Some(body_content.trim().as_bytes().to_vec());
+                body_content.clear();
+            }
+            reading_body = false;
+            reading_expect = true;
+            current_expect.get_or_insert_with(Expect::default);
+            continue;
+        }
+
+        // Expectation lines run until the end of the target
+        if reading_expect {
+            let expect = current_expect.get_or_insert_with(Expect::default);
+            parse_expect_line(expect, trimmed_line)?;
+            continue;
+        }
+
         // If we're reading the body, collect lines until we hit an empty line
         if reading_body {
             // For HTTP format, we're already reading the body after an empty line
@@ -463,7 +828,43 @@ pub fn parse_file_targets<R: BufRead>(reader: R) -> Result<Vec<Target>> {
             url: current_url.unwrap(),
             headers: current_headers,
             body: current_body,
+            expect: current_expect,
+            slo: None,
+            extract: Vec::new(),
+            when: None,
+            checks: Vec::new(),
+            tags: BTreeMap::new(),
         });
     }
     Ok(targets)
 }
+
+/// Parse one line of an `Expect:` section, like "Status: 200", "Header: Content-Type: application/json",
+/// "Body: some text" or "Golden: path/to/body"
+fn parse_expect_line(expect: &mut Expect, line: &str) -> Result<()> {
+    let Some((kind, value)) = line.split_once(':') else {
+        anyhow::bail!("Invalid expectation: {}", line);
+    };
+    let value = value.trim();
+
+    match kind.trim().to_ascii_lowercase().as_str() {
+        "status" => {
+            expect.status = Some(value.parse().context(format!("Invalid expected status: {}", value))?);
+        }
+        "header" => {
+            let Some((name, value)) = value.split_once(':') else {
+                anyhow::bail!("Invalid expected header, expected format <name>: <value>: {}", value);
+            };
+            expect.headers.push(Header {
+                name: name.trim().to_string(),
+                value: value.trim().to_string(),
+            });
+        }
+        "body" => expect.body_contains = Some(value.to_string()),
+        "golden" => expect.golden = Some(Golden::new(value, GoldenCompare::Exact)),
+        "golden-json" => expect.golden = Some(Golden::new(value, GoldenCompare::Json)),
+        _ => anyhow::bail!("Unknown expectation: {}", line),
+    }
+
+    Ok(())
+}